@@ -0,0 +1,188 @@
+use std::io::{self, Read, Write};
+
+use powermeter_protocol::event::Event;
+use powermeter_protocol::serial::{ErrorCode, FrameReader, Request, Response, SessionEntry, MAX_FRAME_LEN, VERSION};
+use powermeter_protocol::stream::Sample;
+
+/// Request/response side of the serial protocol on top of any byte stream,
+/// usually a serial port with a read timeout.
+pub struct Client<P: Read + Write> {
+    port: P,
+    reader: FrameReader<MAX_FRAME_LEN>,
+    /// the last complete frame as received, kept for writing it to a file
+    frame: Vec<u8>,
+    message: Vec<u8>,
+}
+
+impl<P: Read + Write> Client<P> {
+    pub fn new(port: P) -> Self {
+        Client {
+            port,
+            reader: FrameReader::new(),
+            frame: Vec::with_capacity(MAX_FRAME_LEN),
+            message: Vec::new(),
+        }
+    }
+
+    pub fn send(&mut self, request: &Request) -> io::Result<()> {
+        let mut buf = [0u8; MAX_FRAME_LEN];
+        let len = request.encode(&mut buf).ok_or_else(|| invalid("request too long"))?;
+        self.port.write_all(&buf[..len])?;
+        self.port.flush()
+    }
+
+    /// Wait for the next valid response. Damaged frames are skipped.
+    pub fn receive(&mut self) -> io::Result<Response<'_>> {
+        self.next_frame()?;
+        decode(&self.message)
+    }
+
+    fn next_frame(&mut self) -> io::Result<()> {
+        self.frame.clear();
+        let mut byte = [0u8; 1];
+        loop {
+            if self.port.read(&mut byte)? == 0 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "device closed the port"));
+            }
+            self.frame.push(byte[0]);
+            match self.reader.push(byte[0]) {
+                None => {}
+                Some(Ok(message)) => {
+                    self.message.clear();
+                    self.message.extend_from_slice(message);
+                    return Ok(());
+                }
+                Some(Err(e)) => {
+                    eprintln!("dropping frame: {:?}", e);
+                    self.frame.clear();
+                }
+            }
+        }
+    }
+
+    /// Raw bytes of the frame returned by the last receive().
+    pub fn last_frame(&self) -> &[u8] {
+        &self.frame
+    }
+
+    pub fn ping(&mut self) -> io::Result<u8> {
+        self.send(&Request::Ping)?;
+        match self.receive()? {
+            Response::Pong { version } => {
+                if version != VERSION {
+                    eprintln!("device protocol version {} differs from {}", version, VERSION);
+                }
+                Ok(version)
+            }
+            other => Err(unexpected(&other)),
+        }
+    }
+
+    pub fn config_get(&mut self, name: &str) -> io::Result<String> {
+        self.send(&Request::ConfigGet { name })?;
+        match self.receive()? {
+            Response::ConfigValue { value } => Ok(value.to_string()),
+            other => Err(unexpected(&other)),
+        }
+    }
+
+    pub fn config_set(&mut self, name: &str, value: &str) -> io::Result<()> {
+        self.send(&Request::ConfigSet { name, value })?;
+        match self.receive()? {
+            Response::Ok => Ok(()),
+            other => Err(unexpected(&other)),
+        }
+    }
+
+    pub fn sessions(&mut self) -> io::Result<Vec<SessionEntry>> {
+        self.send(&Request::SessionList)?;
+        let mut sessions = Vec::new();
+        loop {
+            match self.receive()? {
+                Response::Session(entry) => sessions.push(entry),
+                Response::End => return Ok(sessions),
+                other => return Err(unexpected(&other)),
+            }
+        }
+    }
+
+    /// The event log of the device, the oldest first.
+    pub fn events(&mut self) -> io::Result<Vec<Event>> {
+        self.send(&Request::EventList)?;
+        let mut events = Vec::new();
+        loop {
+            match self.receive()? {
+                Response::Event(event) => events.push(event),
+                Response::End => return Ok(events),
+                other => return Err(unexpected(&other)),
+            }
+        }
+    }
+
+    /// Calls f with the raw frame of the Session and of every Record
+    /// response of the session.
+    pub fn download(&mut self, id: u32, mut f: impl FnMut(&Response, &[u8]) -> io::Result<()>) -> io::Result<()> {
+        self.send(&Request::SessionRead { id })?;
+        loop {
+            self.next_frame()?;
+            let response = decode(&self.message)?;
+            match response {
+                Response::Session(_) | Response::Record { .. } => {}
+                Response::End => return Ok(()),
+                other => return Err(unexpected(&other)),
+            }
+            f(&response, &self.frame)?;
+        }
+    }
+
+    /// Stream samples to f until it returns false, f also gets the raw
+    /// frame of the sample.
+    pub fn live(&mut self, mut f: impl FnMut(&Sample, &[u8]) -> io::Result<bool>) -> io::Result<()> {
+        self.send(&Request::LiveStart)?;
+        loop {
+            self.next_frame()?;
+            let sample = match decode(&self.message)? {
+                Response::Sample(sample) => sample,
+                other => return Err(unexpected(&other)),
+            };
+            if !f(&sample, &self.frame)? {
+                break;
+            }
+        }
+        self.send(&Request::LiveStop)?;
+        // samples already on the way come before the End
+        loop {
+            match self.receive()? {
+                Response::Sample(_) => {}
+                Response::End => return Ok(()),
+                other => return Err(unexpected(&other)),
+            }
+        }
+    }
+}
+
+fn decode(message: &[u8]) -> io::Result<Response<'_>> {
+    Response::decode(message).map_err(|e| invalid(&format!("bad response {:?}", e)))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn unexpected(response: &Response) -> io::Error {
+    match response {
+        Response::Error(code) => io::Error::other(format!("device error: {}", error_text(*code))),
+        other => invalid(&format!("unexpected response {:?}", other)),
+    }
+}
+
+fn error_text(code: ErrorCode) -> String {
+    match code {
+        ErrorCode::UnknownRequest => "unknown request".to_string(),
+        ErrorCode::UnknownName => "unknown setting".to_string(),
+        ErrorCode::InvalidValue => "invalid value".to_string(),
+        ErrorCode::Storage => "flash storage failed".to_string(),
+        ErrorCode::NotFound => "not found".to_string(),
+        ErrorCode::Other(code) => format!("code {}", code),
+    }
+}
@@ -0,0 +1,140 @@
+// Conversion of captured serial frames to CSV or JSON
+//
+// `capture` and `sessions download` store the frames exactly as they came
+// from the device. A file holds either live Sample responses or one Session
+// response followed by its Record responses.
+
+use std::io::{self, Write};
+
+use powermeter_protocol::serial::{FrameReader, Response, SessionEntry, MAX_FRAME_LEN};
+use powermeter_protocol::time::UtcDateTime;
+use powermeter_storage::datalog::Record;
+use serde_json::json;
+
+pub const CSV_HEADER: &str = "timestamp_us,utc,voltage_v,current_ma,power_mw";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Row {
+    /// us since boot for live samples, since the session start for logs
+    pub timestamp_us: u64,
+    pub utc_us: Option<i64>,
+    pub voltage: f32,
+    pub current: f32,
+    pub power: f32,
+}
+
+#[derive(Debug, Default)]
+pub struct Export {
+    pub session: Option<SessionEntry>,
+    pub rows: Vec<Row>,
+    /// frames that were damaged or could not be decoded
+    pub dropped: usize,
+}
+
+/// Parse a file of captured frames.
+pub fn parse(data: &[u8]) -> Export {
+    let mut export = Export::default();
+    let mut reader = FrameReader::<MAX_FRAME_LEN>::new();
+    let mut compact = Vec::new();
+    for &byte in data {
+        let response = match reader.push(byte) {
+            None => continue,
+            Some(Ok(message)) => Response::decode(message),
+            Some(Err(_)) => {
+                export.dropped += 1;
+                continue;
+            }
+        };
+        match response {
+            Ok(Response::Sample(sample)) => export.rows.push(Row {
+                timestamp_us: sample.timestamp_us,
+                utc_us: None,
+                voltage: sample.voltage,
+                current: sample.current,
+                power: sample.power,
+            }),
+            Ok(Response::Session(entry)) => export.session = Some(entry),
+            Ok(Response::Record { kind, payload }) => match Record::decode(kind, payload) {
+                Some(Record::Sample(sample)) => export.push_logged(sample.offset_us, sample.voltage, sample.current, sample.power),
+                Some(Record::Aggregate(aggregate)) => export.push_logged(aggregate.offset_us, aggregate.voltage, aggregate.current, aggregate.power),
+                Some(Record::Compact(c)) => compact.extend_from_slice(c.as_bytes()),
+                None => export.dropped += 1,
+            },
+            Ok(_) => {}
+            Err(_) => export.dropped += 1,
+        }
+    }
+    if !compact.is_empty() {
+        export.push_compact(&compact);
+    }
+    export
+}
+
+impl Export {
+    fn start_unix_us(&self) -> Option<i64> {
+        self.session.and_then(|session| session.start_unix_us)
+    }
+
+    fn push_logged(&mut self, offset_us: u64, voltage: f32, current: f32, power: f32) {
+        self.rows.push(Row {
+            timestamp_us: offset_us,
+            utc_us: self.start_unix_us().map(|start| start + offset_us as i64),
+            voltage,
+            current,
+            power,
+        });
+    }
+
+    // raw samples carry the uptime, the first one is the session start
+    fn push_compact(&mut self, data: &[u8]) {
+        let log = match powermeter_udp::compact::decode(data) {
+            Ok(log) => log,
+            Err(_) => {
+                self.dropped += 1;
+                return;
+            }
+        };
+        self.dropped += log.bad_blocks;
+        let first_us = log.samples.first().map_or(0, |sample| sample.timestamp_us);
+        for sample in &log.samples {
+            let offset_us = sample.timestamp_us - first_us;
+            self.push_logged(offset_us,
+                             sample.voltage(),
+                             sample.current(&log.calibration),
+                             sample.power(&log.calibration));
+        }
+    }
+
+    pub fn write_csv<W: Write>(&self, mut out: W) -> io::Result<()> {
+        writeln!(out, "{}", CSV_HEADER)?;
+        for row in &self.rows {
+            write!(out, "{},", row.timestamp_us)?;
+            if let Some(utc_us) = row.utc_us {
+                write!(out, "{}", UtcDateTime::from_unix_us(utc_us))?;
+            }
+            writeln!(out, ",{:.3},{:.3},{:.3}", row.voltage, row.current, row.power)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_json<W: Write>(&self, mut out: W) -> io::Result<()> {
+        let rows: Vec<_> = self.rows.iter().map(|row| json!({
+            "timestamp_us": row.timestamp_us,
+            "utc": row.utc_us.map(|utc_us| UtcDateTime::from_unix_us(utc_us).to_string()),
+            "voltage_v": row.voltage,
+            "current_ma": row.current,
+            "power_mw": row.power,
+        })).collect();
+        let session = self.session.map(|session| json!({
+            "id": session.id,
+            "mode": session.mode,
+            "calibration": session.calibration,
+            "interval_us": session.interval_us,
+            "start": session.start_unix_us.map(|utc_us| UtcDateTime::from_unix_us(utc_us).to_string()),
+            "duration_us": session.duration_us,
+            "truncated": session.truncated,
+        }));
+        serde_json::to_writer_pretty(&mut out, &json!({ "session": session, "samples": rows }))?;
+        writeln!(out)
+    }
+}
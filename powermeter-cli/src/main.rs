@@ -0,0 +1,209 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, IsTerminal, Write};
+use std::process::exit;
+use std::time::{Duration, Instant};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use powermeter_cli::client::Client;
+use powermeter_cli::export;
+use powermeter_protocol::event::CSV_HEADER;
+use powermeter_protocol::serial::{Response, BAUD_RATE};
+use powermeter_protocol::time::UtcDateTime;
+use powermeter_storage::datalog::LogMode;
+
+#[derive(Parser)]
+#[command(name = "powermeter-cli", about = "Talk to the powermeter over its serial port")]
+struct Cli {
+    /// serial port of the device, e.g. /dev/ttyUSB0
+    #[arg(short, long, global = true, env = "POWERMETER_PORT")]
+    port: Option<String>,
+    #[arg(short, long, global = true, default_value_t = BAUD_RATE)]
+    baud: u32,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Show the live readout
+    Live {
+        /// stop after this many samples
+        #[arg(short = 'n', long)]
+        samples: Option<u64>,
+    },
+    /// Record live samples to a file for later export
+    Capture {
+        #[arg(short, long)]
+        seconds: u64,
+        #[arg(short, long)]
+        output: String,
+    },
+    /// Convert a capture or a downloaded session
+    Export {
+        input: String,
+        #[arg(short, long, value_enum, default_value_t = Format::Csv)]
+        format: Format,
+        /// defaults to stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    #[command(subcommand)]
+    Sessions(SessionsCommand),
+    /// Print the event log as csv
+    Events,
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    Get { name: String },
+    Set { name: String, value: String },
+}
+
+#[derive(Subcommand)]
+enum SessionsCommand {
+    /// List the sessions in the flash data log
+    List,
+    /// Save a session to a file for later export
+    Download {
+        id: u32,
+        #[arg(short, long)]
+        output: String,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Csv,
+    Json,
+}
+
+fn open(cli: &Cli) -> io::Result<Client<Box<dyn serialport::SerialPort>>> {
+    let path = match &cli.port {
+        Some(path) => path,
+        None => {
+            eprintln!("no serial port given, use --port or POWERMETER_PORT");
+            exit(2);
+        }
+    };
+    let port = serialport::new(path, cli.baud)
+        .timeout(Duration::from_secs(1))
+        .open()
+        .map_err(io::Error::from)?;
+    let mut client = Client::new(port);
+    client.ping()?;
+    Ok(client)
+}
+
+fn main() {
+    let cli = Cli::parse();
+    if let Err(e) = run(&cli) {
+        eprintln!("powermeter-cli: {}", e);
+        exit(1);
+    }
+}
+
+fn run(cli: &Cli) -> io::Result<()> {
+    match &cli.command {
+        Command::Live { samples } => {
+            let mut client = open(cli)?;
+            let terminal = io::stdout().is_terminal();
+            let mut stdout = io::stdout().lock();
+            let mut count = 0;
+            client.live(|sample, _| {
+                if terminal {
+                    write!(stdout, "\r{:8.3} V {:10.3} mA {:10.3} mW", sample.voltage, sample.current, sample.power)?;
+                } else {
+                    writeln!(stdout, "{},{:.3},{:.3},{:.3}", sample.timestamp_us, sample.voltage, sample.current, sample.power)?;
+                }
+                stdout.flush()?;
+                count += 1;
+                Ok(match samples {
+                    Some(limit) => count < *limit,
+                    None => true,
+                })
+            })?;
+            if terminal {
+                writeln!(stdout)?;
+            }
+        }
+        Command::Capture { seconds, output } => {
+            let mut client = open(cli)?;
+            let mut out = BufWriter::new(File::create(output)?);
+            let end = Instant::now() + Duration::from_secs(*seconds);
+            let mut count = 0;
+            client.live(|_, frame| {
+                out.write_all(frame)?;
+                count += 1;
+                Ok(Instant::now() < end)
+            })?;
+            out.flush()?;
+            eprintln!("captured {} samples", count);
+        }
+        Command::Export { input, format, output } => {
+            let export = export::parse(&fs::read(input)?);
+            if export.dropped != 0 {
+                eprintln!("dropped {} damaged frame(s)", export.dropped);
+            }
+            let out: Box<dyn Write> = match output {
+                Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+                None => Box::new(BufWriter::new(io::stdout())),
+            };
+            match format {
+                Format::Csv => export.write_csv(out)?,
+                Format::Json => export.write_json(out)?,
+            }
+        }
+        Command::Config(ConfigCommand::Get { name }) => {
+            println!("{}", open(cli)?.config_get(name)?);
+        }
+        Command::Config(ConfigCommand::Set { name, value }) => {
+            open(cli)?.config_set(name, value)?;
+        }
+        Command::Sessions(SessionsCommand::List) => {
+            println!("{:>4}  {:<24}  {:>10}  {:<10}  {:>8}", "id", "start", "duration", "mode", "records");
+            for session in open(cli)?.sessions()? {
+                let start = match session.start_unix_us {
+                    Some(unix_us) => UtcDateTime::from_unix_us(unix_us).to_string(),
+                    None => "unknown".to_string(),
+                };
+                let mode = match LogMode::from_code(session.mode) {
+                    Some(LogMode::Samples) => "samples",
+                    Some(LogMode::Aggregates) => "aggregates",
+                    Some(LogMode::Compact) => "compact",
+                    None => "?",
+                };
+                println!("{:>4}  {:<24}  {:>9.1}s  {:<10}  {:>8}{}",
+                         session.id,
+                         start,
+                         session.duration_us as f64 / 1e6,
+                         mode,
+                         session.records,
+                         if session.truncated { "  (oldest data overwritten)" } else { "" });
+            }
+        }
+        Command::Sessions(SessionsCommand::Download { id, output }) => {
+            let mut client = open(cli)?;
+            let mut out = BufWriter::new(File::create(output)?);
+            let mut records = 0;
+            client.download(*id, |response, frame| {
+                if let Response::Record { .. } = response {
+                    records += 1;
+                }
+                out.write_all(frame)
+            })?;
+            out.flush()?;
+            eprintln!("downloaded {} records", records);
+        }
+        Command::Events => {
+            let events = open(cli)?.events()?;
+            let mut csv = String::from(CSV_HEADER);
+            for event in &events {
+                event.write_csv(&mut csv).map_err(io::Error::other)?;
+            }
+            print!("{}", csv);
+        }
+    }
+    Ok(())
+}
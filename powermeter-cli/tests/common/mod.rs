@@ -0,0 +1,254 @@
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::fd::{AsFd, OwnedFd};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use embedded_storage::nor_flash::{ErrorType, NorFlash, NorFlashErrorKind, ReadNorFlash};
+use nix::poll::{poll, PollFd, PollFlags, PollTimeout};
+use nix::pty::openpty;
+use nix::sys::termios::{cfmakeraw, tcgetattr, tcsetattr, SetArg};
+use powermeter_protocol::event::Event;
+use powermeter_protocol::serial::{ErrorCode, FrameReader, Request, Response, SessionEntry, MAX_FRAME_LEN, VERSION};
+use powermeter_protocol::stream::Sample;
+use powermeter_storage::config::{SettingError, Settings};
+use powermeter_storage::datalog::{DataLog, SessionInfo, MAX_PAYLOAD};
+
+pub const SECTOR_SIZE: usize = 4096;
+
+/// NOR flash in RAM for the simulated data log.
+pub struct RamFlash {
+    pub data: Vec<u8>,
+}
+
+impl RamFlash {
+    pub fn new(sectors: usize) -> Self {
+        RamFlash { data: vec![0xFF; sectors * SECTOR_SIZE] }
+    }
+}
+
+impl ErrorType for RamFlash {
+    type Error = NorFlashErrorKind;
+}
+
+impl ReadNorFlash for RamFlash {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        let start = offset as usize;
+        let data = self.data.get(start..start + bytes.len()).ok_or(NorFlashErrorKind::OutOfBounds)?;
+        bytes.copy_from_slice(data);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.data.len()
+    }
+}
+
+impl NorFlash for RamFlash {
+    const WRITE_SIZE: usize = 4;
+    const ERASE_SIZE: usize = SECTOR_SIZE;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        let data = self.data.get_mut(from as usize..to as usize).ok_or(NorFlashErrorKind::OutOfBounds)?;
+        data.fill(0xFF);
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        let start = offset as usize;
+        let data = self.data.get_mut(start..start + bytes.len()).ok_or(NorFlashErrorKind::OutOfBounds)?;
+        for (cell, byte) in data.iter_mut().zip(bytes) {
+            *cell &= *byte;
+        }
+        Ok(())
+    }
+}
+
+/// Serves the serial protocol on the master side of a pty the way the
+/// firmware does on its UART. Live samples follow a fixed ramp so tests can
+/// check the values that arrive.
+pub struct SimDevice {
+    pub settings: Settings,
+    pub log: DataLog<RamFlash>,
+    /// the oldest first
+    pub events: Vec<Event>,
+}
+
+pub fn live_sample(n: u64) -> Sample {
+    Sample {
+        timestamp_us: n * 10_000,
+        voltage: 5.0,
+        current: n as f32,
+        power: 5.0 * n as f32,
+        range: 0,
+    }
+}
+
+pub fn session_entry(info: &SessionInfo) -> SessionEntry {
+    SessionEntry {
+        id: info.id,
+        mode: info.config.mode.code(),
+        calibration: info.config.calibration,
+        interval_us: info.config.interval_us,
+        start_unix_us: info.config.start_unix_us,
+        duration_us: info.duration_us,
+        records: info.records,
+        truncated: info.truncated,
+    }
+}
+
+fn error_code(e: SettingError) -> ErrorCode {
+    match e {
+        SettingError::UnknownName => ErrorCode::UnknownName,
+        SettingError::InvalidValue => ErrorCode::InvalidValue,
+    }
+}
+
+impl SimDevice {
+    pub fn new() -> Self {
+        let flash = RamFlash::new(8);
+        let size = flash.data.len() as u32;
+        SimDevice {
+            settings: Settings::default(),
+            log: DataLog::mount(flash, 0, size).unwrap(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Open a pty and serve it from a thread, returns the path for the
+    /// client to open.
+    pub fn serve(self) -> SimHandle {
+        let pty = openpty(None, None).unwrap();
+        let mut termios = tcgetattr(pty.slave.as_fd()).unwrap();
+        cfmakeraw(&mut termios);
+        tcsetattr(pty.slave.as_fd(), SetArg::TCSANOW, &termios).unwrap();
+        let path = slave_path(&pty.slave);
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = stop.clone();
+            let master = File::from(pty.master);
+            thread::spawn(move || self.run(master, &stop))
+        };
+        SimHandle {
+            path,
+            stop,
+            thread: Some(thread),
+            _slave: pty.slave,
+        }
+    }
+
+    fn run(mut self, mut port: File, stop: &AtomicBool) {
+        let mut reader = FrameReader::<MAX_FRAME_LEN>::new();
+        let mut live: Option<u64> = None;
+        let mut buf = [0u8; 256];
+        while !stop.load(Ordering::Relaxed) {
+            let mut fds = [PollFd::new(port.as_fd(), PollFlags::POLLIN)];
+            let ready = poll(&mut fds, PollTimeout::from(10u8)).unwrap();
+            if ready == 0 {
+                if let Some(n) = live.as_mut() {
+                    send(&mut port, &Response::Sample(live_sample(*n)));
+                    *n += 1;
+                }
+                continue;
+            }
+            let len = match port.read(&mut buf) {
+                Ok(len) => len,
+                // no client has the slave open right now
+                Err(_) => {
+                    thread::sleep(std::time::Duration::from_millis(10));
+                    continue;
+                }
+            };
+            for &byte in &buf[..len] {
+                let request = match reader.push(byte) {
+                    Some(Ok(message)) => Request::decode(message),
+                    _ => continue,
+                };
+                match request {
+                    Ok(Request::LiveStart) => live = Some(0),
+                    Ok(Request::LiveStop) => {
+                        live = None;
+                        send(&mut port, &Response::End);
+                    }
+                    Ok(request) => self.handle(&mut port, &request),
+                    Err(_) => send(&mut port, &Response::Error(ErrorCode::UnknownRequest)),
+                }
+            }
+        }
+    }
+
+    fn handle(&mut self, port: &mut File, request: &Request) {
+        match request {
+            Request::Ping => send(port, &Response::Pong { version: VERSION }),
+            Request::ConfigGet { name } => match self.settings.get(name) {
+                Ok(value) => send(port, &Response::ConfigValue { value }),
+                Err(e) => send(port, &Response::Error(error_code(e))),
+            },
+            Request::ConfigSet { name, value } => match self.settings.set(name, value) {
+                Ok(()) => send(port, &Response::Ok),
+                Err(e) => send(port, &Response::Error(error_code(e))),
+            },
+            Request::SessionList => {
+                for info in self.log.sessions().unwrap() {
+                    send(port, &Response::Session(session_entry(&info)));
+                }
+                send(port, &Response::End);
+            }
+            Request::SessionRead { id } => {
+                let sessions = self.log.sessions().unwrap();
+                let info = match sessions.iter().find(|info| info.id == *id) {
+                    Some(info) => info,
+                    None => return send(port, &Response::Error(ErrorCode::NotFound)),
+                };
+                send(port, &Response::Session(session_entry(info)));
+                let mut cursor = self.log.cursor(*id);
+                while let Some(record) = self.log.next_record(&mut cursor).unwrap() {
+                    let mut payload = [0u8; MAX_PAYLOAD];
+                    let (kind, len) = record.encode(&mut payload);
+                    send(port, &Response::Record { kind, payload: &payload[..len] });
+                }
+                send(port, &Response::End);
+            }
+            Request::EventList => {
+                for event in &self.events {
+                    send(port, &Response::Event(*event));
+                }
+                send(port, &Response::End);
+            }
+            Request::LiveStart | Request::LiveStop => {}
+        }
+    }
+}
+
+fn send(port: &mut File, response: &Response) {
+    let mut frame = [0u8; MAX_FRAME_LEN];
+    let len = response.encode(&mut frame).unwrap();
+    // the client may have gone away, like a real device would not notice
+    let _ = port.write_all(&frame[..len]);
+}
+
+fn slave_path(slave: &OwnedFd) -> PathBuf {
+    use std::os::fd::AsRawFd;
+    std::fs::read_link(format!("/proc/self/fd/{}", slave.as_raw_fd())).unwrap()
+}
+
+pub struct SimHandle {
+    pub path: PathBuf,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    // keeps the pty alive between client runs
+    _slave: OwnedFd,
+}
+
+impl Drop for SimHandle {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,246 @@
+mod common;
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+use common::{SimDevice, SimHandle};
+use powermeter_protocol::compact::{encode_header, BlockEncoder, Calibration, RawSample};
+use powermeter_protocol::event::{Event, EventKind};
+use powermeter_storage::datalog::{Aggregate, Compact, LogMode, Record, SessionConfig};
+
+// 2026-03-01T12:00:00Z
+const START_UNIX_US: i64 = 1_772_366_400_000_000;
+
+fn run(device: &SimHandle, args: &[&str]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_powermeter-cli"))
+        .arg("--port")
+        .arg(&device.path)
+        .args(args)
+        .output()
+        .unwrap();
+    if !output.status.success() {
+        eprintln!("{}", String::from_utf8_lossy(&output.stderr));
+    }
+    output
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(output.status.success());
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn export(file: &Path, format: &str) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_powermeter-cli"))
+        .arg("export")
+        .arg(file)
+        .args(["--format", format])
+        .output()
+        .unwrap();
+    stdout(&output)
+}
+
+fn aggregate(second: u64) -> Record {
+    Record::Aggregate(Aggregate {
+        offset_us: second * 1_000_000,
+        count: 100,
+        voltage: 5.0,
+        current: 100.0 + second as f32,
+        power: 500.0 + 5.0 * second as f32,
+        current_min: 90.0,
+        current_max: 110.0,
+    })
+}
+
+fn aggregate_device() -> SimDevice {
+    let mut device = SimDevice::new();
+    device.log.start_session(SessionConfig {
+        mode: LogMode::Aggregates,
+        calibration: 0,
+        interval_us: 1_000_000,
+        start_unix_us: Some(START_UNIX_US),
+    }).unwrap();
+    for second in 1..=3 {
+        device.log.append(&aggregate(second)).unwrap();
+    }
+    device.log.end_session();
+    device
+}
+
+#[test]
+fn config_set_then_get() {
+    let device = SimDevice::new().serve();
+    assert!(run(&device, &["config", "set", "wifi_ssid", "lab"]).status.success());
+    assert_eq!(stdout(&run(&device, &["config", "get", "wifi_ssid"])), "lab\n");
+
+    assert!(run(&device, &["config", "set", "wifi_password", "secret"]).status.success());
+    assert_eq!(stdout(&run(&device, &["config", "get", "wifi_password"])), "********\n");
+
+    let output = run(&device, &["config", "get", "brightness"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown setting"));
+}
+
+#[test]
+fn sessions_list_shows_logged_sessions() {
+    let mut device = aggregate_device();
+    device.log.start_session(SessionConfig {
+        mode: LogMode::Compact,
+        calibration: 1,
+        interval_us: 1_000,
+        start_unix_us: None,
+    }).unwrap();
+    device.log.end_session();
+    let device = device.serve();
+
+    let list = stdout(&run(&device, &["sessions", "list"]));
+    let lines: Vec<&str> = list.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[1].contains("2026-03-01T12:00:00.000Z"));
+    assert!(lines[1].contains("aggregates"));
+    assert!(lines[2].contains("unknown"));
+    assert!(lines[2].contains("compact"));
+}
+
+#[test]
+fn downloaded_session_exports_to_csv_and_json() {
+    let device = aggregate_device().serve();
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("session.pm");
+    let id = stdout(&run(&device, &["sessions", "list"])).lines().nth(1).unwrap()
+        .split_whitespace().next().unwrap().to_string();
+    assert!(run(&device, &["sessions", "download", &id, "-o", file.to_str().unwrap()]).status.success());
+
+    let csv = export(&file, "csv");
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines, [
+        "timestamp_us,utc,voltage_v,current_ma,power_mw",
+        "1000000,2026-03-01T12:00:01.000Z,5.000,101.000,505.000",
+        "2000000,2026-03-01T12:00:02.000Z,5.000,102.000,510.000",
+        "3000000,2026-03-01T12:00:03.000Z,5.000,103.000,515.000",
+    ]);
+
+    let json: serde_json::Value = serde_json::from_str(&export(&file, "json")).unwrap();
+    assert_eq!(json["session"]["start"], "2026-03-01T12:00:00.000Z");
+    assert_eq!(json["samples"].as_array().unwrap().len(), 3);
+    assert_eq!(json["samples"][2]["current_ma"], 103.0);
+}
+
+#[test]
+fn compact_session_is_decoded_on_export() {
+    let calibration = Calibration {
+        config: 0x399F,
+        calibration: 4096,
+        shunt_uohm: 100_000,
+    };
+    let mut device = SimDevice::new();
+    device.log.start_session(SessionConfig {
+        mode: LogMode::Compact,
+        calibration: 0,
+        interval_us: 1_000,
+        start_unix_us: Some(START_UNIX_US),
+    }).unwrap();
+    device.log.append(&Record::Compact(Compact::new(0, &encode_header(&calibration)).unwrap())).unwrap();
+    let mut encoder = BlockEncoder::new();
+    for i in 0..200u64 {
+        let sample = RawSample {
+            timestamp_us: 7_000_000 + i * 1_000,
+            shunt: 120,
+            bus: 1250 << 3,
+            power: 31,
+            current: 1200 + i as i16,
+        };
+        if !encoder.push(&sample) {
+            let offset_us = sample.timestamp_us - 7_000_000;
+            device.log.append(&Record::Compact(Compact::new(offset_us, encoder.finish()).unwrap())).unwrap();
+            assert!(encoder.push(&sample));
+        }
+    }
+    device.log.append(&Record::Compact(Compact::new(199_000, encoder.finish()).unwrap())).unwrap();
+    device.log.end_session();
+    let device = device.serve();
+
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("compact.pm");
+    let id = stdout(&run(&device, &["sessions", "list"])).lines().nth(1).unwrap()
+        .split_whitespace().next().unwrap().to_string();
+    assert!(run(&device, &["sessions", "download", &id, "-o", file.to_str().unwrap()]).status.success());
+
+    let csv = export(&file, "csv");
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 201);
+    assert_eq!(lines[1], "0,2026-03-01T12:00:00.000Z,5.000,120.000,62.000");
+    assert_eq!(lines[200], "199000,2026-03-01T12:00:00.199Z,5.000,139.900,62.000");
+}
+
+#[test]
+fn capture_exports_live_samples_in_order() {
+    let device = SimDevice::new().serve();
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("capture.pm");
+    assert!(run(&device, &["capture", "--seconds", "1", "-o", file.to_str().unwrap()]).status.success());
+
+    let csv = export(&file, "csv");
+    let rows: Vec<&str> = csv.lines().skip(1).collect();
+    assert!(rows.len() >= 10, "only {} samples", rows.len());
+    for (n, row) in rows.iter().enumerate() {
+        let expected = common::live_sample(n as u64);
+        assert_eq!(*row, format!("{},,5.000,{:.3},{:.3}", expected.timestamp_us, expected.current, expected.power));
+    }
+}
+
+#[test]
+fn live_stops_after_requested_samples() {
+    let device = SimDevice::new().serve();
+    let output = stdout(&run(&device, &["live", "-n", "5"]));
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines, [
+        "0,5.000,0.000,0.000",
+        "10000,5.000,1.000,5.000",
+        "20000,5.000,2.000,10.000",
+        "30000,5.000,3.000,15.000",
+        "40000,5.000,4.000,20.000",
+    ]);
+    // the device is back to idle and answers the next request
+    assert!(run(&device, &["config", "get", "wifi_ssid"]).status.success());
+}
+
+#[test]
+fn events_print_as_csv() {
+    let mut device = SimDevice::new();
+    device.events = vec![
+        Event {
+            sequence: 6,
+            boot: 2,
+            kind: EventKind::Boot,
+            channel: 0,
+            uptime_us: 0,
+            unix_us: None,
+            value: 1.0,
+            duration_us: 0,
+        },
+        Event {
+            sequence: 7,
+            boot: 2,
+            kind: EventKind::OverCurrent,
+            channel: 1,
+            uptime_us: 61_500_000,
+            unix_us: Some(START_UNIX_US),
+            value: 512.5,
+            duration_us: 1_500,
+        },
+    ];
+    let device = device.serve();
+    assert_eq!(stdout(&run(&device, &["events"])), "\
+sequence,boot,uptime_s,utc,kind,channel,value,duration_ms
+6,2,0.000,,boot,0,1,0.000
+7,2,61.500,2026-03-01T12:00:00.000Z,over_current,1,512.5,1.500
+");
+}
+
+#[test]
+fn export_without_port_needs_no_device() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("empty.pm");
+    std::fs::write(&file, b"").unwrap();
+    assert_eq!(export(&file, "csv"), "timestamp_us,utc,voltage_v,current_ma,power_mw\n");
+}
@@ -0,0 +1,58 @@
+// Automatic range selection
+//
+// The ranges from Range32V2A to Range16V400mA get finer but measure less.
+// A clipped sample or a reading close to the full scale steps to the next
+// coarser range at once. Stepping to the finer range waits until the
+// reading stayed well inside of it for a few samples, the gap between both
+// thresholds keeps a load at the border from switching back and forth.
+
+use enum_iterator::Sequence;
+
+use crate::drivers::ina219::Calibration;
+use crate::drivers::sensor::PowerMonitor;
+
+/// fraction of the full scale that steps up
+pub const UP_THRESHOLD: f32 = 0.9;
+/// fraction of the finer full scale that steps down
+pub const DOWN_THRESHOLD: f32 = 0.7;
+/// samples in a row below the threshold before stepping down
+pub const DOWN_SAMPLES: u8 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AutoRange {
+    below: u8,
+}
+
+fn fits(calibration: Calibration, reading: &PowerMonitor, threshold: f32) -> bool {
+    reading.current.abs() < calibration.full_scale_ma() * threshold
+        && reading.voltage < calibration.full_scale_v() * threshold
+}
+
+impl AutoRange {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Range to switch to after a sample taken with calibration, None to
+    /// keep it. overflow is the math overflow flag of the sensor.
+    pub fn update(&mut self, calibration: Calibration, reading: &PowerMonitor, overflow: bool) -> Option<Calibration> {
+        if overflow || !fits(calibration, reading, UP_THRESHOLD) {
+            self.below = 0;
+            return calibration.previous();
+        }
+        match calibration.next() {
+            Some(finer) if fits(finer, reading, DOWN_THRESHOLD) => {
+                self.below += 1;
+                if self.below < DOWN_SAMPLES {
+                    return None;
+                }
+                self.below = 0;
+                Some(finer)
+            }
+            _ => {
+                self.below = 0;
+                None
+            }
+        }
+    }
+}
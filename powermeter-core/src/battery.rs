@@ -0,0 +1,221 @@
+// Battery life of the device under test
+//
+// The runtime is the usable part of the capacity over the average current
+// plus the self discharge, which is given per month and taken as a constant
+// current. The average is taken over a rolling window of one second buckets
+// so it follows a changed load within WINDOW_BUCKETS seconds, handle_power
+// feeds it every sample of the primary channel. The confidence only grows
+// with the time the average was taken over, a window shorter than a few
+// wake periods of the device is mostly noise.
+
+use core::fmt::Write;
+
+use enum_iterator::Sequence;
+use heapless::String;
+
+use crate::derived::Integrator;
+
+pub const BUCKET_US: u64 = 1_000_000;
+/// five minutes
+pub const WINDOW_BUCKETS: usize = 300;
+const HOURS_PER_MONTH: f32 = 730.0;
+
+/// mAh, lowest capacity that can be set
+pub const MIN_CAPACITY: f32 = 10.0;
+pub const MAX_CAPACITY: f32 = 100_000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Battery {
+    /// mAh, 0 when not set
+    pub capacity_mah: f32,
+    /// fraction of the capacity the device can use before it browns out
+    pub usable: f32,
+    /// fraction of the capacity lost per month
+    pub self_discharge: f32,
+}
+
+impl Default for Battery {
+    fn default() -> Self {
+        Battery {
+            capacity_mah: 0.0,
+            usable: 0.8,
+            self_discharge: 0.02,
+        }
+    }
+}
+
+/// What the battery page edits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Sequence)]
+pub enum Field {
+    #[default]
+    Capacity,
+    Usable,
+    SelfDischarge,
+}
+
+impl Field {
+    pub fn next_wrapping(&self) -> Field {
+        self.next().unwrap_or_default()
+    }
+}
+
+impl Battery {
+    /// mA the self discharge amounts to.
+    pub fn self_discharge_ma(&self) -> f32 {
+        self.capacity_mah * self.self_discharge / HOURS_PER_MONTH
+    }
+
+    /// Hours until the usable capacity is gone at average_ma.
+    pub fn runtime_h(&self, average_ma: f32) -> Option<f32> {
+        let drain = average_ma + self.self_discharge_ma();
+        if self.capacity_mah <= 0.0 || drain <= 0.0 {
+            return None;
+        }
+        Some(self.capacity_mah * self.usable / drain)
+    }
+
+    /// One step up or down, the capacity in steps that fit its size.
+    pub fn adjust(&mut self, field: Field, up: bool) {
+        let sign = if up { 1.0 } else { -1.0 };
+        match field {
+            Field::Capacity => {
+                // down from 500 goes in the smaller steps below it
+                let from = if up { self.capacity_mah } else { self.capacity_mah - 1.0 };
+                let step = if from < 500.0 { 10.0 } else if from < 2000.0 { 50.0 } else { 100.0 };
+                self.capacity_mah = (self.capacity_mah + sign * step).clamp(MIN_CAPACITY, MAX_CAPACITY);
+            }
+            Field::Usable => self.usable = (self.usable + sign * 0.05).clamp(0.05, 1.0),
+            Field::SelfDischarge => self.self_discharge = (self.self_discharge + sign * 0.005).clamp(0.0, 0.3),
+        }
+    }
+
+    /// Line of the battery page for field.
+    pub fn text(&self, field: Field) -> String<32> {
+        let mut text = String::new();
+        let _ = match field {
+            Field::Capacity => write!(text, "Capacity {:.0} mAh", self.capacity_mah),
+            Field::Usable => write!(text, "Usable   {:.0} %", self.usable * 100.0),
+            Field::SelfDischarge => write!(text, "Self dis {:.1} %/month", self.self_discharge * 100.0),
+        };
+        text
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Confidence {
+    Low,
+    Medium,
+    High,
+}
+
+impl Confidence {
+    pub fn text(&self) -> &'static str {
+        match self {
+            Confidence::Low => "low",
+            Confidence::Medium => "medium",
+            Confidence::High => "high",
+        }
+    }
+
+    /// "[#--]" to "[###]"
+    pub fn bar(&self) -> &'static str {
+        match self {
+            Confidence::Low => "[#--]",
+            Confidence::Medium => "[##-]",
+            Confidence::High => "[###]",
+        }
+    }
+}
+
+/// Mean current of the rolling window.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Average {
+    pub current_ma: f32,
+    /// us the mean is taken over
+    pub window_us: u64,
+}
+
+impl Average {
+    /// High once the window is full, medium from a quarter of it on.
+    pub fn confidence(&self) -> Confidence {
+        let full_us = WINDOW_BUCKETS as u64 * BUCKET_US;
+        if self.window_us >= full_us {
+            Confidence::High
+        } else if self.window_us >= full_us / 4 {
+            Confidence::Medium
+        } else {
+            Confidence::Low
+        }
+    }
+}
+
+/// "12 d 4 h", "5.5 h" below a day.
+pub fn format_runtime(hours: f32) -> String<32> {
+    let mut text = String::new();
+    if hours < 24.0 {
+        let _ = write!(text, "{:.1} h", hours);
+    } else {
+        let hours = hours as u32;
+        let _ = write!(text, "{} d {} h", hours / 24, hours % 24);
+    }
+    text
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RollingCurrent {
+    /// mean mA of every full second, next is written next
+    buckets: [f32; WINDOW_BUCKETS],
+    next: usize,
+    len: usize,
+    /// mA * us and us of the second going on
+    charge: f64,
+    elapsed_us: u64,
+    integrator: Integrator,
+}
+
+impl Default for RollingCurrent {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RollingCurrent {
+    pub const fn new() -> Self {
+        RollingCurrent {
+            buckets: [0.0; WINDOW_BUCKETS],
+            next: 0,
+            len: 0,
+            charge: 0.0,
+            elapsed_us: 0,
+            integrator: Integrator::new(),
+        }
+    }
+
+    pub fn add(&mut self, current_ma: f32, timestamp_us: u64) {
+        if let Some(interval) = self.integrator.add(current_ma, timestamp_us) {
+            self.charge += interval.area();
+            self.elapsed_us += interval.duration_us;
+        }
+        if self.elapsed_us >= BUCKET_US {
+            self.buckets[self.next] = (self.charge / self.elapsed_us as f64) as f32;
+            self.next = (self.next + 1) % WINDOW_BUCKETS;
+            self.len = (self.len + 1).min(WINDOW_BUCKETS);
+            self.charge = 0.0;
+            self.elapsed_us = 0;
+        }
+    }
+
+    /// None before the first interval.
+    pub fn average(&self) -> Option<Average> {
+        let full_us = self.len as u64 * BUCKET_US;
+        let window_us = full_us + self.elapsed_us;
+        if window_us == 0 {
+            return None;
+        }
+        let full: f64 = self.buckets[..self.len].iter().map(|mean| *mean as f64 * BUCKET_US as f64).sum();
+        Some(Average {
+            current_ma: ((full + self.charge) / window_us as f64) as f32,
+            window_us,
+        })
+    }
+}
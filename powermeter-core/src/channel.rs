@@ -0,0 +1,158 @@
+// Measurement channels
+//
+// Every sensor found on the bus is one channel, identified by its address,
+// the INA3221 is three with the same address. Channels have their own
+// range, an optional name and running statistics over all samples since
+// the range was last changed. The energy counts on across range changes.
+
+use core::fmt::Write;
+
+use heapless::String;
+
+use crate::autorange::AutoRange;
+use crate::correction::Correction;
+use crate::derived::{Derived, Energy};
+use crate::drivers::ina219::Calibration;
+use crate::drivers::sensor::PowerMonitor;
+use crate::supervisor::BusStatus;
+
+/// One per sensor address.
+pub const MAX_CHANNELS: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Stats {
+    pub samples: u32,
+    /// mA
+    pub min_current: f32,
+    /// mA
+    pub max_current: f32,
+    sum_current: f64,
+    sum_power: f64,
+    /// of the samples added with add_at
+    pub energy: Energy,
+}
+
+impl Stats {
+    pub fn add(&mut self, reading: &PowerMonitor) {
+        if self.samples == 0 {
+            self.min_current = reading.current;
+            self.max_current = reading.current;
+        } else {
+            self.min_current = self.min_current.min(reading.current);
+            self.max_current = self.max_current.max(reading.current);
+        }
+        self.samples = self.samples.saturating_add(1);
+        self.sum_current += reading.current as f64;
+        self.sum_power += reading.power as f64;
+    }
+
+    /// A sample taken at timestamp_us, it adds to the energy as well.
+    pub fn add_at(&mut self, reading: &PowerMonitor, timestamp_us: u64) {
+        self.add(reading);
+        self.energy.add(reading.power, timestamp_us);
+    }
+
+    /// Everything but the energy starts over.
+    pub fn reset(&mut self) {
+        *self = Stats { energy: self.energy, ..Stats::default() };
+    }
+
+    /// mA, 0 without samples
+    pub fn mean_current(&self) -> f32 {
+        if self.samples == 0 { 0.0 } else { (self.sum_current / self.samples as f64) as f32 }
+    }
+
+    /// mW, 0 without samples
+    pub fn mean_power(&self) -> f32 {
+        if self.samples == 0 { 0.0 } else { (self.sum_power / self.samples as f64) as f32 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Channel {
+    pub address: u8,
+    /// 1 to 3 on an INA3221, 0 for sensors with a single input
+    pub input: u8,
+    /// empty shows the address instead
+    pub name: String<16>,
+    pub calibration: Calibration,
+    /// Some while the range follows the load
+    pub auto_range: Option<AutoRange>,
+    /// by Calibration::index
+    pub corrections: [Correction; 3],
+    pub reading: PowerMonitor,
+    /// of the reading, set by the ui
+    pub derived: Derived,
+    pub stats: Stats,
+    /// anything but Ok means reading is stale
+    pub status: BusStatus,
+}
+
+impl Channel {
+    pub fn new(address: u8) -> Self {
+        Self::with_input(address, 0)
+    }
+
+    pub fn with_input(address: u8, input: u8) -> Self {
+        Channel {
+            address,
+            input,
+            name: String::new(),
+            calibration: Calibration::Range32V2A,
+            auto_range: None,
+            corrections: [Correction::default(); 3],
+            reading: PowerMonitor::default(),
+            derived: Derived::default(),
+            stats: Stats::default(),
+            status: BusStatus::Ok,
+        }
+    }
+
+    /// Name for the display, "0x41" or "0x40/2" for an unnamed channel.
+    pub fn label(&self) -> String<16> {
+        if !self.name.is_empty() {
+            return self.name.clone();
+        }
+        let mut label = String::new();
+        let _ = write!(label, "0x{:02x}", self.address);
+        if self.input != 0 {
+            let _ = write!(label, "/{}", self.input);
+        }
+        label
+    }
+
+    pub fn update(&mut self, reading: PowerMonitor) {
+        self.reading = reading;
+        self.stats.add(&reading);
+    }
+
+    /// update with the time of the sample, for the energy.
+    pub fn update_at(&mut self, reading: PowerMonitor, timestamp_us: u64) {
+        self.reading = reading;
+        self.stats.add_at(&reading, timestamp_us);
+    }
+
+    /// Correction of the range in use.
+    pub fn correction(&self) -> &Correction {
+        &self.corrections[self.calibration.index() as usize]
+    }
+
+    /// The statistics start over when the range in use is corrected.
+    pub fn set_correction(&mut self, calibration: Calibration, correction: Correction) {
+        self.corrections[calibration.index() as usize] = correction;
+        if calibration == self.calibration {
+            self.stats.reset();
+        }
+    }
+
+    /// A new range starts new statistics.
+    pub fn set_calibration(&mut self, calibration: Calibration) {
+        self.calibration = calibration;
+        self.stats.reset();
+    }
+}
+
+/// Sum of the last readings of all channels in mW.
+pub fn total_power(channels: &[Channel]) -> f32 {
+    channels.iter().map(|channel| channel.reading.power).sum()
+}
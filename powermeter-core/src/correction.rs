@@ -0,0 +1,128 @@
+// Reading correction
+//
+// Cheap shunts and the INA219 offset error read a few mA with nothing
+// connected. Every range of a channel has its own correction of the
+// measured current, corrected = gain * measured + offset. A tare only sets
+// the offset, reference points read on a trusted meter set both. The
+// voltage is left alone, the power follows the corrected current.
+
+use core::fmt::Write;
+
+use heapless::String;
+use powermeter_protocol::time::UtcDateTime;
+
+use crate::drivers::sensor::PowerMonitor;
+
+/// readings averaged for the tare and every reference point
+pub const CAPTURE_SAMPLES: u8 = 4;
+/// mA, reference points closer than this give no usable gain
+pub const MIN_SPAN: f32 = 1.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Correction {
+    pub gain: f32,
+    /// mA
+    pub offset: f32,
+    /// unix time in s, None when the clock was not synced
+    pub calibrated_at: Option<u32>,
+}
+
+impl Default for Correction {
+    fn default() -> Self {
+        Correction {
+            gain: 1.0,
+            offset: 0.0,
+            calibrated_at: None,
+        }
+    }
+}
+
+impl Correction {
+    /// The offset that makes zero_ma read as zero.
+    pub fn tare(zero_ma: f32) -> Self {
+        Correction {
+            offset: -zero_ma,
+            ..Correction::default()
+        }
+    }
+
+    /// Gain and offset through two (measured, reference) points in mA,
+    /// None when the points are too close or the gain is not positive.
+    pub fn two_point(a: (f32, f32), b: (f32, f32)) -> Option<Self> {
+        let span = b.0 - a.0;
+        if span.abs() < MIN_SPAN {
+            return None;
+        }
+        let gain = (b.1 - a.1) / span;
+        if gain <= 0.0 {
+            return None;
+        }
+        Some(Correction {
+            gain,
+            offset: a.1 - gain * a.0,
+            calibrated_at: None,
+        })
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.gain == 1.0 && self.offset == 0.0
+    }
+
+    /// mA
+    pub fn current(&self, measured: f32) -> f32 {
+        self.gain * measured + self.offset
+    }
+
+    /// The measured current a corrected one came from.
+    pub fn measured(&self, current: f32) -> f32 {
+        (current - self.offset) / self.gain
+    }
+
+    pub fn apply(&self, reading: &PowerMonitor) -> PowerMonitor {
+        let current = self.current(reading.current);
+        PowerMonitor {
+            current,
+            power: reading.voltage * current,
+            ..*reading
+        }
+    }
+
+    /// "calibrated on 2024-05-01", "not calibrated" without a correction.
+    pub fn text(&self) -> String<32> {
+        let mut text = String::new();
+        match self.calibrated_at {
+            _ if self.is_identity() => {
+                let _ = text.push_str("not calibrated");
+            }
+            Some(unix_s) => {
+                let date = UtcDateTime::from_unix_us(unix_s as i64 * 1_000_000);
+                let _ = write!(text, "calibrated on {:04}-{:02}-{:02}", date.year, date.month, date.day);
+            }
+            None => {
+                let _ = text.push_str("calibrated");
+            }
+        }
+        text
+    }
+}
+
+/// Mean of CAPTURE_SAMPLES currents.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Capture {
+    sum: f32,
+    count: u8,
+}
+
+impl Capture {
+    /// The mean once enough currents were added.
+    pub fn add(&mut self, current: f32) -> Option<f32> {
+        self.sum += current;
+        self.count += 1;
+        if self.count < CAPTURE_SAMPLES {
+            return None;
+        }
+        let mean = self.sum / self.count as f32;
+        *self = Capture::default();
+        Some(mean)
+    }
+}
@@ -0,0 +1,140 @@
+// Derived quantities
+//
+// Computed from the corrected readings. Without a load, or where a value
+// would be divided by about zero, there is nothing to derive and the
+// functions return None.
+//
+// The energy is integrated in handle_power over every sample, the display
+// only sees a few of them. A gap longer than MAX_GAP_US, e.g. while a lost
+// sensor recovers, is left out instead of guessed. The charge of the
+// histogram, the profiler and the battery average goes through Integrator,
+// which charges every interval at the sample it started with, an average
+// over the edge of a wake would raise the current on both sides of it.
+
+use crate::drivers::sensor::PowerMonitor;
+
+/// mA, below this there is no load to derive a resistance from
+pub const MIN_CURRENT: f32 = 0.05;
+/// V, below this the conductance is meaningless
+pub const MIN_VOLTAGE: f32 = 0.01;
+/// mW of the input, below this the converter is off
+pub const MIN_POWER: f32 = 0.1;
+/// longest sample interval that is integrated
+pub const MAX_GAP_US: u64 = 5_000_000;
+
+/// Load resistance V/I in ohm.
+pub fn resistance(reading: &PowerMonitor) -> Option<f32> {
+    if reading.current.abs() < MIN_CURRENT {
+        return None;
+    }
+    Some(reading.voltage * 1000.0 / reading.current)
+}
+
+/// Load conductance I/V in mS.
+pub fn conductance(reading: &PowerMonitor) -> Option<f32> {
+    if reading.voltage.abs() < MIN_VOLTAGE {
+        return None;
+    }
+    Some(reading.current / reading.voltage)
+}
+
+/// Pout/Pin of a converter between two channels, 1 is lossless.
+pub fn efficiency(input: &PowerMonitor, output: &PowerMonitor) -> Option<f32> {
+    if input.power < MIN_POWER {
+        return None;
+    }
+    Some(output.power / input.power)
+}
+
+/// What is derived from the readings of one tick, see Pipeline::process.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Derived {
+    /// ohm
+    pub resistance: Option<f32>,
+    /// mS
+    pub conductance: Option<f32>,
+    /// from the first channel, on the second channel only
+    pub efficiency: Option<f32>,
+}
+
+impl Derived {
+    /// The efficiency needs the reading of the converter input taken in
+    /// the same tick.
+    pub fn new(reading: &PowerMonitor, input: Option<&PowerMonitor>) -> Self {
+        Derived {
+            resistance: resistance(reading),
+            conductance: conductance(reading),
+            efficiency: input.and_then(|input| efficiency(input, reading)),
+        }
+    }
+}
+
+/// What energy_mwh costs at price per kWh.
+pub fn cost(energy_mwh: f64, price_per_kwh: f32) -> f32 {
+    (energy_mwh / 1_000_000.0 * price_per_kwh as f64) as f32
+}
+
+/// Time between two samples and the value of the first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interval {
+    pub duration_us: u64,
+    pub value: f32,
+}
+
+impl Interval {
+    /// value * us, mA * us for a current
+    pub fn area(&self) -> f64 {
+        self.value as f64 * self.duration_us as f64
+    }
+}
+
+/// Splits a trace into the intervals between its samples.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Integrator {
+    /// timestamp in us and value of the last sample
+    last: Option<(u64, f32)>,
+}
+
+impl Integrator {
+    pub const fn new() -> Self {
+        Integrator { last: None }
+    }
+
+    /// A sample of value taken at timestamp_us. Returns the interval it
+    /// ends, None for the first sample and after a gap.
+    pub fn add(&mut self, value: f32, timestamp_us: u64) -> Option<Interval> {
+        let last = self.last.replace((timestamp_us, value));
+        let (last_us, last_value) = last?;
+        let duration_us = timestamp_us.saturating_sub(last_us);
+        (duration_us <= MAX_GAP_US).then_some(Interval { duration_us, value: last_value })
+    }
+}
+
+/// Energy of the samples added so far.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Energy {
+    mwh: f64,
+    /// timestamp in us and power in mW of the last sample
+    last: Option<(u64, f32)>,
+}
+
+impl Energy {
+    /// The power between both samples is taken to change linearly.
+    pub fn add(&mut self, power_mw: f32, timestamp_us: u64) {
+        if let Some((last_us, last_mw)) = self.last {
+            let interval_us = timestamp_us.saturating_sub(last_us);
+            if interval_us <= MAX_GAP_US {
+                self.mwh += (last_mw + power_mw) as f64 / 2.0 * interval_us as f64 / 3_600_000_000.0;
+            }
+        }
+        self.last = Some((timestamp_us, power_mw));
+    }
+
+    pub fn mwh(&self) -> f64 {
+        self.mwh
+    }
+
+    pub fn reset(&mut self) {
+        *self = Energy::default();
+    }
+}
@@ -0,0 +1,65 @@
+use core::fmt::Write;
+
+use embedded_graphics::draw_target::DrawTarget;
+use embedded_graphics::geometry::{Dimensions, Point, Size};
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::pixelcolor::Rgb565;
+use embedded_graphics::primitives::{Primitive, PrimitiveStyle, Rectangle};
+use embedded_graphics::text::renderer::TextRenderer;
+use embedded_graphics::text::{Alignment, Baseline, Text, TextStyle, TextStyleBuilder};
+use embedded_graphics::Drawable;
+use heapless::String;
+
+// the 240x135 panel sits at this offset in the st7789 frame memory
+pub const ROWSTART: i32 = 40;
+pub const COLSTART: i32 = 54;
+
+pub const DISPLAY_SIZE: Size = Size::new(240, 135);
+
+pub fn create_point_from(point: Point) -> Point {
+    create_point(point.x, point.y)
+}
+
+pub fn create_point(x: i32, y: i32) -> Point {
+    Point::new(x + ROWSTART, y + COLSTART)
+}
+
+/// Text hanging from its position, left of, right of or centered on it.
+pub fn top_style(alignment: Alignment) -> TextStyle {
+    TextStyleBuilder::new().alignment(alignment).baseline(Baseline::Top).build()
+}
+
+/// character_style inverted on background, for the selected field.
+pub fn selected_style<'a>(character_style: MonoTextStyle<'a, Rgb565>, background: Rgb565) -> MonoTextStyle<'a, Rgb565> {
+    let mut selected = character_style;
+    selected.text_color = Some(background);
+    selected.background_color = character_style.text_color;
+    selected
+}
+
+/// "45.6 uA" below 1 mA, "1.23 mA" above.
+pub fn format_current(text: &mut String<32>, ma: f32) {
+    let _ = if ma.abs() < 1.0 { write!(text, "{:.1} uA", ma * 1000.0) } else { write!(text, "{:.2} mA", ma) };
+}
+
+pub fn display_text<D, S>(display: &mut D, pos: Point, character_style: S,
+                          text_style: TextStyle, text: &str) where D: DrawTarget<Color=Rgb565>, S: TextRenderer<Color=Rgb565> {
+    let _ = Text::with_text_style(
+        text,
+        pos,
+        character_style,
+        text_style,
+    )
+        .draw(display);
+}
+
+/// Draw text after clearing the line it covers from pos.x over width pixels.
+pub fn display_text_with_background<D, S>(display: &mut D, pos: Point, character_style: S,
+                                          text_style: TextStyle, text: &str,
+                                          background: Rgb565, width: u32) where D: DrawTarget<Color=Rgb565>, S: TextRenderer<Color=Rgb565> {
+    let text = Text::with_text_style(text, pos, character_style, text_style);
+    let bounds = text.bounding_box();
+    let line = Rectangle::new(Point::new(pos.x, bounds.top_left.y), Size::new(width, bounds.size.height));
+    let _ = line.into_styled(PrimitiveStyle::with_fill(background)).draw(display);
+    let _ = text.draw(display);
+}
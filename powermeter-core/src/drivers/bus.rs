@@ -0,0 +1,176 @@
+// I2C bus diagnostics
+//
+// The scan probes every 7-bit address outside the reserved ranges. INA
+// addresses are asked what sensor answers, the others are named from a
+// table of parts found on the board. Sensors and the LiPo monitor talk
+// through Counted, which tries a failed transfer once more and counts per
+// address how often that was needed and how often it did not help.
+
+use core::fmt::Write;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use embedded_hal::i2c::{ErrorType, I2c, Operation};
+use heapless::{String, Vec};
+
+use crate::drivers::ina219::INA219_ADDRESSES;
+use crate::drivers::max17048::MAX17048_ADDR;
+use crate::drivers::sensor::detect;
+
+/// 0x00 to 0x07 and 0x78 to 0x7F are reserved
+pub const SCAN_ADDRESSES: core::ops::RangeInclusive<u8> = 0x08..=0x77;
+pub const MAX_DEVICES: usize = 112;
+
+/// Errors and retries of one address.
+#[derive(Debug, Default)]
+pub struct DeviceCounters {
+    errors: AtomicU32,
+    retries: AtomicU32,
+}
+
+// the ESP32-S2 has no atomic read-modify-write, every address has a single
+// writer so load and store are enough
+fn increment(counter: &AtomicU32) {
+    counter.store(counter.load(Ordering::Relaxed).wrapping_add(1), Ordering::Relaxed);
+}
+
+impl DeviceCounters {
+    pub const fn new() -> Self {
+        DeviceCounters {
+            errors: AtomicU32::new(0),
+            retries: AtomicU32::new(0),
+        }
+    }
+
+    /// transfers that failed again after the retry
+    pub fn errors(&self) -> u32 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    pub fn retries(&self) -> u32 {
+        self.retries.load(Ordering::Relaxed)
+    }
+}
+
+/// Counters of every address on a bus.
+pub struct BusCounters {
+    devices: [DeviceCounters; 128],
+}
+
+impl Default for BusCounters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BusCounters {
+    pub const fn new() -> Self {
+        BusCounters { devices: [const { DeviceCounters::new() }; 128] }
+    }
+
+    pub fn device(&self, address: u8) -> &DeviceCounters {
+        &self.devices[(address & 0x7F) as usize]
+    }
+}
+
+/// A bus device that retries and counts, see BusCounters.
+pub struct Counted<'a, I2C> {
+    i2c: I2C,
+    counters: &'a BusCounters,
+}
+
+impl<'a, I2C> Counted<'a, I2C> {
+    pub fn new(i2c: I2C, counters: &'a BusCounters) -> Self {
+        Counted { i2c, counters }
+    }
+
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+}
+
+impl<I2C: ErrorType> ErrorType for Counted<'_, I2C> {
+    type Error = I2C::Error;
+}
+
+impl<I2C: I2c> Counted<'_, I2C> {
+    fn retry(&mut self, address: u8, mut transfer: impl FnMut(&mut I2C) -> Result<(), I2C::Error>) -> Result<(), I2C::Error> {
+        if transfer(&mut self.i2c).is_ok() {
+            return Ok(());
+        }
+        let counters = self.counters.device(address);
+        increment(&counters.retries);
+        let result = transfer(&mut self.i2c);
+        if result.is_err() {
+            increment(&counters.errors);
+        }
+        result
+    }
+}
+
+impl<I2C: I2c> I2c for Counted<'_, I2C> {
+    fn read(&mut self, address: u8, read: &mut [u8]) -> Result<(), Self::Error> {
+        self.retry(address, |i2c| i2c.read(address, read))
+    }
+
+    fn write(&mut self, address: u8, write: &[u8]) -> Result<(), Self::Error> {
+        self.retry(address, |i2c| i2c.write(address, write))
+    }
+
+    fn write_read(&mut self, address: u8, write: &[u8], read: &mut [u8]) -> Result<(), Self::Error> {
+        self.retry(address, |i2c| i2c.write_read(address, write, read))
+    }
+
+    fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), Self::Error> {
+        self.retry(address, |i2c| i2c.transaction(address, operations))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Device {
+    pub address: u8,
+    pub name: &'static str,
+    pub errors: u32,
+    pub retries: u32,
+}
+
+impl Device {
+    /// "0x40 INA219   e0 r2"
+    pub fn text(&self) -> String<32> {
+        let mut text = String::new();
+        let _ = write!(text, "0x{:02x} {:<8} e{} r{}", self.address, self.name, self.errors, self.retries);
+        text
+    }
+}
+
+/// What usually answers at address on this board.
+pub fn known_name(address: u8) -> &'static str {
+    match address {
+        MAX17048_ADDR => "MAX17048",
+        _ if INA219_ADDRESSES.contains(&address) => "INA2xx",
+        _ => "unknown",
+    }
+}
+
+/// Every device that acknowledges its address, with the counters of the
+/// drivers talking to it.
+pub fn scan<I2C: I2c>(i2c: &mut I2C, counters: &BusCounters) -> Vec<Device, MAX_DEVICES> {
+    let mut devices = Vec::new();
+    for address in SCAN_ADDRESSES {
+        if i2c.read(address, &mut [0]).is_err() {
+            continue;
+        }
+        let name = if INA219_ADDRESSES.contains(&address) {
+            detect(i2c, address).map(|kind| kind.text()).unwrap_or(known_name(address))
+        } else {
+            known_name(address)
+        };
+        let device = counters.device(address);
+        let _ = devices.push(Device {
+            address,
+            name,
+            errors: device.errors(),
+            retries: device.retries(),
+        });
+    }
+    devices
+}
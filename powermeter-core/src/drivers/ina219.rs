@@ -0,0 +1,278 @@
+use core::ops::RangeInclusive;
+
+use embedded_hal::i2c::I2c;
+use enum_iterator::Sequence;
+use heapless::Vec;
+use powermeter_protocol::compact::{self, RawSample};
+
+use crate::drivers::sensor::{Alert, Averaging, PowerMonitor, PowerSensor, Sampling, SensorKind, CONFIG_MODE_MASK};
+
+pub const INA219_ADDR: u8 = 0x40;
+
+/// A0 and A1 select one of 16 addresses.
+pub const INA219_ADDRESSES: RangeInclusive<u8> = 0x40..=0x4F;
+
+// shunt resistor of the Adafruit INA219 breakout
+const SHUNT_UOHM: u32 = 100_000;
+
+const REG_CONFIG: u8 = 0x00;
+const REG_SHUNT_VOLTAGE: u8 = 0x01;
+const REG_BUS_VOLTAGE: u8 = 0x02;
+const REG_POWER: u8 = 0x03;
+const REG_CURRENT: u8 = 0x04;
+const REG_CALIBRATION: u8 = 0x05;
+
+// 12 bit ADC, shunt and bus continuous
+const CONFIG_CONTINUOUS: u16 = 0x019F;
+const CONFIG_BUS_32V: u16 = 0x2000;
+const CONFIG_GAIN_320MV: u16 = 0x1800;
+// BADC bits 10..7 and SADC bits 6..3
+const CONFIG_ADC_MASK: u16 = 0x07F8;
+
+const BUS_CONVERSION_READY: u16 = 0x0002;
+const BUS_MATH_OVERFLOW: u16 = 0x0001;
+
+/// Ranges of the Adafruit library, the values are for the 0.1 ohm shunt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Sequence)]
+pub enum Calibration {
+    Range32V2A,
+    Range32V1A,
+    Range16V400mA,
+}
+
+impl Calibration {
+    /// Position in the selection cycle, stored with data log sessions.
+    pub fn index(&self) -> u8 {
+        *self as u8
+    }
+
+    pub fn from_index(index: u8) -> Option<Calibration> {
+        enum_iterator::all::<Calibration>().nth(index as usize)
+    }
+
+    pub fn text(&self) -> &'static str {
+        match self {
+            Calibration::Range32V2A => "32V - 2A",
+            Calibration::Range32V1A => "32V - 1A",
+            Calibration::Range16V400mA => "16V - 400mA",
+        }
+    }
+
+    pub fn config(&self) -> u16 {
+        match self {
+            Calibration::Range32V2A | Calibration::Range32V1A => CONFIG_BUS_32V | CONFIG_GAIN_320MV | CONFIG_CONTINUOUS,
+            Calibration::Range16V400mA => CONFIG_CONTINUOUS,
+        }
+    }
+
+    pub fn calibration(&self) -> u16 {
+        match self {
+            Calibration::Range32V2A => 4096,
+            Calibration::Range32V1A => 10240,
+            Calibration::Range16V400mA => 8192,
+        }
+    }
+
+    pub fn current_lsb_ma(&self) -> f32 {
+        match self {
+            Calibration::Range32V2A => 0.1,
+            Calibration::Range32V1A => 0.04,
+            Calibration::Range16V400mA => 0.05,
+        }
+    }
+
+    // always 20 times the current lsb
+    pub fn power_lsb_mw(&self) -> f32 {
+        self.current_lsb_ma() * 20.0
+    }
+
+    /// Largest current the range measures, either the shunt voltage or the
+    /// current register runs out.
+    pub fn full_scale_ma(&self) -> f32 {
+        match self {
+            Calibration::Range32V2A => 3200.0,
+            Calibration::Range32V1A => 1300.0,
+            Calibration::Range16V400mA => 400.0,
+        }
+    }
+
+    /// Largest bus voltage of the range.
+    pub fn full_scale_v(&self) -> f32 {
+        match self {
+            Calibration::Range32V2A | Calibration::Range32V1A => 32.0,
+            Calibration::Range16V400mA => 16.0,
+        }
+    }
+}
+
+/// ADC mode for the averaging, beyond 128 samples the INA219 can not go.
+fn adc_mode(averaging: Averaging) -> u16 {
+    match averaging {
+        Averaging::X1 => 0b0011,
+        Averaging::X4 => 0b1010,
+        Averaging::X16 => 0b1100,
+        Averaging::X64 => 0b1110,
+        _ => 0b1111,
+    }
+}
+
+/// Addresses of the INA219 answering on the bus, in ascending order.
+pub fn scan<I2C: I2c>(i2c: &mut I2C) -> Vec<u8, 16> {
+    INA219_ADDRESSES.filter(|&address| i2c.read(address, &mut [0]).is_ok()).collect()
+}
+
+pub struct Ina219<I2C> {
+    i2c: I2C,
+    address: u8,
+    calibration: Calibration,
+    averaging: Averaging,
+    sampling: Sampling,
+    overflow: bool,
+}
+
+impl<I2C: I2c> Ina219<I2C> {
+    pub fn new(i2c: I2C) -> Self {
+        Self::with_address(i2c, INA219_ADDR)
+    }
+
+    pub fn with_address(i2c: I2C, address: u8) -> Self {
+        Ina219 {
+            i2c,
+            address,
+            calibration: Calibration::Range32V2A,
+            averaging: Averaging::X1,
+            sampling: Sampling::Continuous,
+            overflow: false,
+        }
+    }
+
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    pub fn init(&mut self, calibration: Calibration) -> Result<(), I2C::Error> {
+        self.calibration = calibration;
+        self.write(REG_CALIBRATION, calibration.calibration())?;
+        self.write(REG_CONFIG, self.config())
+    }
+
+    fn config(&self) -> u16 {
+        let adc = adc_mode(self.averaging);
+        (self.calibration.config() & !(CONFIG_ADC_MASK | CONFIG_MODE_MASK)) | adc << 7 | adc << 3 | self.sampling.mode_bits()
+    }
+
+    pub fn calibration(&self) -> Calibration {
+        self.calibration
+    }
+
+    pub fn sense(&mut self) -> Result<PowerMonitor, I2C::Error> {
+        // a brownout resets the chip and with it the calibration, without
+        // it current and power read 0
+        self.write(REG_CALIBRATION, self.calibration.calibration())?;
+        let shunt = self.read(REG_SHUNT_VOLTAGE)? as i16;
+        let bus = self.read(REG_BUS_VOLTAGE)?;
+        let current = self.read(REG_CURRENT)? as i16;
+        let power = self.read(REG_POWER)?;
+        self.overflow = bus & BUS_MATH_OVERFLOW != 0;
+        Ok(PowerMonitor {
+            shunt: shunt as f32 * 0.01,
+            voltage: (bus >> 3) as f32 * 0.004,
+            current: current as f32 * self.calibration.current_lsb_ma(),
+            power: power as f32 * self.calibration.power_lsb_mw(),
+        })
+    }
+
+    /// Settings the chip is programmed with, for decoding raw samples.
+    pub fn registers(&mut self) -> Result<compact::Calibration, I2C::Error> {
+        Ok(compact::Calibration {
+            config: self.read(REG_CONFIG)?,
+            calibration: self.read(REG_CALIBRATION)?,
+            shunt_uohm: SHUNT_UOHM,
+        })
+    }
+
+    pub fn raw_sample(&mut self, timestamp_us: u64) -> Result<RawSample, I2C::Error> {
+        Ok(RawSample {
+            timestamp_us,
+            shunt: self.read(REG_SHUNT_VOLTAGE)? as i16,
+            bus: self.read(REG_BUS_VOLTAGE)?,
+            power: self.read(REG_POWER)?,
+            current: self.read(REG_CURRENT)? as i16,
+        })
+    }
+
+    fn read(&mut self, reg: u8) -> Result<u16, I2C::Error> {
+        let mut buf = [0u8; 2];
+        self.i2c.write_read(self.address, &[reg], &mut buf)?;
+        Ok(u16::from_be_bytes(buf))
+    }
+
+    fn write(&mut self, reg: u8, value: u16) -> Result<(), I2C::Error> {
+        let [msb, lsb] = value.to_be_bytes();
+        self.i2c.write(self.address, &[reg, msb, lsb])
+    }
+}
+
+impl<I2C: I2c> PowerSensor for Ina219<I2C> {
+    type Error = I2C::Error;
+
+    fn kind(&self) -> SensorKind {
+        SensorKind::Ina219
+    }
+
+    fn address(&self) -> u8 {
+        self.address
+    }
+
+    fn init(&mut self, calibration: Calibration) -> Result<(), Self::Error> {
+        Ina219::init(self, calibration)
+    }
+
+    fn calibration(&self) -> Calibration {
+        self.calibration
+    }
+
+    fn set_averaging(&mut self, averaging: Averaging) -> Result<(), Self::Error> {
+        self.averaging = averaging;
+        self.write(REG_CONFIG, self.config())
+    }
+
+    fn conversion_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.read(REG_BUS_VOLTAGE)? & BUS_CONVERSION_READY != 0)
+    }
+
+    fn sense(&mut self) -> Result<PowerMonitor, Self::Error> {
+        Ina219::sense(self)
+    }
+
+    fn set_sampling(&mut self, sampling: Sampling) -> Result<(), Self::Error> {
+        self.sampling = sampling;
+        self.write(REG_CONFIG, self.config())
+    }
+
+    // writing the mode starts a triggered conversion, reading the power
+    // register in sense clears CNVR again
+    fn trigger(&mut self) -> Result<(), Self::Error> {
+        match self.sampling {
+            Sampling::Continuous => Ok(()),
+            Sampling::Triggered => self.write(REG_CONFIG, self.config()),
+        }
+    }
+
+    fn overflow(&self) -> bool {
+        self.overflow
+    }
+
+    // no alert pin
+    fn set_alert(&mut self, _alert: Alert) -> Result<bool, Self::Error> {
+        Ok(false)
+    }
+
+    fn alert_active(&mut self) -> Result<bool, Self::Error> {
+        Ok(false)
+    }
+}
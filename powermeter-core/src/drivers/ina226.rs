@@ -0,0 +1,158 @@
+use embedded_hal::i2c::I2c;
+
+use crate::drivers::ina219::Calibration;
+use crate::drivers::sensor::{read_register, register_value, write_register, Alert, Averaging, PowerMonitor, PowerSensor, Sampling, SensorKind, CONFIG_MODE_MASK};
+
+// shunt resistor of the common INA226 breakouts
+const DEFAULT_SHUNT_MOHM: f32 = 100.0;
+
+pub(crate) const REG_CONFIG: u8 = 0x00;
+pub(crate) const REG_SHUNT_VOLTAGE: u8 = 0x01;
+pub(crate) const REG_BUS_VOLTAGE: u8 = 0x02;
+pub(crate) const REG_POWER: u8 = 0x03;
+pub(crate) const REG_CURRENT: u8 = 0x04;
+const REG_CALIBRATION: u8 = 0x05;
+pub(crate) const REG_MASK_ENABLE: u8 = 0x06;
+pub(crate) const REG_ALERT_LIMIT: u8 = 0x07;
+
+// 1.1 ms conversion times, shunt and bus continuous
+const CONFIG_CONTINUOUS: u16 = 0x4127;
+pub(crate) const CONFIG_AVG_SHIFT: u16 = 9;
+
+// mask/enable, the function bits select what asserts the alert pin
+pub(crate) const ALERT_SHUNT_OVER: u16 = 0x8000;
+pub(crate) const ALERT_BUS_OVER: u16 = 0x2000;
+pub(crate) const ALERT_BUS_UNDER: u16 = 0x1000;
+pub(crate) const ALERT_POWER_OVER: u16 = 0x0800;
+pub(crate) const ALERT_FUNCTION_FLAG: u16 = 0x0010;
+pub(crate) const CONVERSION_READY_FLAG: u16 = 0x0008;
+
+/// mV per bit
+pub(crate) const BUS_LSB_MV: f32 = 1.25;
+const SHUNT_LSB_UV: f32 = 2.5;
+
+/// The INA226 has no PGA, the shunt range is a fixed 81.92 mV. The
+/// calibration only picks the current resolution.
+pub struct Ina226<I2C> {
+    i2c: I2C,
+    address: u8,
+    shunt_mohm: f32,
+    calibration: Calibration,
+    averaging: Averaging,
+    sampling: Sampling,
+}
+
+impl<I2C: I2c> Ina226<I2C> {
+    pub fn new(i2c: I2C, address: u8) -> Self {
+        Self::with_shunt(i2c, address, DEFAULT_SHUNT_MOHM)
+    }
+
+    pub fn with_shunt(i2c: I2C, address: u8, shunt_mohm: f32) -> Self {
+        Ina226 {
+            i2c,
+            address,
+            shunt_mohm,
+            calibration: Calibration::Range32V2A,
+            averaging: Averaging::X1,
+            sampling: Sampling::Continuous,
+        }
+    }
+
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    /// Value for the calibration register, 0.00512 / (current lsb * shunt).
+    pub fn calibration_register(&self) -> u16 {
+        let current_lsb_a = self.calibration.current_lsb_ma() / 1000.0;
+        register_value(0.00512 / (current_lsb_a * self.shunt_mohm / 1000.0))
+    }
+
+    fn config(&self) -> u16 {
+        (CONFIG_CONTINUOUS & !CONFIG_MODE_MASK) | self.averaging.bits() << CONFIG_AVG_SHIFT | self.sampling.mode_bits()
+    }
+
+    fn read(&mut self, reg: u8) -> Result<u16, I2C::Error> {
+        read_register(&mut self.i2c, self.address, reg)
+    }
+
+    fn write(&mut self, reg: u8, value: u16) -> Result<(), I2C::Error> {
+        write_register(&mut self.i2c, self.address, reg, value)
+    }
+}
+
+impl<I2C: I2c> PowerSensor for Ina226<I2C> {
+    type Error = I2C::Error;
+
+    fn kind(&self) -> SensorKind {
+        SensorKind::Ina226
+    }
+
+    fn address(&self) -> u8 {
+        self.address
+    }
+
+    fn init(&mut self, calibration: Calibration) -> Result<(), Self::Error> {
+        self.calibration = calibration;
+        self.write(REG_CALIBRATION, self.calibration_register())?;
+        self.write(REG_CONFIG, self.config())
+    }
+
+    fn calibration(&self) -> Calibration {
+        self.calibration
+    }
+
+    fn set_averaging(&mut self, averaging: Averaging) -> Result<(), Self::Error> {
+        self.averaging = averaging;
+        self.write(REG_CONFIG, self.config())
+    }
+
+    fn conversion_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.read(REG_MASK_ENABLE)? & CONVERSION_READY_FLAG != 0)
+    }
+
+    fn sense(&mut self) -> Result<PowerMonitor, Self::Error> {
+        let shunt = self.read(REG_SHUNT_VOLTAGE)? as i16;
+        let bus = self.read(REG_BUS_VOLTAGE)?;
+        let current = self.read(REG_CURRENT)? as i16;
+        let power = self.read(REG_POWER)?;
+        let current_lsb_ma = self.calibration.current_lsb_ma();
+        Ok(PowerMonitor {
+            shunt: shunt as f32 * SHUNT_LSB_UV / 1000.0,
+            voltage: bus as f32 * BUS_LSB_MV / 1000.0,
+            current: current as f32 * current_lsb_ma,
+            // power lsb is 25 times the current lsb
+            power: power as f32 * current_lsb_ma * 25.0,
+        })
+    }
+
+    fn set_sampling(&mut self, sampling: Sampling) -> Result<(), Self::Error> {
+        self.sampling = sampling;
+        self.write(REG_CONFIG, self.config())
+    }
+
+    fn trigger(&mut self) -> Result<(), Self::Error> {
+        match self.sampling {
+            Sampling::Continuous => Ok(()),
+            Sampling::Triggered => self.write(REG_CONFIG, self.config()),
+        }
+    }
+
+    fn set_alert(&mut self, alert: Alert) -> Result<bool, Self::Error> {
+        let (function, limit) = match alert {
+            Alert::Off => (0, 0.0),
+            // the INA226 compares the shunt voltage, not the current
+            Alert::OverCurrent(ma) => (ALERT_SHUNT_OVER, ma * self.shunt_mohm / SHUNT_LSB_UV),
+            Alert::BusOverVoltage(v) => (ALERT_BUS_OVER, v * 1000.0 / BUS_LSB_MV),
+            Alert::BusUnderVoltage(v) => (ALERT_BUS_UNDER, v * 1000.0 / BUS_LSB_MV),
+            Alert::OverPower(mw) => (ALERT_POWER_OVER, mw / (self.calibration.current_lsb_ma() * 25.0)),
+        };
+        self.write(REG_ALERT_LIMIT, register_value(limit))?;
+        self.write(REG_MASK_ENABLE, function)?;
+        Ok(true)
+    }
+
+    fn alert_active(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.read(REG_MASK_ENABLE)? & ALERT_FUNCTION_FLAG != 0)
+    }
+}
@@ -0,0 +1,130 @@
+use embedded_hal::i2c::I2c;
+
+use crate::drivers::ina219::Calibration;
+use crate::drivers::ina226::{ALERT_BUS_OVER, ALERT_BUS_UNDER, ALERT_FUNCTION_FLAG, ALERT_POWER_OVER, BUS_LSB_MV,
+                             CONFIG_AVG_SHIFT, CONVERSION_READY_FLAG, REG_ALERT_LIMIT,
+                             REG_BUS_VOLTAGE, REG_CONFIG, REG_MASK_ENABLE, REG_POWER};
+use crate::drivers::sensor::{read_register, register_value, write_register, Alert, Averaging, PowerMonitor, PowerSensor, Sampling, SensorKind, CONFIG_MODE_MASK};
+
+// the INA226 register map with the current at the shunt voltage address
+const REG_CURRENT: u8 = 0x01;
+
+// 1.1 ms conversion times, current and bus continuous
+const CONFIG_CONTINUOUS: u16 = 0x6127;
+
+// over current limit, bit 15 like the shunt over voltage of the INA226
+const ALERT_CURRENT_OVER: u16 = 0x8000;
+
+// integrated 2 mOhm shunt
+const SHUNT_MOHM: f32 = 2.0;
+const CURRENT_LSB_MA: f32 = 1.25;
+const POWER_LSB_MW: f32 = 10.0;
+
+/// INA226 with a built-in shunt and a fixed 15 A range, there is nothing
+/// to calibrate.
+pub struct Ina260<I2C> {
+    i2c: I2C,
+    address: u8,
+    calibration: Calibration,
+    averaging: Averaging,
+    sampling: Sampling,
+}
+
+impl<I2C: I2c> Ina260<I2C> {
+    pub fn new(i2c: I2C, address: u8) -> Self {
+        Ina260 {
+            i2c,
+            address,
+            calibration: Calibration::Range32V2A,
+            averaging: Averaging::X1,
+            sampling: Sampling::Continuous,
+        }
+    }
+
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    fn config(&self) -> u16 {
+        (CONFIG_CONTINUOUS & !CONFIG_MODE_MASK) | self.averaging.bits() << CONFIG_AVG_SHIFT | self.sampling.mode_bits()
+    }
+
+    fn read(&mut self, reg: u8) -> Result<u16, I2C::Error> {
+        read_register(&mut self.i2c, self.address, reg)
+    }
+
+    fn write(&mut self, reg: u8, value: u16) -> Result<(), I2C::Error> {
+        write_register(&mut self.i2c, self.address, reg, value)
+    }
+}
+
+impl<I2C: I2c> PowerSensor for Ina260<I2C> {
+    type Error = I2C::Error;
+
+    fn kind(&self) -> SensorKind {
+        SensorKind::Ina260
+    }
+
+    fn address(&self) -> u8 {
+        self.address
+    }
+
+    fn init(&mut self, calibration: Calibration) -> Result<(), Self::Error> {
+        self.calibration = calibration;
+        self.write(REG_CONFIG, self.config())
+    }
+
+    fn calibration(&self) -> Calibration {
+        self.calibration
+    }
+
+    fn set_averaging(&mut self, averaging: Averaging) -> Result<(), Self::Error> {
+        self.averaging = averaging;
+        self.write(REG_CONFIG, self.config())
+    }
+
+    fn conversion_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.read(REG_MASK_ENABLE)? & CONVERSION_READY_FLAG != 0)
+    }
+
+    fn sense(&mut self) -> Result<PowerMonitor, Self::Error> {
+        let current = self.read(REG_CURRENT)? as i16 as f32 * CURRENT_LSB_MA;
+        let bus = self.read(REG_BUS_VOLTAGE)?;
+        let power = self.read(REG_POWER)?;
+        Ok(PowerMonitor {
+            shunt: current * SHUNT_MOHM / 1000.0,
+            voltage: bus as f32 * BUS_LSB_MV / 1000.0,
+            current,
+            power: power as f32 * POWER_LSB_MW,
+        })
+    }
+
+    fn set_sampling(&mut self, sampling: Sampling) -> Result<(), Self::Error> {
+        self.sampling = sampling;
+        self.write(REG_CONFIG, self.config())
+    }
+
+    fn trigger(&mut self) -> Result<(), Self::Error> {
+        match self.sampling {
+            Sampling::Continuous => Ok(()),
+            Sampling::Triggered => self.write(REG_CONFIG, self.config()),
+        }
+    }
+
+    fn set_alert(&mut self, alert: Alert) -> Result<bool, Self::Error> {
+        let (function, limit) = match alert {
+            Alert::Off => (0, 0.0),
+            Alert::OverCurrent(ma) => (ALERT_CURRENT_OVER, ma / CURRENT_LSB_MA),
+            Alert::BusOverVoltage(v) => (ALERT_BUS_OVER, v * 1000.0 / BUS_LSB_MV),
+            Alert::BusUnderVoltage(v) => (ALERT_BUS_UNDER, v * 1000.0 / BUS_LSB_MV),
+            Alert::OverPower(mw) => (ALERT_POWER_OVER, mw / POWER_LSB_MW),
+        };
+        self.write(REG_ALERT_LIMIT, register_value(limit))?;
+        self.write(REG_MASK_ENABLE, function)?;
+        Ok(true)
+    }
+
+    fn alert_active(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.read(REG_MASK_ENABLE)? & ALERT_FUNCTION_FLAG != 0)
+    }
+}
@@ -0,0 +1,212 @@
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use embedded_hal::i2c::I2c;
+
+use crate::drivers::ina219::Calibration;
+use crate::drivers::sensor::{read_register, register_value, write_register, Alert, Averaging, PowerMonitor, PowerSensor, Sampling, SensorKind, CONFIG_MODE_MASK};
+
+// shunt resistors of the common INA3221 breakouts
+const DEFAULT_SHUNT_MOHM: f32 = 100.0;
+
+const REG_CONFIG: u8 = 0x00;
+// per input: shunt and bus voltage from 0x01, critical limit from 0x07
+const REG_SHUNT_VOLTAGE_1: u8 = 0x01;
+const REG_CRITICAL_LIMIT_1: u8 = 0x07;
+const REG_MASK_ENABLE: u8 = 0x0F;
+
+// all inputs enabled, 1.1 ms conversion times, shunt and bus continuous
+const CONFIG_CONTINUOUS: u16 = 0x7127;
+const CONFIG_AVG_SHIFT: u16 = 9;
+
+const CONVERSION_READY_FLAG: u16 = 0x0001;
+// CF1 to CF3 are bits 9 to 7
+const CRITICAL_FLAG_1: u16 = 0x0200;
+
+// both voltages are in bits 15..3
+const SHUNT_LSB_UV: f32 = 40.0;
+const BUS_LSB_MV: f32 = 8.0;
+
+// bit 0 is input 1
+const ALL_INPUTS: u8 = 0b111;
+
+/// What the inputs of one chip share of its conversions. The chip has a
+/// single conversion ready flag that reading clears, the input that reads
+/// it marks the conversion fresh for all three. Only load and store like
+/// the bus counters, all inputs are sampled from one task.
+#[derive(Debug, Default)]
+pub struct Ina3221Chip {
+    /// inputs that did not read the finished conversion yet
+    fresh: AtomicU8,
+    /// a triggered conversion of all inputs is going on
+    converting: AtomicBool,
+}
+
+impl Ina3221Chip {
+    pub const fn new() -> Self {
+        Ina3221Chip {
+            fresh: AtomicU8::new(0),
+            converting: AtomicBool::new(false),
+        }
+    }
+}
+
+/// One input of the three channel INA3221. The chip has no current or
+/// power registers, both are calculated from the shunt voltage. The
+/// averaging and the conversions are shared by all inputs, which have to
+/// be created with the same chip.
+pub struct Ina3221<'a, I2C> {
+    i2c: I2C,
+    chip: &'a Ina3221Chip,
+    address: u8,
+    /// 1 to 3
+    input: u8,
+    shunt_mohm: f32,
+    calibration: Calibration,
+    averaging: Averaging,
+    sampling: Sampling,
+}
+
+impl<'a, I2C: I2c> Ina3221<'a, I2C> {
+    pub fn new(i2c: I2C, chip: &'a Ina3221Chip, address: u8, input: u8) -> Self {
+        Self::with_shunt(i2c, chip, address, input, DEFAULT_SHUNT_MOHM)
+    }
+
+    pub fn with_shunt(i2c: I2C, chip: &'a Ina3221Chip, address: u8, input: u8, shunt_mohm: f32) -> Self {
+        Ina3221 {
+            i2c,
+            chip,
+            address,
+            input: input.clamp(1, 3),
+            shunt_mohm,
+            calibration: Calibration::Range32V2A,
+            averaging: Averaging::X1,
+            sampling: Sampling::Continuous,
+        }
+    }
+
+    pub fn input(&self) -> u8 {
+        self.input
+    }
+
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    fn input_offset(&self) -> u8 {
+        2 * (self.input - 1)
+    }
+
+    fn input_bit(&self) -> u8 {
+        1 << (self.input - 1)
+    }
+
+    fn config(&self) -> u16 {
+        (CONFIG_CONTINUOUS & !CONFIG_MODE_MASK) | self.averaging.bits() << CONFIG_AVG_SHIFT | self.sampling.mode_bits()
+    }
+
+    fn read(&mut self, reg: u8) -> Result<u16, I2C::Error> {
+        read_register(&mut self.i2c, self.address, reg)
+    }
+
+    fn write(&mut self, reg: u8, value: u16) -> Result<(), I2C::Error> {
+        write_register(&mut self.i2c, self.address, reg, value)
+    }
+
+    /// The flags of the Mask/Enable register, a finished conversion is
+    /// fresh for all inputs.
+    fn read_flags(&mut self) -> Result<u16, I2C::Error> {
+        let flags = self.read(REG_MASK_ENABLE)?;
+        if flags & CONVERSION_READY_FLAG != 0 {
+            self.chip.fresh.store(ALL_INPUTS, Ordering::Relaxed);
+            self.chip.converting.store(false, Ordering::Relaxed);
+        }
+        Ok(flags)
+    }
+
+    /// Writing the configuration starts a conversion of all inputs over.
+    fn write_config(&mut self) -> Result<(), I2C::Error> {
+        self.write(REG_CONFIG, self.config())?;
+        self.chip.fresh.store(0, Ordering::Relaxed);
+        self.chip.converting.store(self.sampling == Sampling::Triggered, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+impl<I2C: I2c> PowerSensor for Ina3221<'_, I2C> {
+    type Error = I2C::Error;
+
+    fn kind(&self) -> SensorKind {
+        SensorKind::Ina3221
+    }
+
+    fn address(&self) -> u8 {
+        self.address
+    }
+
+    fn init(&mut self, calibration: Calibration) -> Result<(), Self::Error> {
+        self.calibration = calibration;
+        self.write_config()
+    }
+
+    fn calibration(&self) -> Calibration {
+        self.calibration
+    }
+
+    fn set_averaging(&mut self, averaging: Averaging) -> Result<(), Self::Error> {
+        self.averaging = averaging;
+        self.write_config()
+    }
+
+    fn conversion_ready(&mut self) -> Result<bool, Self::Error> {
+        if self.chip.fresh.load(Ordering::Relaxed) & self.input_bit() == 0 {
+            self.read_flags()?;
+        }
+        let fresh = self.chip.fresh.load(Ordering::Relaxed);
+        self.chip.fresh.store(fresh & !self.input_bit(), Ordering::Relaxed);
+        Ok(fresh & self.input_bit() != 0)
+    }
+
+    fn sense(&mut self) -> Result<PowerMonitor, Self::Error> {
+        let reg = REG_SHUNT_VOLTAGE_1 + self.input_offset();
+        let shunt = (self.read(reg)? as i16 >> 3) as f32 * SHUNT_LSB_UV / 1000.0;
+        let voltage = (self.read(reg + 1)? as i16 >> 3) as f32 * BUS_LSB_MV / 1000.0;
+        let current = shunt * 1000.0 / self.shunt_mohm;
+        Ok(PowerMonitor {
+            shunt,
+            voltage,
+            current,
+            power: current * voltage,
+        })
+    }
+
+    fn set_sampling(&mut self, sampling: Sampling) -> Result<(), Self::Error> {
+        self.sampling = sampling;
+        self.write_config()
+    }
+
+    // the first input triggered starts the conversion of all three, the
+    // others wait for it
+    fn trigger(&mut self) -> Result<(), Self::Error> {
+        match self.sampling {
+            Sampling::Continuous => Ok(()),
+            Sampling::Triggered if self.chip.converting.load(Ordering::Relaxed) => Ok(()),
+            Sampling::Triggered => self.write_config(),
+        }
+    }
+
+    // only the critical limit, it compares the shunt voltage of the input
+    fn set_alert(&mut self, alert: Alert) -> Result<bool, Self::Error> {
+        let limit = match alert {
+            // the reset value, as high as it goes
+            Alert::Off => 0x7FF8,
+            Alert::OverCurrent(ma) => register_value(ma * self.shunt_mohm / SHUNT_LSB_UV) << 3,
+            _ => return Ok(false),
+        };
+        self.write(REG_CRITICAL_LIMIT_1 + self.input_offset(), limit)?;
+        Ok(true)
+    }
+
+    fn alert_active(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.read_flags()? & (CRITICAL_FLAG_1 >> (self.input - 1)) != 0)
+    }
+}
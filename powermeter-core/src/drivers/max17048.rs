@@ -1,9 +1,6 @@
-
-#![no_std]
-
 use embedded_hal::i2c::I2c;
 
-const MAX17048_ADDR: u8 = 0x36;
+pub const MAX17048_ADDR: u8 = 0x36;
 const DEFAULT_RCOMP: u8 = 0x97;
 
 
@@ -14,13 +11,14 @@ pub struct Max17048<I2C> {
 
 impl<I2C: I2c> Max17048<I2C>
 {
-    pub fn new(i2c: I2C) -> Self {
+    /// Fails when the monitor does not answer the compensation write
+    pub fn new(i2c: I2C) -> Result<Self, I2C::Error> {
         let mut max = Max17048 {
-            i2c: i2c,
+            i2c,
             recv_buffer: [0u8; 2]
         };
-        max.compensation(DEFAULT_RCOMP).unwrap();
-        max
+        max.compensation(DEFAULT_RCOMP)?;
+        Ok(max)
     }
 
     pub fn version(&mut self) -> Result<u16, I2C::Error> {
@@ -84,7 +82,7 @@ impl<I2C: I2c> Max17048<I2C>
     fn write(&mut self, reg: u8, value: u16) -> Result<(), I2C::Error> {
         self.i2c.write(MAX17048_ADDR, &[reg])?;
         let msb = ((value & 0xFF00) >> 8) as u8;
-        let lsb = ((value & 0x00FF) >> 0) as u8;
+        let lsb = (value & 0x00FF) as u8;
         self.i2c.write(MAX17048_ADDR, &[msb, lsb])?;
         Ok(())
     }
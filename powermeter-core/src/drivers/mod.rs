@@ -0,0 +1,7 @@
+pub mod bus;
+pub mod ina219;
+pub mod ina226;
+pub mod ina260;
+pub mod ina3221;
+pub mod max17048;
+pub mod sensor;
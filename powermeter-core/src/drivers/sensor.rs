@@ -0,0 +1,300 @@
+// Power sensors
+//
+// The TI current monitors share most of their register layout. INA226,
+// INA260 and INA3221 identify themselves with the manufacturer ID 0xFE and
+// the die ID 0xFF registers, the INA219 has neither and is what is left
+// when a device answers without them.
+
+use embedded_hal::i2c::I2c;
+
+use crate::drivers::ina219::{Calibration, Ina219};
+use crate::drivers::ina226::Ina226;
+use crate::drivers::ina260::Ina260;
+use crate::drivers::ina3221::{Ina3221, Ina3221Chip};
+
+const REG_MANUFACTURER_ID: u8 = 0xFE;
+const REG_DIE_ID: u8 = 0xFF;
+
+/// "TI"
+const MANUFACTURER_TI: u16 = 0x5449;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PowerMonitor {
+    /// mV
+    pub shunt: f32,
+    /// V
+    pub voltage: f32,
+    /// mA
+    pub current: f32,
+    /// mW
+    pub power: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SensorKind {
+    Ina219,
+    Ina226,
+    Ina260,
+    Ina3221,
+}
+
+impl SensorKind {
+    /// Measurement channels of one chip.
+    pub fn inputs(&self) -> u8 {
+        match self {
+            SensorKind::Ina3221 => 3,
+            _ => 1,
+        }
+    }
+
+    pub fn text(&self) -> &'static str {
+        match self {
+            SensorKind::Ina219 => "INA219",
+            SensorKind::Ina226 => "INA226",
+            SensorKind::Ina260 => "INA260",
+            SensorKind::Ina3221 => "INA3221",
+        }
+    }
+}
+
+/// Samples averaged per conversion, the AVG field of INA226, INA260 and
+/// INA3221. The INA219 averages at most 128 samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Averaging {
+    #[default]
+    X1,
+    X4,
+    X16,
+    X64,
+    X128,
+    X256,
+    X512,
+    X1024,
+}
+
+impl Averaging {
+    /// Value of the AVG field, config register bits 11..9.
+    pub fn bits(&self) -> u16 {
+        *self as u16
+    }
+}
+
+/// How conversions are started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Sampling {
+    /// one conversion after the other
+    #[default]
+    Continuous,
+    /// a single conversion per trigger, the sensor powers down in between
+    Triggered,
+}
+
+/// MODE field, config register bits 2..0 of all supported sensors.
+pub(crate) const CONFIG_MODE_MASK: u16 = 0x0007;
+
+impl Sampling {
+    /// Value of the MODE field, shunt and bus voltage are both converted.
+    pub fn mode_bits(&self) -> u16 {
+        match self {
+            Sampling::Continuous => 0b111,
+            Sampling::Triggered => 0b011,
+        }
+    }
+
+    pub fn text(&self) -> &'static str {
+        match self {
+            Sampling::Continuous => "Continuous",
+            Sampling::Triggered => "Triggered",
+        }
+    }
+}
+
+/// Condition that asserts the alert pin.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Alert {
+    Off,
+    /// mA
+    OverCurrent(f32),
+    /// V
+    BusOverVoltage(f32),
+    /// V
+    BusUnderVoltage(f32),
+    /// mW
+    OverPower(f32),
+}
+
+pub trait PowerSensor {
+    type Error;
+
+    fn kind(&self) -> SensorKind;
+
+    fn address(&self) -> u8;
+
+    /// Program the range and start continuous conversions. Sensors with a
+    /// fixed range only remember it.
+    fn init(&mut self, calibration: Calibration) -> Result<(), Self::Error>;
+
+    fn calibration(&self) -> Calibration;
+
+    fn set_averaging(&mut self, averaging: Averaging) -> Result<(), Self::Error>;
+
+    /// A new conversion finished since the last read.
+    fn conversion_ready(&mut self) -> Result<bool, Self::Error>;
+
+    fn sense(&mut self) -> Result<PowerMonitor, Self::Error>;
+
+    /// Switching to triggered starts the first conversion.
+    fn set_sampling(&mut self, sampling: Sampling) -> Result<(), Self::Error>;
+
+    /// Start a single conversion, nothing to do when converting continuously.
+    fn trigger(&mut self) -> Result<(), Self::Error>;
+
+    /// The sample of a finished conversion, None until the next one is
+    /// done. Every conversion is returned once.
+    fn sense_ready(&mut self) -> Result<Option<PowerMonitor>, Self::Error> {
+        if !self.conversion_ready()? {
+            return Ok(None);
+        }
+        self.sense().map(Some)
+    }
+
+    /// The last sample was clipped, current and power are not valid.
+    /// Sensors that can not tell always report false.
+    fn overflow(&self) -> bool {
+        false
+    }
+
+    /// false when the sensor can not watch for the condition.
+    fn set_alert(&mut self, alert: Alert) -> Result<bool, Self::Error>;
+
+    /// The alert condition was met, reading clears the flag.
+    fn alert_active(&mut self) -> Result<bool, Self::Error>;
+}
+
+pub(crate) fn read_register<I2C: I2c>(i2c: &mut I2C, address: u8, reg: u8) -> Result<u16, I2C::Error> {
+    let mut buf = [0u8; 2];
+    i2c.write_read(address, &[reg], &mut buf)?;
+    Ok(u16::from_be_bytes(buf))
+}
+
+pub(crate) fn write_register<I2C: I2c>(i2c: &mut I2C, address: u8, reg: u8, value: u16) -> Result<(), I2C::Error> {
+    let [msb, lsb] = value.to_be_bytes();
+    i2c.write(address, &[reg, msb, lsb])
+}
+
+/// Nearest register value, limits and calibrations are never negative.
+pub(crate) fn register_value(value: f32) -> u16 {
+    (value + 0.5) as u16
+}
+
+/// Which sensor answers at address.
+pub fn detect<I2C: I2c>(i2c: &mut I2C, address: u8) -> Result<SensorKind, I2C::Error> {
+    i2c.read(address, &mut [0])?;
+    match read_register(i2c, address, REG_MANUFACTURER_ID) {
+        Ok(MANUFACTURER_TI) => {}
+        _ => return Ok(SensorKind::Ina219),
+    }
+    // the low nibble is the die revision
+    Ok(match read_register(i2c, address, REG_DIE_ID)? >> 4 {
+        0x226 => SensorKind::Ina226,
+        0x227 => SensorKind::Ina260,
+        0x322 => SensorKind::Ina3221,
+        _ => SensorKind::Ina219,
+    })
+}
+
+/// Any of the supported sensors, for a bus with mixed chips.
+pub enum Sensor<'a, I2C> {
+    Ina219(Ina219<I2C>),
+    Ina226(Ina226<I2C>),
+    Ina260(Ina260<I2C>),
+    Ina3221(Ina3221<'a, I2C>),
+}
+
+impl<'a, I2C: I2c> Sensor<'a, I2C> {
+    /// input is the INA3221 channel 1 to 3 and chip what its inputs share,
+    /// both are ignored for the others.
+    pub fn new(kind: SensorKind, i2c: I2C, chip: &'a Ina3221Chip, address: u8, input: u8) -> Self {
+        match kind {
+            SensorKind::Ina219 => Sensor::Ina219(Ina219::with_address(i2c, address)),
+            SensorKind::Ina226 => Sensor::Ina226(Ina226::new(i2c, address)),
+            SensorKind::Ina260 => Sensor::Ina260(Ina260::new(i2c, address)),
+            SensorKind::Ina3221 => Sensor::Ina3221(Ina3221::new(i2c, chip, address, input)),
+        }
+    }
+
+    /// Only the INA219 registers can be raw logged.
+    pub fn as_ina219(&mut self) -> Option<&mut Ina219<I2C>> {
+        match self {
+            Sensor::Ina219(ina219) => Some(ina219),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! dispatch {
+    ($self:ident, $sensor:ident => $call:expr) => {
+        match $self {
+            Sensor::Ina219($sensor) => $call,
+            Sensor::Ina226($sensor) => $call,
+            Sensor::Ina260($sensor) => $call,
+            Sensor::Ina3221($sensor) => $call,
+        }
+    };
+}
+
+impl<I2C: I2c> PowerSensor for Sensor<'_, I2C> {
+    type Error = I2C::Error;
+
+    fn kind(&self) -> SensorKind {
+        dispatch!(self, sensor => sensor.kind())
+    }
+
+    fn address(&self) -> u8 {
+        dispatch!(self, sensor => PowerSensor::address(sensor))
+    }
+
+    fn init(&mut self, calibration: Calibration) -> Result<(), Self::Error> {
+        dispatch!(self, sensor => PowerSensor::init(sensor, calibration))
+    }
+
+    fn calibration(&self) -> Calibration {
+        dispatch!(self, sensor => PowerSensor::calibration(sensor))
+    }
+
+    fn set_averaging(&mut self, averaging: Averaging) -> Result<(), Self::Error> {
+        dispatch!(self, sensor => sensor.set_averaging(averaging))
+    }
+
+    fn conversion_ready(&mut self) -> Result<bool, Self::Error> {
+        dispatch!(self, sensor => sensor.conversion_ready())
+    }
+
+    fn sense(&mut self) -> Result<PowerMonitor, Self::Error> {
+        dispatch!(self, sensor => PowerSensor::sense(sensor))
+    }
+
+    fn set_sampling(&mut self, sampling: Sampling) -> Result<(), Self::Error> {
+        dispatch!(self, sensor => sensor.set_sampling(sampling))
+    }
+
+    fn trigger(&mut self) -> Result<(), Self::Error> {
+        dispatch!(self, sensor => sensor.trigger())
+    }
+
+    fn sense_ready(&mut self) -> Result<Option<PowerMonitor>, Self::Error> {
+        dispatch!(self, sensor => sensor.sense_ready())
+    }
+
+    fn overflow(&self) -> bool {
+        dispatch!(self, sensor => sensor.overflow())
+    }
+
+    fn set_alert(&mut self, alert: Alert) -> Result<bool, Self::Error> {
+        dispatch!(self, sensor => sensor.set_alert(alert))
+    }
+
+    fn alert_active(&mut self) -> Result<bool, Self::Error> {
+        dispatch!(self, sensor => sensor.alert_active())
+    }
+}
@@ -0,0 +1,176 @@
+// Power event log
+//
+// The newest MAX_EVENTS events are kept in a ring, the firmware restores it
+// from the flash event store at boot and appends every new event to both.
+// A boot counts one up from the newest event restored, so events of
+// different boots can be told apart without a synced clock.
+//
+// Dropouts come from the quality page's detector. An over current spike
+// starts at the first sample above the threshold and ends at the first one
+// below it, like a dropout. The LiPo warns once when it drops below
+// LOW_LIPO_V and again only after it was charged above LIPO_RECOVERED_V.
+
+use core::fmt::Write;
+
+use heapless::{Deque, String};
+use powermeter_protocol::event::{Event, EventKind};
+use powermeter_protocol::time::UtcDateTime;
+
+use crate::drivers::ina219::Calibration;
+use crate::quality::format_uptime;
+use crate::supervisor::BusStatus;
+
+pub const MAX_EVENTS: usize = 64;
+/// reset reason of the ESP32-S2 brown-out detector
+pub const BROWN_OUT_RESET: u8 = 0x0F;
+/// V, the LiPo of the meter is nearly empty
+pub const LOW_LIPO_V: f32 = 3.4;
+pub const LIPO_RECOVERED_V: f32 = 3.6;
+
+#[derive(Debug, Clone, Default)]
+pub struct EventLog {
+    events: Deque<Event, MAX_EVENTS>,
+    boot: u16,
+    sequence: u32,
+}
+
+impl EventLog {
+    /// Continue after the events restored, the oldest first. sequence is
+    /// the one the next event gets.
+    pub fn restore(restored: impl IntoIterator<Item=Event>, sequence: u32) -> Self {
+        let mut log = EventLog { sequence, ..EventLog::default() };
+        for event in restored {
+            log.push(event);
+        }
+        log.boot = log.events.back().map(|event| event.boot.wrapping_add(1)).unwrap_or(0);
+        log
+    }
+
+    /// Add an event of this boot, the oldest one drops out of a full log.
+    /// The event is returned for the flash store.
+    pub fn record(&mut self, kind: EventKind, channel: u8, value: f32, duration_us: u32, uptime_us: u64,
+                  unix_us: Option<i64>) -> Event {
+        let event = Event {
+            sequence: self.sequence,
+            boot: self.boot,
+            kind,
+            channel,
+            uptime_us,
+            unix_us,
+            value,
+            duration_us,
+        };
+        self.sequence = self.sequence.wrapping_add(1);
+        self.push(event);
+        event
+    }
+
+    fn push(&mut self, event: Event) {
+        if self.events.is_full() {
+            self.events.pop_front();
+        }
+        let _ = self.events.push_back(event);
+    }
+
+    /// The oldest first.
+    pub fn events(&self) -> &Deque<Event, MAX_EVENTS> {
+        &self.events
+    }
+
+    pub fn boot(&self) -> u16 {
+        self.boot
+    }
+}
+
+/// Line of the event page, the time of day when the clock was synced and
+/// the time since the boot otherwise, e.g. "14:03:12 Drop 3.20V 8ms".
+pub fn event_line(event: &Event) -> String<32> {
+    let mut line = String::new();
+    match event.unix_us {
+        Some(unix_us) => {
+            let time = UtcDateTime::from_unix_us(unix_us);
+            let _ = write!(line, "{:02}:{:02}:{:02}", time.hour, time.minute, time.second);
+        }
+        None => {
+            let _ = line.push('+');
+            let _ = line.push_str(&format_uptime(event.uptime_us));
+        }
+    }
+    let mut duration = String::<8>::new();
+    let _ = match event.duration_us {
+        0..=999_999 => write!(duration, "{}ms", event.duration_us / 1000),
+        us => write!(duration, "{:.1}s", us as f32 / 1e6),
+    };
+    let _ = match event.kind {
+        EventKind::Boot if event.value as u8 == BROWN_OUT_RESET => write!(line, " Brown-out #{}", event.boot),
+        EventKind::Boot => write!(line, " Boot #{}", event.boot),
+        EventKind::Dropout => write!(line, " Drop {:.2}V {}", event.value, duration),
+        EventKind::OverCurrent => write!(line, " Spike {:.0}mA {}", event.value, duration),
+        EventKind::RangeChange => match Calibration::from_index(event.value as u8) {
+            Some(calibration) => write!(line, " Ch{} {}", event.channel + 1, calibration.text()),
+            None => write!(line, " Ch{} range ?", event.channel + 1),
+        },
+        EventKind::SensorError => match BusStatus::from_code(event.value as u8) {
+            Some(status) => write!(line, " Ch{} {}", event.channel + 1, status.text()),
+            None => write!(line, " Ch{} sensor error", event.channel + 1),
+        },
+        EventKind::BatteryLow => write!(line, " LiPo low {:.2}V", event.value),
+    };
+    line
+}
+
+/// A current above the threshold.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spike {
+    /// timestamp of the first sample above
+    pub start_us: u64,
+    pub duration_us: u64,
+    /// mA, highest sample of the spike
+    pub peak_ma: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SpikeDetector {
+    threshold_ma: f32,
+    /// start and highest current of the spike going on
+    spike: Option<(u64, f32)>,
+}
+
+impl SpikeDetector {
+    /// A threshold of 0 turns the detector off.
+    pub fn new(threshold_ma: f32) -> Self {
+        SpikeDetector { threshold_ma, spike: None }
+    }
+
+    /// A sample of the current taken at timestamp_us, the spike once the
+    /// current is back below the threshold.
+    pub fn add(&mut self, current: f32, timestamp_us: u64) -> Option<Spike> {
+        if self.threshold_ma <= 0.0 {
+            return None;
+        }
+        if current > self.threshold_ma {
+            let (_, peak) = self.spike.get_or_insert((timestamp_us, current));
+            *peak = peak.max(current);
+            return None;
+        }
+        let (start_us, peak_ma) = self.spike.take()?;
+        Some(Spike { start_us, duration_us: timestamp_us.saturating_sub(start_us), peak_ma })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LipoWarning {
+    warned: bool,
+}
+
+impl LipoWarning {
+    /// True once the voltage dropped below LOW_LIPO_V.
+    pub fn update(&mut self, voltage: f32) -> bool {
+        if self.warned {
+            self.warned = voltage < LIPO_RECOVERED_V;
+            return false;
+        }
+        self.warned = voltage < LOW_LIPO_V;
+        self.warned
+    }
+}
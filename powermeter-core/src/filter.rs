@@ -0,0 +1,186 @@
+// Reading filters
+//
+// The last digit of a reading jitters, the display redraws whenever it
+// changes. A filter between the sensor and its consumers smooths that out,
+// the display and the sample consumers (stream, data log, live view) have
+// their own so the logs can stay unfiltered while the display is calm. The
+// statistics and the raw log always see the readings as measured.
+//
+// A moving average follows a step within WINDOW samples, the exponential
+// filter approaches it without a fixed end and the median ignores spikes
+// shorter than half of MEDIAN_WINDOW.
+
+use enum_iterator::Sequence;
+
+use crate::drivers::sensor::PowerMonitor;
+
+/// samples of the moving average
+pub const WINDOW: usize = 8;
+/// samples of the median, the last ones of the moving average window
+pub const MEDIAN_WINDOW: usize = 5;
+/// weight of a new sample in the exponential filter
+pub const EMA_ALPHA: f32 = 0.25;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Sequence)]
+pub enum FilterKind {
+    #[default]
+    Off,
+    MovingAverage,
+    Exponential,
+    Median,
+}
+
+impl FilterKind {
+    pub fn text(&self) -> &'static str {
+        match self {
+            FilterKind::Off => "off",
+            FilterKind::MovingAverage => "average",
+            FilterKind::Exponential => "exponential",
+            FilterKind::Median => "median",
+        }
+    }
+
+    pub fn next_wrapping(&self) -> FilterKind {
+        self.next().unwrap_or_default()
+    }
+}
+
+/// Which consumers a filter is for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterTarget {
+    Display,
+    /// stream, data log and live view
+    Log,
+}
+
+impl FilterTarget {
+    pub fn text(&self) -> &'static str {
+        match self {
+            FilterTarget::Display => "Display",
+            FilterTarget::Log => "Log",
+        }
+    }
+}
+
+/// Filter of a single value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Filter {
+    kind: FilterKind,
+    /// ring of the last samples, next is written next
+    history: [f32; WINDOW],
+    next: usize,
+    len: usize,
+    ema: Option<f32>,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self::new(FilterKind::Off)
+    }
+}
+
+impl Filter {
+    pub const fn new(kind: FilterKind) -> Self {
+        Filter {
+            kind,
+            history: [0.0; WINDOW],
+            next: 0,
+            len: 0,
+            ema: None,
+        }
+    }
+
+    pub fn kind(&self) -> FilterKind {
+        self.kind
+    }
+
+    /// Another kind starts without history.
+    pub fn set_kind(&mut self, kind: FilterKind) {
+        *self = Filter::new(kind);
+    }
+
+    pub fn reset(&mut self) {
+        self.set_kind(self.kind);
+    }
+
+    /// The filtered value after sample.
+    pub fn update(&mut self, sample: f32) -> f32 {
+        self.history[self.next] = sample;
+        self.next = (self.next + 1) % WINDOW;
+        self.len = (self.len + 1).min(WINDOW);
+        match self.kind {
+            FilterKind::Off => sample,
+            FilterKind::MovingAverage => self.history[..self.len].iter().sum::<f32>() / self.len as f32,
+            FilterKind::Exponential => {
+                let ema = match self.ema {
+                    Some(ema) => ema + EMA_ALPHA * (sample - ema),
+                    None => sample,
+                };
+                self.ema = Some(ema);
+                ema
+            }
+            FilterKind::Median => self.median(),
+        }
+    }
+
+    fn median(&self) -> f32 {
+        let count = self.len.min(MEDIAN_WINDOW);
+        let mut last = [0.0; MEDIAN_WINDOW];
+        for (i, value) in last.iter_mut().take(count).enumerate() {
+            *value = self.history[(self.next + WINDOW - 1 - i) % WINDOW];
+        }
+        let last = &mut last[..count];
+        last.sort_unstable_by(|a, b| a.total_cmp(b));
+        if count % 2 == 1 {
+            last[count / 2]
+        } else {
+            (last[count / 2 - 1] + last[count / 2]) / 2.0
+        }
+    }
+}
+
+/// Filters of the values of a reading, the power follows the filtered
+/// voltage and current.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ReadingFilter {
+    shunt: Filter,
+    voltage: Filter,
+    current: Filter,
+}
+
+impl ReadingFilter {
+    pub const fn new(kind: FilterKind) -> Self {
+        ReadingFilter {
+            shunt: Filter::new(kind),
+            voltage: Filter::new(kind),
+            current: Filter::new(kind),
+        }
+    }
+
+    pub fn kind(&self) -> FilterKind {
+        self.current.kind()
+    }
+
+    pub fn set_kind(&mut self, kind: FilterKind) {
+        *self = ReadingFilter::new(kind);
+    }
+
+    pub fn reset(&mut self) {
+        self.set_kind(self.kind());
+    }
+
+    pub fn update(&mut self, reading: &PowerMonitor) -> PowerMonitor {
+        let shunt = self.shunt.update(reading.shunt);
+        let voltage = self.voltage.update(reading.voltage);
+        let current = self.current.update(reading.current);
+        if self.kind() == FilterKind::Off {
+            return *reading;
+        }
+        PowerMonitor {
+            shunt,
+            voltage,
+            current,
+            power: voltage * current,
+        }
+    }
+}
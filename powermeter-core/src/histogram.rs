@@ -0,0 +1,177 @@
+// Current distribution
+//
+// An average hides a load that switches between two levels, e.g. a radio
+// going on and off. handle_power bins every sample of the primary channel
+// by its current into buckets of half a decade from 1 uA to 1 A, lower
+// currents, reverse ones included, go to the first and higher to the last.
+// Each bucket adds up the time and the charge of the samples in it.
+
+use core::fmt::Write;
+
+use embedded_graphics::draw_target::DrawTarget;
+use embedded_graphics::geometry::Size;
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::pixelcolor::{Rgb565, RgbColor};
+use embedded_graphics::primitives::{Primitive, PrimitiveStyle, Rectangle};
+use embedded_graphics::text::Alignment;
+use embedded_graphics::Drawable;
+use heapless::String;
+
+use crate::derived::Integrator;
+use crate::display::{create_point, display_text, selected_style, top_style, DISPLAY_SIZE};
+
+pub const BUCKETS: usize = 14;
+/// mA, lower edge of every bucket but the first
+pub const EDGES: [f32; BUCKETS - 1] = [0.001, 0.003, 0.01, 0.03, 0.1, 0.3, 1.0, 3.0, 10.0, 30.0, 100.0, 300.0, 1000.0];
+const LABELS: [&str; BUCKETS] = [
+    "<1uA", "1-3uA", "3-10uA", "10-30uA", "30-100uA", "0.1-0.3mA", "0.3-1mA",
+    "1-3mA", "3-10mA", "10-30mA", "30-100mA", "0.1-0.3A", "0.3-1A", ">1A",
+];
+/// under the edges at the start of every decade
+const DECADES: [&str; 7] = ["1u", "10u", "100u", "1m", "10m", "100m", "1A"];
+
+// the bars between the selected bucket at the top and the decades at the bottom
+const PLOT_TOP: i32 = 19;
+const PLOT_HEIGHT: i32 = 96;
+const BAR_WIDTH: i32 = DISPLAY_SIZE.width as i32 / BUCKETS as i32;
+const BAR: Rgb565 = Rgb565::YELLOW;
+const CHARGE: Rgb565 = Rgb565::CYAN;
+
+/// Index of the bucket current_ma goes to.
+pub fn bucket(current_ma: f32) -> usize {
+    EDGES.iter().take_while(|edge| **edge <= current_ma).count()
+}
+
+/// "<1uA", "10-30mA", ">1A"
+pub fn label(bucket: usize) -> &'static str {
+    LABELS.get(bucket).copied().unwrap_or("")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Bucket {
+    pub time_us: u64,
+    /// mA * us
+    pub charge: f64,
+}
+
+impl Bucket {
+    pub fn charge_mah(&self) -> f32 {
+        (self.charge / 3_600_000_000.0) as f32
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Histogram {
+    buckets: [Bucket; BUCKETS],
+    integrator: Integrator,
+}
+
+fn fraction(part: f64, total: f64) -> Option<f32> {
+    (total > 0.0).then(|| (part / total) as f32)
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A sample of current_ma taken at timestamp_us.
+    pub fn add(&mut self, current_ma: f32, timestamp_us: u64) {
+        if let Some(interval) = self.integrator.add(current_ma, timestamp_us) {
+            let bucket = &mut self.buckets[bucket(interval.value)];
+            bucket.time_us += interval.duration_us;
+            bucket.charge += interval.area();
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Histogram::default();
+    }
+
+    pub fn buckets(&self) -> &[Bucket; BUCKETS] {
+        &self.buckets
+    }
+
+    pub fn total_us(&self) -> u64 {
+        self.buckets.iter().map(|bucket| bucket.time_us).sum()
+    }
+
+    fn total_charge(&self) -> f64 {
+        self.buckets.iter().map(|bucket| bucket.charge).sum()
+    }
+
+    /// Fraction of the time spent in bucket, None before any.
+    pub fn time_fraction(&self, bucket: usize) -> Option<f32> {
+        fraction(self.buckets.get(bucket)?.time_us as f64, self.total_us() as f64)
+    }
+
+    /// Fraction of the charge drawn in bucket, None before any.
+    pub fn charge_fraction(&self, bucket: usize) -> Option<f32> {
+        fraction(self.buckets.get(bucket)?.charge, self.total_charge())
+    }
+
+    /// The bucket most of the time was spent in.
+    pub fn busiest(&self) -> Option<usize> {
+        (self.total_us() > 0).then(|| {
+            (0..BUCKETS).max_by_key(|bucket| self.buckets[*bucket].time_us).unwrap_or(0)
+        })
+    }
+
+    /// "1-3mA 42.1% 12.3uAh"
+    pub fn text(&self, bucket: usize) -> String<32> {
+        let mut text = String::new();
+        let _ = write!(text, "{} ", label(bucket));
+        match self.time_fraction(bucket) {
+            Some(time) => {
+                let _ = write!(text, "{:.1}% ", time * 100.0);
+                format_charge(&mut text, self.buckets[bucket].charge_mah());
+            }
+            None => {
+                let _ = text.push_str("---");
+            }
+        }
+        text
+    }
+}
+
+fn format_charge(text: &mut String<32>, mah: f32) {
+    let _ = if mah.abs() < 1.0 { write!(text, "{:.1}uAh", mah * 1000.0) } else { write!(text, "{:.2}mAh", mah) };
+}
+
+/// Bars of the time in every bucket, the highest fills the plot, a line
+/// across each at the height of its charge. The selected bucket is white
+/// and has its numbers at the top.
+pub fn draw_histogram<D>(display: &mut D, histogram: &Histogram, selected: usize, character_style: MonoTextStyle<Rgb565>)
+    where D: DrawTarget<Color=Rgb565> {
+    let _ = display.clear(Rgb565::BLACK);
+    display_text(display, create_point(2, 0), selected_style(character_style, Rgb565::BLACK), top_style(Alignment::Left), &histogram.text(selected));
+
+    let highest = |f: &dyn Fn(usize) -> Option<f32>| (0..BUCKETS).filter_map(f).fold(0.0, f32::max);
+    let highest_time = highest(&|bucket| histogram.time_fraction(bucket));
+    let highest_charge = highest(&|bucket| histogram.charge_fraction(bucket));
+    let bottom = PLOT_TOP + PLOT_HEIGHT;
+    let height = |fraction: f32, highest: f32| if highest > 0.0 { (fraction / highest * PLOT_HEIGHT as f32) as i32 } else { 0 };
+    for bucket in 0..BUCKETS {
+        let x = bucket as i32 * BAR_WIDTH;
+        let color = if bucket == selected { Rgb565::WHITE } else { BAR };
+        // an empty bucket still shows where it is
+        let bar = height(histogram.time_fraction(bucket).unwrap_or(0.0), highest_time).max(1);
+        let _ = Rectangle::new(create_point(x + 1, bottom - bar), Size::new(BAR_WIDTH as u32 - 2, bar as u32))
+            .into_styled(PrimitiveStyle::with_fill(color))
+            .draw(display);
+        if let Some(charge) = histogram.charge_fraction(bucket).filter(|charge| *charge > 0.0) {
+            let y = bottom - height(charge, highest_charge).max(2);
+            let _ = Rectangle::new(create_point(x, y), Size::new(BAR_WIDTH as u32, 2))
+                .into_styled(PrimitiveStyle::with_fill(CHARGE))
+                .draw(display);
+        }
+    }
+    for (decade, text) in DECADES.iter().enumerate() {
+        // bucket 1 starts at 1 uA, every second one at a decade
+        let x = (1 + 2 * decade as i32) * BAR_WIDTH;
+        let _ = Rectangle::new(create_point(x, bottom), Size::new(1, 3))
+            .into_styled(PrimitiveStyle::with_fill(Rgb565::WHITE))
+            .draw(display);
+        display_text(display, create_point(x, bottom + 4), character_style, top_style(Alignment::Center), text);
+    }
+}
@@ -0,0 +1,22 @@
+#![no_std]
+
+pub mod autorange;
+pub mod battery;
+pub mod channel;
+pub mod correction;
+pub mod derived;
+pub mod display;
+pub mod drivers;
+pub mod events;
+pub mod filter;
+pub mod histogram;
+pub mod menu;
+pub mod modifier;
+pub mod pipeline;
+pub mod profile;
+pub mod quality;
+pub mod scope;
+pub mod spectrum;
+pub mod supervisor;
+pub mod ui;
+pub mod wizard;
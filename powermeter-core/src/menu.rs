@@ -0,0 +1,88 @@
+use embedded_graphics::draw_target::DrawTarget;
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::pixelcolor::{Rgb565, RgbColor};
+use embedded_graphics::text::Alignment;
+use enum_iterator::{all, Sequence};
+use heapless::Vec;
+
+use crate::display::{create_point, display_text, selected_style, top_style};
+
+const VISIBLE_ITEMS: usize = 4;
+const LINE_HEIGHT: i32 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Sequence)]
+pub enum SettingsItem {
+    WifiSetup,
+    DataLog,
+    RawLog,
+    Calibrate,
+    Sampling,
+    DisplayFilter,
+    LogFilter,
+    Profile,
+    BatteryLife,
+    Scope,
+    Histogram,
+    Spectrum,
+    Quality,
+    Events,
+    Diagnostics,
+    AutoRange,
+    Channels,
+    Exit,
+}
+
+impl SettingsItem {
+    pub fn title(&self) -> &'static str {
+        match self {
+            SettingsItem::WifiSetup => "Wi-Fi setup",
+            SettingsItem::DataLog => "Data logging",
+            SettingsItem::RawLog => "Raw logging",
+            SettingsItem::Calibrate => "Calibration",
+            SettingsItem::Sampling => "Sampling",
+            SettingsItem::DisplayFilter => "Display filter",
+            SettingsItem::LogFilter => "Log filter",
+            SettingsItem::Profile => "Sleep profile",
+            SettingsItem::BatteryLife => "Battery life",
+            SettingsItem::Scope => "Scope",
+            SettingsItem::Histogram => "Histogram",
+            SettingsItem::Spectrum => "Spectrum",
+            SettingsItem::Quality => "Quality",
+            SettingsItem::Events => "Events",
+            SettingsItem::Diagnostics => "I2C bus",
+            SettingsItem::AutoRange => "Auto range",
+            SettingsItem::Channels => "Channels",
+            SettingsItem::Exit => "Exit",
+        }
+    }
+
+    pub fn next_wrapping(&self) -> SettingsItem {
+        self.next().unwrap_or_else(|| enum_iterator::first().unwrap())
+    }
+
+    pub fn previous_wrapping(&self) -> SettingsItem {
+        self.previous().unwrap_or_else(|| enum_iterator::last().unwrap())
+    }
+}
+
+pub fn draw_menu<D>(display: &mut D, selected: SettingsItem, character_style: MonoTextStyle<Rgb565>) where D: DrawTarget<Color=Rgb565> {
+    let titles: Vec<&str, { SettingsItem::CARDINALITY }> = all::<SettingsItem>().map(|item| item.title()).collect();
+    let selected_index = all::<SettingsItem>().position(|item| item == selected).unwrap_or(0);
+    draw_list(display, &titles, selected_index, character_style);
+}
+
+/// Lines with the selected one inverted, scrolled so it is visible.
+pub fn draw_list<D>(display: &mut D, lines: &[&str], selected: usize, character_style: MonoTextStyle<Rgb565>) where D: DrawTarget<Color=Rgb565> {
+    let _ = display.clear(Rgb565::BLACK);
+
+    let text_style = top_style(Alignment::Left);
+    let selected_style = selected_style(character_style, Rgb565::BLACK);
+
+    let first_visible = (selected + 1).saturating_sub(VISIBLE_ITEMS)
+        .min(lines.len().saturating_sub(VISIBLE_ITEMS));
+
+    for (line, (index, text)) in lines.iter().enumerate().skip(first_visible).take(VISIBLE_ITEMS).enumerate() {
+        let style = if index == selected { selected_style } else { character_style };
+        display_text(display, create_point(10, 5 + line as i32 * LINE_HEIGHT), style, text_style, text);
+    }
+}
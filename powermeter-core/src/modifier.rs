@@ -0,0 +1,91 @@
+// Display modifiers
+//
+// Bench meter functions on top of the reading pages. HOLD freezes the
+// reading, REL shows the difference to a reference taken when it was turned
+// on, MAX and MIN the extreme since then. Every page has its own value so
+// switching pages keeps the modifier, a reset takes a new reference or
+// starts the peaks over from the latest reading.
+
+use enum_iterator::Sequence;
+
+use crate::ui::{PageValues, PowerDisplay};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Sequence)]
+pub enum Modifier {
+    #[default]
+    Off,
+    Hold,
+    Relative,
+    Max,
+    Min,
+}
+
+impl Modifier {
+    /// Shown next to the value, empty without a modifier.
+    pub fn annunciator(&self) -> &'static str {
+        match self {
+            Modifier::Off => "",
+            Modifier::Hold => "HOLD",
+            Modifier::Relative => "REL",
+            Modifier::Max => "MAX",
+            Modifier::Min => "MIN",
+        }
+    }
+
+    pub fn next_wrapping(&self) -> Modifier {
+        self.next().unwrap_or_default()
+    }
+}
+
+/// A modifier and the values of every page it works with.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Modified {
+    modifier: Modifier,
+    /// held, reference or peak, by page
+    values: PageValues,
+}
+
+fn peak(stored: Option<f32>, value: Option<f32>, f: fn(f32, f32) -> f32) -> Option<f32> {
+    match (stored, value) {
+        (Some(stored), Some(value)) => Some(f(stored, value)),
+        (stored, value) => stored.or(value),
+    }
+}
+
+impl Modified {
+    /// Start modifier at the page values of the latest reading.
+    pub fn new(modifier: Modifier, values: &PageValues) -> Self {
+        Modified { modifier, values: *values }
+    }
+
+    pub fn modifier(&self) -> Modifier {
+        self.modifier
+    }
+
+    /// Hold, reference or peaks start over from values.
+    pub fn reset(&mut self, values: &PageValues) {
+        self.values = *values;
+    }
+
+    /// Page values of a new reading, only the peaks follow them.
+    pub fn update(&mut self, values: &PageValues) {
+        for (stored, value) in self.values.iter_mut().zip(values) {
+            match self.modifier {
+                Modifier::Max => *stored = peak(*stored, *value, f32::max),
+                Modifier::Min => *stored = peak(*stored, *value, f32::min),
+                _ => {}
+            }
+        }
+    }
+
+    /// What the page shows with values of the latest reading.
+    pub fn value(&self, page: PowerDisplay, values: &PageValues) -> Option<f32> {
+        let stored = self.values[page as usize];
+        let value = values[page as usize];
+        match self.modifier {
+            Modifier::Off => value,
+            Modifier::Relative => Some(value? - stored?),
+            Modifier::Hold | Modifier::Max | Modifier::Min => stored,
+        }
+    }
+}
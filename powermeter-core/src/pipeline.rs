@@ -0,0 +1,403 @@
+// Measurement pipeline
+//
+// handle_power samples the sensor as fast as the most demanding consumer
+// needs and hands every sample to the consumers that want it. The display
+// gets one reading per DISPLAY_INTERVAL_US whatever the sample rate is.
+//
+// The first channel is the primary one, it alone feeds the stream, the
+// data log, the serial live view and the pages that follow one channel:
+// battery, histogram, profile, quality, scope and spectrum. The battery
+// average, the histogram and the dropout detector take every sample
+// whatever page is up, the others only while their page is. Every channel
+// has its own spike detector, spikes shorter than the sample interval are
+// missed. The samples of a spectrum burst only go to the spike and dropout
+// detectors of the primary channel, the others would be skewed by the
+// burst rate. The display and the sample consumers get filtered readings,
+// each with a filter of its own, everything else works on the readings as
+// measured. The resistance, the conductance and the efficiency are derived
+// from the display readings of one tick.
+//
+// A sensor that stops answering is recovered by its supervisor. The rail
+// is only power cycled when no other sensor answers either, a single lost
+// sensor is just tried again later.
+
+use heapless::Vec;
+use powermeter_protocol::event::EventKind;
+use powermeter_protocol::stream::Sample;
+
+use crate::battery::{Average, RollingCurrent};
+use crate::channel::{Channel, Stats, MAX_CHANNELS};
+use crate::derived::Derived;
+use crate::drivers::ina219::Calibration;
+use crate::drivers::sensor::PowerMonitor;
+use crate::events::SpikeDetector;
+use crate::filter::{FilterKind, FilterTarget, ReadingFilter};
+use crate::histogram::Histogram;
+use crate::profile::{Profile, Profiler};
+use crate::quality::{DropoutDetector, Quality, QualitySettings, RippleWindow, Window};
+use crate::scope::{Scope, ScopeSettings, Trace};
+use crate::supervisor::{BusStatus, Recovery, Supervisor};
+use crate::ui::Input;
+
+/// sample interval without other consumers
+pub const DISPLAY_INTERVAL_US: u64 = 1_000_000;
+/// while a UDP receiver is subscribed
+pub const STREAM_INTERVAL_US: u64 = 1_000;
+/// while profiling the sleep current, wakes can be short
+pub const PROFILE_INTERVAL_US: u64 = 1_000;
+/// while the scope page is up
+pub const SCOPE_INTERVAL_US: u64 = 1_000;
+/// while the quality page is up, the ripple wants every conversion
+pub const QUALITY_INTERVAL_US: u64 = 1_000;
+/// while the flash data log is recording
+pub const LOG_INTERVAL_US: u64 = 10_000;
+/// while the companion cli shows live data
+pub const LIVE_INTERVAL_US: u64 = 100_000;
+
+/// Who is currently interested in samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Consumers {
+    pub streaming: bool,
+    pub logging: bool,
+    /// the data log wants raw registers instead of samples
+    pub raw_logging: bool,
+    pub live: bool,
+    /// the sleep current profiler
+    pub profiling: bool,
+    /// the scope on the display
+    pub scoping: bool,
+    /// ripple of the quality page
+    pub quality: bool,
+    /// bursts of the spectrum page, instead of display readings
+    pub spectrum: bool,
+}
+
+impl Consumers {
+    pub fn sample_interval_us(&self) -> u64 {
+        if self.streaming {
+            STREAM_INTERVAL_US
+        } else if self.profiling {
+            PROFILE_INTERVAL_US
+        } else if self.scoping {
+            SCOPE_INTERVAL_US
+        } else if self.quality {
+            QUALITY_INTERVAL_US
+        } else if self.logging {
+            LOG_INTERVAL_US
+        } else if self.live {
+            LIVE_INTERVAL_US
+        } else {
+            DISPLAY_INTERVAL_US
+        }
+    }
+}
+
+/// Where one sample goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Route {
+    pub stream: bool,
+    pub log: bool,
+    /// read the raw registers for the data log
+    pub raw: bool,
+    /// raw logging just started, the data log needs the calibration first
+    pub raw_header: bool,
+    pub live: bool,
+    pub display: bool,
+}
+
+/// An event for the log, see EventKind for the value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Occurrence {
+    pub kind: EventKind,
+    pub channel: u8,
+    pub value: f32,
+    pub duration_us: u64,
+    pub at_us: u64,
+}
+
+/// What handle_power does about a sensor that stopped answering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recover {
+    /// send clock pulses until SDA is released
+    ClockOut,
+    /// the others still answer, neither the bus nor the rail is at fault,
+    /// try the sensor again after wait_ms
+    Retry { wait_ms: u32 },
+    /// wait wait_ms, then power cycle the rail, every sensor lost its
+    /// configuration
+    PowerCycle { wait_ms: u32 },
+}
+
+/// What one sample of a channel produced.
+#[derive(Debug, Clone, Default)]
+pub struct Outputs {
+    pub channel: u8,
+    /// filtered for the consumers of the route, primary channel only
+    pub sample: Option<Sample>,
+    pub events: Vec<Occurrence, 2>,
+    /// capture a burst for the spectrum page once the tick is over
+    pub burst: bool,
+    battery: Option<Average>,
+    histogram: Option<Histogram>,
+    profile: Option<Profile>,
+    quality: Option<Quality>,
+    trace: Option<Trace>,
+    /// filtered for the display and as measured, the range, the
+    /// statistics and what is derived from the filtered one
+    reading: Option<(PowerMonitor, PowerMonitor, Calibration, Stats, Derived)>,
+}
+
+impl Outputs {
+    /// For the ui, in the order to send them.
+    pub fn inputs(self) -> impl Iterator<Item=Input> {
+        let channel = self.channel;
+        let reading = self.reading.map(|(reading, unfiltered, calibration, stats, derived)| {
+            Input::Reading { channel, reading, unfiltered, calibration, stats, derived }
+        });
+        self.battery.map(Input::Battery).into_iter()
+            .chain(self.histogram.map(Input::Histogram))
+            .chain(self.profile.map(Input::Profile))
+            .chain(self.quality.map(Input::Quality))
+            .chain(self.trace.map(Input::Trace))
+            .chain(reading)
+    }
+}
+
+pub struct Pipeline {
+    last_display_us: Option<u64>,
+    last_live_us: Option<u64>,
+    raw_logging: bool,
+    /// of the latest route
+    consumers: Consumers,
+    route: Route,
+    channels: usize,
+    supervisors: [Supervisor; MAX_CHANNELS],
+    spikes: [SpikeDetector; MAX_CHANNELS],
+    display_filters: [ReadingFilter; MAX_CHANNELS],
+    /// display reading of the first channel and the tick it is from, the
+    /// input of the efficiency
+    converter_input: Option<(u64, PowerMonitor)>,
+    log_filter: ReadingFilter,
+    rolling_current: RollingCurrent,
+    histogram: Histogram,
+    profiler: Profiler,
+    scope: Option<Scope>,
+    quality_settings: QualitySettings,
+    ripple: RippleWindow,
+    /// the last full window
+    ripple_shown: Option<Window>,
+    dropouts: DropoutDetector,
+}
+
+fn due(last_us: &mut Option<u64>, timestamp_us: u64, interval_us: u64) -> bool {
+    let due = match *last_us {
+        Some(last) => timestamp_us.saturating_sub(last) >= interval_us,
+        None => true,
+    };
+    if due {
+        *last_us = Some(timestamp_us);
+    }
+    due
+}
+
+impl Pipeline {
+    /// For the first channels sensors, overcurrent_ma of 0 turns the spike
+    /// detectors off.
+    pub fn new(channels: usize, overcurrent_ma: f32) -> Self {
+        let quality_settings = QualitySettings::default();
+        Pipeline {
+            last_display_us: None,
+            last_live_us: None,
+            raw_logging: false,
+            consumers: Consumers::default(),
+            route: Route::default(),
+            channels: channels.min(MAX_CHANNELS),
+            supervisors: [Supervisor::new(); MAX_CHANNELS],
+            spikes: [SpikeDetector::new(overcurrent_ma); MAX_CHANNELS],
+            display_filters: [ReadingFilter::default(); MAX_CHANNELS],
+            converter_input: None,
+            log_filter: ReadingFilter::default(),
+            rolling_current: RollingCurrent::new(),
+            histogram: Histogram::new(),
+            profiler: Profiler::new(),
+            scope: None,
+            quality_settings,
+            ripple: RippleWindow::new(quality_settings.window_us()),
+            ripple_shown: None,
+            dropouts: DropoutDetector::new(quality_settings.dropout_v),
+        }
+    }
+
+    /// A sensor started answering after boot, its channel gets the next index.
+    pub fn add_channel(&mut self) {
+        self.channels = (self.channels + 1).min(MAX_CHANNELS);
+    }
+
+    /// Where the samples taken at timestamp_us go.
+    pub fn route(&mut self, consumers: &Consumers, timestamp_us: u64) -> Route {
+        if !consumers.profiling {
+            // starts over the next time the page comes up
+            self.profiler = Profiler::new();
+        }
+        let raw_header = consumers.raw_logging && !self.raw_logging;
+        self.raw_logging = consumers.raw_logging;
+        let display = consumers.sample_interval_us() == DISPLAY_INTERVAL_US
+            || due(&mut self.last_display_us, timestamp_us, DISPLAY_INTERVAL_US);
+        if display {
+            self.last_display_us = Some(timestamp_us);
+        }
+        self.consumers = *consumers;
+        self.route = Route {
+            stream: consumers.streaming,
+            log: consumers.logging && !consumers.raw_logging,
+            raw: consumers.raw_logging,
+            raw_header,
+            live: consumers.live && due(&mut self.last_live_us, timestamp_us, LIVE_INTERVAL_US),
+            display,
+        };
+        self.route
+    }
+
+    pub fn set_filter(&mut self, target: FilterTarget, kind: FilterKind) {
+        match target {
+            FilterTarget::Display => self.display_filters.iter_mut().for_each(|filter| filter.set_kind(kind)),
+            FilterTarget::Log => self.log_filter.set_kind(kind),
+        }
+    }
+
+    pub fn reset_histogram(&mut self) {
+        self.histogram.reset();
+    }
+
+    /// Arm the scope, None turns it off.
+    pub fn set_scope(&mut self, settings: Option<ScopeSettings>) {
+        self.scope = settings.map(Scope::new);
+    }
+
+    pub fn is_scoping(&self) -> bool {
+        self.scope.is_some()
+    }
+
+    /// A new window length starts the ripple over.
+    pub fn set_quality(&mut self, settings: QualitySettings) {
+        if settings.window_ms != self.quality_settings.window_ms {
+            self.ripple = RippleWindow::new(settings.window_us());
+            self.ripple_shown = None;
+        }
+        self.dropouts.set_threshold(settings.dropout_v);
+        self.quality_settings = settings;
+    }
+
+    /// The sensor of channel index answered, true when it is back after a
+    /// recovery.
+    pub fn answered(&mut self, index: usize) -> bool {
+        self.supervisors[index].success()
+    }
+
+    /// The sensor of channel index failed, what to do about it.
+    pub fn failed(&mut self, index: usize) -> Option<Recover> {
+        let recovery = self.supervisors[index].error()?;
+        let others_healthy = (0..self.channels).any(|i| i != index && self.supervisors[i].is_healthy());
+        Some(match recovery {
+            Recovery::ClockOut => Recover::ClockOut,
+            Recovery::PowerCycle { wait_ms } if others_healthy => Recover::Retry { wait_ms },
+            Recovery::PowerCycle { wait_ms } => Recover::PowerCycle { wait_ms },
+        })
+    }
+
+    pub fn status(&self, index: usize) -> BusStatus {
+        self.supervisors[index].status()
+    }
+
+    /// A sample of channel index as measured at timestamp_us, after the
+    /// latest route.
+    pub fn process(&mut self, index: usize, channel: &mut Channel, measured: &PowerMonitor, timestamp_us: u64) -> Outputs {
+        let mut outputs = Outputs { channel: index as u8, ..Outputs::default() };
+        let reading = channel.correction().apply(measured);
+        channel.update_at(reading, timestamp_us);
+        self.detect_spike(index, &reading, timestamp_us, &mut outputs.events);
+        if index == 0 {
+            self.process_primary(channel, &reading, timestamp_us, &mut outputs);
+        }
+        // filtered on every sample, the display only gets some of them
+        let displayed = self.display_filters[index].update(&reading);
+        if index == 0 {
+            self.converter_input = Some((timestamp_us, displayed));
+        }
+        if self.route.display {
+            // the second channel is the output of a converter fed by the
+            // first, a reading of an earlier tick would skew the efficiency
+            let input = self.converter_input
+                .filter(|(input_us, _)| index == 1 && *input_us == timestamp_us)
+                .map(|(_, input)| input);
+            let derived = Derived::new(&displayed, input.as_ref());
+            outputs.reading = Some((displayed, reading, channel.calibration, channel.stats, derived));
+        }
+        outputs
+    }
+
+    fn process_primary(&mut self, channel: &Channel, reading: &PowerMonitor, timestamp_us: u64, outputs: &mut Outputs) {
+        let display = self.route.display;
+        let filtered = self.log_filter.update(reading);
+        outputs.sample = Some(Sample {
+            timestamp_us,
+            voltage: filtered.voltage,
+            current: filtered.current,
+            power: filtered.power,
+            range: channel.calibration.index(),
+        });
+        self.rolling_current.add(reading.current, timestamp_us);
+        self.histogram.add(reading.current, timestamp_us);
+        if display {
+            outputs.battery = self.rolling_current.average();
+            outputs.histogram = Some(self.histogram);
+        }
+        if self.consumers.profiling {
+            self.profiler.add(reading.current, timestamp_us);
+            outputs.profile = display.then(|| self.profiler.profile());
+        }
+        self.detect_dropout(reading, timestamp_us, &mut outputs.events);
+        if self.consumers.quality {
+            if let Some(window) = self.ripple.add(reading, timestamp_us) {
+                self.ripple_shown = Some(window);
+            }
+            outputs.quality = display.then(|| Quality::new(self.ripple_shown, &self.dropouts));
+        }
+        outputs.trace = self.scope.as_mut().and_then(|scope| scope.add(reading, timestamp_us));
+        outputs.burst = display && self.consumers.spectrum;
+    }
+
+    /// A sample of a burst of the primary channel as measured at
+    /// timestamp_us, the current for the spectrum and the events.
+    pub fn process_burst(&mut self, channel: &Channel, measured: &PowerMonitor, timestamp_us: u64) -> (f32, Vec<Occurrence, 2>) {
+        let reading = channel.correction().apply(measured);
+        let mut events = Vec::new();
+        self.detect_spike(0, &reading, timestamp_us, &mut events);
+        self.detect_dropout(&reading, timestamp_us, &mut events);
+        (reading.current, events)
+    }
+
+    fn detect_spike(&mut self, index: usize, reading: &PowerMonitor, timestamp_us: u64, events: &mut Vec<Occurrence, 2>) {
+        if let Some(spike) = self.spikes[index].add(reading.current, timestamp_us) {
+            let _ = events.push(Occurrence {
+                kind: EventKind::OverCurrent,
+                channel: index as u8,
+                value: spike.peak_ma,
+                duration_us: spike.duration_us,
+                at_us: spike.start_us,
+            });
+        }
+    }
+
+    fn detect_dropout(&mut self, reading: &PowerMonitor, timestamp_us: u64, events: &mut Vec<Occurrence, 2>) {
+        if let Some(dropout) = self.dropouts.add(reading.voltage, timestamp_us) {
+            let _ = events.push(Occurrence {
+                kind: EventKind::Dropout,
+                channel: 0,
+                value: dropout.lowest_v,
+                duration_us: dropout.duration_us,
+                at_us: dropout.start_us,
+            });
+        }
+    }
+}
@@ -0,0 +1,233 @@
+// Sleep current profiling
+//
+// Battery powered nodes sleep at a few uA and wake up to tens of mA. The
+// profiler follows the lowest and the highest current with envelopes that
+// slowly let go and splits the trace between both on a log scale. Entering
+// and leaving a wake use different thresholds so the noise on its edges
+// does not count as several wakes. Without a clear gap between the
+// envelopes, e.g. a constant load, everything counts as sleep.
+//
+// The battery line is the runtime of the battery page at the average
+// current.
+
+use core::fmt::Write;
+
+use heapless::String;
+
+use crate::battery::{format_runtime, Battery};
+use crate::derived::Integrator;
+use crate::display::format_current;
+
+/// us, time constant of the envelopes letting go
+pub const ENVELOPE_TAU_US: f32 = 60_000_000.0;
+/// highest to lowest current before there are two phases
+pub const MIN_RATIO: f32 = 3.0;
+/// fraction of the log span between the envelopes that starts a wake
+pub const ENTER_ACTIVE: f32 = 0.6;
+/// and that ends it
+pub const LEAVE_ACTIVE: f32 = 0.4;
+/// mA, lower currents are taken as this, there is no log of zero
+pub const FLOOR_MA: f32 = 0.0001;
+
+/// lines of the summary page
+pub const PROFILE_LINES: usize = 9;
+
+/// What the profiler found so far, None where it has not seen enough.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Profile {
+    /// mA, mean while asleep
+    pub sleep_current: Option<f32>,
+    /// mA, mean while awake
+    pub active_current: Option<f32>,
+    /// us, mean length of a wake
+    pub wake_duration_us: Option<u64>,
+    /// us, mean time from one wake to the next
+    pub wake_period_us: Option<u64>,
+    /// fraction of the time awake
+    pub duty_cycle: Option<f32>,
+    /// mC, mean of the wakes
+    pub charge_per_wake: Option<f32>,
+    /// mA over the whole trace
+    pub average_current: Option<f32>,
+    /// finished wakes
+    pub wakes: u32,
+    /// us of trace
+    pub duration_us: u64,
+}
+
+fn format_duration(text: &mut String<32>, us: u64) {
+    let _ = if us < 1_000_000 { write!(text, "{} ms", us / 1000) } else { write!(text, "{:.1} s", us as f32 / 1e6) };
+}
+
+impl Profile {
+    /// The summary page with the runtime of battery.
+    pub fn lines(&self, battery: &Battery) -> [String<32>; PROFILE_LINES] {
+        let mut lines: [String<32>; PROFILE_LINES] = Default::default();
+        let labels = ["Sleep   ", "Active  ", "Wake    ", "Period  ", "Duty    ", "Charge  ", "Average "];
+        for (line, label) in lines.iter_mut().zip(labels) {
+            let _ = line.push_str(label);
+        }
+        let none = |line: &mut String<32>| {
+            let _ = line.push_str("---");
+        };
+        let [sleep, active, wake, period, duty, charge, average, runtime, trace] = &mut lines;
+        match self.sleep_current {
+            Some(ma) => format_current(sleep, ma),
+            None => none(sleep),
+        }
+        match self.active_current {
+            Some(ma) => format_current(active, ma),
+            None => none(active),
+        }
+        match self.wake_duration_us {
+            Some(us) => format_duration(wake, us),
+            None => none(wake),
+        }
+        match self.wake_period_us {
+            Some(us) => format_duration(period, us),
+            None => none(period),
+        }
+        match self.duty_cycle {
+            Some(duty_cycle) => {
+                let _ = write!(duty, "{:.2} %", duty_cycle * 100.0);
+            }
+            None => none(duty),
+        }
+        match self.charge_per_wake {
+            Some(mc) => {
+                let _ = write!(charge, "{:.3} mC", mc);
+            }
+            None => none(charge),
+        }
+        match self.average_current {
+            Some(ma) => format_current(average, ma),
+            None => none(average),
+        }
+        match self.average_current.and_then(|average| battery.runtime_h(average)) {
+            Some(hours) => {
+                let _ = write!(runtime, "Battery {} {:.0}mAh", format_runtime(hours), battery.capacity_mah);
+            }
+            None if battery.capacity_mah <= 0.0 => {
+                let _ = runtime.push_str("Battery: no capacity");
+            }
+            None => {
+                let _ = runtime.push_str("Battery ---");
+            }
+        }
+        let _ = write!(trace, "{} wakes in ", self.wakes);
+        format_duration(trace, self.duration_us);
+        lines
+    }
+}
+
+/// log2 good to a few percent, plenty to place thresholds between decades.
+fn log2(x: f32) -> f32 {
+    let bits = x.to_bits();
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127;
+    let mantissa = f32::from_bits((bits & 0x007f_ffff) | 0x3f80_0000);
+    exponent as f32 + mantissa - 1.0
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Profiler {
+    /// log2 of the mA of the envelopes
+    low: f32,
+    high: f32,
+    active: bool,
+    integrator: Integrator,
+    /// mA * us
+    sleep_charge: f64,
+    sleep_us: u64,
+    active_charge: f64,
+    active_us: u64,
+    /// start and charge of the wake going on
+    wake: Option<(u64, f64)>,
+    /// finished wakes, their length and charge
+    wakes: u32,
+    wakes_us: u64,
+    wakes_charge: f64,
+    /// wakes started and when the first and the last one did
+    starts: u32,
+    first_start_us: u64,
+    last_start_us: u64,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// A sample of current_ma taken at timestamp_us.
+    pub fn add(&mut self, current_ma: f32, timestamp_us: u64) {
+        let level = log2(current_ma.max(FLOOR_MA));
+        // the first sample and one after a gap start the envelopes over
+        let release = match self.integrator.add(current_ma, timestamp_us) {
+            Some(interval) => {
+                let charge = interval.area();
+                if self.active {
+                    self.active_charge += charge;
+                    self.active_us += interval.duration_us;
+                    if let Some((_, wake_charge)) = self.wake.as_mut() {
+                        *wake_charge += charge;
+                    }
+                } else {
+                    self.sleep_charge += charge;
+                    self.sleep_us += interval.duration_us;
+                }
+                (interval.duration_us as f32 / ENVELOPE_TAU_US).min(1.0)
+            }
+            None => 1.0,
+        };
+        self.low = if level < self.low { level } else { self.low + (level - self.low) * release };
+        self.high = if level > self.high { level } else { self.high - (self.high - level) * release };
+
+        let span = self.high - self.low;
+        let active = if span < log2(MIN_RATIO) {
+            false
+        } else if self.active {
+            level > self.low + span * LEAVE_ACTIVE
+        } else {
+            level > self.low + span * ENTER_ACTIVE
+        };
+        match (self.active, active) {
+            (false, true) => {
+                if self.starts == 0 {
+                    self.first_start_us = timestamp_us;
+                }
+                self.starts += 1;
+                self.last_start_us = timestamp_us;
+                self.wake = Some((timestamp_us, 0.0));
+            }
+            (true, false) => {
+                if let Some((start_us, charge)) = self.wake.take() {
+                    self.wakes += 1;
+                    self.wakes_us += timestamp_us - start_us;
+                    self.wakes_charge += charge;
+                }
+            }
+            _ => {}
+        }
+        self.active = active;
+    }
+
+    pub fn profile(&self) -> Profile {
+        let mean = |charge: f64, us: u64| (us > 0).then(|| (charge / us as f64) as f32);
+        let total_us = self.sleep_us + self.active_us;
+        Profile {
+            sleep_current: mean(self.sleep_charge, self.sleep_us),
+            active_current: mean(self.active_charge, self.active_us),
+            wake_duration_us: (self.wakes > 0).then(|| self.wakes_us / self.wakes as u64),
+            wake_period_us: (self.starts > 1).then(|| (self.last_start_us - self.first_start_us) / (self.starts - 1) as u64),
+            duty_cycle: (total_us > 0).then(|| self.active_us as f32 / total_us as f32),
+            // mA * us to mC
+            charge_per_wake: (self.wakes > 0).then(|| (self.wakes_charge / self.wakes as f64 / 1e6) as f32),
+            average_current: mean(self.sleep_charge + self.active_charge, total_us),
+            wakes: self.wakes,
+            duration_us: total_us,
+        }
+    }
+}
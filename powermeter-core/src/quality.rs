@@ -0,0 +1,349 @@
+// Supply quality
+//
+// An average hides how clean a supply is. While the quality page is up
+// handle_power samples the primary channel at QUALITY_INTERVAL_US, the
+// fastest the pipeline goes, and a ripple window takes the peak to peak and
+// the AC RMS, the RMS without the mean, of the bus voltage and the current
+// over WINDOWS_MS. The dropout detector takes every sample of the primary
+// channel whatever page is up. A dip starts at the first sample below the
+// threshold and ends at the first one above it, so the duration is rounded
+// up to the sample interval. With nothing sampling fast that is a second,
+// shorter dips slip through.
+
+use core::fmt::Write;
+
+use enum_iterator::Sequence;
+use heapless::String;
+
+use crate::derived::MAX_GAP_US;
+use crate::drivers::sensor::PowerMonitor;
+use crate::spectrum::sqrt;
+
+/// ms the ripple is taken over
+pub const WINDOWS_MS: [u32; 8] = [10, 20, 50, 100, 200, 500, 1000, 5000];
+/// V, highest dropout threshold, the INA219 bus range
+pub const MAX_DROPOUT_V: f32 = 26.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualitySettings {
+    pub window_ms: u32,
+    /// V, 0 turns the dropout detector off
+    pub dropout_v: f32,
+}
+
+impl Default for QualitySettings {
+    fn default() -> Self {
+        QualitySettings {
+            window_ms: 1000,
+            dropout_v: 0.0,
+        }
+    }
+}
+
+/// What the quality page edits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Sequence)]
+pub enum QualityField {
+    #[default]
+    Window,
+    Dropout,
+}
+
+impl QualityField {
+    pub fn next_wrapping(&self) -> QualityField {
+        self.next().unwrap_or_default()
+    }
+}
+
+impl QualitySettings {
+    pub fn window_us(&self) -> u64 {
+        self.window_ms as u64 * 1000
+    }
+
+    /// One step up or down, the window along WINDOWS_MS and the threshold in
+    /// steps of 0.1 V up to 5 V and 0.5 V above.
+    pub fn adjust(&mut self, field: QualityField, up: bool) {
+        match field {
+            QualityField::Window => {
+                let index = WINDOWS_MS.iter().position(|ms| *ms >= self.window_ms).unwrap_or(WINDOWS_MS.len() - 1);
+                let index = if up { (index + 1).min(WINDOWS_MS.len() - 1) } else { index.saturating_sub(1) };
+                self.window_ms = WINDOWS_MS[index];
+            }
+            QualityField::Dropout => {
+                // down from 5 V goes in the smaller steps below it
+                let from = if up { self.dropout_v } else { self.dropout_v - 0.05 };
+                let step = if from < 5.0 { 0.1 } else { 0.5 };
+                let volts = self.dropout_v + if up { step } else { -step };
+                // in whole steps, repeated adds drift
+                self.dropout_v = ((volts / step + 0.5) as i32 as f32 * step).clamp(0.0, MAX_DROPOUT_V);
+            }
+        }
+    }
+
+    /// Line of the quality page for field.
+    pub fn text(&self, field: QualityField) -> String<32> {
+        let mut text = String::new();
+        let _ = match field {
+            QualityField::Window => write!(text, "Window  {} ms", self.window_ms),
+            QualityField::Dropout if self.dropout_v <= 0.0 => write!(text, "Dropout off"),
+            QualityField::Dropout => write!(text, "Dropout < {:.1} V", self.dropout_v),
+        };
+        text
+    }
+}
+
+/// Spread of one quantity over a window.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Ripple {
+    pub mean: f32,
+    pub peak_to_peak: f32,
+    /// RMS without the mean
+    pub ac_rms: f32,
+}
+
+/// Sums relative to the first sample, the mean of a bus voltage is far
+/// bigger than its ripple.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Accumulator {
+    count: u32,
+    offset: f32,
+    sum: f64,
+    sum_squares: f64,
+    low: f32,
+    high: f32,
+}
+
+impl Accumulator {
+    const fn new() -> Self {
+        Accumulator { count: 0, offset: 0.0, sum: 0.0, sum_squares: 0.0, low: f32::MAX, high: f32::MIN }
+    }
+
+    fn add(&mut self, value: f32) {
+        if self.count == 0 {
+            self.offset = value;
+        }
+        let delta = (value - self.offset) as f64;
+        self.count += 1;
+        self.sum += delta;
+        self.sum_squares += delta * delta;
+        self.low = self.low.min(value);
+        self.high = self.high.max(value);
+    }
+
+    fn ripple(&self) -> Ripple {
+        let count = self.count.max(1) as f64;
+        let mean = self.sum / count;
+        let variance = (self.sum_squares / count - mean * mean).max(0.0);
+        Ripple {
+            mean: self.offset + mean as f32,
+            peak_to_peak: if self.count > 0 { self.high - self.low } else { 0.0 },
+            ac_rms: sqrt(variance as f32),
+        }
+    }
+}
+
+/// Ripple of a full window.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Window {
+    /// V
+    pub voltage: Ripple,
+    /// mA
+    pub current: Ripple,
+    pub samples: u32,
+    pub duration_us: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RippleWindow {
+    window_us: u64,
+    start_us: Option<u64>,
+    last_us: u64,
+    voltage: Accumulator,
+    current: Accumulator,
+}
+
+impl RippleWindow {
+    pub fn new(window_us: u64) -> Self {
+        RippleWindow {
+            window_us,
+            start_us: None,
+            last_us: 0,
+            voltage: Accumulator::new(),
+            current: Accumulator::new(),
+        }
+    }
+
+    /// A sample taken at timestamp_us, the window once it is full. A gap
+    /// longer than MAX_GAP_US starts the window over.
+    pub fn add(&mut self, reading: &PowerMonitor, timestamp_us: u64) -> Option<Window> {
+        let start_us = match self.start_us {
+            Some(start_us) if timestamp_us.saturating_sub(self.last_us) <= MAX_GAP_US => start_us,
+            _ => {
+                *self = RippleWindow::new(self.window_us);
+                self.start_us = Some(timestamp_us);
+                timestamp_us
+            }
+        };
+        self.last_us = timestamp_us;
+        self.voltage.add(reading.voltage);
+        self.current.add(reading.current);
+        let duration_us = timestamp_us - start_us;
+        if duration_us < self.window_us || self.voltage.count < 2 {
+            return None;
+        }
+        let window = Window {
+            voltage: self.voltage.ripple(),
+            current: self.current.ripple(),
+            samples: self.voltage.count,
+            duration_us,
+        };
+        // the next window starts where this one ended
+        self.voltage = Accumulator::new();
+        self.current = Accumulator::new();
+        self.start_us = Some(timestamp_us);
+        Some(window)
+    }
+}
+
+/// A dip of the bus voltage below the threshold.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Dropout {
+    /// timestamp of the first sample below
+    pub start_us: u64,
+    pub duration_us: u64,
+    /// V, lowest sample of the dip
+    pub lowest_v: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DropoutDetector {
+    threshold_v: f32,
+    /// start and lowest voltage of the dip going on
+    dip: Option<(u64, f32)>,
+    count: u32,
+    last: Option<Dropout>,
+}
+
+impl DropoutDetector {
+    pub fn new(threshold_v: f32) -> Self {
+        DropoutDetector { threshold_v, ..Default::default() }
+    }
+
+    /// A new threshold ends a dip going on without counting it.
+    pub fn set_threshold(&mut self, threshold_v: f32) {
+        if threshold_v != self.threshold_v {
+            self.threshold_v = threshold_v;
+            self.dip = None;
+        }
+    }
+
+    /// A sample of the bus voltage taken at timestamp_us, the dip once the
+    /// voltage is back.
+    pub fn add(&mut self, voltage: f32, timestamp_us: u64) -> Option<Dropout> {
+        if self.threshold_v <= 0.0 {
+            return None;
+        }
+        if voltage < self.threshold_v {
+            let (_, lowest) = self.dip.get_or_insert((timestamp_us, voltage));
+            *lowest = lowest.min(voltage);
+            return None;
+        }
+        let (start_us, lowest_v) = self.dip.take()?;
+        let dropout = Dropout { start_us, duration_us: timestamp_us.saturating_sub(start_us), lowest_v };
+        self.count += 1;
+        self.last = Some(dropout);
+        Some(dropout)
+    }
+
+    /// The voltage is below the threshold right now.
+    pub fn dipping(&self) -> bool {
+        self.dip.is_some()
+    }
+
+    /// Dropouts since boot.
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    pub fn last(&self) -> Option<Dropout> {
+        self.last
+    }
+}
+
+/// What the quality page shows.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Quality {
+    /// the last full window
+    pub window: Option<Window>,
+    pub dropouts: u32,
+    pub last_dropout: Option<Dropout>,
+    pub dipping: bool,
+}
+
+/// Lines of the quality page above the settings.
+pub const QUALITY_LINES: usize = 4;
+
+impl Quality {
+    pub fn new(window: Option<Window>, detector: &DropoutDetector) -> Self {
+        Quality {
+            window,
+            dropouts: detector.count(),
+            last_dropout: detector.last(),
+            dipping: detector.dipping(),
+        }
+    }
+
+    /// "V pp 12.3 rms 3.4 mV", "I pp 1.234 rms 0.321 mA", the dropouts
+    /// and the last one.
+    pub fn lines(&self) -> [String<32>; QUALITY_LINES] {
+        let mut lines: [String<32>; QUALITY_LINES] = Default::default();
+        match &self.window {
+            Some(window) => {
+                let _ = write!(lines[0], "V pp {:.1} rms {:.1} mV", window.voltage.peak_to_peak * 1000.0,
+                               window.voltage.ac_rms * 1000.0);
+                let _ = lines[1].push_str("I pp ");
+                format_ma(&mut lines[1], window.current.peak_to_peak);
+                let _ = lines[1].push_str(" rms ");
+                format_ma(&mut lines[1], window.current.ac_rms);
+                let _ = lines[1].push_str(" mA");
+            }
+            None => {
+                let _ = lines[0].push_str("V ---");
+                let _ = lines[1].push_str("I ---");
+            }
+        }
+        let _ = write!(lines[2], "Dropouts {}", self.dropouts);
+        if self.dipping {
+            let _ = lines[2].push_str(" now");
+        }
+        match &self.last_dropout {
+            Some(dropout) => {
+                let _ = lines[3].push_str("at ");
+                let _ = lines[3].push_str(&format_uptime(dropout.start_us));
+                let _ = write!(lines[3], " {}ms {:.2}V", dropout.duration_us / 1000, dropout.lowest_v);
+            }
+            None => {
+                let _ = lines[3].push_str("at ---");
+            }
+        }
+        lines
+    }
+}
+
+/// Four digits at most up to 1 A.
+fn format_ma(text: &mut String<32>, ma: f32) {
+    let _ = if ma < 10.0 {
+        write!(text, "{:.3}", ma)
+    } else if ma < 100.0 {
+        write!(text, "{:.2}", ma)
+    } else {
+        write!(text, "{:.1}", ma)
+    };
+}
+
+/// Time since boot, "1:02:03".
+pub fn format_uptime(timestamp_us: u64) -> String<32> {
+    let seconds = timestamp_us / 1_000_000;
+    let mut text = String::new();
+    let _ = write!(text, "{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60);
+    text
+}
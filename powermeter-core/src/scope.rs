@@ -0,0 +1,640 @@
+// Triggered waveform
+//
+// A scope on top of fast sampling of the primary channel. Samples are
+// averaged into the columns of the display as they come in, one column is
+// COLUMNS_PER_DIVISION-th of a division, so a trace never holds more than a
+// screen however long the timebase is. Columns without a sample, at the
+// fastest timebases, stay empty and the line is drawn across them.
+//
+// The trigger is looked for between two samples once the columns before it
+// on the screen are recorded. Column boundaries run on from when the scope
+// was armed, the trigger sits up to a column off its marker. In auto mode a
+// trace is taken without a trigger once none came for a screen, at least
+// AUTO_MIN_US, in normal mode the scope waits. Single stops after a trace
+// until armed again.
+
+use core::fmt::Write;
+
+use embedded_graphics::draw_target::DrawTarget;
+use embedded_graphics::geometry::Point;
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::pixelcolor::{Rgb565, RgbColor};
+use embedded_graphics::primitives::{Line, PointsIter, Primitive, PrimitiveStyle, Triangle};
+use embedded_graphics::text::Alignment;
+use embedded_graphics::{Drawable, Pixel};
+use enum_iterator::Sequence;
+use heapless::String;
+
+use crate::display::{create_point, create_point_from, display_text, selected_style, top_style, DISPLAY_SIZE};
+use crate::drivers::sensor::PowerMonitor;
+
+/// one per pixel of the display width
+pub const COLUMNS: usize = DISPLAY_SIZE.width as usize;
+pub const DIVISIONS: usize = 10;
+pub const COLUMNS_PER_DIVISION: usize = COLUMNS / DIVISIONS;
+/// shortest wait for a trigger in auto mode
+pub const AUTO_MIN_US: u64 = 100_000;
+/// columns a cursor moves per press
+pub const CURSOR_STEP: usize = COLUMNS_PER_DIVISION / 4;
+
+// the plot between the settings line at the top and the cursors at the bottom
+const PLOT_TOP: i32 = 17;
+const PLOT_HEIGHT: i32 = 100;
+const PLOT_ROWS: i32 = 4;
+const GRID: Rgb565 = Rgb565::new(8, 16, 8);
+const TRACE: Rgb565 = Rgb565::YELLOW;
+const LEVEL: Rgb565 = Rgb565::RED;
+const CURSOR: Rgb565 = Rgb565::CYAN;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Sequence)]
+pub enum Timebase {
+    Ms1,
+    Ms2,
+    Ms5,
+    #[default]
+    Ms10,
+    Ms20,
+    Ms50,
+    Ms100,
+    Ms200,
+    Ms500,
+    S1,
+}
+
+impl Timebase {
+    pub fn us_per_division(&self) -> u64 {
+        match self {
+            Timebase::Ms1 => 1_000,
+            Timebase::Ms2 => 2_000,
+            Timebase::Ms5 => 5_000,
+            Timebase::Ms10 => 10_000,
+            Timebase::Ms20 => 20_000,
+            Timebase::Ms50 => 50_000,
+            Timebase::Ms100 => 100_000,
+            Timebase::Ms200 => 200_000,
+            Timebase::Ms500 => 500_000,
+            Timebase::S1 => 1_000_000,
+        }
+    }
+
+    pub fn text(&self) -> &'static str {
+        match self {
+            Timebase::Ms1 => "1 ms/div",
+            Timebase::Ms2 => "2 ms/div",
+            Timebase::Ms5 => "5 ms/div",
+            Timebase::Ms10 => "10 ms/div",
+            Timebase::Ms20 => "20 ms/div",
+            Timebase::Ms50 => "50 ms/div",
+            Timebase::Ms100 => "100 ms/div",
+            Timebase::Ms200 => "200 ms/div",
+            Timebase::Ms500 => "500 ms/div",
+            Timebase::S1 => "1 s/div",
+        }
+    }
+
+    /// us from the start of column 0 to the start of column.
+    pub fn column_us(&self, column: i64) -> i64 {
+        column * self.us_per_division() as i64 / COLUMNS_PER_DIVISION as i64
+    }
+}
+
+/// What the trigger and the trace follow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Sequence)]
+pub enum Source {
+    #[default]
+    Current,
+    Voltage,
+}
+
+impl Source {
+    pub fn text(&self) -> &'static str {
+        match self {
+            Source::Current => "current",
+            Source::Voltage => "voltage",
+        }
+    }
+
+    pub fn unit(&self) -> &'static str {
+        match self {
+            Source::Current => "mA",
+            Source::Voltage => "V",
+        }
+    }
+
+    pub fn value(&self, reading: &PowerMonitor) -> f32 {
+        match self {
+            Source::Current => reading.current,
+            Source::Voltage => reading.voltage,
+        }
+    }
+
+    fn default_level(&self) -> f32 {
+        match self {
+            Source::Current => 10.0,
+            Source::Voltage => 3.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Sequence)]
+pub enum Edge {
+    #[default]
+    Rising,
+    Falling,
+}
+
+impl Edge {
+    pub fn text(&self) -> &'static str {
+        match self {
+            Edge::Rising => "rising",
+            Edge::Falling => "falling",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Sequence)]
+pub enum TriggerMode {
+    #[default]
+    Auto,
+    Normal,
+    Single,
+}
+
+impl TriggerMode {
+    pub fn text(&self) -> &'static str {
+        match self {
+            TriggerMode::Auto => "auto",
+            TriggerMode::Normal => "normal",
+            TriggerMode::Single => "single",
+        }
+    }
+}
+
+/// What the scope page edits, the cursors only change the readout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Sequence)]
+pub enum ScopeField {
+    #[default]
+    Timebase,
+    Level,
+    Edge,
+    Source,
+    Mode,
+    PreTrigger,
+    CursorA,
+    CursorB,
+}
+
+impl ScopeField {
+    pub fn next_wrapping(&self) -> ScopeField {
+        self.next().unwrap_or_default()
+    }
+
+    pub fn is_cursor(&self) -> bool {
+        matches!(self, ScopeField::CursorA | ScopeField::CursorB)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScopeSettings {
+    pub timebase: Timebase,
+    pub source: Source,
+    pub edge: Edge,
+    /// mA or V of the source
+    pub level: f32,
+    pub mode: TriggerMode,
+    /// divisions on the screen before the trigger
+    pub pre_trigger: u8,
+}
+
+impl Default for ScopeSettings {
+    fn default() -> Self {
+        ScopeSettings {
+            timebase: Timebase::default(),
+            source: Source::default(),
+            edge: Edge::default(),
+            level: Source::default().default_level(),
+            mode: TriggerMode::default(),
+            pre_trigger: 2,
+        }
+    }
+}
+
+/// the level moves in steps of the decade it is in
+const LEVEL_STEPS: [f32; 7] = [0.001, 0.01, 0.1, 1.0, 10.0, 100.0, 1000.0];
+
+/// 1, 2 .. 9, 10, 20, down from a decade in the steps below it.
+fn level_step(level: f32, up: bool) -> f32 {
+    let magnitude = if up { level.abs() } else { level.abs() * 0.999 };
+    LEVEL_STEPS.iter().rev().find(|step| **step <= magnitude).copied().unwrap_or(LEVEL_STEPS[0])
+}
+
+impl ScopeSettings {
+    /// The column the trigger is drawn at.
+    pub fn trigger_column(&self) -> usize {
+        self.pre_trigger as usize * COLUMNS_PER_DIVISION
+    }
+
+    /// One step up or down, the timebase and pre-trigger stop at their ends.
+    pub fn adjust(&mut self, field: ScopeField, up: bool) {
+        match field {
+            ScopeField::Timebase => {
+                let timebase = if up { self.timebase.next() } else { self.timebase.previous() };
+                self.timebase = timebase.unwrap_or(self.timebase);
+            }
+            ScopeField::Level => {
+                // away from zero the steps grow
+                let step = level_step(self.level, up == (self.level >= 0.0));
+                let level = if up { self.level + step } else { self.level - step };
+                // steps do not add up exactly, keep the level on them
+                let steps = level / step;
+                let steps = if steps < 0.0 { steps - 0.5 } else { steps + 0.5 } as i32;
+                self.level = steps as f32 * step;
+            }
+            ScopeField::Edge => self.edge = self.edge.next().unwrap_or_default(),
+            ScopeField::Source => {
+                self.source = self.source.next().unwrap_or_default();
+                self.level = self.source.default_level();
+            }
+            ScopeField::Mode => {
+                let mode = if up { self.mode.next() } else { self.mode.previous() };
+                self.mode = mode.unwrap_or(if up { TriggerMode::Auto } else { TriggerMode::Single });
+            }
+            ScopeField::PreTrigger => {
+                self.pre_trigger = if up { (self.pre_trigger + 1).min(DIVISIONS as u8) } else { self.pre_trigger.saturating_sub(1) };
+            }
+            ScopeField::CursorA | ScopeField::CursorB => {}
+        }
+    }
+}
+
+/// Two columns the readout takes the difference of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursors {
+    pub a: usize,
+    pub b: usize,
+}
+
+impl Default for Cursors {
+    fn default() -> Self {
+        Cursors { a: 3 * COLUMNS_PER_DIVISION, b: 7 * COLUMNS_PER_DIVISION }
+    }
+}
+
+impl Cursors {
+    pub fn adjust(&mut self, field: ScopeField, up: bool) {
+        let cursor = match field {
+            ScopeField::CursorA => &mut self.a,
+            ScopeField::CursorB => &mut self.b,
+            _ => return,
+        };
+        *cursor = if up { (*cursor + CURSOR_STEP).min(COLUMNS - 1) } else { cursor.saturating_sub(CURSOR_STEP) };
+    }
+}
+
+/// "850 us", "12.5 ms", "1.20 s"
+pub fn format_time(text: &mut String<32>, us: i64) {
+    let _ = if us.abs() < 1_000 {
+        write!(text, "{} us", us)
+    } else if us.abs() < 1_000_000 {
+        write!(text, "{:.1} ms", us as f32 / 1e3)
+    } else {
+        write!(text, "{:.2} s", us as f32 / 1e6)
+    };
+}
+
+/// Three significant digits, the level moves in steps of the third.
+fn format_level(text: &mut String<32>, level: f32) {
+    let magnitude = level.abs();
+    let _ = if magnitude < 1.0 {
+        write!(text, "{:.3}", level)
+    } else if magnitude < 10.0 {
+        write!(text, "{:.2}", level)
+    } else if magnitude < 100.0 {
+        write!(text, "{:.1}", level)
+    } else {
+        write!(text, "{:.0}", level)
+    };
+}
+
+/// Line of the scope page for field.
+pub fn field_text(settings: &ScopeSettings, cursors: &Cursors, field: ScopeField) -> String<32> {
+    let mut text = String::new();
+    let cursor = |text: &mut String<32>, name: &str, column: usize| {
+        let _ = write!(text, "Cursor {} ", name);
+        format_time(text, settings.timebase.column_us(column as i64 - settings.trigger_column() as i64));
+    };
+    match field {
+        ScopeField::Timebase => {
+            let _ = text.push_str(settings.timebase.text());
+        }
+        ScopeField::Level => {
+            let _ = text.push_str("Level ");
+            format_level(&mut text, settings.level);
+            let _ = write!(text, " {}", settings.source.unit());
+        }
+        ScopeField::Edge => {
+            let _ = write!(text, "Edge {}", settings.edge.text());
+        }
+        ScopeField::Source => {
+            let _ = write!(text, "Trigger on {}", settings.source.text());
+        }
+        ScopeField::Mode => {
+            let _ = write!(text, "Mode {}", settings.mode.text());
+        }
+        ScopeField::PreTrigger => {
+            let _ = write!(text, "Pre-trigger {} div", settings.pre_trigger);
+        }
+        ScopeField::CursorA => cursor(&mut text, "A", cursors.a),
+        ScopeField::CursorB => cursor(&mut text, "B", cursors.b),
+    }
+    text
+}
+
+/// Mean of the samples of one column.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Column {
+    /// mA
+    pub current: f32,
+    /// V
+    pub voltage: f32,
+}
+
+impl Column {
+    pub fn value(&self, source: Source) -> f32 {
+        match source {
+            Source::Current => self.current,
+            Source::Voltage => self.voltage,
+        }
+    }
+}
+
+/// One screen of columns around a trigger.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trace {
+    pub columns: [Option<Column>; COLUMNS],
+    pub timebase: Timebase,
+    pub trigger_column: usize,
+    /// false when auto mode took it without a trigger
+    pub triggered: bool,
+}
+
+impl Trace {
+    /// us from cursor a to b and the change of the current between them,
+    /// None where a cursor is on an empty column.
+    pub fn delta(&self, cursors: &Cursors) -> (i64, Option<f32>) {
+        let us = self.timebase.column_us(cursors.b as i64 - cursors.a as i64);
+        let current = |column: usize| self.columns.get(column).copied().flatten().map(|column| column.current);
+        let di = match (current(cursors.a), current(cursors.b)) {
+            (Some(a), Some(b)) => Some(b - a),
+            _ => None,
+        };
+        (us, di)
+    }
+
+    /// Lowest and highest value of source, None without a column.
+    pub fn range(&self, source: Source) -> Option<(f32, f32)> {
+        self.columns.iter().flatten().map(|column| column.value(source))
+            .fold(None, |range, value| match range {
+                Some((low, high)) => Some((f32::min(low, value), f32::max(high, value))),
+                None => Some((value, value)),
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    /// since the timestamp in us
+    Armed(u64),
+    /// columns until the trace is complete, whether a trigger started it
+    Triggered { remaining: usize, triggered: bool },
+    Stopped,
+}
+
+pub struct Scope {
+    settings: ScopeSettings,
+    state: State,
+    /// ring of the latest columns, next is written next
+    columns: [Option<Column>; COLUMNS],
+    next: usize,
+    /// columns finished since armed
+    finished: usize,
+    /// timestamp in us column 0 starts at
+    origin_us: Option<u64>,
+    /// number of the column taking samples and their sums
+    column: u64,
+    current_sum: f32,
+    voltage_sum: f32,
+    samples: u32,
+    /// source value of the last sample
+    last: Option<f32>,
+}
+
+impl Scope {
+    pub fn new(settings: ScopeSettings) -> Self {
+        Scope {
+            settings,
+            state: State::Armed(0),
+            columns: [None; COLUMNS],
+            next: 0,
+            finished: 0,
+            origin_us: None,
+            column: 0,
+            current_sum: 0.0,
+            voltage_sum: 0.0,
+            samples: 0,
+            last: None,
+        }
+    }
+
+    pub fn settings(&self) -> &ScopeSettings {
+        &self.settings
+    }
+
+    /// Start over with settings, a stopped single shot is armed again.
+    pub fn set_settings(&mut self, settings: ScopeSettings) {
+        *self = Scope::new(settings);
+    }
+
+    /// Waiting for a trigger or taking a trace.
+    pub fn is_running(&self) -> bool {
+        self.state != State::Stopped
+    }
+
+    fn finish_column(&mut self) {
+        self.columns[self.next] = (self.samples > 0).then(|| Column {
+            current: self.current_sum / self.samples as f32,
+            voltage: self.voltage_sum / self.samples as f32,
+        });
+        self.next = (self.next + 1) % COLUMNS;
+        self.finished += 1;
+        self.column += 1;
+        self.current_sum = 0.0;
+        self.voltage_sum = 0.0;
+        self.samples = 0;
+    }
+
+    /// The last COLUMNS columns, the oldest first.
+    fn trace(&self, triggered: bool) -> Trace {
+        let mut columns = [None; COLUMNS];
+        for (i, column) in columns.iter_mut().enumerate() {
+            *column = self.columns[(self.next + i) % COLUMNS];
+        }
+        Trace {
+            columns,
+            timebase: self.settings.timebase,
+            trigger_column: self.settings.trigger_column(),
+            triggered,
+        }
+    }
+
+    /// A sample taken at timestamp_us, a trace when it completed one.
+    pub fn add(&mut self, reading: &PowerMonitor, timestamp_us: u64) -> Option<Trace> {
+        if self.state == State::Stopped {
+            return None;
+        }
+        let origin_us = match self.origin_us {
+            Some(origin_us) => origin_us,
+            None => {
+                self.origin_us = Some(timestamp_us);
+                self.state = State::Armed(timestamp_us);
+                timestamp_us
+            }
+        };
+        let column = timestamp_us.saturating_sub(origin_us) * COLUMNS_PER_DIVISION as u64
+            / self.settings.timebase.us_per_division();
+        // after a gap longer than the screen every column is empty anyway
+        self.column = self.column.max(column.saturating_sub(COLUMNS as u64));
+
+        let mut trace = None;
+        while self.column < column && self.state != State::Stopped {
+            self.finish_column();
+            if let State::Triggered { remaining, triggered } = self.state {
+                if remaining > 1 {
+                    self.state = State::Triggered { remaining: remaining - 1, triggered };
+                    continue;
+                }
+                trace = Some(self.trace(triggered));
+                self.finished = 0;
+                self.state = match self.settings.mode {
+                    TriggerMode::Single => State::Stopped,
+                    _ => State::Armed(timestamp_us),
+                };
+            }
+        }
+        if self.state == State::Stopped {
+            return trace;
+        }
+
+        self.current_sum += reading.current;
+        self.voltage_sum += reading.voltage;
+        self.samples += 1;
+
+        let level = self.settings.level;
+        let value = self.settings.source.value(reading);
+        let crossed = match (self.last, self.settings.edge) {
+            (Some(last), Edge::Rising) => last < level && value >= level,
+            (Some(last), Edge::Falling) => last > level && value <= level,
+            (None, _) => false,
+        };
+        self.last = Some(value);
+        if let State::Armed(since_us) = self.state {
+            let pre_trigger = self.settings.trigger_column();
+            let screen_us = self.settings.timebase.us_per_division() * DIVISIONS as u64;
+            let timed_out = self.settings.mode == TriggerMode::Auto
+                && timestamp_us.saturating_sub(since_us) >= screen_us.max(AUTO_MIN_US);
+            if self.finished >= pre_trigger && (crossed || timed_out) {
+                // the column going on is the first after the trigger
+                self.state = State::Triggered { remaining: COLUMNS - pre_trigger, triggered: crossed };
+            }
+        }
+        trace
+    }
+}
+
+fn draw_line<D>(display: &mut D, from: Point, to: Point, color: Rgb565) where D: DrawTarget<Color=Rgb565> {
+    let _ = Line::new(create_point_from(from), create_point_from(to))
+        .into_styled(PrimitiveStyle::with_stroke(color, 1))
+        .draw(display);
+}
+
+/// Every step-th pixel of a vertical or horizontal line.
+fn draw_dotted<D>(display: &mut D, from: Point, to: Point, step: usize, color: Rgb565) where D: DrawTarget<Color=Rgb565> {
+    let pixels = Line::new(from, to).points().step_by(step).map(|point| Pixel(create_point_from(point), color));
+    let _ = display.draw_iter(pixels);
+}
+
+/// The page with the latest trace, field inverted at the top.
+pub fn draw_scope<D>(display: &mut D, trace: Option<&Trace>, settings: &ScopeSettings, cursors: &Cursors, field: ScopeField,
+                     character_style: MonoTextStyle<Rgb565>) where D: DrawTarget<Color=Rgb565> {
+    let _ = display.clear(Rgb565::BLACK);
+    let right = COLUMNS as i32 - 1;
+    let bottom = PLOT_TOP + PLOT_HEIGHT;
+
+    display_text(display, create_point(2, 0), selected_style(character_style, Rgb565::BLACK), top_style(Alignment::Left), &field_text(settings, cursors, field));
+    let state = match trace {
+        None => "WAIT",
+        Some(_) if settings.mode == TriggerMode::Single => "STOP",
+        Some(trace) if trace.triggered => "TRIG",
+        Some(_) => "AUTO",
+    };
+    display_text(display, create_point(right, 0), character_style, top_style(Alignment::Right), state);
+
+    for division in 0..=DIVISIONS as i32 {
+        let x = (division * COLUMNS_PER_DIVISION as i32).min(right);
+        draw_dotted(display, Point::new(x, PLOT_TOP), Point::new(x, bottom), 5, GRID);
+    }
+    for row in 0..=PLOT_ROWS {
+        let y = PLOT_TOP + row * PLOT_HEIGHT / PLOT_ROWS;
+        draw_dotted(display, Point::new(0, y), Point::new(right, y), 6, GRID);
+    }
+
+    let trigger_x = trace.map(|trace| trace.trigger_column).unwrap_or(settings.trigger_column()) as i32;
+    let _ = Triangle::new(create_point(trigger_x - 4, PLOT_TOP), create_point(trigger_x + 4, PLOT_TOP),
+                          create_point(trigger_x, PLOT_TOP + 6))
+        .into_styled(PrimitiveStyle::with_fill(LEVEL))
+        .draw(display);
+
+    // the level stays on the screen
+    let level = settings.level;
+    let (mut low, mut high) = trace.and_then(|trace| trace.range(settings.source)).unwrap_or((level, level));
+    low = low.min(level);
+    high = high.max(level);
+    let margin = ((high - low) * 0.05).max(high.abs().max(low.abs()) * 0.05).max(0.001);
+    low -= margin;
+    high += margin;
+    let y = |value: f32| bottom - ((value - low) / (high - low) * PLOT_HEIGHT as f32) as i32;
+    draw_dotted(display, Point::new(0, y(level)), Point::new(right, y(level)), 3, LEVEL);
+
+    if let Some(trace) = trace {
+        let mut last: Option<Point> = None;
+        for (x, column) in trace.columns.iter().enumerate() {
+            let Some(column) = column else {
+                continue;
+            };
+            let point = Point::new(x as i32, y(column.value(settings.source)));
+            draw_line(display, last.unwrap_or(point), point, TRACE);
+            last = Some(point);
+        }
+    }
+
+    for cursor in [cursors.a, cursors.b] {
+        draw_dotted(display, Point::new(cursor as i32, PLOT_TOP), Point::new(cursor as i32, bottom), 2, CURSOR);
+    }
+    // a trace keeps the timebase it was taken with
+    let (us, di) = match trace {
+        Some(trace) => trace.delta(cursors),
+        None => (settings.timebase.column_us(cursors.b as i64 - cursors.a as i64), None),
+    };
+    let mut readout: String<32> = String::new();
+    let _ = readout.push_str("dt ");
+    format_time(&mut readout, us);
+    match di {
+        Some(di) => {
+            let _ = write!(readout, "  dI {:.3} mA", di);
+        }
+        None => {
+            let _ = readout.push_str("  dI ---");
+        }
+    }
+    display_text(display, create_point(2, bottom + 2), character_style, top_style(Alignment::Left), &readout);
+}
@@ -0,0 +1,269 @@
+// Spectrum of the current
+//
+// handle_power captures a burst of FFT_SIZE currents of the primary channel
+// as fast as the sensor converts, a few kHz at most, into a buffer on the
+// PSRAM heap. The ripple is taken from the burst as it is, the spectrum
+// from the burst without its mean under a Hann window. A sensor averages
+// over its conversion, a switching regulator at hundreds of kHz still adds
+// to the ripple but its frequency folds down below the Nyquist frequency,
+// periodic load patterns slower than that show where they are.
+//
+// There is no libm, sine and cosine of the twiddles are series in f64 and
+// the square root is a few Newton steps.
+
+use core::f64::consts::PI;
+use core::fmt::Write;
+
+use embedded_graphics::draw_target::DrawTarget;
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::pixelcolor::{Rgb565, RgbColor};
+use embedded_graphics::primitives::{Line, Primitive, PrimitiveStyle};
+use embedded_graphics::text::Alignment;
+use embedded_graphics::Drawable;
+use heapless::{String, Vec};
+
+use crate::display::{create_point, display_text, format_current, selected_style, top_style, DISPLAY_SIZE};
+
+/// samples of a burst, a power of two
+pub const FFT_SIZE: usize = 512;
+/// DC to the Nyquist frequency
+pub const BINS: usize = FFT_SIZE / 2 + 1;
+/// dominant frequencies that are labelled
+pub const PEAKS: usize = 3;
+/// fraction of the strongest peak a weaker one needs to be labelled
+pub const PEAK_FLOOR: f32 = 0.1;
+
+// the bars between the selected peak at the top and the ripple at the bottom
+const PLOT_TOP: i32 = 17;
+const PLOT_HEIGHT: i32 = 100;
+const BAR: Rgb565 = Rgb565::YELLOW;
+const PEAK: Rgb565 = Rgb565::CYAN;
+
+/// Sine and cosine of x, exact to f32 for |x| up to 2 pi.
+fn sin_cos(x: f64) -> (f64, f64) {
+    // back to -pi..pi where the series converges quickly
+    let turns = x / (2.0 * PI);
+    let x = x - (if turns < 0.0 { turns - 0.5 } else { turns + 0.5 } as i64) as f64 * 2.0 * PI;
+    let (mut sin, mut cos) = (0.0, 0.0);
+    let (mut sin_term, mut cos_term) = (x, 1.0);
+    for n in 1..16 {
+        sin += sin_term;
+        cos += cos_term;
+        let n = n as f64;
+        sin_term *= -x * x / ((2.0 * n) * (2.0 * n + 1.0));
+        cos_term *= -x * x / ((2.0 * n - 1.0) * (2.0 * n));
+    }
+    (sin, cos)
+}
+
+/// Square root, 0 for anything not positive.
+pub fn sqrt(x: f32) -> f32 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    // half the exponent is a guess within a few percent
+    let mut root = f32::from_bits((x.to_bits() >> 1) + 0x1fbd_1df5);
+    for _ in 0..3 {
+        root = 0.5 * (root + x / root);
+    }
+    root
+}
+
+/// Hann window weight of sample i of n.
+pub fn hann(i: usize, n: usize) -> f32 {
+    let (_, cos) = sin_cos(2.0 * PI * i as f64 / n as f64);
+    (0.5 - 0.5 * cos) as f32
+}
+
+/// In place radix 2 FFT, both have a length that is a power of two.
+pub fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    assert!(n.is_power_of_two() && im.len() == n);
+    let bits = n.trailing_zeros();
+    for i in 0..n {
+        let j = i.reverse_bits() >> (usize::BITS - bits);
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let half = len / 2;
+        for k in 0..half {
+            let (sin, cos) = sin_cos(-2.0 * PI * k as f64 / len as f64);
+            let (w_re, w_im) = (cos as f32, sin as f32);
+            for start in (0..n).step_by(len) {
+                let (a, b) = (start + k, start + k + half);
+                let t_re = re[b] * w_re - im[b] * w_im;
+                let t_im = re[b] * w_im + im[b] * w_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len *= 2;
+    }
+}
+
+/// A dominant frequency of the spectrum.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Peak {
+    pub bin: usize,
+    /// between the bins around it
+    pub frequency_hz: f32,
+    /// mA, of the sine at that frequency
+    pub amplitude: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spectrum {
+    /// mA, amplitude of a sine in each bin from DC on
+    pub magnitudes: [f32; BINS],
+    pub bin_hz: f32,
+    /// strongest first
+    pub peaks: Vec<Peak, PEAKS>,
+    /// mA of the burst
+    pub mean: f32,
+    pub peak_to_peak: f32,
+    /// mA, RMS of the burst without its mean
+    pub rms: f32,
+}
+
+impl Spectrum {
+    /// Spectrum of the first FFT_SIZE samples, None with fewer.
+    pub fn analyze(samples: &[f32], sample_rate_hz: f32) -> Option<Spectrum> {
+        let samples = samples.get(..FFT_SIZE)?;
+        let mean = samples.iter().sum::<f32>() / FFT_SIZE as f32;
+        let (low, high) = samples.iter().fold((f32::MAX, f32::MIN), |(low, high), sample| (low.min(*sample), high.max(*sample)));
+        let variance = samples.iter().map(|sample| (sample - mean) * (sample - mean)).sum::<f32>() / FFT_SIZE as f32;
+
+        let mut re = [0.0; FFT_SIZE];
+        let mut im = [0.0; FFT_SIZE];
+        let mut window_sum = 0.0;
+        for (i, (re, sample)) in re.iter_mut().zip(samples).enumerate() {
+            let weight = hann(i, FFT_SIZE);
+            *re = (sample - mean) * weight;
+            window_sum += weight;
+        }
+        fft(&mut re, &mut im);
+        let mut magnitudes = [0.0; BINS];
+        for (bin, magnitude) in magnitudes.iter_mut().enumerate() {
+            // both halves of the spectrum
+            *magnitude = 2.0 * sqrt(re[bin] * re[bin] + im[bin] * im[bin]) / window_sum;
+        }
+
+        let bin_hz = sample_rate_hz / FFT_SIZE as f32;
+        let mut candidates: Vec<Peak, BINS> = Vec::new();
+        for bin in 1..BINS - 1 {
+            let (left, peak, right) = (magnitudes[bin - 1], magnitudes[bin], magnitudes[bin + 1]);
+            if peak <= left || peak < right {
+                continue;
+            }
+            // a parabola through the bins finds the top between them
+            let curvature = left - 2.0 * peak + right;
+            let offset = if curvature < 0.0 { 0.5 * (left - right) / curvature } else { 0.0 };
+            let _ = candidates.push(Peak {
+                bin,
+                frequency_hz: (bin as f32 + offset) * bin_hz,
+                amplitude: peak - 0.25 * (left - right) * offset,
+            });
+        }
+        candidates.sort_unstable_by(|a, b| b.amplitude.total_cmp(&a.amplitude));
+        let strongest = candidates.first().map(|peak| peak.amplitude).unwrap_or(0.0);
+        let peaks = candidates.iter()
+            .take_while(|peak| peak.amplitude > strongest * PEAK_FLOOR && peak.amplitude > 0.0)
+            .take(PEAKS)
+            .copied()
+            .collect();
+
+        Some(Spectrum {
+            magnitudes,
+            bin_hz,
+            peaks,
+            mean,
+            peak_to_peak: high - low,
+            rms: sqrt(variance),
+        })
+    }
+
+    pub fn nyquist_hz(&self) -> f32 {
+        self.bin_hz * (BINS - 1) as f32
+    }
+}
+
+/// "50 Hz", "1.25 kHz"
+pub fn format_frequency(text: &mut String<32>, hz: f32) {
+    let _ = if hz < 1000.0 { write!(text, "{:.0} Hz", hz) } else { write!(text, "{:.2} kHz", hz / 1000.0) };
+}
+
+/// "1 50 Hz 1.23 mA", peak is counted from 0.
+pub fn peak_text(spectrum: &Spectrum, peak: usize) -> String<32> {
+    let mut text = String::new();
+    match spectrum.peaks.get(peak) {
+        Some(found) => {
+            let _ = write!(text, "{} ", peak + 1);
+            format_frequency(&mut text, found.frequency_hz);
+            let _ = text.push(' ');
+            format_current(&mut text, found.amplitude);
+        }
+        None => {
+            let _ = text.push_str("no peak");
+        }
+    }
+    text
+}
+
+/// "pp 2.35 rms 0.812 mA"
+pub fn ripple_text(spectrum: &Spectrum) -> String<32> {
+    let mut text = String::new();
+    let _ = write!(text, "pp {:.3} rms {:.3} mA", spectrum.peak_to_peak, spectrum.rms);
+    text
+}
+
+/// Magnitudes from the first bin over DC to the Nyquist frequency across
+/// the display, the strongest peak fills the plot. The peaks are numbered,
+/// the selected one has its frequency at the top.
+pub fn draw_spectrum<D>(display: &mut D, spectrum: Option<&Spectrum>, selected: usize, character_style: MonoTextStyle<Rgb565>)
+    where D: DrawTarget<Color=Rgb565> {
+    let _ = display.clear(Rgb565::BLACK);
+    let Some(spectrum) = spectrum else {
+        display_text(display, create_point(2, 0), character_style, top_style(Alignment::Left), "Capturing...");
+        return;
+    };
+    display_text(display, create_point(2, 0), selected_style(character_style, Rgb565::BLACK), top_style(Alignment::Left), &peak_text(spectrum, selected));
+
+    let width = DISPLAY_SIZE.width as i32;
+    let bottom = PLOT_TOP + PLOT_HEIGHT;
+    let highest = spectrum.magnitudes[1..].iter().fold(0.0, |highest: f32, magnitude| highest.max(*magnitude));
+    let height = |magnitude: f32| if highest > 0.0 { (magnitude / highest * PLOT_HEIGHT as f32) as i32 } else { 0 };
+    let x = |bin: usize| ((bin - 1) as i32 * (width - 1)) / (BINS - 2) as i32;
+    // bins sharing a column show the highest of them
+    let mut column_top = [bottom; DISPLAY_SIZE.width as usize];
+    for bin in 1..BINS {
+        let top = &mut column_top[x(bin) as usize];
+        *top = (*top).min(bottom - height(spectrum.magnitudes[bin]));
+    }
+    for (x, top) in column_top.iter().enumerate() {
+        let _ = Line::new(create_point(x as i32, *top), create_point(x as i32, bottom))
+            .into_styled(PrimitiveStyle::with_stroke(BAR, 1))
+            .draw(display);
+    }
+    for (number, peak) in spectrum.peaks.iter().enumerate() {
+        let color = if number == selected { Rgb565::WHITE } else { PEAK };
+        let x = x(peak.bin);
+        let top = column_top[x as usize];
+        let _ = Line::new(create_point(x, top), create_point(x, bottom))
+            .into_styled(PrimitiveStyle::with_stroke(color, 1))
+            .draw(display);
+        let mut label: String<32> = String::new();
+        let _ = write!(label, "{}", number + 1);
+        let mut style = character_style;
+        style.text_color = Some(color);
+        let y = (top - character_style.font.character_size.height as i32 - 1).max(PLOT_TOP);
+        let x = x.clamp(6, width - 6);
+        display_text(display, create_point(x, y), style, top_style(Alignment::Center), &label);
+    }
+    display_text(display, create_point(2, bottom + 2), character_style, top_style(Alignment::Left), &ripple_text(spectrum));
+}
@@ -0,0 +1,102 @@
+// Sensor bus supervision
+//
+// Every sensor has its own supervisor counting transfers that failed in a
+// row. A few of them mean the sensor is gone or a device holds SDA low, and
+// recovery escalates: clock the stuck transfer out, then switch the sensor
+// rail off and on with a growing pause between the attempts. After every
+// step the sensor is probed and initialised again.
+
+/// failed transfers in a row before recovering
+pub const ERROR_LIMIT: u8 = 3;
+/// ms, pause before the first power cycle, doubled for every next one
+pub const BACKOFF_MS: u32 = 250;
+pub const MAX_BACKOFF_MS: u32 = 8000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BusStatus {
+    #[default]
+    Ok,
+    /// the bus was clocked free
+    Recovering,
+    /// the sensor rail was switched off and on
+    PowerCycled,
+    /// still no answer after a power cycle
+    Lost,
+}
+
+impl BusStatus {
+    pub fn text(&self) -> &'static str {
+        match self {
+            BusStatus::Ok => "Ok",
+            BusStatus::Recovering => "Bus recovery",
+            BusStatus::PowerCycled => "Power cycle",
+            BusStatus::Lost => "Sensor lost",
+        }
+    }
+
+    /// Stored with sensor error events.
+    pub fn code(&self) -> u8 {
+        *self as u8
+    }
+
+    pub fn from_code(code: u8) -> Option<BusStatus> {
+        [BusStatus::Ok, BusStatus::Recovering, BusStatus::PowerCycled, BusStatus::Lost].get(code as usize).copied()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recovery {
+    /// send clock pulses until SDA is released
+    ClockOut,
+    /// wait wait_ms, then switch i2c_power off and on
+    PowerCycle { wait_ms: u32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Supervisor {
+    errors: u8,
+    attempts: u8,
+}
+
+impl Supervisor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A transfer worked, true when the sensor is back after a recovery.
+    pub fn success(&mut self) -> bool {
+        let recovered = self.attempts > 0;
+        *self = Supervisor::default();
+        recovered
+    }
+
+    /// A transfer failed, what to do about it.
+    pub fn error(&mut self) -> Option<Recovery> {
+        self.errors = self.errors.saturating_add(1);
+        if self.errors < ERROR_LIMIT {
+            return None;
+        }
+        self.errors = 0;
+        let attempt = self.attempts;
+        self.attempts = self.attempts.saturating_add(1);
+        if attempt == 0 {
+            return Some(Recovery::ClockOut);
+        }
+        // 8000 ms after five doublings
+        let wait_ms = (BACKOFF_MS << (attempt - 1).min(5)).min(MAX_BACKOFF_MS);
+        Some(Recovery::PowerCycle { wait_ms })
+    }
+
+    pub fn status(&self) -> BusStatus {
+        match self.attempts {
+            0 => BusStatus::Ok,
+            1 => BusStatus::Recovering,
+            2 => BusStatus::PowerCycled,
+            _ => BusStatus::Lost,
+        }
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.attempts == 0
+    }
+}
@@ -0,0 +1,1291 @@
+use core::fmt::Write;
+
+use embedded_graphics::draw_target::DrawTarget;
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::pixelcolor::Rgb565;
+use embedded_graphics::text::renderer::TextRenderer;
+use embedded_graphics::text::{Alignment, Baseline, TextStyle, TextStyleBuilder};
+use enum_iterator::{all, cardinality, Sequence};
+use heapless::{String, Vec};
+
+use crate::autorange::AutoRange;
+use crate::battery::{self, Average, Battery, Field};
+use crate::channel::{self, Channel, Stats, MAX_CHANNELS};
+use crate::correction::Correction;
+use crate::derived::{self, Derived};
+use crate::display::{create_point, display_text, display_text_with_background, selected_style, top_style, DISPLAY_SIZE};
+use crate::drivers::bus::Device;
+use crate::drivers::ina219::{Calibration, INA219_ADDR};
+use crate::drivers::sensor::{PowerMonitor, Sampling};
+use crate::events::{self, EventLog, MAX_EVENTS};
+use crate::filter::{FilterKind, FilterTarget};
+use crate::histogram::{self, Histogram, BUCKETS};
+use crate::menu::{self, SettingsItem};
+use crate::modifier::{Modified, Modifier};
+use crate::profile::{Profile, PROFILE_LINES};
+use crate::quality::{Quality, QualityField, QualitySettings, QUALITY_LINES};
+use crate::scope::{self, Cursors, ScopeField, ScopeSettings, Trace};
+use crate::spectrum::{self, Spectrum};
+use crate::supervisor::BusStatus;
+use crate::wizard::{self, Outcome, Wizard};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+    /// D0, long press opens the settings menu
+    Select,
+    /// D1
+    Previous,
+    /// D2
+    Next,
+}
+
+// no allocator to box the trace, the input channel holds a single input
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Input {
+    /// a long press of Previous resets the modifier, of Next changes it
+    Button { button: Button, long_press: bool },
+    /// latest reading of the channel with that index filtered for the
+    /// display and as measured for the calibration wizard, the range it was
+    /// taken with, the statistics and what is derived from the reading
+    Reading { channel: u8, reading: PowerMonitor, unfiltered: PowerMonitor, calibration: Calibration, stats: Stats, derived: Derived },
+    /// the bus of the channel with that index failed or recovered
+    Status { channel: u8, status: BusStatus },
+    /// a sensor started answering after boot, its channel gets the next index
+    Channel(Channel),
+    /// shown until the next reading, e.g. from the provisioning portal
+    Message(String<32>),
+    /// latest result of the sleep current profiler
+    Profile(Profile),
+    /// rolling average current of the first channel
+    Battery(Average),
+    /// the scope took a trace
+    Trace(Trace),
+    /// current distribution of the first channel so far
+    Histogram(Histogram),
+    /// spectrum of the latest burst of the first channel
+    Spectrum(Spectrum),
+    /// latest ripple window and dropouts of the first channel
+    Quality(Quality),
+}
+
+/// What the board has to do after an input.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    None,
+    /// program the sensor of the channel with a new range, auto range ends
+    Calibrate { channel: u8, calibration: Calibration },
+    /// let the range of the channel follow the load or stay where it is
+    AutoRange { channel: u8, enabled: bool },
+    /// start conversions of every sensor this way from now on
+    Sampling(Sampling),
+    /// smooth the readings for the display or the sample consumers
+    Filter { target: FilterTarget, kind: FilterKind },
+    /// the calibration wizard finished, store and apply the correction
+    Correct { channel: u8, calibration: Calibration, correction: Correction },
+    /// start or stop the sleep current profiler, it starts over every time
+    Profile(bool),
+    /// the battery page was closed, store the battery
+    Battery(Battery),
+    /// arm the scope with the settings, None stops it
+    Scope(Option<ScopeSettings>),
+    /// start the current distribution over
+    ResetHistogram,
+    /// start or stop capturing bursts for the spectrum
+    Spectrum(bool),
+    /// take the ripple with the settings, None closes the quality page and
+    /// stores them
+    Quality(Option<QualitySettings>),
+    /// a settings item was chosen, the menu is closed
+    Activate(SettingsItem),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Sequence)]
+pub enum PowerDisplay {
+    Voltage,
+    Current,
+    Power,
+    /// of the load, V/I
+    Resistance,
+    Conductance,
+    /// since boot, with its cost in the bottom line
+    Energy,
+    /// with two channels, the first measures the input of a converter and
+    /// the second its output
+    Efficiency,
+}
+
+impl PowerDisplay {
+    pub fn unit(&self) -> &'static str {
+        match self {
+            PowerDisplay::Voltage => "V ",
+            PowerDisplay::Current => "mA",
+            PowerDisplay::Power => "mW",
+            PowerDisplay::Resistance => "R ",
+            PowerDisplay::Conductance => "mS",
+            PowerDisplay::Energy => "Wh",
+            PowerDisplay::Efficiency => "% ",
+        }
+    }
+}
+
+/// What every page shows, by page, None when there is nothing to show.
+pub type PageValues = [Option<f32>; PowerDisplay::CARDINALITY];
+
+/// What the reading screen shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum View {
+    /// index into the channels
+    Channel(usize),
+    /// power of all channels added up
+    Total,
+}
+
+/// Fonts and colors, the seven segment digits are a separate renderer.
+#[derive(Clone)]
+pub struct Theme<'a, S> {
+    pub digits: S,
+    pub large: MonoTextStyle<'a, Rgb565>,
+    pub medium: MonoTextStyle<'a, Rgb565>,
+    pub background: Rgb565,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Screen {
+    /// a new bus status starts over, it is not drawn in digits
+    Reading(View, BusStatus, Modifier),
+    Message,
+    Menu(SettingsItem),
+    Channels(usize),
+    Wizard(wizard::Step),
+    Diagnostics(usize),
+    Events(usize),
+    Profile(usize),
+    Battery(Field),
+    Scope(ScopeField),
+    Histogram(usize),
+    Spectrum(usize),
+    Quality(QualityField),
+}
+
+/// lines of the diagnostics page, the bus and the devices
+const DIAGNOSTIC_LINES: usize = 24;
+
+/// What is up instead of the reading, one at a time.
+// no allocator to box the trace, the ui holds a single page
+#[allow(clippy::large_enum_variant)]
+enum Page {
+    Menu(SettingsItem),
+    /// selection, the entry after the channels is Total
+    Channels(usize),
+    Wizard(Wizard),
+    /// lines, the bus and the devices, and the selected one
+    Diagnostics(Vec<String<32>, DIAGNOSTIC_LINES>, usize),
+    /// lines, the newest event first, and the selected one
+    Events(Vec<String<32>, { MAX_EVENTS + 1 }>, usize),
+    /// latest profile and the selected line
+    Profile(Profile, usize),
+    /// latest average and the field edited
+    Battery(Option<Average>, Field),
+    /// latest trace and the field edited
+    Scope(Option<Trace>, ScopeField),
+    /// latest distribution and the selected bucket
+    Histogram(Histogram, usize),
+    /// latest spectrum and the selected peak
+    Spectrum(Option<Spectrum>, usize),
+    /// latest quality and the field edited
+    Quality(Option<Quality>, QualityField),
+}
+
+pub struct Ui {
+    power_display: PowerDisplay,
+    sampling: Sampling,
+    /// per kWh, 0 when not set
+    energy_price: f32,
+    /// of the device under test
+    battery: Battery,
+    display_filter: FilterKind,
+    log_filter: FilterKind,
+    channels: Vec<Channel, MAX_CHANNELS>,
+    view: View,
+    /// of the channel shown
+    modified: Modified,
+    page: Option<Page>,
+    /// kept for the next time the scope page comes up
+    scope_settings: ScopeSettings,
+    cursors: Cursors,
+    /// ripple window and dropout threshold
+    quality_settings: QualitySettings,
+    message: Option<String<128>>,
+    drawn: Option<Screen>,
+    last_value: String<64>,
+    last_stats: String<32>,
+}
+
+fn center_text_style() -> TextStyle {
+    TextStyleBuilder::new()
+        .alignment(Alignment::Left)
+        .baseline(Baseline::Middle)
+        .build()
+}
+
+/// What the page shows of the reading, without load the measured values
+/// read zero. The derived values and the energy need more than a reading.
+pub fn page_value(power_display: PowerDisplay, reading: &PowerMonitor) -> Option<f32> {
+    let loaded = reading.current != 0.0;
+    match power_display {
+        PowerDisplay::Voltage => Some(if loaded { reading.voltage } else { 0.0 }),
+        PowerDisplay::Current => Some(if loaded { reading.current } else { 0.0 }),
+        PowerDisplay::Power => Some(if loaded { reading.power } else { 0.0 }),
+        PowerDisplay::Resistance | PowerDisplay::Conductance | PowerDisplay::Energy | PowerDisplay::Efficiency => None,
+    }
+}
+
+/// page_value of every page, the resistance and the conductance as derived
+/// from the reading. The efficiency is in %.
+pub fn reading_values(reading: &PowerMonitor, derived: &Derived) -> PageValues {
+    let mut values = PageValues::default();
+    for page in all::<PowerDisplay>() {
+        values[page as usize] = page_value(page, reading);
+    }
+    values[PowerDisplay::Resistance as usize] = derived.resistance;
+    values[PowerDisplay::Conductance as usize] = derived.conductance;
+    values[PowerDisplay::Efficiency as usize] = derived.efficiency.map(|e| e * 100.0);
+    values
+}
+
+/// Value and unit of a page, dashes without a value.
+pub fn format_value(power_display: PowerDisplay, page_value: Option<f32>) -> (String<64>, &'static str) {
+    let mut value = String::new();
+    let Some(page_value) = page_value else {
+        let _ = value.push_str("  ---");
+        return (value, power_display.unit());
+    };
+    let _ = match power_display {
+        PowerDisplay::Voltage => write!(value, "{:>2.3}", page_value),
+        PowerDisplay::Current | PowerDisplay::Power => write!(value, "{:>5}", page_value),
+        PowerDisplay::Resistance | PowerDisplay::Efficiency => write!(value, "{:>5.1}", page_value),
+        PowerDisplay::Conductance | PowerDisplay::Energy => write!(value, "{:>5.3}", page_value),
+    };
+    (value, power_display.unit())
+}
+
+/// Value and unit of the reading for the page.
+pub fn format_reading(power_display: PowerDisplay, reading: &PowerMonitor) -> (String<64>, &'static str) {
+    format_value(power_display, page_value(power_display, reading))
+}
+
+/// Value and unit of the combined view.
+pub fn format_total(channels: &[Channel]) -> (String<64>, &'static str) {
+    let mut value = String::new();
+    let _ = write!(value, "{:>5}", channel::total_power(channels));
+    (value, "mW")
+}
+
+/// Current statistics of a channel for the bottom line.
+pub fn format_stats(stats: &Stats) -> String<32> {
+    let mut text = String::new();
+    let _ = write!(text, "min {:.0} max {:.0} avg {:.0} mA", stats.min_current, stats.max_current, stats.mean_current());
+    text
+}
+
+/// Cost of the energy for the bottom line of the energy page.
+pub fn format_cost(stats: &Stats, price_per_kwh: f32) -> String<32> {
+    let mut text = String::new();
+    if price_per_kwh <= 0.0 {
+        let _ = text.push_str("no energy price set");
+    } else {
+        let _ = write!(text, "cost {:.4} at {:.2}/kWh", derived::cost(stats.energy.mwh(), price_per_kwh), price_per_kwh);
+    }
+    text
+}
+
+impl Default for Ui {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ui {
+    /// A single sensor at the default address.
+    pub fn new() -> Self {
+        Self::with_channels([Channel::new(INA219_ADDR)])
+    }
+
+    /// Channels in the order of their index in Input::Reading.
+    pub fn with_channels<I: IntoIterator<Item=Channel>>(channels: I) -> Self {
+        Ui {
+            power_display: PowerDisplay::Voltage,
+            sampling: Sampling::Continuous,
+            energy_price: 0.0,
+            battery: Battery::default(),
+            display_filter: FilterKind::Off,
+            log_filter: FilterKind::Off,
+            channels: channels.into_iter().take(MAX_CHANNELS).collect(),
+            view: View::Channel(0),
+            modified: Modified::default(),
+            page: None,
+            scope_settings: ScopeSettings::default(),
+            cursors: Cursors::default(),
+            quality_settings: QualitySettings::default(),
+            message: None,
+            drawn: None,
+            last_value: String::new(),
+            last_stats: String::new(),
+        }
+    }
+
+    pub fn power_display(&self) -> PowerDisplay {
+        self.power_display
+    }
+
+    pub fn sampling(&self) -> Sampling {
+        self.sampling
+    }
+
+    /// Price per kWh the cost of the energy is shown in.
+    pub fn set_energy_price(&mut self, price_per_kwh: f32) {
+        self.energy_price = price_per_kwh;
+    }
+
+    pub fn filter(&self, target: FilterTarget) -> FilterKind {
+        match target {
+            FilterTarget::Display => self.display_filter,
+            FilterTarget::Log => self.log_filter,
+        }
+    }
+
+    pub fn channels(&self) -> &[Channel] {
+        &self.channels
+    }
+
+    pub fn view(&self) -> View {
+        self.view
+    }
+
+    /// HOLD, REL, MAX or MIN on the channel shown.
+    pub fn modifier(&self) -> Modifier {
+        self.modified.modifier()
+    }
+
+    /// The value the reading page shows, None in the combined view and
+    /// without a value.
+    pub fn displayed_value(&self) -> Option<f32> {
+        self.channel()?;
+        self.modified.value(self.power_display, &self.page_values())
+    }
+
+    /// Values of every page for the channel shown.
+    fn page_values(&self) -> PageValues {
+        let Some(channel) = self.channel() else {
+            return PageValues::default();
+        };
+        let mut values = reading_values(&channel.reading, &channel.derived);
+        values[PowerDisplay::Energy as usize] = Some((channel.stats.energy.mwh() / 1000.0) as f32);
+        // both channels of the converter show its efficiency
+        if let [_, output, ..] = self.channels.as_slice() {
+            values[PowerDisplay::Efficiency as usize] = output.derived.efficiency.map(|e| e * 100.0);
+        }
+        values
+    }
+
+    /// The efficiency needs a second channel.
+    fn has_page(&self, page: PowerDisplay) -> bool {
+        page != PowerDisplay::Efficiency || self.channels.len() > 1
+    }
+
+    /// Range of the channel shown, the default one in the combined view.
+    pub fn calibration(&self) -> Calibration {
+        self.channel().map(|channel| channel.calibration).unwrap_or(Calibration::Range32V2A)
+    }
+
+    pub fn channel_page(&self) -> Option<usize> {
+        match self.page {
+            Some(Page::Channels(selected)) => Some(selected),
+            _ => None,
+        }
+    }
+
+    pub fn wizard(&self) -> Option<&Wizard> {
+        match &self.page {
+            Some(Page::Wizard(wizard)) => Some(wizard),
+            _ => None,
+        }
+    }
+
+    /// Replace a correction, e.g. once it is stored with its date.
+    pub fn set_correction(&mut self, channel: usize, calibration: Calibration, correction: Correction) {
+        if let Some(channel) = self.channels.get_mut(channel) {
+            channel.set_correction(calibration, correction);
+        }
+    }
+
+    /// Battery of the device under test the battery life is estimated for.
+    pub fn set_battery(&mut self, battery: Battery) {
+        self.battery = battery;
+    }
+
+    pub fn battery(&self) -> &Battery {
+        &self.battery
+    }
+
+    /// Estimated runtime while the battery page is up, None without an
+    /// average or capacity.
+    pub fn runtime_h(&self) -> Option<f32> {
+        let Some(Page::Battery(Some(average), _)) = &self.page else {
+            return None;
+        };
+        self.battery.runtime_h(average.current_ma)
+    }
+
+    /// Field edited while the battery page is up.
+    pub fn battery_field(&self) -> Option<Field> {
+        match self.page {
+            Some(Page::Battery(_, field)) => Some(field),
+            _ => None,
+        }
+    }
+
+    pub fn scope_settings(&self) -> &ScopeSettings {
+        &self.scope_settings
+    }
+
+    pub fn cursors(&self) -> &Cursors {
+        &self.cursors
+    }
+
+    /// Latest trace while the scope page is up.
+    pub fn trace(&self) -> Option<&Trace> {
+        match &self.page {
+            Some(Page::Scope(trace, _)) => trace.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Field edited while the scope page is up.
+    pub fn scope_field(&self) -> Option<ScopeField> {
+        match self.page {
+            Some(Page::Scope(_, field)) => Some(field),
+            _ => None,
+        }
+    }
+
+    /// Latest distribution and the selected bucket while the histogram page is up.
+    pub fn histogram(&self) -> Option<(&Histogram, usize)> {
+        match &self.page {
+            Some(Page::Histogram(histogram, selected)) => Some((histogram, *selected)),
+            _ => None,
+        }
+    }
+
+    /// Latest spectrum while the spectrum page is up.
+    pub fn spectrum(&self) -> Option<&Spectrum> {
+        match &self.page {
+            Some(Page::Spectrum(spectrum, _)) => spectrum.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Peak selected while the spectrum page is up.
+    pub fn spectrum_peak(&self) -> Option<usize> {
+        match self.page {
+            Some(Page::Spectrum(_, selected)) => Some(selected),
+            _ => None,
+        }
+    }
+
+    pub fn set_quality_settings(&mut self, settings: QualitySettings) {
+        self.quality_settings = settings;
+    }
+
+    pub fn quality_settings(&self) -> &QualitySettings {
+        &self.quality_settings
+    }
+
+    /// Latest quality while the quality page is up.
+    pub fn quality(&self) -> Option<&Quality> {
+        match &self.page {
+            Some(Page::Quality(quality, _)) => quality.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Field edited while the quality page is up.
+    pub fn quality_field(&self) -> Option<QualityField> {
+        match self.page {
+            Some(Page::Quality(_, field)) => Some(field),
+            _ => None,
+        }
+    }
+
+    /// Latest profile while the profile page is up.
+    pub fn profile(&self) -> Option<&Profile> {
+        match &self.page {
+            Some(Page::Profile(profile, _)) => Some(profile),
+            _ => None,
+        }
+    }
+
+    /// Show the result of a bus scan until Select is pressed.
+    pub fn show_diagnostics(&mut self, devices: &[Device], clock_khz: u32) {
+        let mut lines = Vec::new();
+        let mut header = String::new();
+        let _ = write!(header, "I2C0 {} kHz, {} found", clock_khz, devices.len());
+        let _ = lines.push(header);
+        for device in devices {
+            if lines.push(device.text()).is_err() {
+                break;
+            }
+        }
+        self.page = Some(Page::Diagnostics(lines, 0));
+    }
+
+    /// Lines of the diagnostics page while it is up.
+    pub fn diagnostics(&self) -> Option<&[String<32>]> {
+        match &self.page {
+            Some(Page::Diagnostics(lines, _)) => Some(lines),
+            _ => None,
+        }
+    }
+
+    /// Show the event log, the newest first, until Select is pressed.
+    pub fn show_events(&mut self, log: &EventLog) {
+        let mut lines = Vec::new();
+        let mut header = String::new();
+        let _ = write!(header, "{} events, boot #{}", log.events().len(), log.boot());
+        let _ = lines.push(header);
+        for event in log.events().iter().rev() {
+            let _ = lines.push(events::event_line(event));
+        }
+        self.page = Some(Page::Events(lines, 0));
+    }
+
+    /// Lines of the event page while it is up.
+    pub fn events(&self) -> Option<&[String<32>]> {
+        match &self.page {
+            Some(Page::Events(lines, _)) => Some(lines),
+            _ => None,
+        }
+    }
+
+    pub fn menu(&self) -> Option<SettingsItem> {
+        match self.page {
+            Some(Page::Menu(item)) => Some(item),
+            _ => None,
+        }
+    }
+
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    /// Show text instead of the reading until the next reading arrives.
+    pub fn show_message(&mut self, text: &str) {
+        let mut message = String::new();
+        let _ = message.push_str(text);
+        self.message = Some(message);
+    }
+
+    fn channel(&self) -> Option<&Channel> {
+        match self.view {
+            View::Channel(index) => self.channels.get(index),
+            View::Total => None,
+        }
+    }
+
+    pub fn handle(&mut self, input: &Input) -> Action {
+        let (button, long_press) = match input {
+            Input::Reading { channel, reading, unfiltered, calibration, stats, derived } => {
+                let index = *channel as usize;
+                if let Some(Page::Wizard(wizard)) = self.page.as_mut() {
+                    if wizard.channel() == index {
+                        let outcome = wizard.reading(*calibration, unfiltered.current);
+                        return self.wizard_outcome(outcome);
+                    }
+                }
+                if let Some(channel) = self.channels.get_mut(index) {
+                    channel.status = BusStatus::Ok;
+                    channel.reading = *reading;
+                    channel.derived = *derived;
+                    channel.stats = *stats;
+                    // manual ranges are only changed here, a reading can
+                    // still be from before the last change
+                    if channel.auto_range.is_some() {
+                        channel.calibration = *calibration;
+                    }
+                }
+                if self.view == View::Channel(index) {
+                    let values = self.page_values();
+                    self.modified.update(&values);
+                }
+                // the menus stay up while readings keep coming
+                if self.page.is_none() {
+                    self.message = None;
+                }
+                return Action::None;
+            }
+            Input::Status { channel, status } => {
+                if let Some(channel) = self.channels.get_mut(*channel as usize) {
+                    channel.status = *status;
+                }
+                return Action::None;
+            }
+            Input::Channel(channel) => {
+                if self.channels.push(channel.clone()).is_ok() {
+                    self.drawn = None;
+                }
+                return Action::None;
+            }
+            Input::Message(text) => {
+                self.show_message(text);
+                return Action::None;
+            }
+            Input::Profile(profile) => {
+                if let Some(Page::Profile(shown, _)) = self.page.as_mut() {
+                    if shown != profile {
+                        *shown = *profile;
+                        // same screen, new lines
+                        self.drawn = None;
+                    }
+                }
+                return Action::None;
+            }
+            Input::Battery(average) => {
+                if let Some(Page::Battery(shown, _)) = self.page.as_mut() {
+                    if *shown != Some(*average) {
+                        *shown = Some(*average);
+                        self.drawn = None;
+                    }
+                }
+                return Action::None;
+            }
+            Input::Trace(trace) => {
+                if let Some(Page::Scope(shown, _)) = self.page.as_mut() {
+                    *shown = Some(trace.clone());
+                    self.drawn = None;
+                }
+                return Action::None;
+            }
+            Input::Histogram(histogram) => {
+                if let Some(Page::Histogram(shown, selected)) = self.page.as_mut() {
+                    if shown != histogram {
+                        // the page opens on where the load spends its time
+                        if shown.total_us() == 0 {
+                            *selected = histogram.busiest().unwrap_or(*selected);
+                        }
+                        *shown = *histogram;
+                        self.drawn = None;
+                    }
+                }
+                return Action::None;
+            }
+            Input::Spectrum(spectrum) => {
+                if let Some(Page::Spectrum(shown, selected)) = self.page.as_mut() {
+                    // a new burst can have fewer peaks
+                    *selected = (*selected).min(spectrum.peaks.len().saturating_sub(1));
+                    *shown = Some(spectrum.clone());
+                    self.drawn = None;
+                }
+                return Action::None;
+            }
+            Input::Quality(quality) => {
+                if let Some(Page::Quality(shown, _)) = self.page.as_mut() {
+                    if *shown != Some(*quality) {
+                        *shown = Some(*quality);
+                        self.drawn = None;
+                    }
+                }
+                return Action::None;
+            }
+            Input::Button { button, long_press } => (*button, *long_press),
+        };
+        let Some(page) = self.page.as_mut() else {
+            return self.reading_button(button, long_press);
+        };
+        match page {
+            Page::Menu(item) => {
+                let item = *item;
+                self.menu_button(item, button, long_press)
+            }
+            Page::Channels(selected) => {
+                let selected = *selected;
+                self.channel_page_button(selected, button, long_press)
+            }
+            Page::Wizard(wizard) => {
+                let outcome = wizard.button(button, long_press);
+                self.wizard_outcome(outcome)
+            }
+            Page::Diagnostics(lines, selected) => {
+                match button {
+                    Button::Select => self.page = None,
+                    Button::Previous => *selected = (*selected + lines.len() - 1) % lines.len(),
+                    Button::Next => *selected = (*selected + 1) % lines.len(),
+                }
+                Action::None
+            }
+            Page::Events(lines, selected) => {
+                match button {
+                    Button::Select => self.page = None,
+                    Button::Previous => *selected = (*selected + lines.len() - 1) % lines.len(),
+                    Button::Next => *selected = (*selected + 1) % lines.len(),
+                }
+                Action::None
+            }
+            Page::Profile(_, selected) => {
+                match button {
+                    Button::Select => {
+                        self.page = None;
+                        return Action::Profile(false);
+                    }
+                    Button::Previous => *selected = (*selected + PROFILE_LINES - 1) % PROFILE_LINES,
+                    Button::Next => *selected = (*selected + 1) % PROFILE_LINES,
+                }
+                Action::None
+            }
+            Page::Battery(_, field) => {
+                match (button, long_press) {
+                    (Button::Select, true) => {
+                        self.page = None;
+                        return Action::Battery(self.battery);
+                    }
+                    (Button::Select, false) => *field = field.next_wrapping(),
+                    (button, _) => {
+                        self.battery.adjust(*field, button == Button::Next);
+                        self.drawn = None;
+                    }
+                }
+                Action::None
+            }
+            Page::Quality(_, field) => {
+                match (button, long_press) {
+                    (Button::Select, true) => {
+                        self.page = None;
+                        return Action::Quality(None);
+                    }
+                    (Button::Select, false) => *field = field.next_wrapping(),
+                    (button, _) => {
+                        self.quality_settings.adjust(*field, button == Button::Next);
+                        self.drawn = None;
+                        return Action::Quality(Some(self.quality_settings));
+                    }
+                }
+                Action::None
+            }
+            Page::Spectrum(shown, selected) => {
+                let peaks = shown.as_ref().map(|spectrum| spectrum.peaks.len()).unwrap_or(0).max(1);
+                match button {
+                    Button::Select => {
+                        self.page = None;
+                        return Action::Spectrum(false);
+                    }
+                    Button::Previous => *selected = (*selected + peaks - 1) % peaks,
+                    Button::Next => *selected = (*selected + 1) % peaks,
+                }
+                Action::None
+            }
+            Page::Histogram(shown, selected) => {
+                match (button, long_press) {
+                    (Button::Select, _) => self.page = None,
+                    (Button::Previous, true) => {
+                        shown.reset();
+                        self.drawn = None;
+                        return Action::ResetHistogram;
+                    }
+                    (Button::Previous, false) => *selected = (*selected + BUCKETS - 1) % BUCKETS,
+                    (Button::Next, _) => *selected = (*selected + 1) % BUCKETS,
+                }
+                Action::None
+            }
+            Page::Scope(_, field) => {
+                match (button, long_press) {
+                    (Button::Select, true) => {
+                        self.page = None;
+                        return Action::Scope(None);
+                    }
+                    (Button::Select, false) => *field = field.next_wrapping(),
+                    // arms a single shot again
+                    (Button::Next, true) => return Action::Scope(Some(self.scope_settings)),
+                    (Button::Previous, true) => {}
+                    (button, false) if field.is_cursor() => {
+                        self.cursors.adjust(*field, button == Button::Next);
+                        self.drawn = None;
+                    }
+                    (button, false) => {
+                        self.scope_settings.adjust(*field, button == Button::Next);
+                        self.drawn = None;
+                        return Action::Scope(Some(self.scope_settings));
+                    }
+                }
+                Action::None
+            }
+        }
+    }
+
+    fn channel_page_button(&mut self, selected: usize, button: Button, long_press: bool) -> Action {
+        // the channels and Total
+        let entries = self.channels.len() + 1;
+        match (button, long_press) {
+            (Button::Select, true) => {}
+            (Button::Select, false) => {
+                self.view = if selected < self.channels.len() { View::Channel(selected) } else { View::Total };
+                // the values are of the channel shown before
+                self.modified = Modified::default();
+            }
+            (Button::Previous, _) => {
+                self.page = Some(Page::Channels((selected + entries - 1) % entries));
+                return Action::None;
+            }
+            (Button::Next, _) => {
+                self.page = Some(Page::Channels((selected + 1) % entries));
+                return Action::None;
+            }
+        }
+        self.page = None;
+        Action::None
+    }
+
+    fn menu_button(&mut self, item: SettingsItem, button: Button, long_press: bool) -> Action {
+        match (button, long_press) {
+            (Button::Select, true) => {
+                self.page = None;
+                Action::None
+            }
+            (Button::Select, false) => {
+                self.page = None;
+                match item {
+                    SettingsItem::Exit => Action::None,
+                    SettingsItem::Calibrate => self.start_wizard(),
+                    SettingsItem::Sampling => {
+                        self.sampling = match self.sampling {
+                            Sampling::Continuous => Sampling::Triggered,
+                            Sampling::Triggered => Sampling::Continuous,
+                        };
+                        let mut message: String<32> = String::new();
+                        let _ = write!(message, "{} sampling", self.sampling.text());
+                        self.show_message(&message);
+                        Action::Sampling(self.sampling)
+                    }
+                    SettingsItem::DisplayFilter => self.next_filter(FilterTarget::Display),
+                    SettingsItem::LogFilter => self.next_filter(FilterTarget::Log),
+                    SettingsItem::Profile => {
+                        self.page = Some(Page::Profile(Profile::default(), 0));
+                        Action::Profile(true)
+                    }
+                    SettingsItem::BatteryLife => {
+                        self.page = Some(Page::Battery(None, Field::default()));
+                        Action::None
+                    }
+                    SettingsItem::Scope => {
+                        self.page = Some(Page::Scope(None, ScopeField::default()));
+                        Action::Scope(Some(self.scope_settings))
+                    }
+                    SettingsItem::Histogram => {
+                        self.page = Some(Page::Histogram(Histogram::new(), 0));
+                        Action::None
+                    }
+                    SettingsItem::Spectrum => {
+                        self.page = Some(Page::Spectrum(None, 0));
+                        Action::Spectrum(true)
+                    }
+                    SettingsItem::Quality => {
+                        self.page = Some(Page::Quality(None, QualityField::default()));
+                        Action::Quality(Some(self.quality_settings))
+                    }
+                    SettingsItem::AutoRange => self.toggle_auto_range(),
+                    SettingsItem::Channels => {
+                        self.page = Some(Page::Channels(match self.view {
+                            View::Channel(index) => index,
+                            View::Total => self.channels.len(),
+                        }));
+                        Action::None
+                    }
+                    item => Action::Activate(item),
+                }
+            }
+            (Button::Previous, _) => {
+                self.page = Some(Page::Menu(item.previous_wrapping()));
+                Action::None
+            }
+            (Button::Next, _) => {
+                self.page = Some(Page::Menu(item.next_wrapping()));
+                Action::None
+            }
+        }
+    }
+
+    /// Buttons while the reading is up.
+    fn reading_button(&mut self, button: Button, long_press: bool) -> Action {
+        match button {
+            Button::Select if long_press => {
+                self.page = enum_iterator::first::<SettingsItem>().map(Page::Menu);
+                Action::None
+            }
+            Button::Select => {
+                let View::Channel(index) = self.view else {
+                    return Action::None;
+                };
+                let Some(channel) = self.channels.get_mut(index) else {
+                    return Action::None;
+                };
+                let position = enum_iterator::all::<Calibration>().position(|c| c == channel.calibration).unwrap_or(0);
+                let calibration = enum_iterator::all::<Calibration>()
+                    .nth((position + 1) % cardinality::<Calibration>())
+                    .unwrap_or(Calibration::Range32V2A);
+                channel.set_calibration(calibration);
+                channel.auto_range = None;
+                self.show_message(calibration.text());
+                Action::Calibrate { channel: index as u8, calibration }
+            }
+            Button::Previous if long_press => {
+                if self.channel().is_some() {
+                    let values = self.page_values();
+                    self.modified.reset(&values);
+                }
+                Action::None
+            }
+            Button::Next if long_press => {
+                if self.channel().is_some() {
+                    let values = self.page_values();
+                    self.modified = Modified::new(self.modified.modifier().next_wrapping(), &values);
+                }
+                Action::None
+            }
+            // the pages stop at both ends
+            Button::Previous => {
+                let mut page = self.power_display;
+                while let Some(previous) = page.previous() {
+                    page = previous;
+                    if self.has_page(page) {
+                        self.power_display = page;
+                        break;
+                    }
+                }
+                Action::None
+            }
+            Button::Next => {
+                let mut page = self.power_display;
+                while let Some(next) = page.next() {
+                    page = next;
+                    if self.has_page(page) {
+                        self.power_display = page;
+                        break;
+                    }
+                }
+                Action::None
+            }
+        }
+    }
+
+    fn start_wizard(&mut self) -> Action {
+        let View::Channel(index) = self.view else {
+            return Action::None;
+        };
+        if let Some(channel) = self.channels.get(index) {
+            self.page = Some(Page::Wizard(Wizard::new(index, channel.calibration, *channel.correction())));
+        }
+        Action::None
+    }
+
+    fn wizard_outcome(&mut self, outcome: Outcome) -> Action {
+        if outcome == Outcome::Continue {
+            return Action::None;
+        }
+        let Some(Page::Wizard(wizard)) = self.page.take() else {
+            return Action::None;
+        };
+        match outcome {
+            Outcome::Done(Some(correction)) => {
+                self.set_correction(wizard.channel(), wizard.calibration(), correction);
+                self.show_message("Calibrated");
+                Action::Correct { channel: wizard.channel() as u8, calibration: wizard.calibration(), correction }
+            }
+            Outcome::Done(None) => {
+                self.show_message("Points too close");
+                Action::None
+            }
+            _ => Action::None,
+        }
+    }
+
+    fn next_filter(&mut self, target: FilterTarget) -> Action {
+        let kind = match target {
+            FilterTarget::Display => &mut self.display_filter,
+            FilterTarget::Log => &mut self.log_filter,
+        };
+        *kind = kind.next_wrapping();
+        let kind = *kind;
+        let mut message: String<32> = String::new();
+        let _ = write!(message, "{} filter {}", target.text(), kind.text());
+        self.show_message(&message);
+        Action::Filter { target, kind }
+    }
+
+    fn toggle_auto_range(&mut self) -> Action {
+        let View::Channel(index) = self.view else {
+            return Action::None;
+        };
+        let Some(channel) = self.channels.get_mut(index) else {
+            return Action::None;
+        };
+        let enabled = channel.auto_range.is_none();
+        channel.auto_range = if enabled { Some(AutoRange::new()) } else { None };
+        self.show_message(if enabled { "Auto range on" } else { "Auto range off" });
+        Action::AutoRange { channel: index as u8, enabled }
+    }
+
+    fn screen(&self) -> Screen {
+        match &self.page {
+            Some(Page::Menu(item)) => Screen::Menu(*item),
+            Some(Page::Channels(selected)) => Screen::Channels(*selected),
+            Some(Page::Wizard(wizard)) => Screen::Wizard(wizard.step()),
+            Some(Page::Diagnostics(_, selected)) => Screen::Diagnostics(*selected),
+            Some(Page::Events(_, selected)) => Screen::Events(*selected),
+            Some(Page::Profile(_, selected)) => Screen::Profile(*selected),
+            Some(Page::Battery(_, field)) => Screen::Battery(*field),
+            Some(Page::Scope(_, field)) => Screen::Scope(*field),
+            Some(Page::Histogram(_, selected)) => Screen::Histogram(*selected),
+            Some(Page::Spectrum(_, selected)) => Screen::Spectrum(*selected),
+            Some(Page::Quality(_, field)) => Screen::Quality(*field),
+            None if self.message.is_some() => Screen::Message,
+            None => Screen::Reading(self.view, self.channel().map(|channel| channel.status).unwrap_or_default(),
+                                    self.modified.modifier()),
+        }
+    }
+
+    /// Bring the display up to date, only what changed is drawn.
+    pub fn draw<D, S>(&mut self, display: &mut D, theme: &Theme<S>)
+        where D: DrawTarget<Color=Rgb565>, S: TextRenderer<Color=Rgb565> + Clone {
+        let screen = self.screen();
+        let changed = self.drawn != Some(screen);
+        if changed {
+            self.last_value.clear();
+            self.last_stats.clear();
+        }
+        self.drawn = Some(screen);
+        match screen {
+            Screen::Menu(item) => {
+                if changed {
+                    menu::draw_menu(display, item, theme.medium);
+                }
+            }
+            Screen::Message => {
+                if changed {
+                    if let Some(message) = &self.message {
+                        draw_message(display, theme, message);
+                    }
+                }
+            }
+            Screen::Channels(selected) => {
+                if changed {
+                    self.draw_channel_page(display, theme, selected);
+                }
+            }
+            Screen::Diagnostics(selected) => {
+                if let Some(Page::Diagnostics(lines, _)) = self.page.as_ref().filter(|_| changed) {
+                    let lines: Vec<&str, DIAGNOSTIC_LINES> = lines.iter().map(|line| line.as_str()).collect();
+                    menu::draw_list(display, &lines, selected, theme.medium);
+                }
+            }
+            Screen::Events(selected) => {
+                if let Some(Page::Events(lines, _)) = self.page.as_ref().filter(|_| changed) {
+                    let lines: Vec<&str, { MAX_EVENTS + 1 }> = lines.iter().map(|line| line.as_str()).collect();
+                    menu::draw_list(display, &lines, selected, theme.medium);
+                }
+            }
+            Screen::Profile(selected) => {
+                if let Some(Page::Profile(profile, _)) = self.page.as_ref().filter(|_| changed) {
+                    let lines = profile.lines(&self.battery);
+                    menu::draw_list(display, &lines.each_ref().map(|line| line.as_str()), selected, theme.medium);
+                }
+            }
+            Screen::Battery(field) => {
+                if let Some(Page::Battery(average, _)) = self.page.as_ref().filter(|_| changed) {
+                    self.draw_battery_page(display, theme, average.as_ref(), field);
+                }
+            }
+            Screen::Scope(field) => {
+                if let Some(Page::Scope(trace, _)) = self.page.as_ref().filter(|_| changed) {
+                    scope::draw_scope(display, trace.as_ref(), &self.scope_settings, &self.cursors, field, theme.medium);
+                }
+            }
+            Screen::Histogram(selected) => {
+                if let Some(Page::Histogram(histogram, _)) = self.page.as_ref().filter(|_| changed) {
+                    histogram::draw_histogram(display, histogram, selected, theme.medium);
+                }
+            }
+            Screen::Spectrum(selected) => {
+                if let Some(Page::Spectrum(spectrum, _)) = self.page.as_ref().filter(|_| changed) {
+                    spectrum::draw_spectrum(display, spectrum.as_ref(), selected, theme.medium);
+                }
+            }
+            Screen::Quality(field) => {
+                if let Some(Page::Quality(quality, _)) = self.page.as_ref().filter(|_| changed) {
+                    self.draw_quality_page(display, theme, quality.as_ref(), field);
+                }
+            }
+            Screen::Wizard(_) => {
+                if let Some(Page::Wizard(wizard)) = self.page.as_ref().filter(|_| changed) {
+                    let lines = wizard.lines();
+                    draw_lines(display, theme, &lines.each_ref().map(|line| line.as_str()));
+                }
+            }
+            Screen::Reading(view, status, modifier) => {
+                // a single sensor needs no channel name
+                let labeled = self.channels.len() > 1 || view == View::Total;
+                if changed {
+                    let _ = display.clear(theme.background);
+                    if labeled {
+                        let label = match self.channel() {
+                            Some(channel) => channel.label(),
+                            None => String::try_from("Total").unwrap_or_default(),
+                        };
+                        display_text(display, create_point(5, 2), theme.medium, top_style(Alignment::Left), &label);
+                    }
+                    if modifier != Modifier::Off {
+                        draw_annunciator(display, theme, modifier.annunciator());
+                    }
+                }
+                // a frozen number would look like a valid reading
+                let (value, unit) = match self.channel() {
+                    Some(_) if status != BusStatus::Ok => (String::try_from(status.text()).unwrap_or_default(), ""),
+                    Some(_) => format_value(self.power_display, self.modified.value(self.power_display, &self.page_values())),
+                    None if view == View::Total => format_total(&self.channels),
+                    None => format_reading(self.power_display, &PowerMonitor::default()),
+                };
+                let stats = match self.channel() {
+                    Some(channel) if self.power_display == PowerDisplay::Energy => format_cost(&channel.stats, self.energy_price),
+                    Some(channel) if labeled => format_stats(&channel.stats),
+                    None if labeled => {
+                        let mut text = String::new();
+                        let _ = write!(text, "sum of {} channels", self.channels.len());
+                        text
+                    }
+                    _ => String::new(),
+                };
+                if stats != self.last_stats {
+                    // a space clears the line of another page
+                    let text = if stats.is_empty() { " " } else { stats.as_str() };
+                    let y = DISPLAY_SIZE.height as i32 - theme.medium.font.character_size.height as i32 - 2;
+                    display_text_with_background(display, create_point(5, y), theme.medium, top_style(Alignment::Left),
+                                                 text, theme.background, DISPLAY_SIZE.width - 5);
+                    self.last_stats = stats;
+                }
+                if value != self.last_value {
+                    let y = (DISPLAY_SIZE.height / 2) as i32;
+                    let unit_width = (theme.large.font.character_size.width * 2) as i32;
+                    if status != BusStatus::Ok {
+                        display_text_with_background(display, create_point(10, y), theme.large, center_text_style(),
+                                                     &value, theme.background, DISPLAY_SIZE.width);
+                    } else {
+                        display_text_with_background(display, create_point(0, y), theme.digits.clone(), center_text_style(),
+                                                     &value, theme.background, DISPLAY_SIZE.width);
+                        display_text_with_background(display, create_point(DISPLAY_SIZE.width as i32 - unit_width, y), theme.large,
+                                                     center_text_style(), unit, theme.background, DISPLAY_SIZE.width);
+                    }
+                    self.last_value = value;
+                }
+            }
+        }
+    }
+
+    fn draw_battery_page<D, S>(&self, display: &mut D, theme: &Theme<S>, average: Option<&Average>, field: Field)
+        where D: DrawTarget<Color=Rgb565> {
+        let mut lines: [String<32>; 5] = Default::default();
+        let _ = lines[0].push_str("Runtime ");
+        match average.and_then(|average| self.battery.runtime_h(average.current_ma)) {
+            Some(hours) => {
+                let _ = lines[0].push_str(&battery::format_runtime(hours));
+            }
+            None => {
+                let _ = lines[0].push_str("---");
+            }
+        }
+        match average {
+            Some(average) => {
+                let seconds = average.window_us / 1_000_000;
+                let _ = write!(lines[1], "Avg {:.3} mA {}:{:02} {}", average.current_ma, seconds / 60, seconds % 60,
+                               average.confidence().bar());
+            }
+            None => {
+                let _ = lines[1].push_str("Avg ---");
+            }
+        }
+        for (line, field) in lines[2..].iter_mut().zip(all::<Field>()) {
+            *line = self.battery.text(field);
+        }
+        let selected = 2 + all::<Field>().position(|f| f == field).unwrap_or(0);
+        let lines = lines.each_ref().map(|line| line.as_str());
+        draw_fields(display, theme, &lines, selected);
+    }
+
+    fn draw_quality_page<D, S>(&self, display: &mut D, theme: &Theme<S>, quality: Option<&Quality>, field: QualityField)
+        where D: DrawTarget<Color=Rgb565> {
+        let mut lines: [String<32>; QUALITY_LINES + 2] = Default::default();
+        let measured = quality.copied().unwrap_or_default().lines();
+        lines[..QUALITY_LINES].clone_from_slice(&measured);
+        for (line, field) in lines[QUALITY_LINES..].iter_mut().zip(all::<QualityField>()) {
+            *line = self.quality_settings.text(field);
+        }
+        let selected = QUALITY_LINES + all::<QualityField>().position(|f| f == field).unwrap_or(0);
+        let lines = lines.each_ref().map(|line| line.as_str());
+        draw_fields(display, theme, &lines, selected);
+    }
+
+    fn draw_channel_page<D, S>(&self, display: &mut D, theme: &Theme<S>, selected: usize) where D: DrawTarget<Color=Rgb565> {
+        let mut lines: Vec<String<32>, { MAX_CHANNELS + 1 }> = Vec::new();
+        for channel in &self.channels {
+            let mut line = String::new();
+            let _ = write!(line, "{:<10}{:>8.1} mW", channel.label(), channel.reading.power);
+            let _ = lines.push(line);
+        }
+        let mut line = String::new();
+        let _ = write!(line, "{:<10}{:>8.1} mW", "Total", channel::total_power(&self.channels));
+        let _ = lines.push(line);
+        let lines: Vec<&str, { MAX_CHANNELS + 1 }> = lines.iter().map(|line| line.as_str()).collect();
+        menu::draw_list(display, &lines, selected, theme.medium);
+    }
+
+    /// Forget what is on the display, the next draw starts from scratch.
+    pub fn invalidate(&mut self) {
+        self.drawn = None;
+    }
+}
+
+/// Inverted text in the top right corner.
+fn draw_annunciator<D, S>(display: &mut D, theme: &Theme<S>, text: &str) where D: DrawTarget<Color=Rgb565> {
+    let style = selected_style(theme.medium, theme.background);
+    let width = (style.font.character_size.width * text.len() as u32) as i32;
+    display_text(display, create_point(DISPLAY_SIZE.width as i32 - width - 5, 2), style, top_style(Alignment::Left), text);
+}
+
+/// Lines of medium text filling the display, selected inverted.
+fn draw_fields<D, S>(display: &mut D, theme: &Theme<S>, lines: &[&str], selected: usize) where D: DrawTarget<Color=Rgb565> {
+    let _ = display.clear(theme.background);
+    let selected_style = selected_style(theme.medium, theme.background);
+    let spacing = DISPLAY_SIZE.height as i32 / lines.len().max(1) as i32;
+    for (i, line) in lines.iter().enumerate() {
+        let style = if i == selected { selected_style } else { theme.medium };
+        display_text(display, create_point(10, 5 + i as i32 * spacing), style, top_style(Alignment::Left), line);
+    }
+}
+
+/// One line of large text in the middle of the display.
+pub fn draw_message<D, S>(display: &mut D, theme: &Theme<S>, text: &str) where D: DrawTarget<Color=Rgb565> {
+    let _ = display.clear(theme.background);
+    display_text(display, create_point(10, (DISPLAY_SIZE.height / 2) as i32), theme.large, center_text_style(), text);
+}
+
+/// Splash with the LiPo voltage shown at boot.
+pub fn draw_battery<D, S>(display: &mut D, theme: &Theme<S>, voltage: f32) where D: DrawTarget<Color=Rgb565> {
+    let mut text: String<32> = String::new();
+    let _ = write!(text, "Battery {:1.1} V", voltage);
+    draw_message(display, theme, &text);
+}
+
+/// Up to three lines of medium text, e.g. the Wi-Fi setup instructions.
+pub fn draw_lines<D, S>(display: &mut D, theme: &Theme<S>, lines: &[&str]) where D: DrawTarget<Color=Rgb565> {
+    let _ = display.clear(theme.background);
+    for (i, line) in lines.iter().take(3).enumerate() {
+        display_text(display, create_point(10, 10 + i as i32 * 40), theme.medium, top_style(Alignment::Left), line);
+    }
+}
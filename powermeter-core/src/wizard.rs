@@ -0,0 +1,170 @@
+// Calibration wizard
+//
+// A tare with the load removed, then up to two reference points. For a
+// point the current shown on a trusted meter is dialed in digit by digit,
+// Previous and Next change the digit under the cursor and Select moves on.
+// After the last digit the wizard averages its own readings. A long press
+// finishes with the points so far: the tare alone only moves the offset,
+// with one point the tare is the other end of the line.
+
+use core::fmt::Write;
+
+use heapless::{String, Vec};
+
+use crate::correction::{Capture, Correction};
+use crate::drivers::ina219::Calibration;
+use crate::ui::Button;
+
+/// the reference is entered as 0000.0 mA
+pub const ENTRY_DIGITS: u8 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    /// waiting for the load to be removed
+    Zero,
+    /// reference of point 0 or 1 in 0.1 mA, cursor is the digit edited
+    Reference { point: u8, entry: u32, cursor: u8 },
+    /// averaging readings for the tare or the point just entered
+    Measuring,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Continue,
+    Cancel,
+    /// None when the points gave no usable correction
+    Done(Option<Correction>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Wizard {
+    channel: usize,
+    calibration: Calibration,
+    step: Step,
+    /// in effect while calibrating, the readings are corrected with it
+    previous: Correction,
+    capture: Capture,
+    zero: Option<f32>,
+    /// (measured, reference) in mA
+    points: Vec<(f32, f32), 2>,
+    /// reference of the point being measured, mA
+    reference: f32,
+}
+
+fn place(cursor: u8) -> u32 {
+    10u32.pow((ENTRY_DIGITS - 1 - cursor) as u32)
+}
+
+impl Wizard {
+    /// Calibrate calibration of the channel at index, previous is its
+    /// current correction for that range.
+    pub fn new(channel: usize, calibration: Calibration, previous: Correction) -> Self {
+        Wizard {
+            channel,
+            calibration,
+            step: Step::Zero,
+            previous,
+            capture: Capture::default(),
+            zero: None,
+            points: Vec::new(),
+            reference: 0.0,
+        }
+    }
+
+    pub fn channel(&self) -> usize {
+        self.channel
+    }
+
+    pub fn calibration(&self) -> Calibration {
+        self.calibration
+    }
+
+    pub fn step(&self) -> Step {
+        self.step
+    }
+
+    pub fn button(&mut self, button: Button, long_press: bool) -> Outcome {
+        match (self.step, button, long_press) {
+            (Step::Reference { .. }, Button::Select, true) => self.finish(),
+            (_, Button::Select, true) => Outcome::Cancel,
+            (Step::Zero, Button::Select, false) => {
+                self.step = Step::Measuring;
+                Outcome::Continue
+            }
+            (Step::Reference { point, entry, cursor }, Button::Select, false) => {
+                self.step = if cursor + 1 < ENTRY_DIGITS {
+                    Step::Reference { point, entry, cursor: cursor + 1 }
+                } else {
+                    self.reference = entry as f32 / 10.0;
+                    Step::Measuring
+                };
+                Outcome::Continue
+            }
+            (Step::Reference { point, entry, cursor }, button, _) => {
+                let place = place(cursor);
+                let digit = entry / place % 10;
+                let changed = if button == Button::Next { (digit + 1) % 10 } else { (digit + 9) % 10 };
+                self.step = Step::Reference { point, entry: entry - digit * place + changed * place, cursor };
+                Outcome::Continue
+            }
+            _ => Outcome::Continue,
+        }
+    }
+
+    /// A corrected reading of the channel, taken with calibration.
+    pub fn reading(&mut self, calibration: Calibration, current: f32) -> Outcome {
+        // auto range may have moved on, those readings are for another range
+        if self.step != Step::Measuring || calibration != self.calibration {
+            return Outcome::Continue;
+        }
+        let Some(measured) = self.capture.add(self.previous.measured(current)) else {
+            return Outcome::Continue;
+        };
+        if self.zero.is_none() {
+            self.zero = Some(measured);
+        } else {
+            let _ = self.points.push((measured, self.reference));
+            if self.points.is_full() {
+                return self.finish();
+            }
+        }
+        self.step = Step::Reference { point: self.points.len() as u8, entry: 0, cursor: 0 };
+        Outcome::Continue
+    }
+
+    fn finish(&self) -> Outcome {
+        let zero = self.zero.unwrap_or(0.0);
+        Outcome::Done(match self.points.as_slice() {
+            [a, b] => Correction::two_point(*a, *b),
+            [a] => Correction::two_point((zero, 0.0), *a),
+            _ => Some(Correction::tare(zero)),
+        })
+    }
+
+    /// What the wizard screen shows.
+    pub fn lines(&self) -> [String<32>; 3] {
+        let mut lines: [String<32>; 3] = Default::default();
+        match self.step {
+            Step::Zero => {
+                let _ = lines[0].push_str("Remove the load");
+                let _ = lines[1].push_str("Select: tare");
+                lines[2] = self.previous.text();
+            }
+            Step::Reference { point, entry, cursor } => {
+                let _ = write!(lines[0], "Point {} meter mA", point + 1);
+                let _ = write!(lines[1], "{:04}.{}", entry / 10, entry % 10);
+                // the decimal point sits before the last digit
+                let column = if cursor + 1 < ENTRY_DIGITS { cursor } else { cursor + 1 };
+                for _ in 0..column {
+                    let _ = lines[2].push(' ');
+                }
+                let _ = lines[2].push_str("^ hold: done");
+            }
+            Step::Measuring => {
+                let _ = lines[0].push_str("Measuring");
+                let _ = lines[1].push_str("keep the load");
+            }
+        }
+        lines
+    }
+}
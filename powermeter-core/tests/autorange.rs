@@ -0,0 +1,53 @@
+mod common;
+
+use common::reading;
+use powermeter_core::autorange::{AutoRange, DOWN_SAMPLES};
+use powermeter_core::drivers::ina219::Calibration;
+
+#[test]
+fn overflow_steps_up_at_once() {
+    let mut auto = AutoRange::new();
+    assert_eq!(auto.update(Calibration::Range16V400mA, &reading(5.0, 10.0), true), Some(Calibration::Range32V1A));
+    assert_eq!(auto.update(Calibration::Range32V1A, &reading(5.0, 10.0), true), Some(Calibration::Range32V2A));
+    // nothing coarser to go to
+    assert_eq!(auto.update(Calibration::Range32V2A, &reading(5.0, 10.0), true), None);
+}
+
+#[test]
+fn near_full_scale_steps_up() {
+    let mut auto = AutoRange::new();
+    assert_eq!(auto.update(Calibration::Range16V400mA, &reading(5.0, 380.0), false), Some(Calibration::Range32V1A));
+    assert_eq!(auto.update(Calibration::Range32V1A, &reading(5.0, -1250.0), false), Some(Calibration::Range32V2A));
+    // the 16 V range is too small for the bus
+    assert_eq!(auto.update(Calibration::Range16V400mA, &reading(15.0, 10.0), false), Some(Calibration::Range32V1A));
+}
+
+#[test]
+fn steps_down_after_samples_in_a_row() {
+    let mut auto = AutoRange::new();
+    for _ in 1..DOWN_SAMPLES {
+        assert_eq!(auto.update(Calibration::Range32V2A, &reading(5.0, 100.0), false), None);
+    }
+    assert_eq!(auto.update(Calibration::Range32V2A, &reading(5.0, 100.0), false), Some(Calibration::Range32V1A));
+
+    // one sample above the threshold starts over
+    for _ in 1..DOWN_SAMPLES {
+        auto.update(Calibration::Range32V1A, &reading(5.0, 100.0), false);
+    }
+    assert_eq!(auto.update(Calibration::Range32V1A, &reading(5.0, 350.0), false), None);
+    assert_eq!(auto.update(Calibration::Range32V1A, &reading(5.0, 100.0), false), None);
+}
+
+#[test]
+fn hysteresis_keeps_the_range() {
+    let mut auto = AutoRange::new();
+    // above the down threshold of the finer range, below the up threshold
+    for _ in 0..2 * DOWN_SAMPLES {
+        assert_eq!(auto.update(Calibration::Range32V1A, &reading(5.0, 300.0), false), None);
+        assert_eq!(auto.update(Calibration::Range16V400mA, &reading(5.0, 300.0), false), None);
+    }
+    // the finest range has nowhere to go
+    for _ in 0..2 * DOWN_SAMPLES {
+        assert_eq!(auto.update(Calibration::Range16V400mA, &reading(5.0, 1.0), false), None);
+    }
+}
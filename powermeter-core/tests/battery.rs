@@ -0,0 +1,104 @@
+mod common;
+
+use common::close_rel;
+use powermeter_core::battery::{format_runtime, Average, Battery, Confidence, Field, RollingCurrent, BUCKET_US, WINDOW_BUCKETS};
+
+#[test]
+fn runtime_of_the_usable_capacity() {
+    let battery = Battery { capacity_mah: 1000.0, usable: 0.8, self_discharge: 0.0 };
+    assert_eq!(battery.runtime_h(10.0), Some(80.0));
+    assert_eq!(battery.runtime_h(0.0), None);
+    assert_eq!(Battery::default().runtime_h(10.0), None);
+}
+
+#[test]
+fn self_discharge_is_a_constant_current() {
+    // 7.3 % of 1000 mAh in 730 h is 0.1 mA
+    let battery = Battery { capacity_mah: 1000.0, usable: 1.0, self_discharge: 0.073 };
+    assert!(close_rel(battery.self_discharge_ma(), 0.1, 1e-4));
+    assert!(close_rel(battery.runtime_h(0.1).unwrap(), 5000.0, 1e-4));
+    // a device that does not draw anything still runs down
+    assert!(close_rel(battery.runtime_h(0.0).unwrap(), 10_000.0, 1e-4));
+}
+
+#[test]
+fn adjust_steps_and_limits() {
+    let mut battery = Battery { capacity_mah: 490.0, ..Battery::default() };
+    battery.adjust(Field::Capacity, true);
+    assert_eq!(battery.capacity_mah, 500.0);
+    battery.adjust(Field::Capacity, true);
+    assert_eq!(battery.capacity_mah, 550.0);
+    battery.adjust(Field::Capacity, false);
+    battery.adjust(Field::Capacity, false);
+    assert_eq!(battery.capacity_mah, 490.0);
+
+    battery.capacity_mah = 0.0;
+    battery.adjust(Field::Capacity, false);
+    assert_eq!(battery.capacity_mah, 10.0);
+
+    battery.adjust(Field::Usable, true);
+    assert!(close_rel(battery.usable, 0.85, 1e-5));
+    for _ in 0..10 {
+        battery.adjust(Field::Usable, true);
+    }
+    assert_eq!(battery.usable, 1.0);
+    battery.adjust(Field::SelfDischarge, false);
+    assert!(close_rel(battery.self_discharge, 0.015, 1e-5));
+    assert_eq!(battery.text(Field::Usable).as_str(), "Usable   100 %");
+    assert_eq!(battery.text(Field::SelfDischarge).as_str(), "Self dis 1.5 %/month");
+}
+
+#[test]
+fn fields_wrap() {
+    assert_eq!(Field::Capacity.next_wrapping(), Field::Usable);
+    assert_eq!(Field::SelfDischarge.next_wrapping(), Field::Capacity);
+}
+
+#[test]
+fn rolling_average_follows_a_new_load() {
+    let mut rolling = RollingCurrent::new();
+    assert_eq!(rolling.average(), None);
+    // 100 ms samples, 10 mA for the whole window then 20 mA for half of it
+    let mut timestamp_us = 0;
+    for _ in 0..WINDOW_BUCKETS * 10 {
+        rolling.add(10.0, timestamp_us);
+        timestamp_us += 100_000;
+    }
+    assert!(close_rel(rolling.average().unwrap().current_ma, 10.0, 1e-4));
+    for _ in 0..WINDOW_BUCKETS * 5 {
+        rolling.add(20.0, timestamp_us);
+        timestamp_us += 100_000;
+    }
+    let average = rolling.average().unwrap();
+    assert!(close_rel(average.current_ma, 15.0, 0.01), "{:?}", average);
+    assert_eq!(average.confidence(), Confidence::High);
+}
+
+#[test]
+fn gaps_are_left_out() {
+    let mut rolling = RollingCurrent::new();
+    rolling.add(10.0, 0);
+    rolling.add(10.0, 500_000);
+    // the sensor was lost for a minute
+    rolling.add(1000.0, 60_500_000);
+    rolling.add(10.0, 61_000_000);
+    let average = rolling.average().unwrap();
+    assert_eq!(average.window_us, 1_000_000);
+    assert!(close_rel(average.current_ma, 505.0, 1e-4));
+}
+
+#[test]
+fn confidence_grows_with_the_window() {
+    let full_us = WINDOW_BUCKETS as u64 * BUCKET_US;
+    let confidence = |window_us| Average { current_ma: 1.0, window_us }.confidence();
+    assert_eq!(confidence(10 * BUCKET_US), Confidence::Low);
+    assert_eq!(confidence(full_us / 4), Confidence::Medium);
+    assert_eq!(confidence(full_us), Confidence::High);
+    assert_eq!(Confidence::Medium.bar(), "[##-]");
+}
+
+#[test]
+fn runtime_in_days_and_hours() {
+    assert_eq!(format_runtime(5.54).as_str(), "5.5 h");
+    assert_eq!(format_runtime(292.5).as_str(), "12 d 4 h");
+}
@@ -0,0 +1,64 @@
+use embedded_hal::i2c::{ErrorKind, I2c, NoAcknowledgeSource};
+use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+use powermeter_core::drivers::bus::{scan, BusCounters, Counted, Device, SCAN_ADDRESSES};
+
+const NACK: ErrorKind = ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address);
+
+fn read(address: u8, reg: u8, value: u16) -> Transaction {
+    Transaction::write_read(address, vec![reg], value.to_be_bytes().to_vec())
+}
+
+#[test]
+fn scan_names_what_answers() {
+    let mut expectations = Vec::new();
+    for address in SCAN_ADDRESSES {
+        match address {
+            0x36 => expectations.push(Transaction::read(address, vec![0])),
+            0x40 => expectations.extend([
+                Transaction::read(address, vec![0]),
+                Transaction::read(address, vec![0]),
+                read(address, 0xFE, 0x399F),
+            ]),
+            0x41 => expectations.extend([
+                Transaction::read(address, vec![0]),
+                Transaction::read(address, vec![0]),
+                read(address, 0xFE, 0x5449),
+                read(address, 0xFF, 0x2260),
+            ]),
+            0x50 => expectations.push(Transaction::read(address, vec![0])),
+            _ => expectations.push(Transaction::read(address, vec![0]).with_error(NACK)),
+        }
+    }
+    let mut i2c = Mock::new(&expectations);
+    let counters = BusCounters::new();
+    let devices = scan(&mut i2c, &counters);
+    let found: Vec<(u8, &str)> = devices.iter().map(|device| (device.address, device.name)).collect();
+    assert_eq!(found, [(0x36, "MAX17048"), (0x40, "INA219"), (0x41, "INA226"), (0x50, "unknown")]);
+    i2c.done();
+}
+
+#[test]
+fn counted_retries_once() {
+    let counters = BusCounters::new();
+    let mut i2c = Mock::new(&[
+        read(0x40, 0x01, 0x1234).with_error(NACK),
+        read(0x40, 0x01, 0x1234),
+        Transaction::write(0x40, vec![0x00, 0x39, 0x9F]).with_error(NACK),
+        Transaction::write(0x40, vec![0x00, 0x39, 0x9F]).with_error(NACK),
+    ]);
+    let mut counted = Counted::new(i2c.clone(), &counters);
+    let mut buf = [0u8; 2];
+    assert_eq!(counted.write_read(0x40, &[0x01], &mut buf), Ok(()));
+    assert_eq!(buf, [0x12, 0x34]);
+    assert_eq!(counted.write(0x40, &[0x00, 0x39, 0x9F]), Err(NACK));
+
+    assert_eq!((counters.device(0x40).retries(), counters.device(0x40).errors()), (2, 1));
+    assert_eq!(counters.device(0x41).retries(), 0);
+    i2c.done();
+}
+
+#[test]
+fn device_line() {
+    let device = Device { address: 0x40, name: "INA219", errors: 0, retries: 12 };
+    assert_eq!(device.text().as_str(), "0x40 INA219   e0 r12");
+}
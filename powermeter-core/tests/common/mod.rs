@@ -0,0 +1,131 @@
+// Helpers shared by the test binaries, each uses only some of them.
+#![allow(dead_code)]
+
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::PathBuf;
+
+use embedded_graphics::mono_font::ascii::{FONT_10X20, FONT_9X15};
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::pixelcolor::{Rgb565, Rgb888, RgbColor};
+use embedded_graphics::prelude::*;
+use powermeter_core::display::{COLSTART, DISPLAY_SIZE, ROWSTART};
+use powermeter_core::drivers::sensor::PowerMonitor;
+use powermeter_core::ui::Theme;
+
+const WIDTH: usize = DISPLAY_SIZE.width as usize;
+const HEIGHT: usize = DISPLAY_SIZE.height as usize;
+
+/// The visible 240x135 area of the panel in memory. The UI draws in st7789
+/// frame memory coordinates, `panel()` moves them back to the origin.
+pub struct Framebuffer {
+    pub pixels: Vec<Rgb565>,
+}
+
+impl Framebuffer {
+    pub fn new() -> Self {
+        Framebuffer { pixels: vec![Rgb565::BLACK; WIDTH * HEIGHT] }
+    }
+
+    pub fn panel(&mut self) -> impl DrawTarget<Color=Rgb565, Error=core::convert::Infallible> + '_ {
+        self.translated(Point::new(-ROWSTART, -COLSTART))
+    }
+}
+
+impl OriginDimensions for Framebuffer {
+    fn size(&self) -> Size {
+        DISPLAY_SIZE
+    }
+}
+
+impl DrawTarget for Framebuffer {
+    type Color = Rgb565;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I: IntoIterator<Item=Pixel<Rgb565>>>(&mut self, pixels: I) -> Result<(), Self::Error> {
+        for Pixel(point, color) in pixels {
+            if (0..WIDTH as i32).contains(&point.x) && (0..HEIGHT as i32).contains(&point.y) {
+                self.pixels[point.y as usize * WIDTH + point.x as usize] = color;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The firmware uses profont and seven segment digits, snapshots use the
+/// fonts built into embedded-graphics so they only need host crates.
+pub fn theme() -> Theme<'static, MonoTextStyle<'static, Rgb565>> {
+    let mut large = MonoTextStyle::new(&FONT_10X20, Rgb565::WHITE);
+    large.background_color = Some(Rgb565::BLACK);
+    let mut medium = MonoTextStyle::new(&FONT_9X15, Rgb565::WHITE);
+    medium.background_color = Some(Rgb565::BLACK);
+    Theme {
+        digits: large,
+        large,
+        medium,
+        background: Rgb565::BLACK,
+    }
+}
+
+fn snapshot_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots").join(format!("{}.png", name))
+}
+
+fn write_png(path: &PathBuf, framebuffer: &Framebuffer) {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path).unwrap()), WIDTH as u32, HEIGHT as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let data: Vec<u8> = framebuffer.pixels.iter()
+        .flat_map(|&color| {
+            let color = Rgb888::from(color);
+            [color.r(), color.g(), color.b()]
+        })
+        .collect();
+    encoder.write_header().unwrap().write_image_data(&data).unwrap();
+}
+
+fn read_png(path: &PathBuf) -> Option<Vec<Rgb565>> {
+    let decoder = png::Decoder::new(File::open(path).ok()?);
+    let mut reader = decoder.read_info().unwrap();
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data).unwrap();
+    assert_eq!((info.width, info.height), (WIDTH as u32, HEIGHT as u32), "{} has the wrong size", path.display());
+    assert_eq!(info.color_type, png::ColorType::Rgb);
+    Some(data[..info.buffer_size()].chunks(3)
+        .map(|rgb| Rgb565::from(Rgb888::new(rgb[0], rgb[1], rgb[2])))
+        .collect())
+}
+
+/// Compare with tests/snapshots/<name>.png. Run with UPDATE_SNAPSHOTS=1
+/// to write the current rendering as the new snapshot instead.
+pub fn assert_snapshot(name: &str, framebuffer: &Framebuffer) {
+    let path = snapshot_path(name);
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        write_png(&path, framebuffer);
+        return;
+    }
+    let expected = read_png(&path)
+        .unwrap_or_else(|| panic!("no snapshot {}, run with UPDATE_SNAPSHOTS=1 to create it", path.display()));
+    let differing = expected.iter().zip(&framebuffer.pixels).filter(|(a, b)| a != b).count();
+    if differing != 0 {
+        let actual = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("{}.png", name));
+        fs::create_dir_all(actual.parent().unwrap()).unwrap();
+        write_png(&actual, framebuffer);
+        panic!("{} pixels differ from {}, rendering saved to {}", differing, path.display(), actual.display());
+    }
+}
+
+/// A reading across a 0.1 ohm shunt.
+pub fn reading(voltage: f32, current: f32) -> PowerMonitor {
+    PowerMonitor { shunt: current * 0.1, voltage, current, power: voltage * current }
+}
+
+/// a within tolerance times b of b.
+pub fn close_rel(a: f32, b: f32, tolerance: f32) -> bool {
+    (a - b).abs() <= b.abs() * tolerance
+}
+
+/// a within tolerance of b.
+pub fn close_abs(a: f32, b: f32, tolerance: f32) -> bool {
+    (a - b).abs() <= tolerance
+}
@@ -0,0 +1,134 @@
+use powermeter_core::correction::{Capture, Correction, CAPTURE_SAMPLES};
+use powermeter_core::drivers::ina219::Calibration;
+use powermeter_core::drivers::sensor::PowerMonitor;
+use powermeter_core::ui::Button;
+use powermeter_core::wizard::{Outcome, Step, Wizard};
+
+fn measure(wizard: &mut Wizard, current: f32) -> Outcome {
+    let mut outcome = Outcome::Continue;
+    for _ in 0..CAPTURE_SAMPLES {
+        outcome = wizard.reading(Calibration::Range32V2A, current);
+    }
+    outcome
+}
+
+/// Dial in a reference of up to 9999.9 mA.
+fn enter(wizard: &mut Wizard, tenths: u32) {
+    for digit in format!("{:05}", tenths).bytes() {
+        for _ in 0..digit - b'0' {
+            wizard.button(Button::Next, false);
+        }
+        wizard.button(Button::Select, false);
+    }
+}
+
+#[test]
+fn tare_and_two_point() {
+    let tare = Correction::tare(3.0);
+    assert_eq!(tare.current(3.0), 0.0);
+    assert_eq!(tare.current(103.0), 100.0);
+
+    let correction = Correction::two_point((10.0, 12.0), (110.0, 112.0 + 100.0)).unwrap();
+    assert_eq!(correction.gain, 2.0);
+    assert_eq!(correction.offset, -8.0);
+    assert_eq!(correction.measured(correction.current(42.0)), 42.0);
+
+    // no gain from a single current or a line going down
+    assert_eq!(Correction::two_point((10.0, 12.0), (10.5, 20.0)), None);
+    assert_eq!(Correction::two_point((10.0, 12.0), (110.0, 2.0)), None);
+}
+
+#[test]
+fn power_follows_corrected_current() {
+    let reading = PowerMonitor { shunt: 1.0, voltage: 5.0, current: 12.0, power: 60.0 };
+    let corrected = Correction::tare(2.0).apply(&reading);
+    assert_eq!(corrected, PowerMonitor { shunt: 1.0, voltage: 5.0, current: 10.0, power: 50.0 });
+}
+
+#[test]
+fn date_of_calibration() {
+    assert_eq!(Correction::default().text().as_str(), "not calibrated");
+    let correction = Correction { calibrated_at: Some(1_714_564_800), ..Correction::tare(1.0) };
+    assert_eq!(correction.text().as_str(), "calibrated on 2024-05-01");
+    assert_eq!(Correction::tare(1.0).text().as_str(), "calibrated");
+}
+
+#[test]
+fn capture_averages_readings() {
+    let mut capture = Capture::default();
+    for current in [1.0, 2.0, 3.0] {
+        assert_eq!(capture.add(current), None);
+    }
+    assert_eq!(capture.add(6.0), Some(3.0));
+    // and starts over
+    assert_eq!(capture.add(6.0), None);
+}
+
+#[test]
+fn wizard_tare_only() {
+    let mut wizard = Wizard::new(0, Calibration::Range32V2A, Correction::default());
+    // nothing is measured before Select
+    assert_eq!(measure(&mut wizard, 2.0), Outcome::Continue);
+    assert_eq!(wizard.step(), Step::Zero);
+    wizard.button(Button::Select, false);
+    assert_eq!(measure(&mut wizard, 2.0), Outcome::Continue);
+    assert_eq!(wizard.step(), Step::Reference { point: 0, entry: 0, cursor: 0 });
+    assert_eq!(wizard.button(Button::Select, true), Outcome::Done(Some(Correction::tare(2.0))));
+}
+
+#[test]
+fn wizard_undoes_the_previous_correction() {
+    let mut wizard = Wizard::new(0, Calibration::Range32V2A, Correction::tare(5.0));
+    wizard.button(Button::Select, false);
+    // reads -3 with the old tare, the sensor measured 2
+    measure(&mut wizard, -3.0);
+    assert_eq!(wizard.button(Button::Select, true), Outcome::Done(Some(Correction::tare(2.0))));
+}
+
+#[test]
+fn wizard_reference_points() {
+    let mut wizard = Wizard::new(1, Calibration::Range32V2A, Correction::default());
+    wizard.button(Button::Select, false);
+    measure(&mut wizard, 0.0);
+
+    // Previous wraps the digit, 9 then 8
+    wizard.button(Button::Previous, false);
+    wizard.button(Button::Previous, false);
+    assert_eq!(wizard.step(), Step::Reference { point: 0, entry: 80000, cursor: 0 });
+    wizard.button(Button::Next, false);
+    wizard.button(Button::Next, false);
+    enter(&mut wizard, 1000);
+    assert_eq!(wizard.step(), Step::Measuring);
+    // readings of another range are not used
+    for _ in 0..CAPTURE_SAMPLES {
+        wizard.reading(Calibration::Range32V1A, 0.0);
+    }
+    assert_eq!(measure(&mut wizard, 98.0), Outcome::Continue);
+
+    enter(&mut wizard, 5000);
+    let Outcome::Done(Some(correction)) = measure(&mut wizard, 488.0) else {
+        panic!("no correction");
+    };
+    assert!((correction.gain - 400.0 / 390.0).abs() < 1e-6);
+    assert!((correction.current(98.0) - 100.0).abs() < 0.001);
+    assert!((correction.current(488.0) - 500.0).abs() < 0.001);
+}
+
+#[test]
+fn wizard_one_point_goes_through_zero() {
+    let mut wizard = Wizard::new(0, Calibration::Range32V2A, Correction::default());
+    wizard.button(Button::Select, false);
+    measure(&mut wizard, 1.0);
+    enter(&mut wizard, 1000);
+    measure(&mut wizard, 51.0);
+    assert_eq!(wizard.button(Button::Select, true), Outcome::Done(Correction::two_point((1.0, 0.0), (51.0, 100.0))));
+    assert_eq!(Correction::two_point((1.0, 0.0), (51.0, 100.0)).unwrap().gain, 2.0);
+}
+
+#[test]
+fn wizard_cancel() {
+    let mut wizard = Wizard::new(0, Calibration::Range32V2A, Correction::default());
+    assert_eq!(wizard.button(Button::Select, true), Outcome::Cancel);
+    wizard.button(Button::Select, false);
+    assert_eq!(wizard.button(Button::Select, true), Outcome::Cancel);
+}
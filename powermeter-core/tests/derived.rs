@@ -0,0 +1,95 @@
+mod common;
+
+use common::{close_rel, reading};
+use powermeter_core::derived::{conductance, cost, efficiency, resistance, Energy, Integrator, Interval, MAX_GAP_US};
+
+#[test]
+fn resistance_and_conductance_of_a_load() {
+    let load = reading(5.0, 50.0);
+    assert!(close_rel(resistance(&load).unwrap(), 100.0, 1e-4));
+    assert!(close_rel(conductance(&load).unwrap(), 10.0, 1e-4));
+    // current flowing back keeps its sign
+    assert!(close_rel(resistance(&reading(5.0, -50.0)).unwrap(), -100.0, 1e-4));
+}
+
+#[test]
+fn no_load_has_no_resistance() {
+    assert_eq!(resistance(&reading(5.0, 0.0)), None);
+    assert_eq!(resistance(&reading(5.0, 0.01)), None);
+    // an open circuit conducts nothing
+    assert_eq!(conductance(&reading(5.0, 0.0)), Some(0.0));
+}
+
+#[test]
+fn no_voltage_has_no_conductance() {
+    assert_eq!(conductance(&reading(0.0, 10.0)), None);
+    // a short
+    assert_eq!(resistance(&reading(0.0, 10.0)), Some(0.0));
+}
+
+#[test]
+fn efficiency_of_a_converter() {
+    let input = reading(5.0, 100.0);
+    let output = reading(3.3, 120.0);
+    assert!(close_rel(efficiency(&input, &output).unwrap(), 396.0 / 500.0, 1e-4));
+    // the converter is off
+    assert_eq!(efficiency(&reading(5.0, 0.0), &output), None);
+    assert_eq!(efficiency(&reading(0.0, 0.0), &reading(0.0, 0.0)), None);
+}
+
+#[test]
+fn cost_of_energy() {
+    // 1 kWh
+    assert!(close_rel(cost(1_000_000.0, 0.3), 0.3, 1e-4));
+    assert_eq!(cost(0.0, 0.3), 0.0);
+    assert_eq!(cost(500.0, 0.0), 0.0);
+}
+
+#[test]
+fn energy_integrates_the_power() {
+    let mut energy = Energy::default();
+    // 1 W for an hour in one second steps
+    for second in 0..=3600u64 {
+        energy.add(1000.0, second * 1_000_000);
+    }
+    assert!((energy.mwh() - 1000.0).abs() < 1e-6);
+}
+
+#[test]
+fn energy_follows_a_ramp() {
+    let mut energy = Energy::default();
+    energy.add(0.0, 0);
+    energy.add(3600.0, 1_000_000);
+    // the mean of 1800 mW for a second
+    assert!((energy.mwh() - 0.5).abs() < 1e-9);
+}
+
+#[test]
+fn energy_skips_gaps() {
+    let mut energy = Energy::default();
+    energy.add(1000.0, 0);
+    energy.add(1000.0, MAX_GAP_US + 1);
+    assert_eq!(energy.mwh(), 0.0);
+    energy.add(1000.0, MAX_GAP_US + 1 + 3_600_000);
+    assert!((energy.mwh() - 1.0).abs() < 1e-9);
+    energy.reset();
+    assert_eq!(energy.mwh(), 0.0);
+}
+
+#[test]
+fn integrator_charges_the_interval_to_its_first_sample() {
+    let mut integrator = Integrator::new();
+    assert_eq!(integrator.add(1.0, 0), None);
+    assert_eq!(integrator.add(20.0, 1_000), Some(Interval { duration_us: 1_000, value: 1.0 }));
+    let interval = integrator.add(1.0, 1_500).unwrap();
+    assert_eq!(interval, Interval { duration_us: 500, value: 20.0 });
+    assert_eq!(interval.area(), 10_000.0);
+}
+
+#[test]
+fn integrator_skips_gaps() {
+    let mut integrator = Integrator::new();
+    integrator.add(1.0, 0);
+    assert_eq!(integrator.add(2.0, MAX_GAP_US + 1), None);
+    assert_eq!(integrator.add(3.0, MAX_GAP_US + 2), Some(Interval { duration_us: 1, value: 2.0 }));
+}
@@ -0,0 +1,87 @@
+use powermeter_core::events::{event_line, EventLog, LipoWarning, SpikeDetector, BROWN_OUT_RESET, MAX_EVENTS};
+use powermeter_protocol::event::{Event, EventKind};
+
+// 2026-03-01T12:00:00Z
+const UNIX_US: i64 = 1_772_366_400_000_000;
+
+fn event(kind: EventKind, value: f32) -> Event {
+    Event {
+        sequence: 0,
+        boot: 4,
+        kind,
+        channel: 1,
+        uptime_us: 3_723_000_000,
+        unix_us: None,
+        value,
+        duration_us: 8_000,
+    }
+}
+
+#[test]
+fn boot_counts_up_from_the_restored_events() {
+    let log = EventLog::restore([], 0);
+    assert_eq!(log.boot(), 0);
+
+    let mut log = EventLog::restore([event(EventKind::Boot, 1.0)], 12);
+    assert_eq!(log.boot(), 5);
+    let recorded = log.record(EventKind::Dropout, 0, 3.2, 8_000, 1_000_000, Some(UNIX_US));
+    assert_eq!(recorded.sequence, 12);
+    assert_eq!(recorded.boot, 5);
+    assert_eq!(log.events().back(), Some(&recorded));
+    assert_eq!(log.record(EventKind::Boot, 0, 1.0, 0, 0, None).sequence, 13);
+}
+
+#[test]
+fn full_log_drops_the_oldest() {
+    let mut log = EventLog::default();
+    for i in 0..MAX_EVENTS + 5 {
+        log.record(EventKind::OverCurrent, 0, i as f32, 0, i as u64, None);
+    }
+    assert_eq!(log.events().len(), MAX_EVENTS);
+    assert_eq!(log.events().front().map(|event| event.sequence), Some(5));
+    assert_eq!(log.events().back().map(|event| event.value), Some((MAX_EVENTS + 4) as f32));
+}
+
+#[test]
+fn lines_name_the_event() {
+    assert_eq!(event_line(&event(EventKind::Dropout, 3.2)), "+1:02:03 Drop 3.20V 8ms");
+    assert_eq!(event_line(&Event { unix_us: Some(UNIX_US + 61_000_000), ..event(EventKind::OverCurrent, 512.4) }),
+               "12:01:01 Spike 512mA 8ms");
+    assert_eq!(event_line(&Event { duration_us: 1_540_000, ..event(EventKind::OverCurrent, 1812.0) }),
+               "+1:02:03 Spike 1812mA 1.5s");
+    assert_eq!(event_line(&event(EventKind::RangeChange, 2.0)), "+1:02:03 Ch2 16V - 400mA");
+    assert_eq!(event_line(&event(EventKind::SensorError, 3.0)), "+1:02:03 Ch2 Sensor lost");
+    assert_eq!(event_line(&event(EventKind::BatteryLow, 3.38)), "+1:02:03 LiPo low 3.38V");
+    assert_eq!(event_line(&event(EventKind::Boot, 1.0)), "+1:02:03 Boot #4");
+    assert_eq!(event_line(&event(EventKind::Boot, BROWN_OUT_RESET as f32)), "+1:02:03 Brown-out #4");
+}
+
+#[test]
+fn spike_is_reported_with_its_peak() {
+    let mut detector = SpikeDetector::new(500.0);
+    let currents = [100.0, 650.0, 900.0, 520.0, 120.0, 100.0];
+    let spikes: Vec<_> = currents.iter().enumerate()
+        .filter_map(|(ms, current)| detector.add(*current, ms as u64 * 1000))
+        .collect();
+    assert_eq!(spikes.len(), 1);
+    assert_eq!(spikes[0].start_us, 1000);
+    assert_eq!(spikes[0].duration_us, 3000);
+    assert_eq!(spikes[0].peak_ma, 900.0);
+
+    let mut off = SpikeDetector::new(0.0);
+    assert_eq!(off.add(5000.0, 0), None);
+    assert_eq!(off.add(0.0, 1000), None);
+}
+
+#[test]
+fn lipo_warns_once_until_charged() {
+    let mut warning = LipoWarning::default();
+    assert!(!warning.update(3.9));
+    assert!(warning.update(3.35));
+    assert!(!warning.update(3.3));
+    // a little recovery under load is not a charge
+    assert!(!warning.update(3.5));
+    assert!(!warning.update(3.35));
+    assert!(!warning.update(3.8));
+    assert!(warning.update(3.39));
+}
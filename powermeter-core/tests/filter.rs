@@ -0,0 +1,125 @@
+mod common;
+
+use common::close_abs;
+use powermeter_core::drivers::sensor::PowerMonitor;
+use powermeter_core::filter::{Filter, FilterKind, ReadingFilter, EMA_ALPHA, MEDIAN_WINDOW, WINDOW};
+
+/// Outputs for a step from 0 to 100 after a settled start.
+fn step(kind: FilterKind, samples: usize) -> Vec<f32> {
+    let mut filter = Filter::new(kind);
+    for _ in 0..WINDOW {
+        assert_eq!(filter.update(0.0), 0.0);
+    }
+    (0..samples).map(|_| filter.update(100.0)).collect()
+}
+
+/// Outputs after a single sample of 100 in a settled start of 0.
+fn impulse(kind: FilterKind, samples: usize) -> Vec<f32> {
+    let mut filter = Filter::new(kind);
+    for _ in 0..WINDOW {
+        filter.update(0.0);
+    }
+    let mut outputs = vec![filter.update(100.0)];
+    outputs.extend((1..samples).map(|_| filter.update(0.0)));
+    outputs
+}
+
+#[test]
+fn off_passes_samples() {
+    assert_eq!(step(FilterKind::Off, 2), [100.0, 100.0]);
+    assert_eq!(impulse(FilterKind::Off, 2), [100.0, 0.0]);
+}
+
+#[test]
+fn moving_average_step_is_a_ramp() {
+    let outputs = step(FilterKind::MovingAverage, WINDOW + 1);
+    for (i, output) in outputs.iter().enumerate().take(WINDOW) {
+        assert!(close_abs(*output, 100.0 * (i + 1) as f32 / WINDOW as f32, 1e-4), "{} {}", i, output);
+    }
+    assert!(close_abs(outputs[WINDOW], 100.0, 1e-4));
+}
+
+#[test]
+fn moving_average_spreads_an_impulse() {
+    let outputs = impulse(FilterKind::MovingAverage, WINDOW + 1);
+    for output in &outputs[..WINDOW] {
+        assert!(close_abs(*output, 100.0 / WINDOW as f32, 1e-4));
+    }
+    assert!(close_abs(outputs[WINDOW], 0.0, 1e-4));
+}
+
+#[test]
+fn exponential_step_approaches_geometrically() {
+    let outputs = step(FilterKind::Exponential, 20);
+    for (i, output) in outputs.iter().enumerate() {
+        let expected = 100.0 * (1.0 - (1.0 - EMA_ALPHA).powi(i as i32 + 1));
+        assert!(close_abs(*output, expected, 1e-4), "{} {}", i, output);
+    }
+    assert!(outputs.windows(2).all(|pair| pair[1] > pair[0]));
+}
+
+#[test]
+fn exponential_impulse_decays() {
+    let outputs = impulse(FilterKind::Exponential, 10);
+    assert!(close_abs(outputs[0], 100.0 * EMA_ALPHA, 1e-4));
+    for pair in outputs.windows(2) {
+        assert!(close_abs(pair[1], pair[0] * (1.0 - EMA_ALPHA), 1e-4));
+    }
+}
+
+#[test]
+fn exponential_starts_at_the_first_sample() {
+    let mut filter = Filter::new(FilterKind::Exponential);
+    assert_eq!(filter.update(42.0), 42.0);
+}
+
+#[test]
+fn median_step_switches_at_the_majority() {
+    let outputs = step(FilterKind::Median, MEDIAN_WINDOW);
+    let majority = MEDIAN_WINDOW / 2;
+    assert!(outputs[..majority].iter().all(|output| *output == 0.0));
+    assert!(outputs[majority..].iter().all(|output| *output == 100.0));
+}
+
+#[test]
+fn median_rejects_an_impulse() {
+    assert!(impulse(FilterKind::Median, MEDIAN_WINDOW + 1).iter().all(|output| *output == 0.0));
+
+    // two spikes in a row are still a minority
+    let mut filter = Filter::new(FilterKind::Median);
+    for sample in [0.0, 0.0, 0.0, 100.0, 100.0] {
+        assert_eq!(filter.update(sample), 0.0);
+    }
+}
+
+#[test]
+fn median_before_the_window_is_full() {
+    let mut filter = Filter::new(FilterKind::Median);
+    assert_eq!(filter.update(10.0), 10.0);
+    assert_eq!(filter.update(20.0), 15.0);
+    assert_eq!(filter.update(-5.0), 10.0);
+}
+
+#[test]
+fn another_kind_forgets_the_history() {
+    let mut filter = Filter::new(FilterKind::MovingAverage);
+    filter.update(100.0);
+    filter.set_kind(FilterKind::Exponential);
+    assert_eq!(filter.kind(), FilterKind::Exponential);
+    assert_eq!(filter.update(0.0), 0.0);
+}
+
+#[test]
+fn reading_power_follows_filtered_values() {
+    let mut filter = ReadingFilter::new(FilterKind::MovingAverage);
+    filter.update(&PowerMonitor { shunt: 1.0, voltage: 5.0, current: 100.0, power: 500.0 });
+    let filtered = filter.update(&PowerMonitor { shunt: 3.0, voltage: 5.0, current: 300.0, power: 1500.0 });
+    assert!(close_abs(filtered.shunt, 2.0, 1e-4));
+    assert!(close_abs(filtered.current, 200.0, 1e-4));
+    assert!(close_abs(filtered.power, 1000.0, 1e-4));
+
+    // unfiltered keeps the power of the sensor
+    let mut off = ReadingFilter::default();
+    let reading = PowerMonitor { shunt: 1.0, voltage: 5.0, current: 100.0, power: 498.0 };
+    assert_eq!(off.update(&reading), reading);
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use common::close_rel;
+use powermeter_core::histogram::{bucket, label, Histogram, BUCKETS};
+
+/// 1 ms samples of a radio on for on_ms every period_ms.
+fn radio(histogram: &mut Histogram, periods: u64, period_ms: u64, on_ms: u64) {
+    for ms in 0..periods * period_ms {
+        let current = if ms % period_ms < on_ms { 50.0 } else { 0.005 };
+        histogram.add(current, ms * 1000);
+    }
+}
+
+#[test]
+fn buckets_are_half_decades() {
+    assert_eq!(bucket(-1.0), 0);
+    assert_eq!(bucket(0.0005), 0);
+    assert_eq!(bucket(0.001), 1);
+    assert_eq!(bucket(0.005), 2);
+    assert_eq!(bucket(2.0), 7);
+    assert_eq!(bucket(50.0), 10);
+    assert_eq!(bucket(5000.0), BUCKETS - 1);
+    assert_eq!(label(2), "3-10uA");
+    assert_eq!(label(10), "30-100mA");
+}
+
+#[test]
+fn bimodal_load_splits_time_and_charge() {
+    let mut histogram = Histogram::new();
+    radio(&mut histogram, 10, 1000, 100);
+    let on = bucket(50.0);
+    let off = bucket(0.005);
+    assert!(close_rel(histogram.time_fraction(on).unwrap(), 0.1, 0.01));
+    assert!(close_rel(histogram.time_fraction(off).unwrap(), 0.9, 0.01));
+    // the radio draws almost all of the charge in a tenth of the time
+    assert!(histogram.charge_fraction(on).unwrap() > 0.999);
+    // 50 mA for 1 s
+    assert!(close_rel(histogram.buckets()[on].charge_mah(), 50.0 / 3600.0, 0.01));
+    assert_eq!(histogram.busiest(), Some(off));
+    let empty = bucket(1.0);
+    assert_eq!(histogram.time_fraction(empty), Some(0.0));
+    assert_eq!(histogram.text(on).as_str(), "30-100mA 10.0% 13.9uAh");
+}
+
+#[test]
+fn gaps_are_left_out() {
+    let mut histogram = Histogram::new();
+    histogram.add(1.0, 0);
+    histogram.add(1.0, 1_000_000);
+    // the sensor was lost for a minute at 100 mA
+    histogram.add(100.0, 2_000_000);
+    histogram.add(1.0, 62_000_000);
+    assert_eq!(histogram.total_us(), 2_000_000);
+    assert_eq!(histogram.time_fraction(bucket(100.0)), Some(0.0));
+}
+
+#[test]
+fn reset_starts_over() {
+    let mut histogram = Histogram::new();
+    assert_eq!(histogram.time_fraction(0), None);
+    assert_eq!(histogram.busiest(), None);
+    radio(&mut histogram, 2, 100, 10);
+    histogram.reset();
+    assert_eq!(histogram, Histogram::new());
+    assert_eq!(histogram.text(7).as_str(), "1-3mA ---");
+}
@@ -0,0 +1,142 @@
+use embedded_hal::i2c::{ErrorKind, NoAcknowledgeSource};
+use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+use powermeter_core::drivers::ina219::{scan, Calibration, Ina219, INA219_ADDR};
+use powermeter_core::drivers::sensor::{PowerMonitor, PowerSensor, Sampling};
+use powermeter_protocol::compact;
+
+fn read(reg: u8, value: u16) -> Transaction {
+    Transaction::write_read(INA219_ADDR, vec![reg], value.to_be_bytes().to_vec())
+}
+
+fn write(reg: u8, value: u16) -> Transaction {
+    let [msb, lsb] = value.to_be_bytes();
+    Transaction::write(INA219_ADDR, vec![reg, msb, lsb])
+}
+
+#[test]
+fn init_programs_calibration_then_config() {
+    let mut i2c = Mock::new(&[write(0x05, 10240), write(0x00, 0x399F)]);
+    let mut ina219 = Ina219::new(i2c.clone());
+    ina219.init(Calibration::Range32V1A).unwrap();
+    assert_eq!(ina219.calibration(), Calibration::Range32V1A);
+    i2c.done();
+}
+
+#[test]
+fn sense_converts_registers() {
+    let mut i2c = Mock::new(&[
+        write(0x05, 4096),
+        // 1.2 mV over the shunt
+        read(0x01, 120),
+        // 5.0 V, conversion ready
+        read(0x02, 1250 << 3 | 0x02),
+        // 12.0 mA
+        read(0x04, 120),
+        // 60 mW
+        read(0x03, 30),
+    ]);
+    let mut ina219 = Ina219::new(i2c.clone());
+    let reading = ina219.sense().unwrap();
+    let expected = PowerMonitor {
+        shunt: 1.2,
+        voltage: 5.0,
+        current: 12.0,
+        power: 60.0,
+    };
+    assert!((reading.shunt - expected.shunt).abs() < 1e-4);
+    assert!((reading.voltage - expected.voltage).abs() < 1e-4);
+    assert!((reading.current - expected.current).abs() < 1e-4);
+    assert!((reading.power - expected.power).abs() < 1e-4);
+    i2c.done();
+}
+
+#[test]
+fn negative_current_keeps_its_sign() {
+    let mut i2c = Mock::new(&[
+        write(0x05, 8192),
+        write(0x00, 0x019F),
+        write(0x05, 8192),
+        read(0x01, (-250i16) as u16),
+        read(0x02, 400 << 3),
+        read(0x04, (-500i16) as u16),
+        read(0x03, 40),
+    ]);
+    let mut ina219 = Ina219::new(i2c.clone());
+    ina219.init(Calibration::Range16V400mA).unwrap();
+    let reading = ina219.sense().unwrap();
+    assert!((reading.shunt + 2.5).abs() < 1e-4);
+    assert!((reading.voltage - 1.6).abs() < 1e-4);
+    assert!((reading.current + 25.0).abs() < 1e-4);
+    assert!((reading.power - 40.0).abs() < 1e-4);
+    i2c.done();
+}
+
+#[test]
+fn registers_match_the_compact_decoder() {
+    let mut i2c = Mock::new(&[
+        read(0x00, 0x399F),
+        read(0x05, 4096),
+        read(0x01, 120),
+        read(0x02, 1250 << 3),
+        read(0x03, 30),
+        read(0x04, 120),
+    ]);
+    let mut ina219 = Ina219::new(i2c.clone());
+    let registers = ina219.registers().unwrap();
+    assert_eq!(registers, compact::Calibration { config: 0x399F, calibration: 4096, shunt_uohm: 100_000 });
+    assert!((registers.current_lsb_ma() - Calibration::Range32V2A.current_lsb_ma()).abs() < 1e-6);
+    assert!((registers.power_lsb_mw() - Calibration::Range32V2A.power_lsb_mw()).abs() < 1e-6);
+
+    let sample = ina219.raw_sample(42).unwrap();
+    assert_eq!(sample.timestamp_us, 42);
+    assert!((sample.current(&registers) - 12.0).abs() < 1e-4);
+    i2c.done();
+}
+
+#[test]
+fn scan_finds_every_answering_address() {
+    let expectations: Vec<Transaction> = (0x40..=0x4F)
+        .map(|address| match address {
+            0x40 | 0x41 | 0x45 => Transaction::read(address, vec![0]),
+            _ => Transaction::read(address, vec![0]).with_error(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address)),
+        })
+        .collect();
+    let mut i2c = Mock::new(&expectations);
+    assert_eq!(scan(&mut i2c).as_slice(), &[0x40, 0x41, 0x45]);
+    i2c.done();
+}
+
+#[test]
+fn triggered_conversion_is_read_once() {
+    let mut i2c = Mock::new(&[
+        // shunt and bus triggered
+        write(0x00, 0x399B),
+        write(0x00, 0x399B),
+        // still converting
+        read(0x02, 1250 << 3),
+        read(0x02, 1250 << 3 | 0x02),
+        write(0x05, 4096),
+        read(0x01, 120),
+        read(0x02, 1250 << 3 | 0x02),
+        read(0x04, 120),
+        // reading the power clears CNVR
+        read(0x03, 30),
+        read(0x02, 1250 << 3),
+    ]);
+    let mut ina219 = Ina219::new(i2c.clone());
+    ina219.set_sampling(Sampling::Triggered).unwrap();
+    ina219.trigger().unwrap();
+    assert_eq!(ina219.sense_ready().unwrap(), None);
+    let reading = ina219.sense_ready().unwrap().unwrap();
+    assert!((reading.current - 12.0).abs() < 1e-4);
+    assert_eq!(ina219.sense_ready().unwrap(), None);
+    i2c.done();
+}
+
+#[test]
+fn continuous_needs_no_trigger() {
+    let mut i2c = Mock::new(&[]);
+    let mut ina219 = Ina219::new(i2c.clone());
+    ina219.trigger().unwrap();
+    i2c.done();
+}
@@ -0,0 +1,83 @@
+use powermeter_core::derived::Derived;
+use powermeter_core::drivers::sensor::PowerMonitor;
+use powermeter_core::modifier::{Modified, Modifier};
+use powermeter_core::ui::{reading_values, PageValues, PowerDisplay};
+
+fn values(voltage: f32, current: f32) -> PageValues {
+    let reading = PowerMonitor {
+        shunt: current / 10.0,
+        voltage,
+        current,
+        power: voltage * current,
+    };
+    reading_values(&reading, &Derived::new(&reading, None))
+}
+
+#[test]
+fn off_shows_the_reading() {
+    let modified = Modified::default();
+    assert_eq!(modified.value(PowerDisplay::Current, &values(5.0, 20.0)), Some(20.0));
+    assert_eq!(modified.modifier().annunciator(), "");
+}
+
+#[test]
+fn hold_freezes_every_page() {
+    let mut modified = Modified::new(Modifier::Hold, &values(5.0, 20.0));
+    let later = values(4.0, 30.0);
+    modified.update(&later);
+    assert_eq!(modified.value(PowerDisplay::Voltage, &later), Some(5.0));
+    assert_eq!(modified.value(PowerDisplay::Current, &later), Some(20.0));
+    assert_eq!(modified.value(PowerDisplay::Power, &later), Some(100.0));
+    assert_eq!(modified.value(PowerDisplay::Resistance, &later), Some(250.0));
+}
+
+#[test]
+fn relative_shows_the_deviation() {
+    let mut modified = Modified::new(Modifier::Relative, &values(5.0, 20.0));
+    assert_eq!(modified.value(PowerDisplay::Current, &values(5.0, 25.0)), Some(5.0));
+    assert_eq!(modified.value(PowerDisplay::Current, &values(5.0, 15.0)), Some(-5.0));
+    assert_eq!(modified.value(PowerDisplay::Voltage, &values(4.5, 15.0)), Some(-0.5));
+
+    // a reset takes the latest reading as reference
+    modified.reset(&values(5.0, 15.0));
+    assert_eq!(modified.value(PowerDisplay::Current, &values(5.0, 15.0)), Some(0.0));
+
+    // nothing to compare without a load
+    assert_eq!(modified.value(PowerDisplay::Resistance, &values(5.0, 0.0)), None);
+}
+
+#[test]
+fn peaks_keep_the_extremes() {
+    let mut max = Modified::new(Modifier::Max, &values(5.0, 20.0));
+    let mut min = Modified::new(Modifier::Min, &values(5.0, 20.0));
+    for current in [10.0, 40.0, 30.0] {
+        max.update(&values(5.0, current));
+        min.update(&values(5.0, current));
+    }
+    let latest = values(5.0, 30.0);
+    assert_eq!(max.value(PowerDisplay::Current, &latest), Some(40.0));
+    assert_eq!(max.value(PowerDisplay::Power, &latest), Some(200.0));
+    assert_eq!(min.value(PowerDisplay::Current, &latest), Some(10.0));
+
+    max.reset(&latest);
+    assert_eq!(max.value(PowerDisplay::Current, &latest), Some(30.0));
+}
+
+#[test]
+fn peaks_start_with_the_first_value() {
+    // no resistance without a load, the first loaded reading is the peak
+    let mut max = Modified::new(Modifier::Max, &values(5.0, 0.0));
+    max.update(&values(5.0, 10.0));
+    assert_eq!(max.value(PowerDisplay::Resistance, &values(5.0, 0.0)), Some(500.0));
+}
+
+#[test]
+fn modifiers_cycle_back_to_off() {
+    let mut modifier = Modifier::Off;
+    let mut annunciators = Vec::new();
+    for _ in 0..5 {
+        modifier = modifier.next_wrapping();
+        annunciators.push(modifier.annunciator());
+    }
+    assert_eq!(annunciators, ["HOLD", "REL", "MAX", "MIN", ""]);
+}
@@ -0,0 +1,228 @@
+mod common;
+
+use common::reading;
+use powermeter_core::channel::Channel;
+use powermeter_core::drivers::sensor::PowerMonitor;
+use powermeter_core::filter::{FilterKind, FilterTarget};
+use powermeter_core::histogram::bucket;
+use powermeter_core::pipeline::{Consumers, Outputs, Pipeline, Recover, Route, DISPLAY_INTERVAL_US, LIVE_INTERVAL_US, LOG_INTERVAL_US,
+                                STREAM_INTERVAL_US};
+use powermeter_core::quality::QualitySettings;
+use powermeter_core::supervisor::BusStatus;
+use powermeter_core::ui::Input;
+use powermeter_protocol::event::EventKind;
+
+/// Every sample at interval_us on channel index, the outputs of the last.
+fn process(pipeline: &mut Pipeline, consumers: &Consumers, index: usize, channel: &mut Channel,
+           samples: &[PowerMonitor], start_us: u64, interval_us: u64) -> Outputs {
+    let mut outputs = Outputs::default();
+    for (n, sample) in samples.iter().enumerate() {
+        let timestamp_us = start_us + n as u64 * interval_us;
+        pipeline.route(consumers, timestamp_us);
+        outputs = pipeline.process(index, channel, sample, timestamp_us);
+    }
+    outputs
+}
+
+#[test]
+fn fastest_consumer_sets_the_interval() {
+    let mut consumers = Consumers::default();
+    assert_eq!(consumers.sample_interval_us(), DISPLAY_INTERVAL_US);
+    consumers.live = true;
+    assert_eq!(consumers.sample_interval_us(), LIVE_INTERVAL_US);
+    consumers.logging = true;
+    assert_eq!(consumers.sample_interval_us(), LOG_INTERVAL_US);
+    consumers.streaming = true;
+    assert_eq!(consumers.sample_interval_us(), STREAM_INTERVAL_US);
+}
+
+#[test]
+fn idle_sends_every_sample_to_the_display() {
+    let mut pipeline = Pipeline::new(1, 0.0);
+    let consumers = Consumers::default();
+    for n in 0..3 {
+        let route = pipeline.route(&consumers, n * DISPLAY_INTERVAL_US);
+        assert_eq!(route, Route { display: true, ..Route::default() });
+    }
+}
+
+#[test]
+fn display_and_live_are_decimated() {
+    let mut pipeline = Pipeline::new(1, 0.0);
+    let consumers = Consumers { logging: true, live: true, ..Consumers::default() };
+    let mut display = 0;
+    let mut live = 0;
+    let mut log = 0;
+    // three seconds at the log interval
+    for n in 0..300 {
+        let route = pipeline.route(&consumers, n * LOG_INTERVAL_US);
+        display += route.display as u32;
+        live += route.live as u32;
+        log += route.log as u32;
+        assert!(!route.stream && !route.raw);
+    }
+    assert_eq!(display, 3);
+    assert_eq!(live, 30);
+    assert_eq!(log, 300);
+}
+
+#[test]
+fn raw_logging_gets_one_header() {
+    let mut pipeline = Pipeline::new(1, 0.0);
+    let raw = Consumers { logging: true, raw_logging: true, ..Consumers::default() };
+    let first = pipeline.route(&raw, 0);
+    assert!(first.raw && first.raw_header && !first.log);
+    let second = pipeline.route(&raw, LOG_INTERVAL_US);
+    assert!(second.raw && !second.raw_header);
+
+    pipeline.route(&Consumers::default(), 2 * LOG_INTERVAL_US);
+    assert!(pipeline.route(&raw, 3 * LOG_INTERVAL_US).raw_header);
+}
+
+#[test]
+fn primary_channel_feeds_the_consumers() {
+    let mut pipeline = Pipeline::new(2, 0.0);
+    let consumers = Consumers::default();
+    let mut primary = Channel::new(0x40);
+    let outputs = process(&mut pipeline, &consumers, 0, &mut primary, &[reading(5.0, 10.0); 2], 0, DISPLAY_INTERVAL_US);
+    assert_eq!(outputs.sample.map(|sample| sample.current), Some(10.0));
+    assert!(outputs.events.is_empty() && !outputs.burst);
+    let inputs: Vec<Input> = outputs.inputs().collect();
+    assert!(matches!(inputs[0], Input::Battery(average) if average.current_ma == 10.0));
+    assert!(matches!(inputs[1], Input::Histogram(histogram) if histogram.total_us() == DISPLAY_INTERVAL_US));
+    assert!(matches!(inputs[2], Input::Reading { channel: 0, reading, .. } if reading.current == 10.0));
+    assert_eq!(inputs.len(), 3);
+    assert_eq!(primary.stats.samples, 2);
+
+    let mut other = Channel::new(0x41);
+    let outputs = process(&mut pipeline, &consumers, 1, &mut other, &[reading(5.0, 10.0)], 0, DISPLAY_INTERVAL_US);
+    assert_eq!(outputs.sample, None);
+    let inputs: Vec<Input> = outputs.inputs().collect();
+    assert!(matches!(inputs[..], [Input::Reading { channel: 1, .. }]));
+}
+
+#[test]
+fn pages_get_the_primary_channel_while_up() {
+    let mut pipeline = Pipeline::new(1, 0.0);
+    let consumers = Consumers { profiling: true, quality: true, spectrum: true, ..Consumers::default() };
+    let mut channel = Channel::new(0x40);
+    let outputs = process(&mut pipeline, &consumers, 0, &mut channel, &[reading(5.0, 1.0); 3], 0, DISPLAY_INTERVAL_US);
+    assert!(outputs.burst);
+    let inputs: Vec<Input> = outputs.inputs().collect();
+    assert!(inputs.iter().any(|input| matches!(input, Input::Profile(profile) if profile.average_current == Some(1.0))));
+    assert!(inputs.iter().any(|input| matches!(input, Input::Quality(quality) if quality.window.is_some())));
+}
+
+#[test]
+fn filters_are_per_consumer() {
+    let mut pipeline = Pipeline::new(1, 0.0);
+    pipeline.set_filter(FilterTarget::Display, FilterKind::MovingAverage);
+    let consumers = Consumers::default();
+    let mut channel = Channel::new(0x40);
+    let samples = [reading(5.0, 0.0), reading(5.0, 10.0)];
+    let outputs = process(&mut pipeline, &consumers, 0, &mut channel, &samples, 0, DISPLAY_INTERVAL_US);
+    assert_eq!(outputs.sample.map(|sample| sample.current), Some(10.0));
+    let displayed = outputs.inputs().find_map(|input| match input {
+        Input::Reading { reading, .. } => Some(reading.current),
+        _ => None,
+    });
+    assert_eq!(displayed, Some(5.0));
+    // the statistics are as measured
+    assert_eq!(channel.stats.max_current, 10.0);
+}
+
+#[test]
+fn spikes_and_dropouts_are_events() {
+    let mut pipeline = Pipeline::new(2, 100.0);
+    pipeline.set_quality(QualitySettings { dropout_v: 4.5, ..QualitySettings::default() });
+    let consumers = Consumers::default();
+    let mut channel = Channel::new(0x40);
+    let samples = [reading(5.0, 10.0), reading(4.0, 200.0), reading(5.0, 10.0)];
+    let outputs = process(&mut pipeline, &consumers, 0, &mut channel, &samples, 0, 1000);
+    let kinds: Vec<EventKind> = outputs.events.iter().map(|event| event.kind).collect();
+    assert_eq!(kinds, [EventKind::OverCurrent, EventKind::Dropout]);
+    assert_eq!(outputs.events[0].value, 200.0);
+    assert_eq!((outputs.events[0].at_us, outputs.events[0].duration_us), (1000, 1000));
+    assert_eq!(outputs.events[1].value, 4.0);
+
+    // only the primary channel watches the voltage
+    let mut other = Channel::new(0x41);
+    let outputs = process(&mut pipeline, &consumers, 1, &mut other, &samples, 0, 1000);
+    assert_eq!(outputs.events.len(), 1);
+    assert_eq!(outputs.events[0].channel, 1);
+}
+
+#[test]
+fn lost_sensor_power_cycles_only_alone() {
+    let mut pipeline = Pipeline::new(2, 0.0);
+    let fail = |pipeline: &mut Pipeline, index| (0..3).filter_map(|_| pipeline.failed(index)).last();
+    assert_eq!(fail(&mut pipeline, 1), Some(Recover::ClockOut));
+    assert_eq!(pipeline.status(1), BusStatus::Recovering);
+    // the other sensor still answers, the rail is fine
+    assert!(matches!(fail(&mut pipeline, 1), Some(Recover::Retry { .. })));
+    assert!(matches!(fail(&mut pipeline, 0), Some(Recover::ClockOut)));
+    assert!(matches!(fail(&mut pipeline, 1), Some(Recover::PowerCycle { .. })));
+    assert!(pipeline.answered(1));
+    assert!(!pipeline.answered(1));
+    assert_eq!(pipeline.status(1), BusStatus::Ok);
+}
+
+#[test]
+fn efficiency_is_of_one_tick() {
+    let mut pipeline = Pipeline::new(2, 0.0);
+    let mut input = Channel::new(0x40);
+    let mut output = Channel::new(0x41);
+    let derived = |outputs: Outputs| outputs.inputs().find_map(|input| match input {
+        Input::Reading { derived, .. } => Some(derived),
+        _ => None,
+    });
+    pipeline.route(&Consumers::default(), 0);
+    let fed = derived(pipeline.process(0, &mut input, &reading(5.0, 100.0), 0)).unwrap();
+    assert_eq!(fed.efficiency, None);
+    let converted = derived(pipeline.process(1, &mut output, &reading(5.0, 80.0), 0)).unwrap();
+    assert_eq!(converted.efficiency, Some(0.8));
+    assert_eq!(converted.resistance, Some(62.5));
+    // the input missed the next tick
+    pipeline.route(&Consumers::default(), DISPLAY_INTERVAL_US);
+    let converted = derived(pipeline.process(1, &mut output, &reading(5.0, 80.0), DISPLAY_INTERVAL_US)).unwrap();
+    assert_eq!(converted.efficiency, None);
+}
+
+#[test]
+fn late_channels_keep_the_rail_up() {
+    let mut pipeline = Pipeline::new(1, 0.0);
+    let fail = |pipeline: &mut Pipeline, index| (0..3).filter_map(|_| pipeline.failed(index)).last();
+    assert_eq!(fail(&mut pipeline, 0), Some(Recover::ClockOut));
+    assert!(matches!(fail(&mut pipeline, 0), Some(Recover::PowerCycle { .. })));
+    pipeline.answered(0);
+    // the sensor added after boot still answers
+    pipeline.add_channel();
+    assert_eq!(fail(&mut pipeline, 0), Some(Recover::ClockOut));
+    assert!(matches!(fail(&mut pipeline, 0), Some(Recover::Retry { .. })));
+}
+
+#[test]
+fn bursts_only_feed_the_detectors() {
+    let mut pipeline = Pipeline::new(1, 100.0);
+    pipeline.set_quality(QualitySettings { dropout_v: 4.5, ..QualitySettings::default() });
+    let consumers = Consumers { spectrum: true, ..Consumers::default() };
+    let mut channel = Channel::new(0x40);
+    let outputs = process(&mut pipeline, &consumers, 0, &mut channel, &[reading(5.0, 10.0)], 0, DISPLAY_INTERVAL_US);
+    assert!(outputs.burst);
+
+    // a spike starting in the burst ends with the next sample
+    let (current, events) = pipeline.process_burst(&channel, &reading(4.0, 200.0), 1000);
+    assert_eq!(current, 200.0);
+    assert!(events.is_empty());
+    let (_, events) = pipeline.process_burst(&channel, &reading(5.0, 10.0), 2000);
+    let kinds: Vec<EventKind> = events.iter().map(|event| event.kind).collect();
+    assert_eq!(kinds, [EventKind::OverCurrent, EventKind::Dropout]);
+
+    let outputs = process(&mut pipeline, &consumers, 0, &mut channel, &[reading(5.0, 10.0)], DISPLAY_INTERVAL_US, 0);
+    assert_eq!(channel.stats.samples, 2);
+    let histogram = outputs.inputs().find_map(|input| match input {
+        Input::Histogram(histogram) => Some(histogram),
+        _ => None,
+    });
+    assert_eq!(histogram.map(|histogram| histogram.charge_fraction(bucket(200.0))), Some(Some(0.0)));
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use common::close_rel;
+use powermeter_core::battery::{format_runtime, Battery};
+use powermeter_core::profile::{Profile, Profiler};
+
+const SLEEP_MA: f32 = 0.01;
+const ACTIVE_MA: f32 = 50.0;
+
+/// 1 ms samples of a node waking up for wake_ms every period_ms.
+fn node(profiler: &mut Profiler, periods: u64, period_ms: u64, wake_ms: u64, mut current: impl FnMut(u64, bool) -> f32) {
+    for ms in 0..periods * period_ms {
+        let awake = ms % period_ms >= period_ms - wake_ms;
+        profiler.add(current(ms, awake), ms * 1000);
+    }
+}
+
+#[test]
+fn splits_sleep_and_wakes() {
+    let mut profiler = Profiler::new();
+    node(&mut profiler, 10, 1000, 100, |_, awake| if awake { ACTIVE_MA } else { SLEEP_MA });
+    let profile = profiler.profile();
+    assert_eq!(profile.wakes, 10 - 1);
+    assert!(close_rel(profile.sleep_current.unwrap(), SLEEP_MA, 0.01), "{:?}", profile);
+    assert!(close_rel(profile.active_current.unwrap(), ACTIVE_MA, 0.01));
+    assert_eq!(profile.wake_duration_us, Some(100_000));
+    assert_eq!(profile.wake_period_us, Some(1_000_000));
+    assert!(close_rel(profile.duty_cycle.unwrap(), 0.1, 0.01));
+    // 50 mA for 100 ms
+    assert!(close_rel(profile.charge_per_wake.unwrap(), 5.0, 0.01));
+    assert!(close_rel(profile.average_current.unwrap(), 0.1 * ACTIVE_MA + 0.9 * SLEEP_MA, 0.01));
+}
+
+#[test]
+fn noisy_edges_are_one_wake() {
+    let mut profiler = Profiler::new();
+    // the radio rings between 20 and 50 mA, the sleep current wobbles
+    node(&mut profiler, 5, 500, 50, |ms, awake| match (awake, ms % 3) {
+        (true, 0) => 20.0,
+        (true, _) => ACTIVE_MA,
+        (false, 0) => SLEEP_MA * 1.5,
+        (false, _) => SLEEP_MA,
+    });
+    let profile = profiler.profile();
+    assert_eq!(profile.wakes, 5 - 1);
+    assert!(close_rel(profile.wake_duration_us.unwrap() as f32, 50_000.0, 0.05));
+}
+
+#[test]
+fn constant_load_has_no_wakes() {
+    let mut profiler = Profiler::new();
+    for ms in 0..5000u64 {
+        // 10 % ripple
+        let current = if ms % 2 == 0 { 10.0 } else { 11.0 };
+        profiler.add(current, ms * 1000);
+        assert!(!profiler.is_active());
+    }
+    let profile = profiler.profile();
+    assert_eq!(profile.wakes, 0);
+    assert_eq!(profile.wake_duration_us, None);
+    assert_eq!(profile.wake_period_us, None);
+    assert_eq!(profile.duty_cycle, Some(0.0));
+    assert!(close_rel(profile.sleep_current.unwrap(), 10.5, 0.01));
+}
+
+#[test]
+fn nothing_before_two_samples() {
+    let mut profiler = Profiler::new();
+    assert_eq!(profiler.profile(), Profile::default());
+    profiler.add(1.0, 0);
+    assert_eq!(profiler.profile().average_current, None);
+}
+
+#[test]
+fn runtime_is_the_one_of_the_battery_page() {
+    let profile = Profile { average_current: Some(5.0), ..Profile::default() };
+    let battery = Battery { capacity_mah: 1000.0, usable: 0.8, self_discharge: 0.0 };
+    // 800 usable mAh at 5 mA
+    assert_eq!(profile.lines(&battery)[7], "Battery 6 d 16 h 1000mAh");
+    let battery = Battery { capacity_mah: 1000.0, ..Battery::default() };
+    let expected = format!("Battery {} 1000mAh", format_runtime(battery.runtime_h(5.0).unwrap()));
+    assert_eq!(profile.lines(&battery)[7], expected.as_str());
+    assert_eq!(Profile::default().lines(&battery)[7], "Battery ---");
+}
+
+#[test]
+fn summary_lines() {
+    let mut profiler = Profiler::new();
+    node(&mut profiler, 10, 1000, 100, |_, awake| if awake { ACTIVE_MA } else { SLEEP_MA });
+    let lines = profiler.profile().lines(&Battery { capacity_mah: 2000.0, ..Battery::default() });
+    let lines: Vec<&str> = lines.iter().map(|line| line.as_str()).collect();
+    assert_eq!(lines, [
+        "Sleep   10.0 uA",
+        "Active  50.00 mA",
+        "Wake    100 ms",
+        "Period  1.0 s",
+        "Duty    9.99 %",
+        "Charge  5.000 mC",
+        "Average 5.00 mA",
+        "Battery 13 d 4 h 2000mAh",
+        "9 wakes in 10.0 s",
+    ]);
+    assert_eq!(Profile::default().lines(&Battery::default())[7].as_str(), "Battery: no capacity");
+}
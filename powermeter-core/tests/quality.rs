@@ -0,0 +1,135 @@
+mod common;
+
+use common::{close_abs, reading};
+use powermeter_core::quality::{format_uptime, DropoutDetector, Quality, QualityField, QualitySettings, RippleWindow, MAX_DROPOUT_V};
+
+#[test]
+fn square_ripple_on_a_12v_rail() {
+    let mut ripple = RippleWindow::new(100_000);
+    // 1 ms samples, 20 mV square ripple and 2 mA around 50 mA
+    let windows: Vec<_> = (0..=200u64)
+        .filter_map(|ms| {
+            let high = ms % 10 < 5;
+            let voltage = if high { 12.01 } else { 11.99 };
+            let current = if high { 51.0 } else { 49.0 };
+            ripple.add(&reading(voltage, current), ms * 1000)
+        })
+        .collect();
+    assert_eq!(windows.len(), 2);
+    let window = windows[0];
+    assert_eq!(window.samples, 101);
+    assert_eq!(window.duration_us, 100_000);
+    assert!(close_abs(window.voltage.mean, 12.0, 0.001));
+    assert!(close_abs(window.voltage.peak_to_peak, 0.02, 0.0001));
+    // a square wave has an rms of half its peak to peak
+    assert!(close_abs(window.voltage.ac_rms, 0.01, 0.0002));
+    assert!(close_abs(window.current.peak_to_peak, 2.0, 0.001));
+    assert!(close_abs(window.current.ac_rms, 1.0, 0.02));
+}
+
+#[test]
+fn clean_supply_has_no_ripple() {
+    let mut ripple = RippleWindow::new(10_000);
+    let window = (0..=10u64).filter_map(|ms| ripple.add(&reading(5.0, 10.0), ms * 1000)).next().unwrap();
+    assert_eq!(window.voltage.peak_to_peak, 0.0);
+    assert_eq!(window.voltage.ac_rms, 0.0);
+    assert_eq!(window.current.ac_rms, 0.0);
+}
+
+#[test]
+fn gap_starts_the_window_over() {
+    let mut ripple = RippleWindow::new(10_000);
+    for ms in 0..5u64 {
+        assert_eq!(ripple.add(&reading(5.0, 10.0), ms * 1000), None);
+    }
+    // ten seconds without samples, the page was closed
+    for ms in 10_004..10_014u64 {
+        assert_eq!(ripple.add(&reading(5.0, 10.0), ms * 1000), None);
+    }
+    let window = ripple.add(&reading(5.0, 10.0), 10_014_000).unwrap();
+    assert_eq!(window.samples, 11);
+}
+
+#[test]
+fn dropout_is_timestamped_with_its_lowest_voltage() {
+    let mut detector = DropoutDetector::new(4.5);
+    let voltages = [5.0, 5.0, 4.4, 3.9, 4.2, 5.0, 5.0];
+    let dropouts: Vec<_> = voltages.iter().enumerate()
+        .filter_map(|(ms, voltage)| detector.add(*voltage, ms as u64 * 1000))
+        .collect();
+    assert_eq!(dropouts.len(), 1);
+    assert_eq!(dropouts[0].start_us, 2000);
+    assert_eq!(dropouts[0].duration_us, 3000);
+    assert_eq!(dropouts[0].lowest_v, 3.9);
+    assert_eq!(detector.count(), 1);
+    assert_eq!(detector.last(), Some(dropouts[0]));
+    assert!(!detector.dipping());
+
+    assert_eq!(detector.add(4.0, 10_000), None);
+    assert!(detector.dipping());
+    // a new threshold forgets the dip going on
+    detector.set_threshold(3.0);
+    assert!(!detector.dipping());
+    assert_eq!(detector.add(5.0, 11_000), None);
+    assert_eq!(detector.count(), 1);
+}
+
+#[test]
+fn zero_threshold_turns_the_detector_off() {
+    let mut detector = DropoutDetector::new(0.0);
+    assert_eq!(detector.add(0.0, 0), None);
+    assert_eq!(detector.add(5.0, 1000), None);
+    assert_eq!(detector.count(), 0);
+}
+
+#[test]
+fn settings_step_through_windows_and_volts() {
+    let mut settings = QualitySettings::default();
+    settings.adjust(QualityField::Window, true);
+    assert_eq!(settings.window_ms, 5000);
+    settings.adjust(QualityField::Window, true);
+    assert_eq!(settings.window_ms, 5000);
+    settings.adjust(QualityField::Window, false);
+    settings.adjust(QualityField::Window, false);
+    assert_eq!(settings.window_ms, 500);
+    assert_eq!(settings.text(QualityField::Window), "Window  500 ms");
+
+    assert_eq!(settings.text(QualityField::Dropout), "Dropout off");
+    settings.adjust(QualityField::Dropout, false);
+    assert_eq!(settings.dropout_v, 0.0);
+    for _ in 0..50 {
+        settings.adjust(QualityField::Dropout, true);
+    }
+    assert!(close_abs(settings.dropout_v, 5.0, 0.001));
+    settings.adjust(QualityField::Dropout, true);
+    assert!(close_abs(settings.dropout_v, 5.5, 0.001));
+    settings.adjust(QualityField::Dropout, false);
+    settings.adjust(QualityField::Dropout, false);
+    assert!(close_abs(settings.dropout_v, 4.9, 0.001));
+    assert_eq!(settings.text(QualityField::Dropout), "Dropout < 4.9 V");
+    for _ in 0..100 {
+        settings.adjust(QualityField::Dropout, true);
+    }
+    assert_eq!(settings.dropout_v, MAX_DROPOUT_V);
+}
+
+#[test]
+fn lines_show_ripple_and_last_dropout() {
+    let mut ripple = RippleWindow::new(100_000);
+    let window = (0..=100u64)
+        .filter_map(|ms| ripple.add(&reading(if ms % 2 == 0 { 5.01 } else { 4.99 }, 12.0), ms * 1000))
+        .next();
+    let mut detector = DropoutDetector::new(4.0);
+    detector.add(3.2, 3_723_000_000);
+    detector.add(5.0, 3_723_012_000);
+    let lines = Quality::new(window, &detector).lines();
+    assert_eq!(lines[0], "V pp 20.0 rms 10.0 mV");
+    assert_eq!(lines[1], "I pp 0.000 rms 0.000 mA");
+    assert_eq!(lines[2], "Dropouts 1");
+    assert_eq!(lines[3], "at 1:02:03 12ms 3.20V");
+
+    let lines = Quality::default().lines();
+    assert_eq!(lines[0], "V ---");
+    assert_eq!(lines[3], "at ---");
+    assert_eq!(format_uptime(59_000_000), "0:00:59");
+}
@@ -0,0 +1,136 @@
+use powermeter_core::drivers::sensor::PowerMonitor;
+use powermeter_core::scope::{Cursors, Edge, Scope, ScopeField, ScopeSettings, Source, Timebase, Trace, TriggerMode, COLUMNS, DIVISIONS};
+
+fn reading(current: f32) -> PowerMonitor {
+    PowerMonitor { shunt: current * 0.1, voltage: 5.0 - current * 0.01, current, power: current * 5.0 }
+}
+
+/// 1 ms samples for duration_ms, the traces they completed.
+fn run(scope: &mut Scope, duration_ms: u64, current: impl Fn(u64) -> f32) -> Vec<Trace> {
+    (0..duration_ms).filter_map(|ms| scope.add(&reading(current(ms)), ms * 1000)).collect()
+}
+
+/// 1 mA with a 20 mA pulse from 500 to 700 ms.
+fn pulse(ms: u64) -> f32 {
+    if (500..700).contains(&ms) { 20.0 } else { 1.0 }
+}
+
+fn current(trace: &Trace, column: usize) -> f32 {
+    trace.columns[column].unwrap().current
+}
+
+fn settings() -> ScopeSettings {
+    // 50 ms/div, about two samples a column
+    ScopeSettings { timebase: Timebase::Ms50, mode: TriggerMode::Normal, ..ScopeSettings::default() }
+}
+
+#[test]
+fn rising_edge_at_the_trigger_column() {
+    let mut scope = Scope::new(settings());
+    let traces = run(&mut scope, 1000, pulse);
+    let trace = &traces[0];
+    assert!(trace.triggered);
+    assert_eq!(trace.trigger_column, 2 * COLUMNS / DIVISIONS);
+    assert_eq!(current(trace, trace.trigger_column - 1), 1.0);
+    assert_eq!(current(trace, trace.trigger_column + 1), 20.0);
+    // 200 ms at 2083 us a column
+    assert_eq!(current(trace, trace.trigger_column + 94), 20.0);
+    assert_eq!(current(trace, trace.trigger_column + 98), 1.0);
+    assert!(trace.columns.iter().all(|column| column.is_some()));
+}
+
+#[test]
+fn falling_edge_and_pre_trigger() {
+    let mut scope = Scope::new(ScopeSettings { edge: Edge::Falling, pre_trigger: 5, ..settings() });
+    let traces = run(&mut scope, 1200, pulse);
+    let trace = &traces[0];
+    assert_eq!(trace.trigger_column, COLUMNS / 2);
+    assert_eq!(current(trace, trace.trigger_column - 1), 20.0);
+    assert_eq!(current(trace, trace.trigger_column + 1), 1.0);
+}
+
+#[test]
+fn voltage_trigger() {
+    // the voltage drops under the pulse
+    let mut scope = Scope::new(ScopeSettings { source: Source::Voltage, level: 4.9, edge: Edge::Falling, ..settings() });
+    let traces = run(&mut scope, 1000, pulse);
+    assert!(traces[0].triggered);
+    assert_eq!(current(&traces[0], traces[0].trigger_column + 1), 20.0);
+}
+
+#[test]
+fn normal_waits_and_auto_runs_free() {
+    let mut normal = Scope::new(settings());
+    assert!(run(&mut normal, 3000, |_| 1.0).is_empty());
+
+    let mut auto = Scope::new(ScopeSettings { mode: TriggerMode::Auto, ..settings() });
+    let traces = run(&mut auto, 3000, |_| 1.0);
+    assert!(!traces.is_empty());
+    assert!(traces.iter().all(|trace| !trace.triggered));
+}
+
+#[test]
+fn single_stops_until_armed() {
+    let periodic = |ms: u64| pulse(ms % 1000);
+    let single = ScopeSettings { mode: TriggerMode::Single, ..settings() };
+    let mut scope = Scope::new(single);
+    assert_eq!(run(&mut scope, 5000, periodic).len(), 1);
+    assert!(!scope.is_running());
+    scope.set_settings(single);
+    assert!(scope.is_running());
+    assert_eq!(run(&mut scope, 1000, periodic).len(), 1);
+
+    let mut normal = Scope::new(settings());
+    assert_eq!(run(&mut normal, 5000, periodic).len(), 5);
+}
+
+#[test]
+fn fast_timebase_leaves_columns_empty() {
+    // 1 ms/div, 24 columns a sample
+    let mut scope = Scope::new(ScopeSettings { timebase: Timebase::Ms1, ..ScopeSettings::default() });
+    let traces = run(&mut scope, 1000, pulse);
+    let trace = &traces[0];
+    let filled = trace.columns.iter().filter(|column| column.is_some()).count();
+    assert!((9..=11).contains(&filled), "{}", filled);
+}
+
+#[test]
+fn cursors_read_time_and_current() {
+    let mut scope = Scope::new(settings());
+    let trace = run(&mut scope, 1000, pulse).remove(0);
+    let cursors = Cursors { a: trace.trigger_column - 10, b: trace.trigger_column + 14 };
+    let (us, di) = trace.delta(&cursors);
+    assert_eq!(us, 50_000);
+    assert_eq!(di, Some(19.0));
+}
+
+#[test]
+fn adjust_steps() {
+    let mut settings = ScopeSettings::default();
+    settings.adjust(ScopeField::Level, true);
+    assert!((settings.level - 20.0).abs() < 1e-4);
+    settings.level = 10.0;
+    settings.adjust(ScopeField::Level, false);
+    assert!((settings.level - 9.0).abs() < 1e-4);
+    settings.level = 0.0;
+    settings.adjust(ScopeField::Level, false);
+    assert!((settings.level + 0.001).abs() < 1e-6);
+
+    settings.adjust(ScopeField::Source, true);
+    assert_eq!(settings.source, Source::Voltage);
+    assert_eq!(settings.level, 3.0);
+
+    for _ in 0..20 {
+        settings.adjust(ScopeField::Timebase, true);
+        settings.adjust(ScopeField::PreTrigger, true);
+    }
+    assert_eq!(settings.timebase, Timebase::S1);
+    assert_eq!(settings.pre_trigger as usize, DIVISIONS);
+    settings.adjust(ScopeField::Mode, false);
+    assert_eq!(settings.mode, TriggerMode::Single);
+
+    let mut cursors = Cursors { a: 2, b: COLUMNS - 3 };
+    cursors.adjust(ScopeField::CursorA, false);
+    cursors.adjust(ScopeField::CursorB, true);
+    assert_eq!(cursors, Cursors { a: 0, b: COLUMNS - 1 });
+}
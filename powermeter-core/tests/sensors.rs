@@ -0,0 +1,240 @@
+use embedded_hal::i2c::{ErrorKind, NoAcknowledgeSource};
+use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+use powermeter_core::drivers::ina219::{Calibration, Ina219};
+use powermeter_core::drivers::ina226::Ina226;
+use powermeter_core::drivers::ina260::Ina260;
+use powermeter_core::drivers::ina3221::{Ina3221, Ina3221Chip};
+use powermeter_core::drivers::sensor::{detect, Alert, Averaging, PowerMonitor, PowerSensor, Sampling, Sensor, SensorKind};
+
+const ADDRESS: u8 = 0x41;
+
+fn read(reg: u8, value: u16) -> Transaction {
+    Transaction::write_read(ADDRESS, vec![reg], value.to_be_bytes().to_vec())
+}
+
+fn write(reg: u8, value: u16) -> Transaction {
+    let [msb, lsb] = value.to_be_bytes();
+    Transaction::write(ADDRESS, vec![reg, msb, lsb])
+}
+
+fn assert_close(actual: PowerMonitor, expected: PowerMonitor) {
+    let pairs = [
+        (actual.shunt, expected.shunt),
+        (actual.voltage, expected.voltage),
+        (actual.current, expected.current),
+        (actual.power, expected.power),
+    ];
+    assert!(pairs.iter().all(|(a, b)| (a - b).abs() < 1e-3), "{:?} != {:?}", actual, expected);
+}
+
+#[test]
+fn detect_by_manufacturer_and_die_id() {
+    for (die_id, kind) in [(0x2260, SensorKind::Ina226), (0x2270, SensorKind::Ina260), (0x3220, SensorKind::Ina3221)] {
+        let mut i2c = Mock::new(&[Transaction::read(ADDRESS, vec![0]), read(0xFE, 0x5449), read(0xFF, die_id)]);
+        assert_eq!(detect(&mut i2c, ADDRESS), Ok(kind));
+        i2c.done();
+    }
+
+    // the INA219 has no ID registers, whatever it answers is not "TI"
+    let mut i2c = Mock::new(&[Transaction::read(ADDRESS, vec![0]), read(0xFE, 0x399F)]);
+    assert_eq!(detect(&mut i2c, ADDRESS), Ok(SensorKind::Ina219));
+    i2c.done();
+
+    let nack = ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address);
+    let mut i2c = Mock::new(&[Transaction::read(ADDRESS, vec![0]).with_error(nack)]);
+    assert_eq!(detect(&mut i2c, ADDRESS), Err(nack));
+    i2c.done();
+}
+
+#[test]
+fn ina226_calibrates_and_converts() {
+    let mut i2c = Mock::new(&[
+        // 0.00512 / (0.1 mA * 0.1 ohm)
+        write(0x05, 512),
+        write(0x00, 0x4127),
+        // 1.0 mV
+        read(0x01, 400),
+        // 5.0 V
+        read(0x02, 4000),
+        // 10.0 mA
+        read(0x04, 100),
+        // 50 mW
+        read(0x03, 20),
+        // 16 samples
+        write(0x00, 0x4527),
+        read(0x06, 0x0008),
+    ]);
+    let mut ina226 = Ina226::new(i2c.clone(), ADDRESS);
+    ina226.init(Calibration::Range32V2A).unwrap();
+    let expected = PowerMonitor { shunt: 1.0, voltage: 5.0, current: 10.0, power: 50.0 };
+    assert_close(ina226.sense().unwrap(), expected);
+    ina226.set_averaging(Averaging::X16).unwrap();
+    assert!(ina226.conversion_ready().unwrap());
+    i2c.done();
+}
+
+#[test]
+fn ina226_alerts_compare_the_shunt_voltage() {
+    let mut i2c = Mock::new(&[
+        // 500 mA over 0.1 ohm is 50 mV, 20000 lsb of 2.5 uV
+        write(0x07, 20000),
+        write(0x06, 0x8000),
+        read(0x06, 0x0010),
+        // 4.5 V
+        write(0x07, 3600),
+        write(0x06, 0x1000),
+        read(0x06, 0x0000),
+    ]);
+    let mut ina226 = Ina226::new(i2c.clone(), ADDRESS);
+    assert!(ina226.set_alert(Alert::OverCurrent(500.0)).unwrap());
+    assert!(ina226.alert_active().unwrap());
+    assert!(ina226.set_alert(Alert::BusUnderVoltage(4.5)).unwrap());
+    assert!(!ina226.alert_active().unwrap());
+    i2c.done();
+}
+
+#[test]
+fn ina260_has_a_fixed_range() {
+    let mut i2c = Mock::new(&[
+        write(0x00, 0x6127),
+        // 1 A
+        read(0x01, 800),
+        // 5.0 V
+        read(0x02, 4000),
+        // 5 W
+        read(0x03, 500),
+        // 2 A
+        write(0x07, 1600),
+        write(0x06, 0x8000),
+    ]);
+    let mut ina260 = Ina260::new(i2c.clone(), ADDRESS);
+    ina260.init(Calibration::Range16V400mA).unwrap();
+    let expected = PowerMonitor { shunt: 2.0, voltage: 5.0, current: 1000.0, power: 5000.0 };
+    assert_close(ina260.sense().unwrap(), expected);
+    assert!(ina260.set_alert(Alert::OverCurrent(2000.0)).unwrap());
+    i2c.done();
+}
+
+#[test]
+fn ina3221_inputs_have_their_own_registers() {
+    let mut i2c = Mock::new(&[
+        write(0x00, 0x7127 | 3 << 9),
+        // input 2, 1.0 mV and 5.0 V in bits 15..3
+        read(0x03, 25 << 3),
+        read(0x04, 625 << 3),
+        // critical limit of input 2, 100 mA over 0.1 ohm
+        write(0x09, 250 << 3),
+        read(0x0F, 0x0100),
+    ]);
+    let chip = Ina3221Chip::new();
+    let mut ina3221 = Ina3221::new(i2c.clone(), &chip, ADDRESS, 2);
+    ina3221.set_averaging(Averaging::X64).unwrap();
+    let expected = PowerMonitor { shunt: 1.0, voltage: 5.0, current: 10.0, power: 50.0 };
+    assert_close(ina3221.sense().unwrap(), expected);
+    assert!(ina3221.set_alert(Alert::OverCurrent(100.0)).unwrap());
+    assert!(!ina3221.set_alert(Alert::OverPower(100.0)).unwrap());
+    assert!(ina3221.alert_active().unwrap());
+    i2c.done();
+}
+
+#[test]
+fn ina219_through_the_trait() {
+    let mut i2c = Mock::new(&[
+        // 16 samples for bus and shunt
+        write(0x00, 0x3E67),
+        read(0x02, 1250 << 3 | 0x02),
+    ]);
+    let mut sensor = Sensor::Ina219(Ina219::with_address(i2c.clone(), ADDRESS));
+    assert_eq!(sensor.kind(), SensorKind::Ina219);
+    sensor.set_averaging(Averaging::X16).unwrap();
+    assert!(sensor.conversion_ready().unwrap());
+    assert!(!sensor.set_alert(Alert::OverCurrent(100.0)).unwrap());
+    assert!(sensor.as_ina219().is_some());
+    i2c.done();
+}
+
+#[test]
+fn triggered_sampling_through_sensor() {
+    let mut i2c = Mock::new(&[
+        // INA3221 input 2, shunt and bus triggered
+        write(0x00, 0x7123),
+        read(0x0F, 0x0000),
+        read(0x0F, 0x0001),
+        // 4.0 mV over 0.1 ohm, 3.3 V
+        read(0x03, 100 << 3),
+        read(0x04, 412 << 3),
+        write(0x00, 0x7123),
+    ]);
+    let chip = Ina3221Chip::new();
+    let mut sensor = Sensor::new(SensorKind::Ina3221, i2c.clone(), &chip, ADDRESS, 2);
+    sensor.set_sampling(Sampling::Triggered).unwrap();
+    assert_eq!(sensor.sense_ready().unwrap(), None);
+    let reading = sensor.sense_ready().unwrap().unwrap();
+    assert!((reading.current - 40.0).abs() < 1e-3);
+    assert!((reading.voltage - 3.296).abs() < 1e-3);
+    // the conversion was read, the next trigger starts one
+    sensor.trigger().unwrap();
+    i2c.done();
+}
+
+#[test]
+fn ina3221_inputs_share_the_conversion_ready_flag() {
+    let mut i2c = Mock::new(&[
+        read(0x0F, 0x0001),
+        read(0x01, 25 << 3),
+        read(0x02, 625 << 3),
+        read(0x03, 50 << 3),
+        read(0x04, 625 << 3),
+        read(0x05, 75 << 3),
+        read(0x06, 625 << 3),
+        // reading cleared the flag, nothing new for input 1 and 3
+        read(0x0F, 0x0000),
+        read(0x0F, 0x0000),
+    ]);
+    let chip = Ina3221Chip::new();
+    let mut inputs = [1, 2, 3].map(|input| Ina3221::new(i2c.clone(), &chip, ADDRESS, input));
+    for (input, shunt) in inputs.iter_mut().zip([1.0, 2.0, 3.0]) {
+        let reading = input.sense_ready().unwrap().unwrap();
+        assert!((reading.shunt - shunt).abs() < 1e-3);
+    }
+    assert_eq!(inputs[0].sense_ready().unwrap(), None);
+    assert_eq!(inputs[2].sense_ready().unwrap(), None);
+    i2c.done();
+}
+
+#[test]
+fn ina3221_triggers_its_inputs_once() {
+    let mut i2c = Mock::new(&[
+        // every input switching to triggered starts the conversion over
+        write(0x00, 0x7123),
+        write(0x00, 0x7123),
+        write(0x00, 0x7123),
+        read(0x0F, 0x0001),
+        read(0x01, 25 << 3),
+        read(0x02, 625 << 3),
+        read(0x03, 50 << 3),
+        read(0x04, 625 << 3),
+        read(0x05, 75 << 3),
+        read(0x06, 625 << 3),
+        // one conversion for the next samples of all three
+        write(0x00, 0x7123),
+        read(0x0F, 0x0000),
+    ]);
+    let chip = Ina3221Chip::new();
+    let mut inputs = [1, 2, 3].map(|input| Ina3221::new(i2c.clone(), &chip, ADDRESS, input));
+    for input in inputs.iter_mut() {
+        input.set_sampling(Sampling::Triggered).unwrap();
+    }
+    // the conversion started by the switch is still going on
+    for input in inputs.iter_mut() {
+        input.trigger().unwrap();
+    }
+    for input in inputs.iter_mut() {
+        assert!(input.sense_ready().unwrap().is_some());
+    }
+    for input in inputs.iter_mut() {
+        input.trigger().unwrap();
+    }
+    assert_eq!(inputs[1].sense_ready().unwrap(), None);
+    i2c.done();
+}
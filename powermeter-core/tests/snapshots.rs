@@ -0,0 +1,373 @@
+// Golden images of every screen, see common::assert_snapshot. After an
+// intended UI change update them with
+//   UPDATE_SNAPSHOTS=1 cargo test -p powermeter-core --test snapshots --target x86_64-unknown-linux-gnu
+// and check the new PNGs in tests/snapshots before committing.
+
+mod common;
+
+use common::{assert_snapshot, theme, Framebuffer};
+use powermeter_core::battery::{Average, Battery};
+use powermeter_core::channel::{Channel, Stats};
+use powermeter_core::derived::Derived;
+use powermeter_core::drivers::bus::Device;
+use powermeter_core::drivers::ina219::Calibration;
+use powermeter_core::drivers::sensor::PowerMonitor;
+use powermeter_core::events::EventLog;
+use powermeter_core::histogram::Histogram;
+use powermeter_core::profile::Profile;
+use powermeter_core::quality::{DropoutDetector, Quality, QualitySettings, RippleWindow};
+use powermeter_core::scope::{Scope, ScopeSettings};
+use powermeter_core::spectrum::{Spectrum, FFT_SIZE};
+use powermeter_core::supervisor::BusStatus;
+use powermeter_core::ui::{self, Button, Input, Ui};
+use powermeter_protocol::event::{Event, EventKind};
+
+const READING: PowerMonitor = PowerMonitor {
+    shunt: 12.34,
+    voltage: 5.021,
+    current: 123.4,
+    power: 619.6,
+};
+
+const RAIL: PowerMonitor = PowerMonitor {
+    shunt: 4.1,
+    voltage: 3.302,
+    current: 41.0,
+    power: 135.4,
+};
+
+fn render(inputs: &[Input]) -> Framebuffer {
+    render_ui(Ui::new(), inputs)
+}
+
+fn render_ui(mut ui: Ui, inputs: &[Input]) -> Framebuffer {
+    let mut framebuffer = Framebuffer::new();
+    for input in inputs {
+        ui.handle(input);
+        ui.draw(&mut framebuffer.panel(), &theme());
+    }
+    framebuffer
+}
+
+fn press(button: Button) -> Input {
+    Input::Button { button, long_press: false }
+}
+
+fn sample(channel: u8, reading: PowerMonitor) -> Input {
+    let mut stats = Stats::default();
+    stats.add(&reading);
+    Input::Reading { channel, reading, unfiltered: reading, calibration: Calibration::Range32V2A, stats, derived: Derived::new(&reading, None) }
+}
+
+fn two_channels() -> Ui {
+    let mut rail = Channel::new(0x41);
+    rail.name.push_str("3V3").unwrap();
+    Ui::with_channels([Channel::new(0x40), rail])
+}
+
+fn open_channel_page() -> [Input; 4] {
+    [
+        Input::Button { button: Button::Select, long_press: true },
+        press(Button::Previous),
+        press(Button::Previous),
+        press(Button::Select),
+    ]
+}
+
+#[test]
+fn voltage_page() {
+    assert_snapshot("voltage_page", &render(&[sample(0, READING)]));
+}
+
+#[test]
+fn current_page() {
+    assert_snapshot("current_page", &render(&[sample(0, READING), press(Button::Next)]));
+}
+
+#[test]
+fn power_page() {
+    assert_snapshot("power_page", &render(&[press(Button::Next), press(Button::Next), sample(0, READING)]));
+}
+
+#[test]
+fn page_without_load() {
+    assert_snapshot("page_without_load", &render(&[sample(0, PowerMonitor::default())]));
+}
+
+#[test]
+fn calibration_message() {
+    assert_snapshot("calibration_message", &render(&[sample(0, READING), press(Button::Select)]));
+}
+
+#[test]
+fn settings_menu() {
+    let inputs = [
+        sample(0, READING),
+        Input::Button { button: Button::Select, long_press: true },
+        press(Button::Next),
+    ];
+    assert_snapshot("settings_menu", &render(&inputs));
+}
+
+#[test]
+fn channel_view() {
+    let mut inputs = vec![sample(0, READING), sample(1, RAIL)];
+    inputs.extend(open_channel_page());
+    inputs.extend([press(Button::Next), press(Button::Select), press(Button::Next)]);
+    assert_snapshot("channel_view", &render_ui(two_channels(), &inputs));
+}
+
+#[test]
+fn channel_page() {
+    let mut inputs = vec![sample(0, READING), sample(1, RAIL)];
+    inputs.extend(open_channel_page());
+    inputs.push(press(Button::Next));
+    assert_snapshot("channel_page", &render_ui(two_channels(), &inputs));
+}
+
+#[test]
+fn total_view() {
+    let mut inputs = vec![sample(0, READING), sample(1, RAIL)];
+    inputs.extend(open_channel_page());
+    inputs.extend([press(Button::Previous), press(Button::Select)]);
+    assert_snapshot("total_view", &render_ui(two_channels(), &inputs));
+}
+
+#[test]
+fn no_sensor_found() {
+    let mut framebuffer = Framebuffer::new();
+    ui::draw_message(&mut framebuffer.panel(), &theme(), "No sensor found");
+    assert_snapshot("no_sensor_found", &framebuffer);
+}
+
+#[test]
+fn sensor_lost() {
+    let inputs = [sample(0, READING), Input::Status { channel: 0, status: BusStatus::Lost }];
+    assert_snapshot("sensor_lost", &render(&inputs));
+}
+
+#[test]
+fn battery_splash() {
+    let mut framebuffer = Framebuffer::new();
+    ui::draw_battery(&mut framebuffer.panel(), &theme(), 3.94);
+    assert_snapshot("battery_splash", &framebuffer);
+}
+
+#[test]
+fn wifi_setup() {
+    let mut framebuffer = Framebuffer::new();
+    ui::draw_lines(&mut framebuffer.panel(), &theme(), &["Wi-Fi setup", "powermeter-setup", "http://192.168.4.1"]);
+    assert_snapshot("wifi_setup", &framebuffer);
+}
+
+fn open_wizard() -> [Input; 5] {
+    [
+        Input::Button { button: Button::Select, long_press: true },
+        press(Button::Next),
+        press(Button::Next),
+        press(Button::Next),
+        press(Button::Select),
+    ]
+}
+
+#[test]
+fn calibration_wizard() {
+    assert_snapshot("calibration_wizard", &render(&open_wizard()));
+}
+
+#[test]
+fn calibration_reference() {
+    let mut inputs = open_wizard().to_vec();
+    inputs.push(press(Button::Select));
+    inputs.extend([sample(0, RAIL), sample(0, RAIL), sample(0, RAIL), sample(0, RAIL)]);
+    inputs.extend([press(Button::Select), press(Button::Next), press(Button::Select)]);
+    assert_snapshot("calibration_reference", &render(&inputs));
+}
+
+#[test]
+fn diagnostics_page() {
+    let mut ui = Ui::new();
+    ui.show_diagnostics(&[
+        Device { address: 0x36, name: "MAX17048", errors: 0, retries: 0 },
+        Device { address: 0x40, name: "INA219", errors: 1, retries: 3 },
+        Device { address: 0x41, name: "INA3221", errors: 0, retries: 0 },
+    ], 400);
+    assert_snapshot("diagnostics_page", &render_ui(ui, &[press(Button::Next)]));
+}
+
+#[test]
+fn events_page() {
+    // 2026-03-01T14:03:12Z
+    let synced = Some(1_772_373_792_000_000);
+    let brown_out = Event {
+        sequence: 10,
+        boot: 2,
+        kind: EventKind::Boot,
+        channel: 0,
+        uptime_us: 0,
+        unix_us: None,
+        value: 15.0,
+        duration_us: 0,
+    };
+    let mut log = EventLog::restore([brown_out], 11);
+    log.record(EventKind::Boot, 0, 1.0, 0, 0, None);
+    log.record(EventKind::SensorError, 1, 3.0, 0, 2_000_000, None);
+    log.record(EventKind::RangeChange, 0, 1.0, 0, 3_723_000_000, synced);
+    log.record(EventKind::OverCurrent, 0, 812.0, 4_000, 3_724_000_000, synced.map(|us| us + 1_000_000));
+    log.record(EventKind::Dropout, 0, 3.21, 8_000, 3_731_000_000, synced.map(|us| us + 8_000_000));
+    log.record(EventKind::BatteryLow, 0, 3.38, 0, 3_783_000_000, synced.map(|us| us + 60_000_000));
+    let mut ui = Ui::new();
+    ui.show_events(&log);
+    assert_snapshot("events_page", &render_ui(ui, &[press(Button::Next)]));
+}
+
+#[test]
+fn relative_mode() {
+    let inputs = [
+        sample(0, READING),
+        press(Button::Next),
+        Input::Button { button: Button::Next, long_press: true },
+        Input::Button { button: Button::Next, long_press: true },
+        sample(0, PowerMonitor { current: 110.9, ..READING }),
+    ];
+    assert_snapshot("relative_mode", &render(&inputs));
+}
+
+#[test]
+fn resistance_page() {
+    let inputs = [sample(0, READING), press(Button::Next), press(Button::Next), press(Button::Next)];
+    assert_snapshot("resistance_page", &render(&inputs));
+}
+
+#[test]
+fn energy_page() {
+    let mut stats = Stats::default();
+    for second in 0..=3600u64 {
+        stats.add_at(&READING, second * 1_000_000);
+    }
+    let mut ui = Ui::new();
+    ui.set_energy_price(0.32);
+    let mut inputs = vec![Input::Reading { channel: 0, reading: READING, unfiltered: READING, calibration: Calibration::Range32V2A, stats,
+                                     derived: Derived::new(&READING, None) }];
+    inputs.extend((0..5).map(|_| press(Button::Next)));
+    assert_snapshot("energy_page", &render_ui(ui, &inputs));
+}
+
+#[test]
+fn profile_page() {
+    let profile = Profile {
+        sleep_current: Some(0.0123),
+        active_current: Some(48.5),
+        wake_duration_us: Some(85_000),
+        wake_period_us: Some(10_000_000),
+        duty_cycle: Some(0.0085),
+        charge_per_wake: Some(4.12),
+        average_current: Some(0.424),
+        wakes: 12,
+        duration_us: 125_000_000,
+    };
+    let mut ui = Ui::new();
+    ui.set_battery(Battery { capacity_mah: 2000.0, ..Battery::default() });
+    let mut inputs = vec![Input::Button { button: Button::Select, long_press: true }];
+    inputs.extend((0..7).map(|_| press(Button::Next)));
+    inputs.extend([press(Button::Select), Input::Profile(profile)]);
+    assert_snapshot("profile_page", &render_ui(ui, &inputs));
+}
+
+#[test]
+fn battery_life() {
+    let mut ui = Ui::new();
+    ui.set_battery(Battery { capacity_mah: 2000.0, ..Battery::default() });
+    let mut inputs = vec![Input::Button { button: Button::Select, long_press: true }];
+    inputs.extend((0..8).map(|_| press(Button::Next)));
+    inputs.extend([
+        press(Button::Select),
+        press(Button::Select),
+        Input::Battery(Average { current_ma: 4.25, window_us: 150_000_000 }),
+    ]);
+    assert_snapshot("battery_life", &render_ui(ui, &inputs));
+}
+
+#[test]
+fn scope_page() {
+    // a 20 mA pulse with ringing on a 2 mA load, 1 ms samples
+    let mut scope = Scope::new(ScopeSettings::default());
+    let trace = (0..200u64).find_map(|ms| {
+        let current = match ms {
+            0..=49 => 2.0,
+            50..=79 => 20.0 + if ms % 2 == 0 { 3.0 } else { -3.0 },
+            _ => 2.0,
+        };
+        let reading = PowerMonitor { current, voltage: 5.0, ..PowerMonitor::default() };
+        scope.add(&reading, ms * 1000)
+    }).unwrap();
+    let mut inputs = vec![Input::Button { button: Button::Select, long_press: true }];
+    inputs.extend((0..9).map(|_| press(Button::Next)));
+    inputs.extend([press(Button::Select), Input::Trace(trace)]);
+    assert_snapshot("scope_page", &render_ui(Ui::new(), &inputs));
+}
+
+#[test]
+fn histogram_page() {
+    // a node sleeping at 8 uA, waking to 5 mA and sending at 60 mA
+    let mut histogram = Histogram::new();
+    for ms in 0..10_000u64 {
+        let current = match ms % 1000 {
+            0..=19 => 60.0,
+            20..=149 => 5.0,
+            _ => 0.008,
+        };
+        histogram.add(current, ms * 1000);
+    }
+    let mut inputs = vec![Input::Button { button: Button::Select, long_press: true }];
+    inputs.extend((0..10).map(|_| press(Button::Next)));
+    inputs.extend([press(Button::Select), Input::Histogram(histogram)]);
+    // from the sleep current to the wake
+    inputs.extend((0..6).map(|_| press(Button::Next)));
+    assert_snapshot("histogram_page", &render_ui(Ui::new(), &inputs));
+}
+
+#[test]
+fn spectrum_page() {
+    // 100 Hz from a regulator and a weaker 350 Hz, sampled at 2 kHz
+    let samples: Vec<f32> = (0..FFT_SIZE)
+        .map(|i| {
+            let t = i as f32 / 2000.0;
+            let tau = 2.0 * std::f32::consts::PI;
+            12.0 + 1.5 * (tau * 100.0 * t).sin() + 0.4 * (tau * 350.0 * t).sin()
+        })
+        .collect();
+    let spectrum = Spectrum::analyze(&samples, 2000.0).unwrap();
+    let mut inputs = vec![Input::Button { button: Button::Select, long_press: true }];
+    inputs.extend((0..11).map(|_| press(Button::Next)));
+    inputs.extend([press(Button::Select), Input::Spectrum(spectrum), press(Button::Next)]);
+    assert_snapshot("spectrum_page", &render_ui(Ui::new(), &inputs));
+}
+
+#[test]
+fn quality_page() {
+    // 1 kHz switcher ripple on a 5 V rail sampled every 0.1 ms, one dip to 4.1 V
+    let mut ripple = RippleWindow::new(10_000);
+    let window = (0..=100u64)
+        .filter_map(|n| {
+            let high = n % 10 < 5;
+            let reading = PowerMonitor {
+                shunt: 0.0,
+                voltage: if high { 5.012 } else { 4.988 },
+                current: if high { 82.5 } else { 79.5 },
+                power: 0.0,
+            };
+            ripple.add(&reading, n * 100)
+        })
+        .next();
+    let settings = QualitySettings { window_ms: 10, dropout_v: 4.5 };
+    let mut detector = DropoutDetector::new(settings.dropout_v);
+    detector.add(4.1, 754_000_000);
+    detector.add(5.0, 754_008_000);
+    let mut ui = Ui::new();
+    ui.set_quality_settings(settings);
+    let mut inputs = vec![Input::Button { button: Button::Select, long_press: true }];
+    inputs.extend((0..12).map(|_| press(Button::Next)));
+    inputs.extend([press(Button::Select), Input::Quality(Quality::new(window, &detector)), press(Button::Select)]);
+    assert_snapshot("quality_page", &render_ui(ui, &inputs));
+}
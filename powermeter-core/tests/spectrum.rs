@@ -0,0 +1,104 @@
+mod common;
+
+use std::f32::consts::PI;
+
+use common::close_abs;
+use powermeter_core::spectrum::{fft, peak_text, ripple_text, sqrt, Spectrum, FFT_SIZE};
+
+const SAMPLE_RATE_HZ: f32 = 1000.0;
+const BIN_HZ: f32 = SAMPLE_RATE_HZ / FFT_SIZE as f32;
+
+/// A burst of mean plus sines of (frequency, amplitude).
+fn burst(mean: f32, sines: &[(f32, f32)]) -> Vec<f32> {
+    (0..FFT_SIZE).map(|i| {
+        let t = i as f32 / SAMPLE_RATE_HZ;
+        mean + sines.iter().map(|(hz, amplitude)| amplitude * (2.0 * PI * hz * t).sin()).sum::<f32>()
+    }).collect()
+}
+
+#[test]
+fn fft_of_an_impulse_is_flat() {
+    let mut re = [0.0; 16];
+    let mut im = [0.0; 16];
+    re[0] = 1.0;
+    fft(&mut re, &mut im);
+    assert!(re.iter().all(|re| close_abs(*re, 1.0, 1e-6)));
+    assert!(im.iter().all(|im| close_abs(*im, 0.0, 1e-6)));
+}
+
+#[test]
+fn fft_of_a_cosine_is_two_lines() {
+    let n = 64;
+    let mut re: Vec<f32> = (0..n).map(|i| (2.0 * PI * 5.0 * i as f32 / n as f32).cos()).collect();
+    let mut im = vec![0.0; n];
+    fft(&mut re, &mut im);
+    for bin in 0..n {
+        let expected = if bin == 5 || bin == n - 5 { n as f32 / 2.0 } else { 0.0 };
+        assert!(close_abs(re[bin], expected, 1e-3), "{} {}", bin, re[bin]);
+        assert!(close_abs(im[bin], 0.0, 1e-3));
+    }
+}
+
+#[test]
+fn square_root() {
+    for x in [1e-6, 0.02, 2.0, 144.0, 1e6] {
+        assert!((sqrt(x) - x.sqrt()).abs() <= x.sqrt() * 1e-6, "{}", x);
+    }
+    assert_eq!(sqrt(0.0), 0.0);
+    assert_eq!(sqrt(-1.0), 0.0);
+}
+
+#[test]
+fn sine_on_a_bin() {
+    let spectrum = Spectrum::analyze(&burst(10.0, &[(40.0 * BIN_HZ, 2.0)]), SAMPLE_RATE_HZ).unwrap();
+    assert_eq!(spectrum.bin_hz, BIN_HZ);
+    assert_eq!(spectrum.peaks.len(), 1);
+    let peak = spectrum.peaks[0];
+    assert_eq!(peak.bin, 40);
+    assert!(close_abs(peak.frequency_hz, 40.0 * BIN_HZ, 0.05 * BIN_HZ), "{:?}", peak);
+    assert!(close_abs(peak.amplitude, 2.0, 0.02), "{:?}", peak);
+    assert!(close_abs(spectrum.mean, 10.0, 1e-3));
+    assert!(close_abs(spectrum.peak_to_peak, 4.0, 0.01));
+    assert!(close_abs(spectrum.rms, 2.0 / 2f32.sqrt(), 1e-3));
+    assert!(close_abs(spectrum.nyquist_hz(), 500.0, 1e-3));
+}
+
+#[test]
+fn sine_between_bins() {
+    let hz = 123.4;
+    let spectrum = Spectrum::analyze(&burst(0.0, &[(hz, 1.0)]), SAMPLE_RATE_HZ).unwrap();
+    let peak = spectrum.peaks[0];
+    assert!(close_abs(peak.frequency_hz, hz, 0.25 * BIN_HZ), "{:?}", peak);
+    assert!(close_abs(peak.amplitude, 1.0, 0.1), "{:?}", peak);
+}
+
+#[test]
+fn dominant_frequencies_strongest_first() {
+    let samples = burst(5.0, &[(200.0, 0.5), (50.0, 3.0), (320.0, 0.01)]);
+    let spectrum = Spectrum::analyze(&samples, SAMPLE_RATE_HZ).unwrap();
+    // the third is under the floor
+    assert_eq!(spectrum.peaks.len(), 2);
+    assert!(close_abs(spectrum.peaks[0].frequency_hz, 50.0, 0.5 * BIN_HZ));
+    assert!(close_abs(spectrum.peaks[0].amplitude, 3.0, 0.3));
+    assert!(close_abs(spectrum.peaks[1].frequency_hz, 200.0, 0.5 * BIN_HZ));
+    assert!(close_abs(spectrum.peaks[1].amplitude, 0.5, 0.05));
+}
+
+#[test]
+fn peaks_as_text() {
+    let samples = burst(5.0, &[(128.0 * BIN_HZ, 0.5), (64.0 * BIN_HZ, 3.0)]);
+    let spectrum = Spectrum::analyze(&samples, SAMPLE_RATE_HZ).unwrap();
+    assert_eq!(peak_text(&spectrum, 0).as_str(), "1 125 Hz 3.00 mA");
+    assert_eq!(peak_text(&spectrum, 1).as_str(), "2 250 Hz 500.0 uA");
+    assert_eq!(peak_text(&spectrum, 2).as_str(), "no peak");
+}
+
+#[test]
+fn constant_current_has_no_ripple() {
+    let spectrum = Spectrum::analyze(&[7.5; FFT_SIZE], SAMPLE_RATE_HZ).unwrap();
+    assert!(spectrum.peaks.is_empty());
+    assert_eq!(spectrum.peak_to_peak, 0.0);
+    assert_eq!(spectrum.rms, 0.0);
+    assert_eq!(ripple_text(&spectrum).as_str(), "pp 0.000 rms 0.000 mA");
+    assert_eq!(Spectrum::analyze(&[1.0; FFT_SIZE - 1], SAMPLE_RATE_HZ), None);
+}
@@ -0,0 +1,43 @@
+use powermeter_core::supervisor::{BusStatus, Recovery, Supervisor, ERROR_LIMIT, MAX_BACKOFF_MS};
+
+fn fail(supervisor: &mut Supervisor) -> Option<Recovery> {
+    (0..ERROR_LIMIT).map(|_| supervisor.error()).last().flatten()
+}
+
+#[test]
+fn single_errors_are_tolerated() {
+    let mut supervisor = Supervisor::new();
+    for _ in 0..10 {
+        for _ in 1..ERROR_LIMIT {
+            assert_eq!(supervisor.error(), None);
+        }
+        assert!(!supervisor.success());
+    }
+    assert_eq!(supervisor.status(), BusStatus::Ok);
+}
+
+#[test]
+fn recovery_escalates_with_backoff() {
+    let mut supervisor = Supervisor::new();
+    assert_eq!(fail(&mut supervisor), Some(Recovery::ClockOut));
+    assert_eq!(supervisor.status(), BusStatus::Recovering);
+    assert_eq!(fail(&mut supervisor), Some(Recovery::PowerCycle { wait_ms: 250 }));
+    assert_eq!(supervisor.status(), BusStatus::PowerCycled);
+    assert_eq!(fail(&mut supervisor), Some(Recovery::PowerCycle { wait_ms: 500 }));
+    assert_eq!(supervisor.status(), BusStatus::Lost);
+    for _ in 0..300 {
+        fail(&mut supervisor);
+    }
+    assert_eq!(fail(&mut supervisor), Some(Recovery::PowerCycle { wait_ms: MAX_BACKOFF_MS }));
+}
+
+#[test]
+fn success_after_recovery() {
+    let mut supervisor = Supervisor::new();
+    fail(&mut supervisor);
+    fail(&mut supervisor);
+    assert!(supervisor.success());
+    assert!(supervisor.is_healthy());
+    // the next failure starts with the bus again
+    assert_eq!(fail(&mut supervisor), Some(Recovery::ClockOut));
+}
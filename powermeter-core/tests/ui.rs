@@ -0,0 +1,776 @@
+use core::convert::Infallible;
+
+use embedded_graphics::mono_font::ascii::{FONT_10X20, FONT_9X15};
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::pixelcolor::{Rgb565, RgbColor};
+use embedded_graphics::prelude::{DrawTarget, OriginDimensions, Pixel, Size};
+use powermeter_core::battery::{Average, Battery, Field};
+use powermeter_core::channel::{Channel, Stats};
+use powermeter_core::correction::{Correction, CAPTURE_SAMPLES};
+use powermeter_core::derived::Derived;
+use powermeter_core::drivers::bus::Device;
+use powermeter_core::drivers::ina219::Calibration;
+use powermeter_core::drivers::sensor::{PowerMonitor, Sampling};
+use powermeter_core::events::EventLog;
+use powermeter_core::filter::{FilterKind, FilterTarget};
+use powermeter_core::histogram::{self, Histogram};
+use powermeter_core::menu::SettingsItem;
+use powermeter_core::modifier::Modifier;
+use powermeter_core::pipeline::{Consumers, Pipeline, DISPLAY_INTERVAL_US};
+use powermeter_core::profile::Profile;
+use powermeter_protocol::event::EventKind;
+use powermeter_core::quality::{Quality, QualityField, QualitySettings};
+use powermeter_core::scope::{Cursors, Scope, ScopeField, ScopeSettings, Timebase};
+use powermeter_core::spectrum::{Spectrum, FFT_SIZE};
+use powermeter_core::supervisor::BusStatus;
+use powermeter_core::ui::{format_cost, format_reading, format_total, format_value, Action, Button, Input, PowerDisplay, Theme, Ui, View};
+
+/// Counts pixel writes so tests can tell whether anything was drawn.
+#[derive(Default)]
+struct CountingDisplay {
+    pixels: usize,
+    clears: usize,
+}
+
+impl OriginDimensions for CountingDisplay {
+    fn size(&self) -> Size {
+        Size::new(320, 240)
+    }
+}
+
+impl DrawTarget for CountingDisplay {
+    type Color = Rgb565;
+    type Error = Infallible;
+
+    fn draw_iter<I: IntoIterator<Item=Pixel<Rgb565>>>(&mut self, pixels: I) -> Result<(), Infallible> {
+        self.pixels += pixels.into_iter().count();
+        Ok(())
+    }
+
+    fn clear(&mut self, _color: Rgb565) -> Result<(), Infallible> {
+        self.clears += 1;
+        Ok(())
+    }
+}
+
+fn theme() -> Theme<'static, MonoTextStyle<'static, Rgb565>> {
+    let mut large = MonoTextStyle::new(&FONT_10X20, Rgb565::WHITE);
+    large.background_color = Some(Rgb565::BLACK);
+    let mut medium = MonoTextStyle::new(&FONT_9X15, Rgb565::WHITE);
+    medium.background_color = Some(Rgb565::BLACK);
+    Theme {
+        digits: large,
+        large,
+        medium,
+        background: Rgb565::BLACK,
+    }
+}
+
+fn press(ui: &mut Ui, button: Button) -> Action {
+    ui.handle(&Input::Button { button, long_press: false })
+}
+
+fn long_press(ui: &mut Ui) -> Action {
+    ui.handle(&Input::Button { button: Button::Select, long_press: true })
+}
+
+fn reading(current: f32) -> PowerMonitor {
+    PowerMonitor {
+        shunt: current / 10.0,
+        voltage: 5.0,
+        current,
+        power: current * 5.0,
+    }
+}
+
+fn sample(channel: u8, reading: PowerMonitor) -> Input {
+    let mut stats = Stats::default();
+    stats.add(&reading);
+    Input::Reading { channel, reading, unfiltered: reading, calibration: Calibration::Range32V2A, stats, derived: Derived::new(&reading, None) }
+}
+
+fn two_channels() -> Ui {
+    let mut rail = Channel::new(0x44);
+    rail.name.push_str("5V").unwrap();
+    Ui::with_channels([Channel::new(0x40), rail])
+}
+
+#[test]
+fn select_cycles_calibrations_and_shows_the_range() {
+    let mut ui = Ui::new();
+    assert_eq!(press(&mut ui, Button::Select), Action::Calibrate { channel: 0, calibration: Calibration::Range32V1A });
+    assert_eq!(ui.message(), Some("32V - 1A"));
+    assert_eq!(press(&mut ui, Button::Select), Action::Calibrate { channel: 0, calibration: Calibration::Range16V400mA });
+    assert_eq!(press(&mut ui, Button::Select), Action::Calibrate { channel: 0, calibration: Calibration::Range32V2A });
+    ui.handle(&sample(0, reading(1.0)));
+    assert_eq!(ui.message(), None);
+}
+
+#[test]
+fn previous_and_next_stop_at_the_ends() {
+    let mut ui = Ui::new();
+    press(&mut ui, Button::Previous);
+    assert_eq!(ui.power_display(), PowerDisplay::Voltage);
+    press(&mut ui, Button::Next);
+    press(&mut ui, Button::Next);
+    assert_eq!(ui.power_display(), PowerDisplay::Power);
+    // a single channel has no efficiency page
+    for _ in 0..6 {
+        press(&mut ui, Button::Next);
+    }
+    assert_eq!(ui.power_display(), PowerDisplay::Energy);
+}
+
+#[test]
+fn derived_pages_without_a_load() {
+    let mut ui = Ui::new();
+    ui.handle(&sample(0, reading(0.0)));
+    for _ in 0..3 {
+        press(&mut ui, Button::Next);
+    }
+    assert_eq!(ui.power_display(), PowerDisplay::Resistance);
+    assert_eq!(ui.displayed_value(), None);
+    assert_eq!(format_value(PowerDisplay::Resistance, None).0.as_str(), "  ---");
+
+    ui.handle(&sample(0, reading(50.0)));
+    assert_eq!(ui.displayed_value(), Some(100.0));
+    assert_eq!(format_value(PowerDisplay::Resistance, ui.displayed_value()), ("100.0".try_into().unwrap(), "R "));
+}
+
+#[test]
+fn efficiency_of_two_channels() {
+    let mut ui = two_channels();
+    let mut pipeline = Pipeline::new(2, 0.0);
+    let mut channels = [Channel::new(0x40), Channel::new(0x44)];
+    pipeline.route(&Consumers::default(), 0);
+    for (index, (channel, current)) in channels.iter_mut().zip([100.0, 80.0]).enumerate() {
+        for input in pipeline.process(index, channel, &reading(current), 0).inputs() {
+            ui.handle(&input);
+        }
+    }
+    for _ in 0..10 {
+        press(&mut ui, Button::Next);
+    }
+    assert_eq!(ui.power_display(), PowerDisplay::Efficiency);
+    assert_eq!(ui.displayed_value(), Some(80.0));
+    press(&mut ui, Button::Previous);
+    assert_eq!(ui.power_display(), PowerDisplay::Energy);
+}
+
+#[test]
+fn energy_cost_uses_the_price() {
+    assert_eq!(format_cost(&Stats::default(), 0.0).as_str(), "no energy price set");
+
+    // 2 W for half an hour
+    let mut stats = Stats::default();
+    for second in 0..=1800u64 {
+        stats.add_at(&reading(400.0), second * 1_000_000);
+    }
+    assert_eq!(format_cost(&stats, 0.3).as_str(), "cost 0.0003 at 0.30/kWh");
+    // range changes keep the energy
+    stats.reset();
+    assert_eq!(stats.samples, 0);
+    assert!((stats.energy.mwh() - 1000.0).abs() < 1e-6);
+}
+
+#[test]
+fn menu_navigation_wraps_and_activates() {
+    let mut ui = Ui::new();
+    assert_eq!(long_press(&mut ui), Action::None);
+    assert_eq!(ui.menu(), Some(SettingsItem::WifiSetup));
+    press(&mut ui, Button::Previous);
+    assert_eq!(ui.menu(), Some(SettingsItem::Exit));
+    press(&mut ui, Button::Next);
+    press(&mut ui, Button::Next);
+    assert_eq!(press(&mut ui, Button::Select), Action::Activate(SettingsItem::DataLog));
+    assert_eq!(ui.menu(), None);
+
+    long_press(&mut ui);
+    press(&mut ui, Button::Previous);
+    assert_eq!(press(&mut ui, Button::Select), Action::None);
+    assert_eq!(ui.menu(), None);
+
+    // long press leaves without doing anything
+    long_press(&mut ui);
+    assert_eq!(long_press(&mut ui), Action::None);
+    assert_eq!(ui.menu(), None);
+}
+
+#[test]
+fn readings_are_formatted_per_page() {
+    let loaded = reading(120.5);
+    assert_eq!(format_reading(PowerDisplay::Voltage, &loaded), ("5.000".try_into().unwrap(), "V "));
+    assert_eq!(format_reading(PowerDisplay::Current, &loaded), ("120.5".try_into().unwrap(), "mA"));
+    assert_eq!(format_reading(PowerDisplay::Power, &loaded), ("602.5".try_into().unwrap(), "mW"));
+    // no load, no voltage either
+    assert_eq!(format_reading(PowerDisplay::Voltage, &reading(0.0)).0.as_str(), "0.000");
+    assert_eq!(format_reading(PowerDisplay::Current, &reading(0.0)).0.as_str(), "    0");
+}
+
+#[test]
+fn draw_only_touches_the_display_on_changes() {
+    let mut ui = Ui::new();
+    let theme = theme();
+    let mut display = CountingDisplay::default();
+
+    ui.handle(&sample(0, reading(10.0)));
+    ui.draw(&mut display, &theme);
+    assert_eq!(display.clears, 1);
+    assert!(display.pixels > 0);
+
+    display.pixels = 0;
+    ui.handle(&sample(0, reading(10.0)));
+    ui.draw(&mut display, &theme);
+    assert_eq!(display.pixels, 0);
+
+    ui.handle(&sample(0, reading(11.0)));
+    press(&mut ui, Button::Next);
+    ui.draw(&mut display, &theme);
+    assert!(display.pixels > 0);
+    assert_eq!(display.clears, 1);
+
+    // readings do not replace the menu
+    long_press(&mut ui);
+    ui.draw(&mut display, &theme);
+    assert_eq!(display.clears, 2);
+    display.pixels = 0;
+    ui.handle(&sample(0, reading(12.0)));
+    ui.draw(&mut display, &theme);
+    assert_eq!(display.pixels, 0);
+}
+
+#[test]
+fn messages_last_until_the_next_reading() {
+    let mut ui = Ui::new();
+    ui.handle(&Input::Message("Wi-Fi saved".try_into().unwrap()));
+    assert_eq!(ui.message(), Some("Wi-Fi saved"));
+    ui.handle(&sample(0, reading(1.0)));
+    assert_eq!(ui.message(), None);
+}
+
+#[test]
+fn channel_page_switches_channels_and_total() {
+    let mut ui = two_channels();
+    ui.handle(&sample(0, reading(10.0)));
+    ui.handle(&sample(1, reading(20.0)));
+    assert_eq!(ui.view(), View::Channel(0));
+
+    long_press(&mut ui);
+    press(&mut ui, Button::Previous);
+    press(&mut ui, Button::Previous);
+    assert_eq!(ui.menu(), Some(SettingsItem::Channels));
+    assert_eq!(press(&mut ui, Button::Select), Action::None);
+    assert_eq!(ui.channel_page(), Some(0));
+    press(&mut ui, Button::Next);
+    assert_eq!(press(&mut ui, Button::Select), Action::None);
+    assert_eq!(ui.channel_page(), None);
+    assert_eq!(ui.view(), View::Channel(1));
+
+    // Total follows the channels and wraps around to the first
+    long_press(&mut ui);
+    press(&mut ui, Button::Previous);
+    press(&mut ui, Button::Previous);
+    press(&mut ui, Button::Select);
+    assert_eq!(ui.channel_page(), Some(1));
+    press(&mut ui, Button::Next);
+    press(&mut ui, Button::Select);
+    assert_eq!(ui.view(), View::Total);
+    assert_eq!(format_total(ui.channels()).0.as_str(), "  150");
+
+    long_press(&mut ui);
+    press(&mut ui, Button::Previous);
+    press(&mut ui, Button::Previous);
+    press(&mut ui, Button::Select);
+    press(&mut ui, Button::Next);
+    press(&mut ui, Button::Select);
+    assert_eq!(ui.view(), View::Channel(0));
+}
+
+#[test]
+fn calibration_is_per_channel() {
+    let mut ui = two_channels();
+    ui.handle(&sample(1, reading(20.0)));
+    assert_eq!(ui.channels()[1].stats.samples, 1);
+    long_press(&mut ui);
+    press(&mut ui, Button::Previous);
+    press(&mut ui, Button::Previous);
+    press(&mut ui, Button::Select);
+    press(&mut ui, Button::Next);
+    press(&mut ui, Button::Select);
+
+    assert_eq!(press(&mut ui, Button::Select), Action::Calibrate { channel: 1, calibration: Calibration::Range32V1A });
+    assert_eq!(ui.channels()[0].calibration, Calibration::Range32V2A);
+    assert_eq!(ui.channels()[1].calibration, Calibration::Range32V1A);
+    // a new range starts new statistics
+    assert_eq!(ui.channels()[1].stats.samples, 0);
+
+    // the combined view has no range of its own
+    long_press(&mut ui);
+    press(&mut ui, Button::Previous);
+    press(&mut ui, Button::Previous);
+    press(&mut ui, Button::Select);
+    press(&mut ui, Button::Next);
+    press(&mut ui, Button::Select);
+    assert_eq!(ui.view(), View::Total);
+    assert_eq!(press(&mut ui, Button::Select), Action::None);
+}
+
+#[test]
+fn stats_track_min_max_and_mean() {
+    let mut stats = Stats::default();
+    assert_eq!(stats.mean_current(), 0.0);
+    for current in [10.0, 30.0, 20.0] {
+        stats.add(&reading(current));
+    }
+    assert_eq!((stats.samples, stats.min_current, stats.max_current), (3, 10.0, 30.0));
+    assert_eq!(stats.mean_current(), 20.0);
+    assert_eq!(stats.mean_power(), 100.0);
+    stats.reset();
+    assert_eq!(stats, Stats::default());
+}
+
+#[test]
+fn auto_range_follows_the_readings() {
+    let mut ui = Ui::new();
+    long_press(&mut ui);
+    for _ in 0..3 {
+        press(&mut ui, Button::Previous);
+    }
+    assert_eq!(ui.menu(), Some(SettingsItem::AutoRange));
+    assert_eq!(press(&mut ui, Button::Select), Action::AutoRange { channel: 0, enabled: true });
+    assert!(ui.channels()[0].auto_range.is_some());
+
+    ui.handle(&Input::Reading {
+        channel: 0,
+        reading: reading(100.0),
+        unfiltered: reading(100.0),
+        calibration: Calibration::Range16V400mA,
+        stats: Stats::default(),
+        derived: Derived::default(),
+    });
+    assert_eq!(ui.calibration(), Calibration::Range16V400mA);
+
+    // picking a range by hand ends it
+    assert_eq!(press(&mut ui, Button::Select), Action::Calibrate { channel: 0, calibration: Calibration::Range32V2A });
+    assert!(ui.channels()[0].auto_range.is_none());
+    ui.handle(&Input::Reading {
+        channel: 0,
+        reading: reading(100.0),
+        unfiltered: reading(100.0),
+        calibration: Calibration::Range16V400mA,
+        stats: Stats::default(),
+        derived: Derived::default(),
+    });
+    assert_eq!(ui.calibration(), Calibration::Range32V2A);
+}
+
+#[test]
+fn calibration_wizard_corrects_the_channel() {
+    let mut ui = Ui::new();
+    long_press(&mut ui);
+    for _ in 0..3 {
+        press(&mut ui, Button::Next);
+    }
+    assert_eq!(ui.menu(), Some(SettingsItem::Calibrate));
+    assert_eq!(press(&mut ui, Button::Select), Action::None);
+    assert!(ui.wizard().is_some());
+
+    press(&mut ui, Button::Select);
+    let mut action = Action::None;
+    for _ in 0..CAPTURE_SAMPLES {
+        assert_eq!(action, Action::None);
+        action = ui.handle(&sample(0, reading(4.0)));
+    }
+    assert_eq!(action, Action::None);
+    assert_eq!(long_press(&mut ui), Action::Correct { channel: 0, calibration: Calibration::Range32V2A, correction: Correction::tare(4.0) });
+    assert!(ui.wizard().is_none());
+    assert_eq!(ui.message(), Some("Calibrated"));
+    assert_eq!(ui.channels()[0].correction(), &Correction::tare(4.0));
+    // the other ranges keep theirs
+    assert_eq!(ui.channels()[0].corrections[1], Correction::default());
+}
+
+/// current measured on the primary channel, through the pipeline to ui
+fn measure(ui: &mut Ui, pipeline: &mut Pipeline, channel: &mut Channel, current: f32, timestamp_us: u64) -> Action {
+    pipeline.route(&Consumers::default(), timestamp_us);
+    let outputs = pipeline.process(0, channel, &reading(current), timestamp_us);
+    outputs.inputs().fold(Action::None, |_, input| ui.handle(&input))
+}
+
+#[test]
+fn calibration_wizard_is_not_filtered() {
+    let mut ui = Ui::new();
+    let mut pipeline = Pipeline::new(1, 0.0);
+    let mut channel = Channel::new(0x40);
+    long_press(&mut ui);
+    for _ in 0..5 {
+        press(&mut ui, Button::Next);
+    }
+    let Action::Filter { target, kind } = press(&mut ui, Button::Select) else {
+        panic!("no display filter");
+    };
+    pipeline.set_filter(target, kind);
+    let mut timestamp_us = 0;
+    for _ in 0..3 {
+        measure(&mut ui, &mut pipeline, &mut channel, 0.0, timestamp_us);
+        timestamp_us += DISPLAY_INTERVAL_US;
+    }
+
+    long_press(&mut ui);
+    for _ in 0..3 {
+        press(&mut ui, Button::Next);
+    }
+    assert_eq!(ui.menu(), Some(SettingsItem::Calibrate));
+    press(&mut ui, Button::Select);
+    press(&mut ui, Button::Select);
+    // the filter still remembers the readings without the load
+    for _ in 0..CAPTURE_SAMPLES {
+        assert_eq!(measure(&mut ui, &mut pipeline, &mut channel, 4.0, timestamp_us), Action::None);
+        timestamp_us += DISPLAY_INTERVAL_US;
+    }
+    assert_eq!(long_press(&mut ui), Action::Correct { channel: 0, calibration: Calibration::Range32V2A, correction: Correction::tare(4.0) });
+}
+
+#[test]
+fn bus_status_until_next_reading() {
+    let mut ui = two_channels();
+    ui.handle(&sample(1, reading(20.0)));
+    ui.handle(&Input::Status { channel: 1, status: BusStatus::Lost });
+    assert_eq!(ui.channels()[1].status, BusStatus::Lost);
+    assert_eq!(ui.channels()[0].status, BusStatus::Ok);
+    ui.handle(&sample(1, reading(20.0)));
+    assert_eq!(ui.channels()[1].status, BusStatus::Ok);
+}
+
+#[test]
+fn late_sensors_add_a_channel() {
+    let mut ui = Ui::with_channels([]);
+    ui.show_message("No sensor found");
+    ui.handle(&Input::Channel(Channel::new(0x41)));
+    assert_eq!(ui.channels().len(), 1);
+    assert_eq!(ui.message(), Some("No sensor found"));
+    ui.handle(&sample(0, reading(20.0)));
+    assert_eq!(ui.message(), None);
+    assert_eq!(ui.channels()[0].address, 0x41);
+}
+
+#[test]
+fn diagnostics_page_scrolls_and_closes() {
+    let mut ui = Ui::new();
+    let devices = [
+        Device { address: 0x36, name: "MAX17048", errors: 0, retries: 0 },
+        Device { address: 0x40, name: "INA219", errors: 1, retries: 3 },
+    ];
+    ui.show_diagnostics(&devices, 400);
+    let lines: Vec<&str> = ui.diagnostics().unwrap().iter().map(|line| line.as_str()).collect();
+    assert_eq!(lines, ["I2C0 400 kHz, 2 found", "0x36 MAX17048 e0 r0", "0x40 INA219   e1 r3"]);
+    // readings do not close it
+    ui.handle(&sample(0, reading(10.0)));
+    press(&mut ui, Button::Previous);
+    assert!(ui.diagnostics().is_some());
+    press(&mut ui, Button::Select);
+    assert!(ui.diagnostics().is_none());
+}
+
+#[test]
+fn events_page_lists_the_newest_first() {
+    let mut ui = Ui::new();
+    long_press(&mut ui);
+    for _ in 0..13 {
+        press(&mut ui, Button::Next);
+    }
+    assert_eq!(ui.menu(), Some(SettingsItem::Events));
+    assert_eq!(press(&mut ui, Button::Select), Action::Activate(SettingsItem::Events));
+
+    let mut log = EventLog::restore([], 0);
+    log.record(EventKind::Boot, 0, 1.0, 0, 0, None);
+    log.record(EventKind::Dropout, 0, 3.2, 8_000, 3_723_000_000, None);
+    ui.show_events(&log);
+    let lines: Vec<&str> = ui.events().unwrap().iter().map(|line| line.as_str()).collect();
+    assert_eq!(lines, ["2 events, boot #0", "+1:02:03 Drop 3.20V 8ms", "+0:00:00 Boot #0"]);
+    ui.handle(&sample(0, reading(10.0)));
+    press(&mut ui, Button::Next);
+    assert!(ui.events().is_some());
+    press(&mut ui, Button::Select);
+    assert!(ui.events().is_none());
+}
+
+#[test]
+fn sampling_toggles() {
+    let mut ui = Ui::new();
+    assert_eq!(ui.sampling(), Sampling::Continuous);
+    for expected in [Sampling::Triggered, Sampling::Continuous] {
+        long_press(&mut ui);
+        for _ in 0..4 {
+            press(&mut ui, Button::Next);
+        }
+        assert_eq!(ui.menu(), Some(SettingsItem::Sampling));
+        assert_eq!(press(&mut ui, Button::Select), Action::Sampling(expected));
+        assert_eq!(ui.sampling(), expected);
+    }
+    assert_eq!(ui.message(), Some("Continuous sampling"));
+}
+
+#[test]
+fn filters_cycle_separately() {
+    let mut ui = Ui::new();
+    for expected in [FilterKind::MovingAverage, FilterKind::Exponential] {
+        long_press(&mut ui);
+        for _ in 0..5 {
+            press(&mut ui, Button::Next);
+        }
+        assert_eq!(ui.menu(), Some(SettingsItem::DisplayFilter));
+        assert_eq!(press(&mut ui, Button::Select), Action::Filter { target: FilterTarget::Display, kind: expected });
+    }
+    assert_eq!(ui.message(), Some("Display filter exponential"));
+
+    long_press(&mut ui);
+    for _ in 0..6 {
+        press(&mut ui, Button::Next);
+    }
+    assert_eq!(ui.menu(), Some(SettingsItem::LogFilter));
+    assert_eq!(press(&mut ui, Button::Select), Action::Filter { target: FilterTarget::Log, kind: FilterKind::MovingAverage });
+    assert_eq!(ui.filter(FilterTarget::Display), FilterKind::Exponential);
+    assert_eq!(ui.filter(FilterTarget::Log), FilterKind::MovingAverage);
+}
+
+#[test]
+fn long_presses_drive_the_modifiers() {
+    let mut ui = Ui::new();
+    let theme = theme();
+    let mut display = CountingDisplay::default();
+    ui.handle(&sample(0, reading(20.0)));
+    press(&mut ui, Button::Next);
+    ui.draw(&mut display, &theme);
+
+    ui.handle(&Input::Button { button: Button::Next, long_press: true });
+    assert_eq!(ui.modifier(), Modifier::Hold);
+    assert_eq!(ui.power_display(), PowerDisplay::Current);
+    ui.handle(&sample(0, reading(30.0)));
+    assert_eq!(ui.displayed_value(), Some(20.0));
+    // the annunciator needs a new screen
+    ui.draw(&mut display, &theme);
+    assert_eq!(display.clears, 2);
+
+    ui.handle(&Input::Button { button: Button::Next, long_press: true });
+    assert_eq!(ui.modifier(), Modifier::Relative);
+    ui.handle(&sample(0, reading(35.0)));
+    assert_eq!(ui.displayed_value(), Some(5.0));
+    ui.handle(&Input::Button { button: Button::Previous, long_press: true });
+    assert_eq!(ui.displayed_value(), Some(0.0));
+    assert_eq!(ui.power_display(), PowerDisplay::Current);
+
+    ui.handle(&Input::Button { button: Button::Next, long_press: true });
+    ui.handle(&sample(0, reading(50.0)));
+    ui.handle(&sample(0, reading(40.0)));
+    assert_eq!(ui.modifier(), Modifier::Max);
+    assert_eq!(ui.displayed_value(), Some(50.0));
+}
+
+#[test]
+fn another_channel_turns_the_modifier_off() {
+    let mut ui = two_channels();
+    ui.handle(&sample(0, reading(10.0)));
+    ui.handle(&Input::Button { button: Button::Next, long_press: true });
+    assert_eq!(ui.modifier(), Modifier::Hold);
+
+    long_press(&mut ui);
+    press(&mut ui, Button::Previous);
+    press(&mut ui, Button::Previous);
+    press(&mut ui, Button::Select);
+    press(&mut ui, Button::Next);
+    press(&mut ui, Button::Select);
+    assert_eq!(ui.view(), View::Channel(1));
+    assert_eq!(ui.modifier(), Modifier::Off);
+}
+
+#[test]
+fn profile_page_runs_the_profiler() {
+    let mut ui = Ui::new();
+    ui.set_battery(Battery { capacity_mah: 1000.0, ..Battery::default() });
+    long_press(&mut ui);
+    for _ in 0..7 {
+        press(&mut ui, Button::Next);
+    }
+    assert_eq!(ui.menu(), Some(SettingsItem::Profile));
+    assert_eq!(press(&mut ui, Button::Select), Action::Profile(true));
+    assert_eq!(ui.profile(), Some(&Profile::default()));
+
+    let profile = Profile { wakes: 3, average_current: Some(2.0), ..Profile::default() };
+    ui.handle(&Input::Profile(profile));
+    // readings keep coming, the page stays up
+    ui.handle(&sample(0, reading(2.0)));
+    assert_eq!(ui.profile(), Some(&profile));
+    assert_eq!(press(&mut ui, Button::Next), Action::None);
+    assert_eq!(press(&mut ui, Button::Select), Action::Profile(false));
+    assert_eq!(ui.profile(), None);
+
+    // results without the page are dropped
+    ui.handle(&Input::Profile(profile));
+    assert_eq!(ui.profile(), None);
+}
+
+#[test]
+fn battery_page_edits_and_stores_the_battery() {
+    let mut ui = Ui::new();
+    long_press(&mut ui);
+    for _ in 0..8 {
+        press(&mut ui, Button::Next);
+    }
+    assert_eq!(ui.menu(), Some(SettingsItem::BatteryLife));
+    assert_eq!(press(&mut ui, Button::Select), Action::None);
+    assert_eq!(ui.battery_field(), Some(Field::Capacity));
+    assert_eq!(ui.runtime_h(), None);
+
+    press(&mut ui, Button::Next);
+    ui.handle(&Input::Battery(Average { current_ma: 1.0, window_us: 60_000_000 }));
+    // 10 mAh, 80 % usable, 0.0003 mA self discharge
+    assert!((ui.runtime_h().unwrap() - 7.998).abs() < 0.01);
+    press(&mut ui, Button::Select);
+    assert_eq!(ui.battery_field(), Some(Field::Usable));
+    press(&mut ui, Button::Previous);
+
+    let battery = Battery { capacity_mah: 10.0, usable: 0.75, ..Battery::default() };
+    assert_eq!(long_press(&mut ui), Action::Battery(battery));
+    assert_eq!(ui.battery_field(), None);
+    assert_eq!(ui.battery(), &battery);
+}
+
+#[test]
+fn scope_page_arms_the_scope() {
+    let mut ui = Ui::new();
+    long_press(&mut ui);
+    for _ in 0..9 {
+        press(&mut ui, Button::Next);
+    }
+    assert_eq!(ui.menu(), Some(SettingsItem::Scope));
+    let settings = ScopeSettings::default();
+    assert_eq!(press(&mut ui, Button::Select), Action::Scope(Some(settings)));
+    assert_eq!(ui.scope_field(), Some(ScopeField::Timebase));
+
+    // every change of the trigger or timebase arms it again
+    let slower = ScopeSettings { timebase: Timebase::Ms20, ..settings };
+    assert_eq!(press(&mut ui, Button::Next), Action::Scope(Some(slower)));
+    let mut scope = Scope::new(slower);
+    let trace = (0..1000).find_map(|ms| scope.add(&reading(1.0), ms * 1000)).unwrap();
+    ui.handle(&Input::Trace(trace.clone()));
+    // readings keep coming, the page stays up
+    ui.handle(&sample(0, reading(2.0)));
+    assert_eq!(ui.trace(), Some(&trace));
+
+    for _ in 0..6 {
+        press(&mut ui, Button::Select);
+    }
+    assert_eq!(ui.scope_field(), Some(ScopeField::CursorA));
+    assert_eq!(press(&mut ui, Button::Next), Action::None);
+    assert_eq!(ui.cursors().a, Cursors::default().a + 6);
+    assert_eq!(ui.handle(&Input::Button { button: Button::Next, long_press: true }), Action::Scope(Some(slower)));
+
+    assert_eq!(long_press(&mut ui), Action::Scope(None));
+    assert_eq!(ui.scope_field(), None);
+    // traces without the page are dropped
+    ui.handle(&Input::Trace(trace));
+    assert_eq!(ui.trace(), None);
+    assert_eq!(ui.scope_settings(), &slower);
+}
+
+#[test]
+fn histogram_page_shows_and_resets() {
+    let mut ui = Ui::new();
+    long_press(&mut ui);
+    for _ in 0..10 {
+        press(&mut ui, Button::Next);
+    }
+    assert_eq!(ui.menu(), Some(SettingsItem::Histogram));
+    assert_eq!(press(&mut ui, Button::Select), Action::None);
+
+    let mut distribution = Histogram::new();
+    for ms in 0..1000 {
+        distribution.add(if ms < 300 { 20.0 } else { 2.0 }, ms * 1000);
+    }
+    ui.handle(&Input::Histogram(distribution));
+    // opens on the bucket most of the time was spent in
+    assert_eq!(ui.histogram(), Some((&distribution, histogram::bucket(2.0))));
+    press(&mut ui, Button::Next);
+    press(&mut ui, Button::Next);
+    assert_eq!(ui.histogram().map(|(_, selected)| selected), Some(histogram::bucket(20.0)));
+
+    let reset = Input::Button { button: Button::Previous, long_press: true };
+    assert_eq!(ui.handle(&reset), Action::ResetHistogram);
+    assert_eq!(ui.histogram(), Some((&Histogram::new(), histogram::bucket(20.0))));
+    assert_eq!(press(&mut ui, Button::Select), Action::None);
+    assert_eq!(ui.histogram(), None);
+}
+
+#[test]
+fn spectrum_page_captures_bursts() {
+    let mut ui = Ui::new();
+    long_press(&mut ui);
+    for _ in 0..11 {
+        press(&mut ui, Button::Next);
+    }
+    assert_eq!(ui.menu(), Some(SettingsItem::Spectrum));
+    assert_eq!(press(&mut ui, Button::Select), Action::Spectrum(true));
+    assert_eq!(ui.spectrum(), None);
+    assert_eq!(ui.spectrum_peak(), Some(0));
+
+    // two sines at 125 and 250 Hz sampled at 1 kHz
+    let samples: Vec<f32> = (0..FFT_SIZE)
+        .map(|i| 5.0 + (i as f32 * core::f32::consts::PI / 4.0).sin() + 0.5 * (i as f32 * core::f32::consts::PI / 2.0).sin())
+        .collect();
+    let spectrum = Spectrum::analyze(&samples, 1000.0).unwrap();
+    assert_eq!(spectrum.peaks.len(), 2);
+    ui.handle(&Input::Spectrum(spectrum.clone()));
+    ui.handle(&sample(0, reading(2.0)));
+    assert_eq!(ui.spectrum(), Some(&spectrum));
+    press(&mut ui, Button::Next);
+    assert_eq!(ui.spectrum_peak(), Some(1));
+    press(&mut ui, Button::Next);
+    assert_eq!(ui.spectrum_peak(), Some(0));
+    press(&mut ui, Button::Previous);
+    assert_eq!(ui.spectrum_peak(), Some(1));
+
+    assert_eq!(press(&mut ui, Button::Select), Action::Spectrum(false));
+    assert_eq!(ui.spectrum_peak(), None);
+    // bursts without the page are dropped
+    ui.handle(&Input::Spectrum(spectrum));
+    assert_eq!(ui.spectrum(), None);
+}
+
+#[test]
+fn quality_page_edits_window_and_threshold() {
+    let mut ui = Ui::new();
+    long_press(&mut ui);
+    for _ in 0..12 {
+        press(&mut ui, Button::Next);
+    }
+    assert_eq!(ui.menu(), Some(SettingsItem::Quality));
+    let defaults = QualitySettings::default();
+    assert_eq!(press(&mut ui, Button::Select), Action::Quality(Some(defaults)));
+    assert_eq!(ui.quality(), None);
+    assert_eq!(ui.quality_field(), Some(QualityField::Window));
+
+    let quality = Quality { dropouts: 2, ..Quality::default() };
+    ui.handle(&Input::Quality(quality));
+    ui.handle(&sample(0, reading(2.0)));
+    assert_eq!(ui.quality(), Some(&quality));
+
+    let Action::Quality(Some(settings)) = press(&mut ui, Button::Previous) else {
+        panic!("window not changed");
+    };
+    assert_eq!(settings.window_ms, 500);
+    press(&mut ui, Button::Select);
+    assert_eq!(ui.quality_field(), Some(QualityField::Dropout));
+    let Action::Quality(Some(settings)) = press(&mut ui, Button::Next) else {
+        panic!("threshold not changed");
+    };
+    assert!((settings.dropout_v - 0.1).abs() < 0.001);
+    assert_eq!(ui.quality_settings(), &settings);
+
+    ui.handle(&Input::Button { button: Button::Select, long_press: true });
+    assert_eq!(ui.quality_field(), None);
+    // without the page the quality is dropped, the settings stay
+    ui.handle(&Input::Quality(quality));
+    assert_eq!(ui.quality(), None);
+    assert_eq!(ui.quality_settings(), &settings);
+}
@@ -0,0 +1,362 @@
+// Compact log of raw INA219 registers
+//
+// A log is a header followed by blocks. Every block starts with a keyframe
+// holding the absolute register values, the following samples store the
+// difference to the previous sample as zig-zag varints. Blocks carry a sync
+// marker and a crc so a reader can skip a damaged block and continue with
+// the next one. All fixed size values are little-endian.
+//
+// header (16 bytes)
+//   0  magic        "PMRZ"
+//   4  version      u8
+//   5  reserved     u8
+//   6  config       u16, INA219 configuration register
+//   8  calibration  u16, INA219 calibration register
+//  10  shunt_uohm   u32, shunt resistor in micro ohm
+//  14  crc          u16 over bytes 0..14
+//
+// block
+//   0  sync         0xA5 0x5A
+//   2  length       u16, length of count, keyframe and deltas
+//   4  count        u8, number of samples including the keyframe
+//   5  keyframe     timestamp u64 us, shunt i16, bus u16, power u16, current i16
+//  21  deltas       per sample: varint timestamp delta, then zig-zag varints
+//                   of the shunt, bus, power and current deltas
+//   .  crc          u16 over length, count, keyframe and deltas
+//
+// A typical sample at a steady load takes 5-7 bytes instead of the 16 of
+// a float sample.
+
+use crate::crc::crc16;
+
+pub const MAGIC: [u8; 4] = *b"PMRZ";
+pub const VERSION: u8 = 1;
+pub const HEADER_LEN: usize = 16;
+
+pub const SYNC: [u8; 2] = [0xA5, 0x5A];
+/// fits into a single data log record
+pub const MAX_BLOCK_LEN: usize = 240;
+pub const KEYFRAME_INTERVAL: usize = 32;
+
+const BLOCK_PREFIX_LEN: usize = 4;
+const KEYFRAME_LEN: usize = 1 + 8 + 8;
+const CRC_LEN: usize = 2;
+// 10 bytes timestamp delta plus 3 bytes for each 17 bit register delta
+const MAX_DELTA_LEN: usize = 10 + 4 * 3;
+
+// INA219 register LSBs
+const SHUNT_LSB_MV: f32 = 0.01;
+const BUS_LSB_V: f32 = 0.004;
+
+/// Register settings needed to turn raw values into engineering units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Calibration {
+    pub config: u16,
+    pub calibration: u16,
+    pub shunt_uohm: u32,
+}
+
+impl Calibration {
+    /// Current register LSB, see the INA219 datasheet 8.5.1.
+    pub fn current_lsb_ma(&self) -> f32 {
+        if self.calibration == 0 || self.shunt_uohm == 0 {
+            return 0.0;
+        }
+        40.96 / (self.calibration as f32 * self.shunt_uohm as f32 / 1_000_000.0)
+    }
+
+    pub fn power_lsb_mw(&self) -> f32 {
+        20.0 * self.current_lsb_ma()
+    }
+}
+
+/// INA219 shunt voltage, bus voltage, power and current registers as read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RawSample {
+    pub timestamp_us: u64,
+    pub shunt: i16,
+    /// including the CNVR and OVF flags in bits 1 and 0
+    pub bus: u16,
+    pub power: u16,
+    pub current: i16,
+}
+
+impl RawSample {
+    pub fn shunt_mv(&self) -> f32 {
+        self.shunt as f32 * SHUNT_LSB_MV
+    }
+
+    pub fn voltage(&self) -> f32 {
+        (self.bus >> 3) as f32 * BUS_LSB_V
+    }
+
+    pub fn overflow(&self) -> bool {
+        self.bus & 0x01 != 0
+    }
+
+    pub fn current(&self, calibration: &Calibration) -> f32 {
+        self.current as f32 * calibration.current_lsb_ma()
+    }
+
+    pub fn power(&self, calibration: &Calibration) -> f32 {
+        self.power as f32 * calibration.power_lsb_mw()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompactError {
+    TooShort,
+    BadMagic,
+    UnsupportedVersion(u8),
+    BadCrc,
+    /// block content does not match its count
+    Malformed,
+}
+
+pub fn encode_header(calibration: &Calibration) -> [u8; HEADER_LEN] {
+    let mut buf = [0u8; HEADER_LEN];
+    buf[0..4].copy_from_slice(&MAGIC);
+    buf[4] = VERSION;
+    buf[6..8].copy_from_slice(&calibration.config.to_le_bytes());
+    buf[8..10].copy_from_slice(&calibration.calibration.to_le_bytes());
+    buf[10..14].copy_from_slice(&calibration.shunt_uohm.to_le_bytes());
+    let crc = crc16(&buf[0..14]);
+    buf[14..16].copy_from_slice(&crc.to_le_bytes());
+    buf
+}
+
+pub fn decode_header(buf: &[u8]) -> Result<Calibration, CompactError> {
+    if buf.len() < HEADER_LEN {
+        return Err(CompactError::TooShort);
+    }
+    if buf[0..4] != MAGIC {
+        return Err(CompactError::BadMagic);
+    }
+    if crc16(&buf[0..14]).to_le_bytes() != buf[14..16] {
+        return Err(CompactError::BadCrc);
+    }
+    if buf[4] != VERSION {
+        return Err(CompactError::UnsupportedVersion(buf[4]));
+    }
+    Ok(Calibration {
+        config: u16::from_le_bytes([buf[6], buf[7]]),
+        calibration: u16::from_le_bytes([buf[8], buf[9]]),
+        shunt_uohm: u32::from_le_bytes([buf[10], buf[11], buf[12], buf[13]]),
+    })
+}
+
+pub struct BlockEncoder {
+    buf: [u8; MAX_BLOCK_LEN],
+    pos: usize,
+    count: usize,
+    last: RawSample,
+}
+
+impl BlockEncoder {
+    pub fn new() -> Self {
+        BlockEncoder {
+            buf: [0u8; MAX_BLOCK_LEN],
+            pos: 0,
+            count: 0,
+            last: RawSample::default(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// True if the next push could fail.
+    pub fn is_full(&self) -> bool {
+        self.count == KEYFRAME_INTERVAL || self.pos + MAX_DELTA_LEN + CRC_LEN > MAX_BLOCK_LEN
+    }
+
+    /// Returns false if the sample does not fit into the current block or
+    /// goes back in time. The caller must finish() the block and push again.
+    pub fn push(&mut self, sample: &RawSample) -> bool {
+        if self.count == 0 {
+            self.pos = BLOCK_PREFIX_LEN + KEYFRAME_LEN;
+            let keyframe = &mut self.buf[BLOCK_PREFIX_LEN + 1..self.pos];
+            keyframe[0..8].copy_from_slice(&sample.timestamp_us.to_le_bytes());
+            keyframe[8..10].copy_from_slice(&sample.shunt.to_le_bytes());
+            keyframe[10..12].copy_from_slice(&sample.bus.to_le_bytes());
+            keyframe[12..14].copy_from_slice(&sample.power.to_le_bytes());
+            keyframe[14..16].copy_from_slice(&sample.current.to_le_bytes());
+        } else {
+            if self.is_full() || sample.timestamp_us < self.last.timestamp_us {
+                return false;
+            }
+            let last = self.last;
+            self.put_varint(sample.timestamp_us - last.timestamp_us);
+            self.put_delta(sample.shunt as i32 - last.shunt as i32);
+            self.put_delta(sample.bus as i32 - last.bus as i32);
+            self.put_delta(sample.power as i32 - last.power as i32);
+            self.put_delta(sample.current as i32 - last.current as i32);
+        }
+        self.last = *sample;
+        self.count += 1;
+        true
+    }
+
+    /// Seal the current block and return its bytes, empty if no sample was
+    /// pushed. The next push starts a new block with a keyframe.
+    pub fn finish(&mut self) -> &[u8] {
+        if self.count == 0 {
+            return &[];
+        }
+        let body_len = self.pos - BLOCK_PREFIX_LEN;
+        self.buf[0..2].copy_from_slice(&SYNC);
+        self.buf[2..4].copy_from_slice(&(body_len as u16).to_le_bytes());
+        self.buf[4] = self.count as u8;
+        let crc = crc16(&self.buf[2..self.pos]);
+        self.buf[self.pos..self.pos + CRC_LEN].copy_from_slice(&crc.to_le_bytes());
+        let len = self.pos + CRC_LEN;
+        self.count = 0;
+        self.pos = 0;
+        &self.buf[..len]
+    }
+
+    fn put_varint(&mut self, mut value: u64) {
+        loop {
+            let byte = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                self.buf[self.pos] = byte;
+                self.pos += 1;
+                return;
+            }
+            self.buf[self.pos] = byte | 0x80;
+            self.pos += 1;
+        }
+    }
+
+    fn put_delta(&mut self, delta: i32) {
+        self.put_varint(zigzag(delta) as u64);
+    }
+}
+
+impl Default for BlockEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn zigzag(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+fn unzigzag(value: u32) -> i32 {
+    (value >> 1) as i32 ^ -((value & 1) as i32)
+}
+
+/// Finds the valid blocks in a byte stream. Bytes that do not belong to a
+/// block with a correct crc are skipped and counted.
+pub struct Blocks<'a> {
+    data: &'a [u8],
+    pub skipped_bytes: usize,
+}
+
+impl<'a> Blocks<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Blocks {
+            data,
+            skipped_bytes: 0,
+        }
+    }
+}
+
+impl<'a> Iterator for Blocks<'a> {
+    type Item = Block<'a>;
+
+    fn next(&mut self) -> Option<Block<'a>> {
+        loop {
+            let start = self.data.windows(SYNC.len()).position(|window| window == SYNC)?;
+            self.skipped_bytes += start;
+            self.data = &self.data[start..];
+
+            let candidate = self.data.get(2..4)
+                .map(|len| u16::from_le_bytes([len[0], len[1]]) as usize)
+                .filter(|len| (KEYFRAME_LEN..=MAX_BLOCK_LEN).contains(len))
+                .and_then(|len| self.data.get(..BLOCK_PREFIX_LEN + len + CRC_LEN).map(|block| (len, block)));
+            if let Some((len, block)) = candidate {
+                let crc = u16::from_le_bytes([block[BLOCK_PREFIX_LEN + len], block[BLOCK_PREFIX_LEN + len + 1]]);
+                if crc16(&block[2..BLOCK_PREFIX_LEN + len]) == crc {
+                    self.data = &self.data[block.len()..];
+                    return Some(Block { body: &block[BLOCK_PREFIX_LEN..BLOCK_PREFIX_LEN + len] });
+                }
+            }
+            // not a block, search again after this sync marker
+            self.skipped_bytes += 1;
+            self.data = &self.data[1..];
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Block<'a> {
+    body: &'a [u8],
+}
+
+impl<'a> Block<'a> {
+    pub fn len(&self) -> usize {
+        self.body[0] as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Call f for every sample in the block.
+    pub fn samples(&self, mut f: impl FnMut(RawSample)) -> Result<(), CompactError> {
+        if self.is_empty() {
+            return Err(CompactError::Malformed);
+        }
+        let k = &self.body[1..KEYFRAME_LEN];
+        let mut sample = RawSample {
+            timestamp_us: u64::from_le_bytes([k[0], k[1], k[2], k[3], k[4], k[5], k[6], k[7]]),
+            shunt: i16::from_le_bytes([k[8], k[9]]),
+            bus: u16::from_le_bytes([k[10], k[11]]),
+            power: u16::from_le_bytes([k[12], k[13]]),
+            current: i16::from_le_bytes([k[14], k[15]]),
+        };
+        f(sample);
+
+        let mut deltas = &self.body[KEYFRAME_LEN..];
+        for _ in 1..self.len() {
+            let dt = read_varint(&mut deltas)?;
+            sample.timestamp_us = sample.timestamp_us.checked_add(dt).ok_or(CompactError::Malformed)?;
+            sample.shunt = (sample.shunt as i32 + read_delta(&mut deltas)?) as i16;
+            sample.bus = (sample.bus as i32 + read_delta(&mut deltas)?) as u16;
+            sample.power = (sample.power as i32 + read_delta(&mut deltas)?) as u16;
+            sample.current = (sample.current as i32 + read_delta(&mut deltas)?) as i16;
+            f(sample);
+        }
+        if !deltas.is_empty() {
+            return Err(CompactError::Malformed);
+        }
+        Ok(())
+    }
+}
+
+fn read_varint(buf: &mut &[u8]) -> Result<u64, CompactError> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = buf.split_first().ok_or(CompactError::Malformed)?;
+        *buf = rest;
+        value |= ((byte & 0x7F) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(CompactError::Malformed)
+}
+
+fn read_delta(buf: &mut &[u8]) -> Result<i32, CompactError> {
+    let value = read_varint(buf)?;
+    let value = u32::try_from(value).map_err(|_| CompactError::Malformed)?;
+    Ok(unzigzag(value))
+}
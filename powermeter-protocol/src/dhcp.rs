@@ -0,0 +1,204 @@
+// Minimal DHCP server for the provisioning access point
+//
+// Hands out addresses from a small pool and announces the device as router
+// and DNS server. Only DISCOVER, REQUEST and RELEASE are handled.
+//
+// There is no clock, leases do not expire. A client not in the full pool
+// takes over the lease last used longest ago, offers nobody requested go
+// first, so phones retrying with new random MACs can not lock others out.
+
+pub const SERVER_PORT: u16 = 67;
+pub const CLIENT_PORT: u16 = 68;
+pub const MAX_MESSAGE_LEN: usize = 576;
+
+pub const MAX_LEASES: usize = 4;
+const LEASE_SECS: u32 = 3600;
+
+const OP_REQUEST: u8 = 1;
+const OP_REPLY: u8 = 2;
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+const OPTIONS_START: usize = 240;
+
+const OPTION_PAD: u8 = 0;
+const OPTION_SUBNET_MASK: u8 = 1;
+const OPTION_ROUTER: u8 = 3;
+const OPTION_DNS: u8 = 6;
+const OPTION_REQUESTED_IP: u8 = 50;
+const OPTION_LEASE_TIME: u8 = 51;
+const OPTION_MESSAGE_TYPE: u8 = 53;
+const OPTION_SERVER_ID: u8 = 54;
+const OPTION_END: u8 = 255;
+
+pub const DISCOVER: u8 = 1;
+pub const OFFER: u8 = 2;
+pub const REQUEST: u8 = 3;
+pub const ACK: u8 = 5;
+pub const NAK: u8 = 6;
+pub const RELEASE: u8 = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Lease {
+    mac: [u8; 6],
+    /// the client requested it and got an ACK
+    acked: bool,
+    /// message count when the client was last seen
+    used: u32,
+}
+
+pub struct DhcpServer {
+    server: [u8; 4],
+    leases: [Option<Lease>; MAX_LEASES],
+    /// messages handled, orders the leases by use
+    messages: u32,
+}
+
+impl DhcpServer {
+    /// Addresses are handed out from server + 1 upwards within the /24.
+    pub fn new(server: [u8; 4]) -> Self {
+        DhcpServer {
+            server,
+            leases: [None; MAX_LEASES],
+            messages: 0,
+        }
+    }
+
+    fn address(&self, index: usize) -> [u8; 4] {
+        let mut addr = self.server;
+        addr[3] = addr[3].wrapping_add(1 + index as u8);
+        addr
+    }
+
+    fn index_of(&self, addr: [u8; 4]) -> Option<usize> {
+        (0..MAX_LEASES).find(|index| self.address(*index) == addr)
+    }
+
+    fn find(&self, mac: [u8; 6]) -> Option<usize> {
+        self.leases.iter().position(|lease| lease.is_some_and(|lease| lease.mac == mac))
+    }
+
+    fn lease_for(&mut self, mac: [u8; 6]) -> usize {
+        let used = self.messages;
+        if let Some(index) = self.find(mac) {
+            if let Some(lease) = self.leases[index].as_mut() {
+                lease.used = used;
+            }
+            return index;
+        }
+        let index = self.leases.iter().position(|lease| lease.is_none()).unwrap_or_else(|| {
+            // full, an offer nobody requested before a bound lease
+            (0..MAX_LEASES)
+                .min_by_key(|index| self.leases[*index].map(|lease| (lease.acked, lease.used)))
+                .unwrap_or(0)
+        });
+        self.leases[index] = Some(Lease { mac, acked: false, used });
+        index
+    }
+
+    /// Write the reply to `request` into `out` and return its length. The
+    /// reply is meant to be broadcast to CLIENT_PORT.
+    pub fn handle(&mut self, request: &[u8], out: &mut [u8]) -> Option<usize> {
+        if request.len() < OPTIONS_START || request[0] != OP_REQUEST || request[236..240] != MAGIC_COOKIE {
+            return None;
+        }
+        let mut mac = [0u8; 6];
+        mac.copy_from_slice(&request[28..34]);
+        self.messages = self.messages.wrapping_add(1);
+
+        let mut message_type = None;
+        let mut requested = None;
+        let mut server_id = None;
+        let mut options = &request[OPTIONS_START..];
+        while let Some(&code) = options.first() {
+            match code {
+                OPTION_PAD => {
+                    options = &options[1..];
+                    continue;
+                }
+                OPTION_END => break,
+                _ => {}
+            }
+            let len = *options.get(1)? as usize;
+            let value = options.get(2..2 + len)?;
+            match (code, len) {
+                (OPTION_MESSAGE_TYPE, 1) => message_type = Some(value[0]),
+                (OPTION_REQUESTED_IP, 4) => requested = Some([value[0], value[1], value[2], value[3]]),
+                (OPTION_SERVER_ID, 4) => server_id = Some([value[0], value[1], value[2], value[3]]),
+                _ => {}
+            }
+            options = &options[2 + len..];
+        }
+
+        let ciaddr = [request[12], request[13], request[14], request[15]];
+        let reply_type = match message_type? {
+            DISCOVER => OFFER,
+            REQUEST => {
+                if server_id.is_some_and(|id| id != self.server) {
+                    // client picked another server
+                    return None;
+                }
+                let wanted = requested.unwrap_or(ciaddr);
+                let index = self.lease_for(mac);
+                if self.index_of(wanted) != Some(index) {
+                    NAK
+                } else {
+                    if let Some(lease) = self.leases[index].as_mut() {
+                        lease.acked = true;
+                    }
+                    ACK
+                }
+            }
+            RELEASE => {
+                if let Some(index) = self.find(mac) {
+                    self.leases[index] = None;
+                }
+                return None;
+            }
+            _ => return None,
+        };
+        let yiaddr = match reply_type {
+            NAK => [0; 4],
+            _ => {
+                let index = self.lease_for(mac);
+                self.address(index)
+            }
+        };
+
+        let len = OPTIONS_START + 3 + 6 + 6 + 6 + 6 + 6 + 1;
+        if out.len() < len {
+            return None;
+        }
+        out[..len].fill(0);
+        out[0] = OP_REPLY;
+        out[1..3].copy_from_slice(&request[1..3]);
+        out[4..8].copy_from_slice(&request[4..8]);
+        out[10..12].copy_from_slice(&request[10..12]);
+        out[16..20].copy_from_slice(&yiaddr);
+        out[20..24].copy_from_slice(&self.server);
+        out[28..44].copy_from_slice(&request[28..44]);
+        out[236..240].copy_from_slice(&MAGIC_COOKIE);
+
+        let mut pos = OPTIONS_START;
+        let mut put = |code: u8, value: &[u8]| {
+            out[pos] = code;
+            out[pos + 1] = value.len() as u8;
+            out[pos + 2..pos + 2 + value.len()].copy_from_slice(value);
+            pos += 2 + value.len();
+        };
+        put(OPTION_MESSAGE_TYPE, &[reply_type]);
+        put(OPTION_SERVER_ID, &self.server);
+        put(OPTION_LEASE_TIME, &LEASE_SECS.to_be_bytes());
+        put(OPTION_SUBNET_MASK, &[255, 255, 255, 0]);
+        put(OPTION_ROUTER, &self.server);
+        put(OPTION_DNS, &self.server);
+        out[pos] = OPTION_END;
+        Some(pos + 1)
+    }
+}
+
+/// Returns the message type of a reply produced by DhcpServer::handle.
+pub fn message_type(message: &[u8]) -> Option<u8> {
+    if message.len() < OPTIONS_START + 3 || message[OPTIONS_START] != OPTION_MESSAGE_TYPE {
+        return None;
+    }
+    Some(message[OPTIONS_START + 2])
+}
@@ -0,0 +1,72 @@
+// Catch-all DNS responder for the provisioning access point
+//
+// Every A query is answered with the address of the device so any URL a
+// phone or laptop opens ends up at the portal.
+
+pub const DNS_PORT: u16 = 53;
+pub const MAX_MESSAGE_LEN: usize = 512;
+
+const HEADER_LEN: usize = 12;
+const TYPE_A: u16 = 1;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+const TTL_SECS: u32 = 60;
+
+/// Write the response to `query` into `out` and return its length. Returns
+/// None for anything that is not a single standard query, those are
+/// dropped silently.
+pub fn answer(query: &[u8], addr: [u8; 4], out: &mut [u8]) -> Option<usize> {
+    if query.len() < HEADER_LEN {
+        return None;
+    }
+    let flags = u16::from_be_bytes([query[2], query[3]]);
+    let is_response = flags & 0x8000 != 0;
+    let opcode = (flags >> 11) & 0x0F;
+    let qdcount = u16::from_be_bytes([query[4], query[5]]);
+    if is_response || opcode != 0 || qdcount != 1 {
+        return None;
+    }
+
+    // question name is a sequence of labels, compression is not allowed here
+    let mut pos = HEADER_LEN;
+    loop {
+        let len = *query.get(pos)? as usize;
+        if len & 0xC0 != 0 {
+            return None;
+        }
+        pos += 1 + len;
+        if len == 0 {
+            break;
+        }
+    }
+    let question_end = pos + 4;
+    if query.len() < question_end {
+        return None;
+    }
+    let qtype = u16::from_be_bytes([query[pos], query[pos + 1]]);
+    let qclass = u16::from_be_bytes([query[pos + 2], query[pos + 3]]);
+    let with_answer = qclass == CLASS_IN && (qtype == TYPE_A || qtype == TYPE_ANY);
+
+    let len = question_end + if with_answer { 16 } else { 0 };
+    if out.len() < len {
+        return None;
+    }
+    out[..question_end].copy_from_slice(&query[..question_end]);
+    // response, authoritative, keep recursion desired, recursion available
+    let response_flags = 0x8400 | (flags & 0x0100) | 0x0080;
+    out[2..4].copy_from_slice(&response_flags.to_be_bytes());
+    out[6..8].copy_from_slice(&(with_answer as u16).to_be_bytes());
+    out[8..12].fill(0);
+
+    if with_answer {
+        let answer = &mut out[question_end..len];
+        // name is a pointer to the question
+        answer[0..2].copy_from_slice(&0xC00Cu16.to_be_bytes());
+        answer[2..4].copy_from_slice(&TYPE_A.to_be_bytes());
+        answer[4..6].copy_from_slice(&CLASS_IN.to_be_bytes());
+        answer[6..10].copy_from_slice(&TTL_SECS.to_be_bytes());
+        answer[10..12].copy_from_slice(&4u16.to_be_bytes());
+        answer[12..16].copy_from_slice(&addr);
+    }
+    Some(len)
+}
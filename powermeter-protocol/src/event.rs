@@ -0,0 +1,169 @@
+// Power events
+//
+// Something worth looking at afterwards: a reboot, a dip of the bus
+// voltage, an over current spike, a range change, a sensor that stopped
+// answering or a low LiPo. Events are numbered across reboots and carry the
+// boot they happened in, the time since that boot and the unix time when
+// the clock was synced by then.
+//
+// The log is exported as csv, over serial and on GET EVENTS_PATH.
+//
+// encoded (EVENT_LEN bytes)
+//   0  sequence     u32
+//   4  boot         u16
+//   6  kind         u8
+//   7  channel      u8
+//   8  uptime_us    u64
+//  16  unix_us      i64, i64::MIN if the clock was not synced
+//  24  value        f32, see EventKind
+//  28  duration_us  u32
+
+use core::fmt::{self, Write};
+
+use crate::http::{Method, Request, ResponseWriter};
+use crate::time::UtcDateTime;
+
+pub const EVENT_LEN: usize = 32;
+pub const EVENTS_PATH: &str = "/events";
+
+/// Column names of Event::write_csv.
+pub const CSV_HEADER: &str = "sequence,boot,uptime_s,utc,kind,channel,value,duration_ms\n";
+
+const UNKNOWN_TIME: i64 = i64::MIN;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    /// value is the reset reason of the chip
+    Boot,
+    /// value is the lowest bus voltage in V
+    Dropout,
+    /// value is the highest current in mA
+    OverCurrent,
+    /// value is the index of the new range
+    RangeChange,
+    /// value is the bus status the supervisor went to
+    SensorError,
+    /// value is the LiPo voltage in V
+    BatteryLow,
+}
+
+impl EventKind {
+    pub fn code(self) -> u8 {
+        match self {
+            EventKind::Boot => 0,
+            EventKind::Dropout => 1,
+            EventKind::OverCurrent => 2,
+            EventKind::RangeChange => 3,
+            EventKind::SensorError => 4,
+            EventKind::BatteryLow => 5,
+        }
+    }
+
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(EventKind::Boot),
+            1 => Some(EventKind::Dropout),
+            2 => Some(EventKind::OverCurrent),
+            3 => Some(EventKind::RangeChange),
+            4 => Some(EventKind::SensorError),
+            5 => Some(EventKind::BatteryLow),
+            _ => None,
+        }
+    }
+
+    /// Name in the csv export.
+    pub fn name(self) -> &'static str {
+        match self {
+            EventKind::Boot => "boot",
+            EventKind::Dropout => "dropout",
+            EventKind::OverCurrent => "over_current",
+            EventKind::RangeChange => "range_change",
+            EventKind::SensorError => "sensor_error",
+            EventKind::BatteryLow => "battery_low",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Event {
+    pub sequence: u32,
+    pub boot: u16,
+    pub kind: EventKind,
+    /// index of the channel, 0 for events of the meter itself
+    pub channel: u8,
+    /// since the boot
+    pub uptime_us: u64,
+    pub unix_us: Option<i64>,
+    pub value: f32,
+    /// 0 for events without one
+    pub duration_us: u32,
+}
+
+impl Event {
+    pub fn encode(&self) -> [u8; EVENT_LEN] {
+        let mut buf = [0u8; EVENT_LEN];
+        buf[0..4].copy_from_slice(&self.sequence.to_le_bytes());
+        buf[4..6].copy_from_slice(&self.boot.to_le_bytes());
+        buf[6] = self.kind.code();
+        buf[7] = self.channel;
+        buf[8..16].copy_from_slice(&self.uptime_us.to_le_bytes());
+        buf[16..24].copy_from_slice(&self.unix_us.unwrap_or(UNKNOWN_TIME).to_le_bytes());
+        buf[24..28].copy_from_slice(&self.value.to_le_bytes());
+        buf[28..32].copy_from_slice(&self.duration_us.to_le_bytes());
+        buf
+    }
+
+    /// None for a kind this firmware does not know.
+    pub fn decode(buf: &[u8; EVENT_LEN]) -> Option<Self> {
+        let unix_us = i64::from_le_bytes(buf[16..24].try_into().unwrap());
+        Some(Event {
+            sequence: u32::from_le_bytes(buf[0..4].try_into().unwrap()),
+            boot: u16::from_le_bytes([buf[4], buf[5]]),
+            kind: EventKind::from_code(buf[6])?,
+            channel: buf[7],
+            uptime_us: u64::from_le_bytes(buf[8..16].try_into().unwrap()),
+            unix_us: if unix_us == UNKNOWN_TIME { None } else { Some(unix_us) },
+            value: f32::from_le_bytes(buf[24..28].try_into().unwrap()),
+            duration_us: u32::from_le_bytes(buf[28..32].try_into().unwrap()),
+        })
+    }
+
+    /// One line of the csv export, the utc column is empty when the clock
+    /// was not synced.
+    pub fn write_csv<W: Write>(&self, out: &mut W) -> fmt::Result {
+        write!(out, "{},{},{:.3},", self.sequence, self.boot, self.uptime_us as f64 / 1e6)?;
+        if let Some(unix_us) = self.unix_us {
+            write!(out, "{}", UtcDateTime::from_unix_us(unix_us))?;
+        }
+        writeln!(out, ",{},{},{},{:.3}", self.kind.name(), self.channel, self.value, self.duration_us as f32 / 1000.0)
+    }
+}
+
+/// Serve the events, the oldest first, as csv on GET EVENTS_PATH. Returns
+/// the length written or None if out was too small.
+pub fn handle_http<'e>(request: &Request, events: impl Iterator<Item=&'e Event> + Clone, out: &mut [u8]) -> Option<usize> {
+    let mut writer = ResponseWriter::new(out);
+    if request.method != Method::Get || request.path != EVENTS_PATH {
+        return writer.not_found();
+    }
+    let mut counter = Counter(CSV_HEADER.len());
+    for event in events.clone() {
+        let _ = event.write_csv(&mut counter);
+    }
+    writer.head("200 OK", "text/csv", &[], counter.0);
+    let _ = writer.write_str(CSV_HEADER);
+    for event in events {
+        let _ = event.write_csv(&mut writer);
+    }
+    writer.finish()
+}
+
+/// Length of the text written, for the Content-Length.
+struct Counter(usize);
+
+impl Write for Counter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0 += s.len();
+        Ok(())
+    }
+}
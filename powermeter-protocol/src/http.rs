@@ -0,0 +1,203 @@
+// Just enough HTTP/1.x for small embedded pages: one request per
+// connection, no chunked bodies.
+
+use core::fmt::{self, Write};
+
+pub const HTTP_PORT: u16 = 80;
+/// headers and body, a longer request is malformed
+pub const MAX_REQUEST_LEN: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    /// headers or body not complete yet, read more
+    Incomplete,
+    Malformed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Post,
+    Other,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Request<'a> {
+    pub method: Method,
+    /// path without the query string
+    pub path: &'a str,
+    pub query: Option<&'a str>,
+    pub body: &'a [u8],
+}
+
+pub fn parse_request(buf: &[u8]) -> Result<Request<'_>, ParseError> {
+    let header_end = find(buf, b"\r\n\r\n").ok_or(ParseError::Incomplete)?;
+    let head = core::str::from_utf8(&buf[..header_end]).map_err(|_| ParseError::Malformed)?;
+    let mut lines = head.split("\r\n");
+
+    let mut request_line = lines.next().ok_or(ParseError::Malformed)?.split(' ');
+    let method = match request_line.next() {
+        Some("GET") => Method::Get,
+        Some("POST") => Method::Post,
+        Some(_) => Method::Other,
+        None => return Err(ParseError::Malformed),
+    };
+    let target = request_line.next().ok_or(ParseError::Malformed)?;
+    if !request_line.next().is_some_and(|version| version.starts_with("HTTP/1.")) {
+        return Err(ParseError::Malformed);
+    }
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (target, None),
+    };
+
+    let mut content_length = 0;
+    for line in lines {
+        let (name, value) = line.split_once(':').ok_or(ParseError::Malformed)?;
+        if name.trim().eq_ignore_ascii_case("content-length") {
+            content_length = value.trim().parse().map_err(|_| ParseError::Malformed)?;
+        }
+    }
+
+    let body_start = header_end + 4;
+    let body_end = body_start.checked_add(content_length)
+        .filter(|end| *end <= MAX_REQUEST_LEN)
+        .ok_or(ParseError::Malformed)?;
+    if buf.len() < body_end {
+        return Err(ParseError::Incomplete);
+    }
+    Ok(Request {
+        method,
+        path,
+        query,
+        body: &buf[body_start..body_end],
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// Find `name` in an application/x-www-form-urlencoded body or query
+/// string and decode its value into `out`. Returns Ok(None) if the field is
+/// missing and Malformed if it is badly encoded or does not fit.
+pub fn form_value<'a>(form: &[u8], name: &str, out: &'a mut [u8]) -> Result<Option<&'a str>, ParseError> {
+    for pair in form.split(|b| *b == b'&') {
+        let (key, value) = match pair.iter().position(|b| *b == b'=') {
+            Some(pos) => (&pair[..pos], &pair[pos + 1..]),
+            None => (pair, &pair[pair.len()..]),
+        };
+        if key == name.as_bytes() {
+            let len = url_decode(value, out).ok_or(ParseError::Malformed)?;
+            return core::str::from_utf8(&out[..len]).map(Some).map_err(|_| ParseError::Malformed);
+        }
+    }
+    Ok(None)
+}
+
+fn url_decode(input: &[u8], out: &mut [u8]) -> Option<usize> {
+    let mut len = 0;
+    let mut i = 0;
+    while i < input.len() {
+        let byte = match input[i] {
+            b'+' => b' ',
+            b'%' => {
+                let hi = hex_digit(*input.get(i + 1)?)?;
+                let lo = hex_digit(*input.get(i + 2)?)?;
+                i += 2;
+                hi << 4 | lo
+            }
+            b => b,
+        };
+        *out.get_mut(len)? = byte;
+        len += 1;
+        i += 1;
+    }
+    Some(len)
+}
+
+fn hex_digit(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Writes a complete response into a byte buffer.
+pub struct ResponseWriter<'a> {
+    out: &'a mut [u8],
+    len: usize,
+    overflow: bool,
+}
+
+impl<'a> ResponseWriter<'a> {
+    pub fn new(out: &'a mut [u8]) -> Self {
+        ResponseWriter {
+            out,
+            len: 0,
+            overflow: false,
+        }
+    }
+
+    pub fn ok(self, content_type: &str, body: &str) -> Option<usize> {
+        self.response("200 OK", content_type, &[], body)
+    }
+
+    pub fn redirect(self, location: &str) -> Option<usize> {
+        self.response("302 Found", "text/plain", &[("Location", location)], "")
+    }
+
+    pub fn not_found(self) -> Option<usize> {
+        self.response("404 Not Found", "text/plain", &[], "not found")
+    }
+
+    pub fn bad_request(self, message: &str) -> Option<usize> {
+        self.response("400 Bad Request", "text/plain", &[], message)
+    }
+
+    /// Returns the length written or None if the buffer was too small.
+    pub fn response(self, status: &str, content_type: &str, headers: &[(&str, &str)], body: &str) -> Option<usize> {
+        self.response_parts(status, content_type, headers, &[body])
+    }
+
+    /// Like response() with the body given in pieces, saves assembling a
+    /// page in a separate buffer first.
+    pub fn response_parts(mut self, status: &str, content_type: &str, headers: &[(&str, &str)], body: &[&str]) -> Option<usize> {
+        self.head(status, content_type, headers, body.iter().map(|part| part.len()).sum());
+        for part in body {
+            let _ = self.write_str(part);
+        }
+        self.finish()
+    }
+
+    /// Status line and headers of a body of body_len bytes the caller
+    /// writes next through fmt::Write, then calls finish().
+    pub fn head(&mut self, status: &str, content_type: &str, headers: &[(&str, &str)], body_len: usize) {
+        let _ = write!(self, "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+                       status, content_type, body_len);
+        for (name, value) in headers {
+            let _ = write!(self, "{}: {}\r\n", name, value);
+        }
+        let _ = self.write_str("\r\n");
+    }
+
+    /// Returns the length written or None if the buffer was too small.
+    pub fn finish(self) -> Option<usize> {
+        if self.overflow { None } else { Some(self.len) }
+    }
+}
+
+impl<'a> fmt::Write for ResponseWriter<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        if end > self.out.len() {
+            self.overflow = true;
+            return Err(fmt::Error);
+        }
+        self.out[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
@@ -0,0 +1,13 @@
+#![no_std]
+
+pub mod compact;
+mod crc;
+pub mod dhcp;
+pub mod dns;
+pub mod event;
+pub mod http;
+pub mod portal;
+pub mod serial;
+pub mod sntp;
+pub mod stream;
+pub mod time;
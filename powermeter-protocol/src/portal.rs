@@ -0,0 +1,94 @@
+// Wi-Fi provisioning portal served on the access point
+
+use heapless::String;
+
+use crate::http::{form_value, Method, Request, ResponseWriter};
+
+pub const AP_ADDRESS: [u8; 4] = [192, 168, 4, 1];
+pub const PORTAL_URL: &str = "http://192.168.4.1/";
+
+pub const MAX_SSID_LEN: usize = 32;
+pub const MAX_PASSWORD_LEN: usize = 64;
+
+const PAGE_HEAD: &str = "<!DOCTYPE html><html><head><meta name=\"viewport\" content=\"width=device-width\">\
+<title>powermeter</title></head><body><h2>powermeter Wi-Fi setup</h2>";
+const PAGE_FORM: &str = "<form method=\"post\" action=\"/save\">\
+<p>SSID<br><input name=\"ssid\" maxlength=\"32\" required></p>\
+<p>Password<br><input name=\"password\" type=\"password\" maxlength=\"63\"></p>\
+<p><input type=\"submit\" value=\"Save\"></p></form>";
+const PAGE_SAVED: &str = "<p>Saved. The device restarts and joins the network.</p>";
+const PAGE_TAIL: &str = "</body></html>";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub ssid: String<MAX_SSID_LEN>,
+    pub password: String<MAX_PASSWORD_LEN>,
+}
+
+#[derive(Debug)]
+pub struct Reply {
+    /// bytes of the response written to the output buffer
+    pub len: usize,
+    /// set once the user submitted valid credentials
+    pub credentials: Option<Credentials>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormError {
+    MissingSsid,
+    SsidTooLong,
+    BadPassword,
+}
+
+impl FormError {
+    fn message(&self) -> &'static str {
+        match self {
+            FormError::MissingSsid => "SSID is required",
+            FormError::SsidTooLong => "SSID is longer than 32 characters",
+            FormError::BadPassword => "Password must be empty or 8 to 63 characters",
+        }
+    }
+}
+
+pub fn parse_credentials(form: &[u8]) -> Result<Credentials, FormError> {
+    let mut buf = [0u8; MAX_PASSWORD_LEN];
+    let ssid = match form_value(form, "ssid", &mut buf) {
+        Ok(Some(ssid)) if !ssid.is_empty() => ssid,
+        Ok(_) => return Err(FormError::MissingSsid),
+        Err(_) => return Err(FormError::SsidTooLong),
+    };
+    let ssid: String<MAX_SSID_LEN> = ssid.try_into().map_err(|_| FormError::SsidTooLong)?;
+
+    let password = form_value(form, "password", &mut buf).map_err(|_| FormError::BadPassword)?.unwrap_or("");
+    if !password.is_empty() && !(8..=63).contains(&password.len()) {
+        return Err(FormError::BadPassword);
+    }
+    let password: String<MAX_PASSWORD_LEN> = password.try_into().map_err(|_| FormError::BadPassword)?;
+    Ok(Credentials { ssid, password })
+}
+
+/// Answer one portal request. Unknown paths, including the captive portal
+/// probes of the common operating systems, are redirected to the form.
+pub fn handle(request: &Request, out: &mut [u8]) -> Reply {
+    let mut credentials = None;
+    let len = match (request.method, request.path) {
+        (Method::Get, "/") => page(out, "", PAGE_FORM),
+        (Method::Post, "/save") => match parse_credentials(request.body) {
+            Ok(saved) => {
+                credentials = Some(saved);
+                page(out, "", PAGE_SAVED)
+            }
+            Err(e) => page(out, e.message(), PAGE_FORM),
+        },
+        _ => ResponseWriter::new(out).redirect(PORTAL_URL),
+    };
+    Reply {
+        len: len.unwrap_or(0),
+        credentials,
+    }
+}
+
+fn page(out: &mut [u8], error: &str, content: &str) -> Option<usize> {
+    let (open, close) = if error.is_empty() { ("", "") } else { ("<p style=\"color:red\">", "</p>") };
+    ResponseWriter::new(out).response_parts("200 OK", "text/html", &[], &[PAGE_HEAD, open, error, close, content, PAGE_TAIL])
+}
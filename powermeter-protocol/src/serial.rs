@@ -0,0 +1,455 @@
+// Serial command protocol between the device and powermeter-cli
+//
+// Every message is sent as one frame: the message followed by a crc, COBS
+// encoded and terminated by a zero byte, so a reader can resync on the next
+// zero after garbage. Fixed size values are little-endian.
+//
+// message
+//   0  type         u8
+//   1  payload
+//   .  crc          u16 over type and payload
+//
+// The host sends requests. The device answers Ping with Pong, config
+// requests with ConfigValue, Ok or Error, SessionList with Session
+// responses followed by End and SessionRead with the Session and its
+// Record responses followed by End, EventList with Event responses, the
+// oldest first, followed by End. After LiveStart it sends Sample
+// responses until LiveStop, which is answered with End. Any request can be
+// answered with Error instead.
+
+use crate::crc::crc16;
+use crate::event::{Event, EVENT_LEN};
+use crate::stream::Sample;
+
+pub const VERSION: u8 = 3;
+pub const BAUD_RATE: u32 = 115_200;
+
+/// type, payload and crc before COBS encoding
+pub const MAX_MESSAGE_LEN: usize = 272;
+/// encoded frame including the terminating zero
+pub const MAX_FRAME_LEN: usize = MAX_MESSAGE_LEN + MAX_MESSAGE_LEN / 254 + 2;
+
+const CRC_LEN: usize = 2;
+
+const PING: u8 = 0x01;
+const LIVE_START: u8 = 0x02;
+const LIVE_STOP: u8 = 0x03;
+const CONFIG_GET: u8 = 0x04;
+const CONFIG_SET: u8 = 0x05;
+const SESSION_LIST: u8 = 0x06;
+const SESSION_READ: u8 = 0x07;
+const EVENT_LIST: u8 = 0x08;
+
+const PONG: u8 = 0x81;
+const SAMPLE: u8 = 0x82;
+const CONFIG_VALUE: u8 = 0x83;
+const OK: u8 = 0x84;
+const ERROR: u8 = 0x85;
+const SESSION: u8 = 0x86;
+const RECORD: u8 = 0x87;
+const END: u8 = 0x88;
+const EVENT: u8 = 0x89;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SerialError {
+    /// frame longer than the reader buffer
+    TooLong,
+    BadEncoding,
+    BadCrc,
+    UnknownType(u8),
+    Truncated,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    UnknownRequest,
+    UnknownName,
+    InvalidValue,
+    Storage,
+    NotFound,
+    Other(u8),
+}
+
+impl ErrorCode {
+    fn code(self) -> u8 {
+        match self {
+            ErrorCode::UnknownRequest => 1,
+            ErrorCode::UnknownName => 2,
+            ErrorCode::InvalidValue => 3,
+            ErrorCode::Storage => 4,
+            ErrorCode::NotFound => 5,
+            ErrorCode::Other(code) => code,
+        }
+    }
+
+    fn from_code(code: u8) -> Self {
+        match code {
+            1 => ErrorCode::UnknownRequest,
+            2 => ErrorCode::UnknownName,
+            3 => ErrorCode::InvalidValue,
+            4 => ErrorCode::Storage,
+            5 => ErrorCode::NotFound,
+            code => ErrorCode::Other(code),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Request<'a> {
+    Ping,
+    LiveStart,
+    LiveStop,
+    ConfigGet { name: &'a str },
+    ConfigSet { name: &'a str, value: &'a str },
+    SessionList,
+    SessionRead { id: u32 },
+    EventList,
+}
+
+/// Summary of a data log session, see powermeter_storage::datalog.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionEntry {
+    pub id: u32,
+    pub mode: u8,
+    pub calibration: u8,
+    pub interval_us: u32,
+    pub start_unix_us: Option<i64>,
+    pub duration_us: u64,
+    pub records: u32,
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Response<'a> {
+    Pong { version: u8 },
+    Sample(Sample),
+    ConfigValue { value: &'a str },
+    Ok,
+    Error(ErrorCode),
+    Session(SessionEntry),
+    /// a data log record as kind and payload, see powermeter_storage::datalog
+    Record { kind: u8, payload: &'a [u8] },
+    End,
+    Event(Event),
+}
+
+impl<'a> Request<'a> {
+    /// Write the framed request into out and return its length.
+    pub fn encode(&self, out: &mut [u8]) -> Option<usize> {
+        let mut message = [0u8; MAX_MESSAGE_LEN];
+        let mut w = Writer::new(&mut message);
+        match self {
+            Request::Ping => w.u8(PING)?,
+            Request::LiveStart => w.u8(LIVE_START)?,
+            Request::LiveStop => w.u8(LIVE_STOP)?,
+            Request::ConfigGet { name } => {
+                w.u8(CONFIG_GET)?;
+                w.bytes(name.as_bytes())?;
+            }
+            Request::ConfigSet { name, value } => {
+                w.u8(CONFIG_SET)?;
+                w.u8(u8::try_from(name.len()).ok()?)?;
+                w.bytes(name.as_bytes())?;
+                w.bytes(value.as_bytes())?;
+            }
+            Request::SessionList => w.u8(SESSION_LIST)?,
+            Request::SessionRead { id } => {
+                w.u8(SESSION_READ)?;
+                w.bytes(&id.to_le_bytes())?;
+            }
+            Request::EventList => w.u8(EVENT_LIST)?,
+        }
+        let len = w.len;
+        encode_frame(&message[..len], out)
+    }
+
+    /// Decode a message returned by FrameReader.
+    pub fn decode(message: &'a [u8]) -> Result<Self, SerialError> {
+        let (&kind, payload) = message.split_first().ok_or(SerialError::Truncated)?;
+        let mut r = Reader { buf: payload };
+        let request = match kind {
+            PING => Request::Ping,
+            LIVE_START => Request::LiveStart,
+            LIVE_STOP => Request::LiveStop,
+            CONFIG_GET => Request::ConfigGet { name: r.rest_str()? },
+            CONFIG_SET => {
+                let name_len = r.u8()? as usize;
+                let name = r.str(name_len)?;
+                Request::ConfigSet { name, value: r.rest_str()? }
+            }
+            SESSION_LIST => Request::SessionList,
+            SESSION_READ => Request::SessionRead { id: r.u32()? },
+            EVENT_LIST => Request::EventList,
+            kind => return Err(SerialError::UnknownType(kind)),
+        };
+        Ok(request)
+    }
+}
+
+impl<'a> Response<'a> {
+    /// Write the framed response into out and return its length.
+    pub fn encode(&self, out: &mut [u8]) -> Option<usize> {
+        let mut message = [0u8; MAX_MESSAGE_LEN];
+        let mut w = Writer::new(&mut message);
+        match self {
+            Response::Pong { version } => {
+                w.u8(PONG)?;
+                w.u8(*version)?;
+            }
+            Response::Sample(sample) => {
+                w.u8(SAMPLE)?;
+                w.bytes(&sample.timestamp_us.to_le_bytes())?;
+                w.bytes(&sample.voltage.to_le_bytes())?;
+                w.bytes(&sample.current.to_le_bytes())?;
+                w.bytes(&sample.power.to_le_bytes())?;
+                w.u8(sample.range)?;
+            }
+            Response::ConfigValue { value } => {
+                w.u8(CONFIG_VALUE)?;
+                w.bytes(value.as_bytes())?;
+            }
+            Response::Ok => w.u8(OK)?,
+            Response::Error(code) => {
+                w.u8(ERROR)?;
+                w.u8(code.code())?;
+            }
+            Response::Session(entry) => {
+                w.u8(SESSION)?;
+                w.bytes(&entry.id.to_le_bytes())?;
+                w.u8(entry.mode)?;
+                w.u8(entry.calibration)?;
+                w.bytes(&entry.interval_us.to_le_bytes())?;
+                w.u8(entry.start_unix_us.is_some() as u8)?;
+                w.bytes(&entry.start_unix_us.unwrap_or(0).to_le_bytes())?;
+                w.bytes(&entry.duration_us.to_le_bytes())?;
+                w.bytes(&entry.records.to_le_bytes())?;
+                w.u8(entry.truncated as u8)?;
+            }
+            Response::Record { kind, payload } => {
+                w.u8(RECORD)?;
+                w.u8(*kind)?;
+                w.bytes(payload)?;
+            }
+            Response::End => w.u8(END)?,
+            Response::Event(event) => {
+                w.u8(EVENT)?;
+                w.bytes(&event.encode())?;
+            }
+        }
+        let len = w.len;
+        encode_frame(&message[..len], out)
+    }
+
+    /// Decode a message returned by FrameReader.
+    pub fn decode(message: &'a [u8]) -> Result<Self, SerialError> {
+        let (&kind, payload) = message.split_first().ok_or(SerialError::Truncated)?;
+        let mut r = Reader { buf: payload };
+        let response = match kind {
+            PONG => Response::Pong { version: r.u8()? },
+            SAMPLE => Response::Sample(Sample {
+                timestamp_us: r.u64()?,
+                voltage: f32::from_bits(r.u32()?),
+                current: f32::from_bits(r.u32()?),
+                power: f32::from_bits(r.u32()?),
+                range: r.u8()?,
+            }),
+            CONFIG_VALUE => Response::ConfigValue { value: r.rest_str()? },
+            OK => Response::Ok,
+            ERROR => Response::Error(ErrorCode::from_code(r.u8()?)),
+            SESSION => {
+                let id = r.u32()?;
+                let mode = r.u8()?;
+                let calibration = r.u8()?;
+                let interval_us = r.u32()?;
+                let has_start = r.u8()? != 0;
+                let start_unix_us = r.u64()? as i64;
+                Response::Session(SessionEntry {
+                    id,
+                    mode,
+                    calibration,
+                    interval_us,
+                    start_unix_us: if has_start { Some(start_unix_us) } else { None },
+                    duration_us: r.u64()?,
+                    records: r.u32()?,
+                    truncated: r.u8()? != 0,
+                })
+            }
+            RECORD => Response::Record { kind: r.u8()?, payload: r.buf },
+            END => Response::End,
+            EVENT => {
+                let buf = r.take(EVENT_LEN)?.try_into().unwrap();
+                Response::Event(Event::decode(buf).ok_or(SerialError::BadEncoding)?)
+            }
+            kind => return Err(SerialError::UnknownType(kind)),
+        };
+        Ok(response)
+    }
+}
+
+struct Writer<'a> {
+    out: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> Writer<'a> {
+    fn new(out: &'a mut [u8]) -> Self {
+        Writer { out, len: 0 }
+    }
+
+    fn u8(&mut self, value: u8) -> Option<()> {
+        self.bytes(&[value])
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) -> Option<()> {
+        // room for the crc has to stay
+        let end = self.len + bytes.len();
+        if end + CRC_LEN > self.out.len() {
+            return None;
+        }
+        self.out[self.len..end].copy_from_slice(bytes);
+        self.len = end;
+        Some(())
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SerialError> {
+        if self.buf.len() < len {
+            return Err(SerialError::Truncated);
+        }
+        let (bytes, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, SerialError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, SerialError> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn u64(&mut self) -> Result<u64, SerialError> {
+        let b = self.take(8)?;
+        Ok(u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]))
+    }
+
+    fn str(&mut self, len: usize) -> Result<&'a str, SerialError> {
+        core::str::from_utf8(self.take(len)?).map_err(|_| SerialError::BadEncoding)
+    }
+
+    fn rest_str(&mut self) -> Result<&'a str, SerialError> {
+        self.str(self.buf.len())
+    }
+}
+
+/// Append the crc to message, COBS encode it into out and terminate the
+/// frame with a zero. Returns the frame length.
+pub fn encode_frame(message: &[u8], out: &mut [u8]) -> Option<usize> {
+    let crc = crc16(message).to_le_bytes();
+    let mut code_pos = 0;
+    let mut len = 1;
+    let mut code = 1u8;
+    for &byte in message.iter().chain(crc.iter()) {
+        if byte == 0 {
+            *out.get_mut(code_pos)? = code;
+            code_pos = len;
+            len += 1;
+            code = 1;
+        } else {
+            *out.get_mut(len)? = byte;
+            len += 1;
+            code += 1;
+            if code == 0xFF {
+                *out.get_mut(code_pos)? = code;
+                code_pos = len;
+                len += 1;
+                code = 1;
+            }
+        }
+    }
+    *out.get_mut(code_pos)? = code;
+    *out.get_mut(len)? = 0;
+    Some(len + 1)
+}
+
+/// Collects bytes up to the terminating zero and returns the decoded
+/// message without its crc.
+pub struct FrameReader<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+    overflow: bool,
+}
+
+impl<const N: usize> FrameReader<N> {
+    pub fn new() -> Self {
+        FrameReader {
+            buf: [0u8; N],
+            len: 0,
+            overflow: false,
+        }
+    }
+
+    /// Returns Some once a frame is complete.
+    pub fn push(&mut self, byte: u8) -> Option<Result<&[u8], SerialError>> {
+        if byte != 0 {
+            if self.len < N {
+                self.buf[self.len] = byte;
+                self.len += 1;
+            } else {
+                self.overflow = true;
+            }
+            return None;
+        }
+        let len = core::mem::replace(&mut self.len, 0);
+        if core::mem::replace(&mut self.overflow, false) {
+            return Some(Err(SerialError::TooLong));
+        }
+        if len == 0 {
+            // back to back terminators, nothing in between
+            return None;
+        }
+        Some(decode_frame(&mut self.buf[..len]))
+    }
+}
+
+impl<const N: usize> Default for FrameReader<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn decode_frame(buf: &mut [u8]) -> Result<&[u8], SerialError> {
+    let mut read = 0;
+    let mut write = 0;
+    while read < buf.len() {
+        let code = buf[read] as usize;
+        if code == 0 || read + code > buf.len() {
+            return Err(SerialError::BadEncoding);
+        }
+        read += 1;
+        for _ in 1..code {
+            buf[write] = buf[read];
+            write += 1;
+            read += 1;
+        }
+        if code < 0xFF && read < buf.len() {
+            buf[write] = 0;
+            write += 1;
+        }
+    }
+    if write <= CRC_LEN {
+        return Err(SerialError::Truncated);
+    }
+    let (message, crc) = buf[..write].split_at(write - CRC_LEN);
+    if crc16(message).to_le_bytes() != crc {
+        return Err(SerialError::BadCrc);
+    }
+    Ok(message)
+}
@@ -0,0 +1,107 @@
+// SNTPv4 client packets (RFC 4330)
+//
+// Only the fields needed by a unicast client are handled. Local times are
+// plain microsecond counters, the server times are converted to unix
+// microseconds.
+
+pub const NTP_PORT: u16 = 123;
+pub const PACKET_LEN: usize = 48;
+
+// seconds between 1900-01-01 and 1970-01-01
+const NTP_UNIX_OFFSET: i64 = 2_208_988_800;
+
+const VERSION: u8 = 4;
+const MODE_CLIENT: u8 = 3;
+const MODE_SERVER: u8 = 4;
+const MODE_BROADCAST: u8 = 5;
+const LEAP_NOT_SYNCHRONIZED: u8 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SntpError {
+    TooShort,
+    BadMode(u8),
+    NotSynchronized,
+    KissOfDeath([u8; 4]),
+    OriginMismatch,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Response {
+    pub stratum: u8,
+    pub receive_us: i64,
+    pub transmit_us: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Measurement {
+    /// add to a local timestamp to get unix time
+    pub offset_us: i64,
+    pub delay_us: i64,
+}
+
+/// Build a client request. The nonce is sent as transmit timestamp and
+/// must come back as origin timestamp in the response.
+pub fn request(nonce: u64) -> [u8; PACKET_LEN] {
+    let mut buf = [0u8; PACKET_LEN];
+    buf[0] = VERSION << 3 | MODE_CLIENT;
+    buf[40..48].copy_from_slice(&nonce.to_be_bytes());
+    buf
+}
+
+pub fn parse_response(buf: &[u8], nonce: u64) -> Result<Response, SntpError> {
+    if buf.len() < PACKET_LEN {
+        return Err(SntpError::TooShort);
+    }
+    let leap = buf[0] >> 6;
+    let mode = buf[0] & 0x07;
+    let stratum = buf[1];
+    if mode != MODE_SERVER && mode != MODE_BROADCAST {
+        return Err(SntpError::BadMode(mode));
+    }
+    if stratum == 0 {
+        return Err(SntpError::KissOfDeath([buf[12], buf[13], buf[14], buf[15]]));
+    }
+    if leap == LEAP_NOT_SYNCHRONIZED {
+        return Err(SntpError::NotSynchronized);
+    }
+    if read_u64(&buf[24..32]) != nonce {
+        return Err(SntpError::OriginMismatch);
+    }
+    Ok(Response {
+        stratum,
+        receive_us: ntp_to_unix_us(read_u64(&buf[32..40])),
+        transmit_us: ntp_to_unix_us(read_u64(&buf[40..48])),
+    })
+}
+
+/// Combine a response with the local send (t1) and receive (t4) times.
+pub fn measure(t1_us: i64, response: &Response, t4_us: i64) -> Measurement {
+    let t2 = response.receive_us;
+    let t3 = response.transmit_us;
+    Measurement {
+        offset_us: ((t2 - t1_us) + (t3 - t4_us)) / 2,
+        delay_us: (t4_us - t1_us) - (t3 - t2),
+    }
+}
+
+pub fn ntp_to_unix_us(timestamp: u64) -> i64 {
+    let mut secs = (timestamp >> 32) as i64;
+    // era 1 starts in 2036, values with the msb clear are taken as era 1
+    if secs < 0x8000_0000 {
+        secs += 1 << 32;
+    }
+    let frac_us = ((timestamp & 0xFFFF_FFFF) * 1_000_000) >> 32;
+    (secs - NTP_UNIX_OFFSET) * 1_000_000 + frac_us as i64
+}
+
+pub fn unix_us_to_ntp(unix_us: i64) -> u64 {
+    let secs = (unix_us.div_euclid(1_000_000) + NTP_UNIX_OFFSET) as u64 & 0xFFFF_FFFF;
+    let frac = ((unix_us.rem_euclid(1_000_000) as u64) << 32) / 1_000_000;
+    secs << 32 | frac
+}
+
+fn read_u64(buf: &[u8]) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&buf[..8]);
+    u64::from_be_bytes(bytes)
+}
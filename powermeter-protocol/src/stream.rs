@@ -0,0 +1,217 @@
+// UDP sample stream
+//
+// Every datagram is one packet: a fixed header followed by `count` samples.
+// All values are little-endian.
+//
+// header (28 bytes)
+//   0  magic      "PM"
+//   2  version    u8
+//   3  flags      u8, FLAG_UTC if utc_offset is valid
+//   4  sequence   u32, incremented per packet, wraps
+//   8  base_us    u64, timestamp of the first sample in us since boot
+//  16  utc_offset i64, add to a timestamp to get unix time in us
+//  24  count      u16
+//  26  reserved   u16
+//
+// sample (17 bytes)
+//   0  offset_us  u32, relative to base_us
+//   4  voltage    f32, V
+//   8  current    f32, mA
+//  12  power      f32, mW
+//  16  range      u8, index of the sensor range
+//
+// A receiver subscribes by sending SUBSCRIBE_REQUEST to the device port and
+// has to repeat it at least every SUBSCRIBE_TIMEOUT_SECS to keep the stream.
+
+pub const MAGIC: [u8; 2] = *b"PM";
+pub const VERSION: u8 = 3;
+pub const DEFAULT_PORT: u16 = 4210;
+
+pub const HEADER_LEN: usize = 28;
+pub const SAMPLE_LEN: usize = 17;
+pub const MAX_SAMPLES: usize = 64;
+pub const MAX_PACKET_LEN: usize = HEADER_LEN + MAX_SAMPLES * SAMPLE_LEN;
+
+pub const SUBSCRIBE_REQUEST: [u8; 4] = [MAGIC[0], MAGIC[1], VERSION, 0x01];
+pub const SUBSCRIBE_TIMEOUT_SECS: u64 = 10;
+
+pub const FLAG_UTC: u8 = 0x01;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub timestamp_us: u64,
+    pub voltage: f32,
+    pub current: f32,
+    pub power: f32,
+    /// index of the sensor range the sample was taken with, it changes
+    /// within a stream with auto range
+    pub range: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    TooShort,
+    BadMagic,
+    UnsupportedVersion(u8),
+    Truncated,
+}
+
+pub struct PacketBuilder {
+    buf: [u8; MAX_PACKET_LEN],
+    sequence: u32,
+    base_us: u64,
+    utc_offset_us: Option<i64>,
+    count: usize,
+}
+
+impl PacketBuilder {
+    pub fn new() -> Self {
+        PacketBuilder {
+            buf: [0u8; MAX_PACKET_LEN],
+            sequence: 0,
+            base_us: 0,
+            utc_offset_us: None,
+            count: 0,
+        }
+    }
+
+    /// Offset from the sample timestamps to unix time, written into the
+    /// header of every following packet.
+    pub fn set_utc_offset(&mut self, utc_offset_us: Option<i64>) {
+        self.utc_offset_us = utc_offset_us;
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.count == MAX_SAMPLES
+    }
+
+    pub fn sequence(&self) -> u32 {
+        self.sequence
+    }
+
+    /// Returns false if the sample does not fit into the current packet,
+    /// either because it is full or the timestamp offset would overflow.
+    /// The caller must finish() the packet and push again.
+    pub fn push(&mut self, sample: &Sample) -> bool {
+        if self.is_full() {
+            return false;
+        }
+        if self.count == 0 {
+            self.base_us = sample.timestamp_us;
+        }
+        let offset = match sample.timestamp_us.checked_sub(self.base_us) {
+            Some(offset) if offset <= u32::MAX as u64 => offset as u32,
+            _ => return false,
+        };
+        let start = HEADER_LEN + self.count * SAMPLE_LEN;
+        let out = &mut self.buf[start..start + SAMPLE_LEN];
+        out[0..4].copy_from_slice(&offset.to_le_bytes());
+        out[4..8].copy_from_slice(&sample.voltage.to_le_bytes());
+        out[8..12].copy_from_slice(&sample.current.to_le_bytes());
+        out[12..16].copy_from_slice(&sample.power.to_le_bytes());
+        out[16] = sample.range;
+        self.count += 1;
+        true
+    }
+
+    /// Seal the current packet and return its bytes. The builder starts
+    /// a new packet with the next sequence number on the following push.
+    pub fn finish(&mut self) -> &[u8] {
+        let len = HEADER_LEN + self.count * SAMPLE_LEN;
+        self.buf[0..2].copy_from_slice(&MAGIC);
+        self.buf[2] = VERSION;
+        self.buf[3] = if self.utc_offset_us.is_some() { FLAG_UTC } else { 0 };
+        self.buf[4..8].copy_from_slice(&self.sequence.to_le_bytes());
+        self.buf[8..16].copy_from_slice(&self.base_us.to_le_bytes());
+        self.buf[16..24].copy_from_slice(&self.utc_offset_us.unwrap_or(0).to_le_bytes());
+        self.buf[24..26].copy_from_slice(&(self.count as u16).to_le_bytes());
+        self.buf[26..28].copy_from_slice(&0u16.to_le_bytes());
+        self.sequence = self.sequence.wrapping_add(1);
+        self.count = 0;
+        &self.buf[..len]
+    }
+}
+
+impl Default for PacketBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Packet<'a> {
+    pub sequence: u32,
+    pub base_us: u64,
+    pub utc_offset_us: Option<i64>,
+    samples: &'a [u8],
+}
+
+impl<'a> Packet<'a> {
+    pub fn len(&self) -> usize {
+        self.samples.len() / SAMPLE_LEN
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn utc_us(&self, sample: &Sample) -> Option<i64> {
+        self.utc_offset_us.map(|offset| sample.timestamp_us as i64 + offset)
+    }
+
+    pub fn samples(&self) -> impl Iterator<Item=Sample> + 'a {
+        let base_us = self.base_us;
+        self.samples.chunks_exact(SAMPLE_LEN).map(move |s| {
+            Sample {
+                timestamp_us: base_us + u32::from_le_bytes([s[0], s[1], s[2], s[3]]) as u64,
+                voltage: f32::from_le_bytes([s[4], s[5], s[6], s[7]]),
+                current: f32::from_le_bytes([s[8], s[9], s[10], s[11]]),
+                power: f32::from_le_bytes([s[12], s[13], s[14], s[15]]),
+                range: s[16],
+            }
+        })
+    }
+}
+
+pub fn decode(buf: &[u8]) -> Result<Packet<'_>, DecodeError> {
+    if buf.len() < HEADER_LEN {
+        return Err(DecodeError::TooShort);
+    }
+    if buf[0..2] != MAGIC {
+        return Err(DecodeError::BadMagic);
+    }
+    if buf[2] != VERSION {
+        return Err(DecodeError::UnsupportedVersion(buf[2]));
+    }
+    let flags = buf[3];
+    let sequence = u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]);
+    let mut base = [0u8; 8];
+    base.copy_from_slice(&buf[8..16]);
+    let base_us = u64::from_le_bytes(base);
+    let mut offset = [0u8; 8];
+    offset.copy_from_slice(&buf[16..24]);
+    let utc_offset_us = if flags & FLAG_UTC != 0 { Some(i64::from_le_bytes(offset)) } else { None };
+    let count = u16::from_le_bytes([buf[24], buf[25]]) as usize;
+    let end = HEADER_LEN + count * SAMPLE_LEN;
+    if buf.len() < end {
+        return Err(DecodeError::Truncated);
+    }
+    Ok(Packet {
+        sequence,
+        base_us,
+        utc_offset_us,
+        samples: &buf[HEADER_LEN..end],
+    })
+}
+
+pub fn is_subscribe_request(buf: &[u8]) -> bool {
+    buf == SUBSCRIBE_REQUEST
+}
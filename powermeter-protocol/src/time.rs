@@ -0,0 +1,134 @@
+use core::fmt;
+
+use crate::sntp::Measurement;
+
+// shortest interval between two syncs used for the drift estimate
+const MIN_DRIFT_INTERVAL_US: i64 = 60_000_000;
+
+#[derive(Debug, Clone, Copy)]
+struct SyncPoint {
+    local_us: i64,
+    offset_us: i64,
+}
+
+/// Maps the local microsecond counter to unix time.
+///
+/// Each sync stores the measured offset. The drift of the local oscillator
+/// is estimated from the offset change between syncs at least a minute apart
+/// and applied to timestamps taken after the last sync.
+#[derive(Debug, Clone, Default)]
+pub struct WallClock {
+    last: Option<SyncPoint>,
+    // older sync point the drift is measured against
+    reference: Option<SyncPoint>,
+    drift_ppb: Option<i64>,
+    syncs: u32,
+}
+
+impl WallClock {
+    pub const fn new() -> Self {
+        WallClock {
+            last: None,
+            reference: None,
+            drift_ppb: None,
+            syncs: 0,
+        }
+    }
+
+    pub fn is_synced(&self) -> bool {
+        self.last.is_some()
+    }
+
+    pub fn drift_ppb(&self) -> i64 {
+        self.drift_ppb.unwrap_or(0)
+    }
+
+    pub fn syncs(&self) -> u32 {
+        self.syncs
+    }
+
+    /// local_us is the local time in the middle of the exchange.
+    pub fn update(&mut self, local_us: i64, measurement: &Measurement) {
+        let point = SyncPoint {
+            local_us,
+            offset_us: measurement.offset_us,
+        };
+        match self.reference {
+            Some(reference) => {
+                let interval = local_us - reference.local_us;
+                if interval >= MIN_DRIFT_INTERVAL_US {
+                    let measured = ((point.offset_us - reference.offset_us) as i128 * 1_000_000_000 / interval as i128) as i64;
+                    self.drift_ppb = Some(match self.drift_ppb {
+                        Some(drift_ppb) => (drift_ppb + measured) / 2,
+                        None => measured,
+                    });
+                    self.reference = Some(point);
+                }
+            }
+            None => self.reference = Some(point),
+        }
+        self.last = Some(point);
+        self.syncs += 1;
+    }
+
+    pub fn unix_us(&self, local_us: i64) -> Option<i64> {
+        self.offset_us(local_us).map(|offset| local_us + offset)
+    }
+
+    pub fn offset_us(&self, local_us: i64) -> Option<i64> {
+        self.last.map(|last| last.offset_us + self.drift_correction(last.local_us, local_us))
+    }
+
+    fn drift_correction(&self, from_us: i64, to_us: i64) -> i64 {
+        ((to_us - from_us) as i128 * self.drift_ppb() as i128 / 1_000_000_000) as i64
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UtcDateTime {
+    pub year: i32,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub micros: u32,
+}
+
+impl UtcDateTime {
+    pub fn from_unix_us(unix_us: i64) -> Self {
+        let secs = unix_us.div_euclid(1_000_000);
+        let micros = unix_us.rem_euclid(1_000_000) as u32;
+        let days = secs.div_euclid(86_400);
+        let secs_of_day = secs.rem_euclid(86_400);
+
+        // civil from days, http://howardhinnant.github.io/date_algorithms.html
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+        let year = (yoe + era * 400 + if month <= 2 { 1 } else { 0 }) as i32;
+
+        UtcDateTime {
+            year,
+            month,
+            day,
+            hour: (secs_of_day / 3600) as u8,
+            minute: (secs_of_day / 60 % 60) as u8,
+            second: (secs_of_day % 60) as u8,
+            micros,
+        }
+    }
+}
+
+impl fmt::Display for UtcDateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+               self.year, self.month, self.day,
+               self.hour, self.minute, self.second, self.micros / 1000)
+    }
+}
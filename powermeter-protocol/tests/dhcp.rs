@@ -0,0 +1,115 @@
+use powermeter_protocol::dhcp::{message_type, DhcpServer, ACK, DISCOVER, NAK, OFFER, RELEASE, REQUEST};
+
+const SERVER: [u8; 4] = [192, 168, 4, 1];
+
+fn message(kind: u8, mac: u8, requested: Option<[u8; 4]>) -> Vec<u8> {
+    let mut m = vec![0u8; 240];
+    m[0] = 1;
+    m[1] = 1;
+    m[2] = 6;
+    m[4..8].copy_from_slice(&[0xde, 0xad, 0xbe, mac]);
+    m[28..34].copy_from_slice(&[2, 0, 0, 0, 0, mac]);
+    m[236..240].copy_from_slice(&[99, 130, 83, 99]);
+    m.extend_from_slice(&[53, 1, kind]);
+    if let Some(addr) = requested {
+        m.extend_from_slice(&[50, 4]);
+        m.extend_from_slice(&addr);
+    }
+    m.push(255);
+    m
+}
+
+fn yiaddr(reply: &[u8]) -> [u8; 4] {
+    [reply[16], reply[17], reply[18], reply[19]]
+}
+
+#[test]
+fn discover_and_request_get_lease() {
+    let mut server = DhcpServer::new(SERVER);
+    let mut out = [0u8; 576];
+
+    let len = server.handle(&message(DISCOVER, 1, None), &mut out).unwrap();
+    assert_eq!(message_type(&out[..len]), Some(OFFER));
+    assert_eq!(out[0], 2);
+    assert_eq!(out[4..8], [0xde, 0xad, 0xbe, 1]);
+    let offered = yiaddr(&out);
+    assert_eq!(offered, [192, 168, 4, 2]);
+
+    let len = server.handle(&message(REQUEST, 1, Some(offered)), &mut out).unwrap();
+    assert_eq!(message_type(&out[..len]), Some(ACK));
+    assert_eq!(yiaddr(&out), offered);
+    // router and dns point to the access point
+    let options = &out[240..len];
+    assert!(options.windows(6).any(|w| w == [3, 4, 192, 168, 4, 1]));
+    assert!(options.windows(6).any(|w| w == [6, 4, 192, 168, 4, 1]));
+}
+
+#[test]
+fn clients_get_distinct_addresses() {
+    let mut server = DhcpServer::new(SERVER);
+    let mut out = [0u8; 576];
+    server.handle(&message(DISCOVER, 1, None), &mut out).unwrap();
+    let first = yiaddr(&out);
+    server.handle(&message(DISCOVER, 2, None), &mut out).unwrap();
+    assert_ne!(yiaddr(&out), first);
+    // same client keeps its address
+    server.handle(&message(DISCOVER, 1, None), &mut out).unwrap();
+    assert_eq!(yiaddr(&out), first);
+}
+
+#[test]
+fn wrong_address_is_refused() {
+    let mut server = DhcpServer::new(SERVER);
+    let mut out = [0u8; 576];
+    let len = server.handle(&message(REQUEST, 1, Some([10, 0, 0, 5])), &mut out).unwrap();
+    assert_eq!(message_type(&out[..len]), Some(NAK));
+}
+
+fn bind(server: &mut DhcpServer, mac: u8) -> [u8; 4] {
+    let mut out = [0u8; 576];
+    server.handle(&message(DISCOVER, mac, None), &mut out).unwrap();
+    let offered = yiaddr(&out);
+    let len = server.handle(&message(REQUEST, mac, Some(offered)), &mut out).unwrap();
+    assert_eq!(message_type(&out[..len]), Some(ACK), "client {}", mac);
+    offered
+}
+
+#[test]
+fn released_address_is_reused() {
+    let mut server = DhcpServer::new(SERVER);
+    let mut out = [0u8; 576];
+    for mac in 1..=4 {
+        bind(&mut server, mac);
+    }
+    assert_eq!(server.handle(&message(RELEASE, 2, None), &mut out), None);
+    server.handle(&message(DISCOVER, 5, None), &mut out).unwrap();
+    assert_eq!(yiaddr(&out), [192, 168, 4, 3]);
+}
+
+#[test]
+fn unrequested_offer_makes_room_first() {
+    let mut server = DhcpServer::new(SERVER);
+    let mut out = [0u8; 576];
+    bind(&mut server, 1);
+    bind(&mut server, 2);
+    // a phone that gave up on this address and came back with a new MAC
+    server.handle(&message(DISCOVER, 3, None), &mut out).unwrap();
+    let abandoned = yiaddr(&out);
+    bind(&mut server, 4);
+    assert_eq!(bind(&mut server, 5), abandoned);
+    // the bound clients keep their addresses
+    assert_eq!(bind(&mut server, 1), [192, 168, 4, 2]);
+    assert_eq!(bind(&mut server, 2), [192, 168, 4, 3]);
+}
+
+#[test]
+fn more_clients_than_leases_still_join() {
+    let mut server = DhcpServer::new(SERVER);
+    let addresses: Vec<[u8; 4]> = (1..=9).map(|mac| bind(&mut server, mac)).collect();
+    // the fifth takes over the lease of the first, used longest ago
+    assert_eq!(addresses[4], addresses[0]);
+    let mut latest = addresses[5..].to_vec();
+    latest.sort();
+    latest.dedup();
+    assert_eq!(latest.len(), 4);
+}
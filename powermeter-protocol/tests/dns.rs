@@ -0,0 +1,67 @@
+use powermeter_protocol::dns::answer;
+
+const AP: [u8; 4] = [192, 168, 4, 1];
+
+fn query(id: u16, name: &str, qtype: u16) -> Vec<u8> {
+    let mut q = Vec::new();
+    q.extend_from_slice(&id.to_be_bytes());
+    q.extend_from_slice(&0x0100u16.to_be_bytes());
+    q.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.split('.') {
+        q.push(label.len() as u8);
+        q.extend_from_slice(label.as_bytes());
+    }
+    q.push(0);
+    q.extend_from_slice(&qtype.to_be_bytes());
+    q.extend_from_slice(&1u16.to_be_bytes());
+    q
+}
+
+#[test]
+fn a_query_resolves_to_access_point() {
+    let q = query(0x1234, "connectivitycheck.gstatic.com", 1);
+    let mut out = [0u8; 512];
+    let len = answer(&q, AP, &mut out).unwrap();
+    let r = &out[..len];
+
+    assert_eq!(r[0..2], [0x12, 0x34]);
+    // response, authoritative, recursion desired and available, no error
+    assert_eq!(r[2..4], [0x85, 0x80]);
+    assert_eq!(r[4..6], [0, 1]);
+    assert_eq!(r[6..8], [0, 1]);
+    assert_eq!(&r[12..q.len()], &q[12..]);
+
+    let a = &r[q.len()..];
+    assert_eq!(a[0..2], [0xC0, 0x0C]);
+    assert_eq!(a[2..6], [0, 1, 0, 1]);
+    assert_eq!(a[10..12], [0, 4]);
+    assert_eq!(a[12..16], AP);
+    assert_eq!(len, q.len() + 16);
+}
+
+#[test]
+fn other_types_get_empty_answer() {
+    let q = query(7, "example.com", 28);
+    let mut out = [0u8; 512];
+    let len = answer(&q, AP, &mut out).unwrap();
+    assert_eq!(len, q.len());
+    assert_eq!(out[6..8], [0, 0]);
+}
+
+#[test]
+fn malformed_queries_are_ignored() {
+    let mut out = [0u8; 512];
+    let q = query(7, "example.com", 1);
+    assert_eq!(answer(&q[..10], AP, &mut out), None);
+    assert_eq!(answer(&q[..q.len() - 2], AP, &mut out), None);
+
+    let mut response = q.clone();
+    response[2] |= 0x80;
+    assert_eq!(answer(&response, AP, &mut out), None);
+
+    let mut two_questions = q.clone();
+    two_questions[5] = 2;
+    assert_eq!(answer(&two_questions, AP, &mut out), None);
+
+    assert_eq!(answer(&q, AP, &mut out[..q.len()]), None);
+}
@@ -0,0 +1,75 @@
+use powermeter_protocol::event::{handle_http, Event, EventKind, CSV_HEADER};
+use powermeter_protocol::http::parse_request;
+
+// 2026-03-01T12:00:00Z
+const UNIX_US: i64 = 1_772_366_400_000_000;
+
+fn event(kind: EventKind, unix_us: Option<i64>) -> Event {
+    Event {
+        sequence: 7,
+        boot: 2,
+        kind,
+        channel: 1,
+        uptime_us: 61_500_000,
+        unix_us,
+        value: 512.5,
+        duration_us: 1_500,
+    }
+}
+
+#[test]
+fn every_kind_survives_encoding() {
+    for code in 0..=5 {
+        let kind = EventKind::from_code(code).unwrap();
+        assert_eq!(kind.code(), code);
+        let event = event(kind, Some(UNIX_US));
+        assert_eq!(Event::decode(&event.encode()), Some(event));
+    }
+    let event = event(EventKind::Boot, None);
+    assert_eq!(Event::decode(&event.encode()), Some(event));
+}
+
+#[test]
+fn unknown_kind_is_not_decoded() {
+    let mut buf = event(EventKind::Boot, None).encode();
+    buf[6] = 0xFF;
+    assert_eq!(Event::decode(&buf), None);
+}
+
+#[test]
+fn csv_line_has_utc_when_synced() {
+    assert_eq!(CSV_HEADER.split(',').count(), 8);
+    let mut line = String::new();
+    event(EventKind::OverCurrent, Some(UNIX_US)).write_csv(&mut line).unwrap();
+    assert_eq!(line, "7,2,61.500,2026-03-01T12:00:00.000Z,over_current,1,512.5,1.500\n");
+
+    line.clear();
+    event(EventKind::Dropout, None).write_csv(&mut line).unwrap();
+    assert_eq!(line, "7,2,61.500,,dropout,1,512.5,1.500\n");
+}
+
+fn get(path: &str, events: &[Event], out: &mut [u8]) -> Option<usize> {
+    let raw = format!("GET {} HTTP/1.1\r\nHost: powermeter\r\n\r\n", path);
+    handle_http(&parse_request(raw.as_bytes()).unwrap(), events.iter(), out)
+}
+
+#[test]
+fn http_export_is_csv_with_its_length() {
+    let events = [event(EventKind::Boot, None), event(EventKind::OverCurrent, Some(UNIX_US))];
+    let mut out = [0u8; 1024];
+    let len = get("/events", &events, &mut out).unwrap();
+    let response = std::str::from_utf8(&out[..len]).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(head.contains("Content-Type: text/csv"));
+    assert!(head.contains(&format!("Content-Length: {}", body.len())));
+    assert_eq!(body, "\
+sequence,boot,uptime_s,utc,kind,channel,value,duration_ms
+7,2,61.500,,boot,1,512.5,1.500
+7,2,61.500,2026-03-01T12:00:00.000Z,over_current,1,512.5,1.500
+");
+
+    let len = get("/", &events, &mut out).unwrap();
+    assert!(out[..len].starts_with(b"HTTP/1.1 404"));
+    assert_eq!(get("/events", &events, &mut out[..100]), None);
+}
@@ -0,0 +1,109 @@
+use powermeter_protocol::http::{form_value, parse_request, Method, ParseError, MAX_REQUEST_LEN};
+use powermeter_protocol::portal::{handle, parse_credentials, FormError, PORTAL_URL};
+
+fn post(body: &str) -> String {
+    format!("POST /save HTTP/1.1\r\nHost: 192.168.4.1\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(), body)
+}
+
+fn respond(raw: &str) -> (String, Option<(String, String)>) {
+    let request = parse_request(raw.as_bytes()).unwrap();
+    let mut out = [0u8; 2048];
+    let reply = handle(&request, &mut out);
+    assert!(reply.len > 0);
+    let credentials = reply.credentials.map(|c| (c.ssid.as_str().to_string(), c.password.as_str().to_string()));
+    (String::from_utf8(out[..reply.len].to_vec()).unwrap(), credentials)
+}
+
+#[test]
+fn request_line_and_body_are_parsed() {
+    let raw = post("ssid=lab");
+    let request = parse_request(raw.as_bytes()).unwrap();
+    assert_eq!(request.method, Method::Post);
+    assert_eq!(request.path, "/save");
+    assert_eq!(request.body, b"ssid=lab");
+
+    let request = parse_request(b"GET /generate_204?x=1 HTTP/1.1\r\nHost: a\r\n\r\n").unwrap();
+    assert_eq!(request.method, Method::Get);
+    assert_eq!(request.path, "/generate_204");
+    assert_eq!(request.query, Some("x=1"));
+}
+
+#[test]
+fn partial_requests_ask_for_more_data() {
+    let raw = post("ssid=lab&password=12345678");
+    assert_eq!(parse_request(&raw.as_bytes()[..20]).unwrap_err(), ParseError::Incomplete);
+    assert_eq!(parse_request(&raw.as_bytes()[..raw.len() - 3]).unwrap_err(), ParseError::Incomplete);
+    assert_eq!(parse_request(b"garbage\r\n\r\n").unwrap_err(), ParseError::Malformed);
+    assert_eq!(parse_request(b"GET / HTTP/1.1\r\nContent-Length: x\r\n\r\n").unwrap_err(), ParseError::Malformed);
+}
+
+#[test]
+fn bodies_longer_than_a_request_are_malformed() {
+    let overflowing = format!("POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n", usize::MAX);
+    assert_eq!(parse_request(overflowing.as_bytes()).unwrap_err(), ParseError::Malformed);
+    let too_long = format!("POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n", MAX_REQUEST_LEN);
+    assert_eq!(parse_request(too_long.as_bytes()).unwrap_err(), ParseError::Malformed);
+}
+
+#[test]
+fn form_values_are_url_decoded() {
+    let mut buf = [0u8; 64];
+    assert_eq!(form_value(b"a=1&ssid=my+lab%21&b=", "ssid", &mut buf), Ok(Some("my lab!")));
+    assert_eq!(form_value(b"a=1&b=", "b", &mut buf), Ok(Some("")));
+    assert_eq!(form_value(b"a=1", "ssid", &mut buf), Ok(None));
+    assert_eq!(form_value(b"ssid=%zz", "ssid", &mut buf), Err(ParseError::Malformed));
+    assert_eq!(form_value(b"ssid=%C3%A4", "ssid", &mut buf), Ok(Some("\u{e4}")));
+    let mut small = [0u8; 2];
+    assert_eq!(form_value(b"ssid=abc", "ssid", &mut small), Err(ParseError::Malformed));
+}
+
+#[test]
+fn credentials_are_validated() {
+    let creds = parse_credentials(b"ssid=lab&password=12345678").unwrap();
+    assert_eq!(creds.ssid.as_str(), "lab");
+    assert_eq!(creds.password.as_str(), "12345678");
+
+    assert_eq!(parse_credentials(b"ssid=open").unwrap().password.as_str(), "");
+    assert_eq!(parse_credentials(b"password=12345678"), Err(FormError::MissingSsid));
+    assert_eq!(parse_credentials(b"ssid=&password=12345678"), Err(FormError::MissingSsid));
+    assert_eq!(parse_credentials(b"ssid=lab&password=short"), Err(FormError::BadPassword));
+    let long_ssid = format!("ssid={}", "x".repeat(33));
+    assert_eq!(parse_credentials(long_ssid.as_bytes()), Err(FormError::SsidTooLong));
+    let long_password = format!("ssid=lab&password={}", "x".repeat(64));
+    assert_eq!(parse_credentials(long_password.as_bytes()), Err(FormError::BadPassword));
+}
+
+#[test]
+fn root_serves_form() {
+    let (response, credentials) = respond("GET / HTTP/1.1\r\nHost: 192.168.4.1\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("<form method=\"post\" action=\"/save\">"));
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    assert!(head.contains(&format!("Content-Length: {}", body.len())));
+    assert!(credentials.is_none());
+}
+
+#[test]
+fn valid_post_returns_credentials() {
+    let (response, credentials) = respond(&post("ssid=bench+lab&password=hunter22%26more"));
+    assert!(response.contains("Saved"));
+    assert_eq!(credentials, Some(("bench lab".to_string(), "hunter22&more".to_string())));
+}
+
+#[test]
+fn invalid_post_shows_error_and_form() {
+    let (response, credentials) = respond(&post("ssid=lab&password=1"));
+    assert!(response.contains("Password must be empty or 8 to 63 characters"));
+    assert!(response.contains("<form"));
+    assert!(credentials.is_none());
+}
+
+#[test]
+fn captive_probes_are_redirected() {
+    for path in ["/generate_204", "/hotspot-detect.html", "/connecttest.txt"] {
+        let (response, _) = respond(&format!("GET {} HTTP/1.1\r\nHost: example.com\r\n\r\n", path));
+        assert!(response.starts_with("HTTP/1.1 302 Found\r\n"));
+        assert!(response.contains(&format!("Location: {}\r\n", PORTAL_URL)));
+    }
+}
@@ -0,0 +1,118 @@
+use powermeter_protocol::event::{Event, EventKind};
+use powermeter_protocol::serial::{encode_frame, ErrorCode, FrameReader, Request, Response, SerialError, SessionEntry, MAX_FRAME_LEN, MAX_MESSAGE_LEN};
+use powermeter_protocol::stream::Sample;
+
+fn read_frames(bytes: &[u8]) -> Vec<Result<Vec<u8>, SerialError>> {
+    let mut reader = FrameReader::<MAX_FRAME_LEN>::new();
+    bytes.iter().filter_map(|&byte| reader.push(byte).map(|frame| frame.map(|message| message.to_vec()))).collect()
+}
+
+fn request_round_trip(request: Request) {
+    let mut frame = [0u8; MAX_FRAME_LEN];
+    let len = request.encode(&mut frame).unwrap();
+    assert_eq!(frame[..len].iter().filter(|&&b| b == 0).count(), 1);
+    let messages = read_frames(&frame[..len]);
+    let message = messages[0].as_ref().unwrap();
+    assert_eq!(Request::decode(message).unwrap(), request);
+}
+
+fn response_round_trip(response: Response) {
+    let mut frame = [0u8; MAX_FRAME_LEN];
+    let len = response.encode(&mut frame).unwrap();
+    assert_eq!(frame[len - 1], 0);
+    let messages = read_frames(&frame[..len]);
+    let message = messages[0].as_ref().unwrap();
+    assert_eq!(Response::decode(message).unwrap(), response);
+}
+
+#[test]
+fn requests_survive_framing() {
+    request_round_trip(Request::Ping);
+    request_round_trip(Request::LiveStart);
+    request_round_trip(Request::LiveStop);
+    request_round_trip(Request::ConfigGet { name: "wifi_ssid" });
+    request_round_trip(Request::ConfigSet { name: "wifi_ssid", value: "" });
+    request_round_trip(Request::SessionList);
+    request_round_trip(Request::SessionRead { id: 0 });
+    request_round_trip(Request::EventList);
+}
+
+#[test]
+fn responses_survive_framing() {
+    response_round_trip(Response::Pong { version: 1 });
+    response_round_trip(Response::Sample(Sample {
+        timestamp_us: 0,
+        voltage: 0.0,
+        current: -12.5,
+        power: 100.25,
+        range: 2,
+    }));
+    response_round_trip(Response::ConfigValue { value: "lab wifi" });
+    response_round_trip(Response::Ok);
+    response_round_trip(Response::Error(ErrorCode::NotFound));
+    response_round_trip(Response::Error(ErrorCode::Other(200)));
+    response_round_trip(Response::Session(SessionEntry {
+        id: 7,
+        mode: 2,
+        calibration: 0,
+        interval_us: 1_000,
+        start_unix_us: None,
+        duration_us: 60_000_000,
+        records: 0,
+        truncated: true,
+    }));
+    response_round_trip(Response::End);
+    response_round_trip(Response::Event(Event {
+        sequence: 41,
+        boot: 3,
+        kind: EventKind::Dropout,
+        channel: 0,
+        uptime_us: 12_000_000,
+        unix_us: None,
+        value: 3.2,
+        duration_us: 8_000,
+    }));
+}
+
+#[test]
+fn long_zero_free_and_all_zero_messages() {
+    // COBS needs an extra code byte every 254 non-zero bytes
+    let payload = [0x55u8; 250];
+    response_round_trip(Response::Record { kind: 3, payload: &payload });
+    let zeros = [0u8; 250];
+    response_round_trip(Response::Record { kind: 0, payload: &zeros });
+}
+
+#[test]
+fn reader_recovers_after_damaged_frame() {
+    let mut bytes = Vec::new();
+    let mut frame = [0u8; MAX_FRAME_LEN];
+    let len = Response::Pong { version: 1 }.encode(&mut frame).unwrap();
+    let mut damaged = frame[..len].to_vec();
+    damaged[1] ^= 0x04;
+    bytes.extend_from_slice(&damaged);
+    // line noise before the next frame
+    bytes.extend_from_slice(&[0x00, 0x00]);
+    bytes.extend_from_slice(&frame[..len]);
+
+    let messages = read_frames(&bytes);
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0], Err(SerialError::BadCrc));
+    assert_eq!(Response::decode(messages[1].as_ref().unwrap()).unwrap(), Response::Pong { version: 1 });
+}
+
+#[test]
+fn oversized_messages_are_rejected() {
+    let mut frame = [0u8; MAX_FRAME_LEN];
+    assert!(encode_frame(&[1u8; MAX_MESSAGE_LEN + 1], &mut frame).is_none());
+
+    let mut bytes = vec![0x11u8; MAX_FRAME_LEN + 10];
+    bytes.push(0);
+    assert_eq!(read_frames(&bytes), [Err(SerialError::TooLong)]);
+}
+
+#[test]
+fn unknown_types_are_reported() {
+    assert_eq!(Request::decode(&[0x7F]), Err(SerialError::UnknownType(0x7F)));
+    assert_eq!(Response::decode(&[]), Err(SerialError::Truncated));
+}
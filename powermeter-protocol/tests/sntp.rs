@@ -0,0 +1,153 @@
+use std::net::UdpSocket;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use powermeter_protocol::sntp::{measure, ntp_to_unix_us, parse_response, request, unix_us_to_ntp, Measurement, Response, SntpError, PACKET_LEN};
+use powermeter_protocol::time::{UtcDateTime, WallClock};
+
+// 2024-05-01T12:00:00Z
+const SERVER_EPOCH_US: i64 = 1_714_564_800_000_000;
+
+/// Minimal stand-in for an NTP server. Answers one request per call with
+/// SERVER_EPOCH_US plus the time since `start`.
+fn spawn_server(start: Instant, requests: usize) -> std::net::SocketAddr {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = socket.local_addr().unwrap();
+    thread::spawn(move || {
+        let mut buf = [0u8; PACKET_LEN];
+        for _ in 0..requests {
+            let (_, from) = socket.recv_from(&mut buf).unwrap();
+            let now = || unix_us_to_ntp(SERVER_EPOCH_US + start.elapsed().as_micros() as i64);
+            let receive = now();
+            let mut reply = [0u8; PACKET_LEN];
+            reply[0] = 4 << 3 | 4;
+            reply[1] = 2;
+            reply[24..32].copy_from_slice(&buf[40..48]);
+            reply[32..40].copy_from_slice(&receive.to_be_bytes());
+            reply[40..48].copy_from_slice(&now().to_be_bytes());
+            socket.send_to(&reply, from).unwrap();
+        }
+    });
+    addr
+}
+
+fn server_reply(nonce: u64, receive_us: i64, transmit_us: i64) -> [u8; PACKET_LEN] {
+    let mut reply = [0u8; PACKET_LEN];
+    reply[0] = 4 << 3 | 4;
+    reply[1] = 1;
+    reply[24..32].copy_from_slice(&nonce.to_be_bytes());
+    reply[32..40].copy_from_slice(&unix_us_to_ntp(receive_us).to_be_bytes());
+    reply[40..48].copy_from_slice(&unix_us_to_ntp(transmit_us).to_be_bytes());
+    reply
+}
+
+#[test]
+fn syncs_against_local_server() {
+    // the local clock starts at 0 like the embassy tick counter after boot
+    let boot = Instant::now();
+    let local_us = || boot.elapsed().as_micros() as i64;
+    let server = spawn_server(boot, 1);
+
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+
+    let t1 = local_us();
+    let nonce = t1 as u64;
+    socket.send_to(&request(nonce), server).unwrap();
+    let mut buf = [0u8; PACKET_LEN];
+    let (len, _) = socket.recv_from(&mut buf).unwrap();
+    let t4 = local_us();
+
+    let response = parse_response(&buf[..len], nonce).unwrap();
+    assert_eq!(response.stratum, 2);
+    let measurement = measure(t1, &response, t4);
+    assert!(measurement.delay_us >= 0);
+
+    let mut clock = WallClock::new();
+    assert_eq!(clock.unix_us(t4), None);
+    clock.update((t1 + t4) / 2, &measurement);
+
+    let now = local_us();
+    let error = clock.unix_us(now).unwrap() - (SERVER_EPOCH_US + now);
+    assert!(error.abs() < 5_000, "clock off by {} us", error);
+}
+
+#[test]
+fn request_is_client_mode_with_nonce() {
+    let req = request(0x0102_0304_0506_0708);
+    assert_eq!(req[0], 0x23);
+    assert_eq!(req[40..48], [1, 2, 3, 4, 5, 6, 7, 8]);
+}
+
+#[test]
+fn invalid_responses_are_rejected() {
+    let good = server_reply(42, SERVER_EPOCH_US, SERVER_EPOCH_US);
+    assert!(parse_response(&good, 42).is_ok());
+    assert_eq!(parse_response(&good, 43), Err(SntpError::OriginMismatch));
+    assert_eq!(parse_response(&good[..40], 42), Err(SntpError::TooShort));
+
+    let mut client = good;
+    client[0] = 4 << 3 | 3;
+    assert_eq!(parse_response(&client, 42), Err(SntpError::BadMode(3)));
+
+    let mut kod = good;
+    kod[1] = 0;
+    kod[12..16].copy_from_slice(b"RATE");
+    assert_eq!(parse_response(&kod, 42), Err(SntpError::KissOfDeath(*b"RATE")));
+
+    let mut unsynced = good;
+    unsynced[0] |= 0xC0;
+    assert_eq!(parse_response(&unsynced, 42), Err(SntpError::NotSynchronized));
+}
+
+#[test]
+fn offset_and_delay_follow_rfc() {
+    // server is 1 s ahead, 10 ms each way, 2 ms processing
+    let response = Response {
+        stratum: 1,
+        receive_us: 1_010_000 + 1_000_000,
+        transmit_us: 1_012_000 + 1_000_000,
+    };
+    let m = measure(1_000_000, &response, 1_022_000);
+    assert_eq!(m, Measurement { offset_us: 1_000_000, delay_us: 20_000 });
+}
+
+#[test]
+fn drift_between_syncs_is_tracked() {
+    // local oscillator runs 50 ppm slow, the offset grows 50 us per second
+    let mut clock = WallClock::new();
+    clock.update(0, &Measurement { offset_us: SERVER_EPOCH_US, delay_us: 0 });
+    clock.update(600_000_000, &Measurement { offset_us: SERVER_EPOCH_US + 30_000, delay_us: 0 });
+    assert_eq!(clock.drift_ppb(), 50_000);
+    assert_eq!(clock.syncs(), 2);
+
+    // ten minutes after the last sync the drift is corrected
+    assert_eq!(clock.unix_us(1_200_000_000), Some(SERVER_EPOCH_US + 1_200_000_000 + 60_000));
+}
+
+#[test]
+fn close_syncs_do_not_update_drift() {
+    let mut clock = WallClock::new();
+    clock.update(0, &Measurement { offset_us: 1_000, delay_us: 0 });
+    clock.update(1_000_000, &Measurement { offset_us: 1_500, delay_us: 0 });
+    assert_eq!(clock.drift_ppb(), 0);
+    assert_eq!(clock.unix_us(2_000_000), Some(2_001_500));
+}
+
+#[test]
+fn ntp_timestamps_convert_both_ways() {
+    let ntp = unix_us_to_ntp(SERVER_EPOCH_US + 250_000);
+    assert_eq!(ntp >> 32, 3_923_553_600);
+    assert_eq!(ntp_to_unix_us(ntp), SERVER_EPOCH_US + 250_000);
+    // after the 2036 rollover
+    let era1 = 2_085_978_496_000_000 + 1_000_000;
+    assert_eq!(ntp_to_unix_us(unix_us_to_ntp(era1)), era1);
+}
+
+#[test]
+fn utc_formatting() {
+    assert_eq!(UtcDateTime::from_unix_us(0).to_string(), "1970-01-01T00:00:00.000Z");
+    assert_eq!(UtcDateTime::from_unix_us(SERVER_EPOCH_US + 123_456).to_string(), "2024-05-01T12:00:00.123Z");
+    assert_eq!(UtcDateTime::from_unix_us(951_782_400_000_000).to_string(), "2000-02-29T00:00:00.000Z");
+    assert_eq!(UtcDateTime::from_unix_us(-1).to_string(), "1969-12-31T23:59:59.999Z");
+}
@@ -0,0 +1,471 @@
+// Persistent settings
+//
+// Two erase sectors are written alternately. Each holds at most one record:
+//   0  magic     "PMCF"
+//   4  sequence  u32
+//   8  length    u16, payload length
+//  10  reserved  u16
+//  12  crc       u32 over bytes 4..12 and the payload
+//  16  payload   entries of key u8, length u8, value
+//
+// The valid record with the highest sequence wins, so a save interrupted by
+// a power loss leaves the previous settings in place. Unknown keys are
+// skipped on load so older firmware can read newer records.
+
+use embedded_storage::nor_flash::NorFlash;
+use heapless::{String, Vec};
+
+use crate::crc::Crc32;
+
+pub const SECTOR_COUNT: u32 = 2;
+
+const MAGIC: [u8; 4] = *b"PMCF";
+const HEADER_LEN: usize = 16;
+const RECORD_LEN: usize = 1024;
+const MAX_PAYLOAD: usize = RECORD_LEN - HEADER_LEN;
+
+const KEY_WIFI_SSID: u8 = 1;
+const KEY_WIFI_PASSWORD: u8 = 2;
+// address u8, input u8, calibration u8, name
+const KEY_CHANNEL: u8 = 3;
+// address u8, input u8, range u8, gain f32, offset f32, calibrated at u32
+const KEY_CORRECTION: u8 = 4;
+const CORRECTION_LEN: usize = 15;
+const KEY_ENERGY_PRICE: u8 = 5;
+const KEY_BATTERY_CAPACITY: u8 = 6;
+const KEY_BATTERY_USABLE: u8 = 7;
+const KEY_BATTERY_SELF_DISCHARGE: u8 = 8;
+const KEY_QUALITY_WINDOW: u8 = 9;
+const KEY_DROPOUT_THRESHOLD: u8 = 10;
+const KEY_OVERCURRENT_THRESHOLD: u8 = 11;
+const KEY_SNTP_SERVER: u8 = 12;
+
+/// Used while the sntp_server setting is empty.
+pub const DEFAULT_SNTP_SERVER: &str = "pool.ntp.org";
+
+/// One per INA219 address.
+pub const MAX_CHANNELS: usize = 16;
+
+/// Names accepted by Settings::get and Settings::set. Channels are named
+/// by their sensor address in hex, e.g. "ch41_name", and the input of
+/// sensors with several, e.g. "ch40_2_name".
+pub const SETTING_NAMES: [&str; 11] = [
+    "wifi_ssid", "wifi_password", "energy_price", "battery_capacity", "battery_usable", "battery_self_discharge",
+    "quality_window", "dropout_threshold", "overcurrent_threshold", "sntp_server", "chXX_name",
+];
+
+const MASKED_PASSWORD: &str = "********";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingError {
+    UnknownName,
+    /// too long for the setting
+    InvalidValue,
+}
+
+/// Correction of the current measured in one range, see
+/// powermeter_core::correction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CorrectionSettings {
+    /// index of the range in the selection cycle
+    pub range: u8,
+    pub gain: f32,
+    /// mA
+    pub offset: f32,
+    /// unix time in s, 0 when the clock was not synced
+    pub calibrated_at: u32,
+}
+
+/// What is remembered about one sensor input.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChannelSettings {
+    pub address: u8,
+    /// 0 for sensors with a single input
+    pub input: u8,
+    /// shown instead of the address when not empty, e.g. "3V3"
+    pub name: String<16>,
+    /// index of the range in the selection cycle
+    pub calibration: u8,
+    /// only ranges that were calibrated
+    pub corrections: Vec<CorrectionSettings, 3>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Settings {
+    pub wifi_ssid: String<32>,
+    pub wifi_password: String<64>,
+    /// per kWh as entered, e.g. "0.32", empty when not set
+    pub energy_price: String<16>,
+    /// mAh of the battery of the device under test, empty when not set
+    pub battery_capacity: String<16>,
+    /// % of the capacity the device can use, empty when not set
+    pub battery_usable: String<16>,
+    /// % of the capacity lost per month, empty when not set
+    pub battery_self_discharge: String<16>,
+    /// ms the ripple is taken over, empty when not set
+    pub quality_window: String<16>,
+    /// V the bus voltage dips below for a dropout, empty when not set
+    pub dropout_threshold: String<16>,
+    /// mA above which a spike goes to the event log, empty when not set
+    pub overcurrent_threshold: String<16>,
+    /// host name of the time server, empty when not set
+    pub sntp_server: String<32>,
+    pub channels: Vec<ChannelSettings, MAX_CHANNELS>,
+}
+
+impl Settings {
+    pub fn has_wifi_credentials(&self) -> bool {
+        !self.wifi_ssid.is_empty()
+    }
+
+    /// Price per kWh, 0 when not set.
+    pub fn energy_price_per_kwh(&self) -> f32 {
+        self.energy_price.parse().unwrap_or(0.0)
+    }
+
+    /// mAh, 0 when not set.
+    pub fn battery_capacity_mah(&self) -> f32 {
+        self.battery_capacity.parse().unwrap_or(0.0)
+    }
+
+    /// Fraction of the capacity the device can use, None when not set.
+    pub fn battery_usable(&self) -> Option<f32> {
+        self.battery_usable.parse::<f32>().ok().map(|percent| percent / 100.0)
+    }
+
+    /// Fraction of the capacity lost per month, None when not set.
+    pub fn battery_self_discharge(&self) -> Option<f32> {
+        self.battery_self_discharge.parse::<f32>().ok().map(|percent| percent / 100.0)
+    }
+
+    /// ms, None when not set.
+    pub fn quality_window_ms(&self) -> Option<u32> {
+        self.quality_window.parse::<f32>().ok().map(|ms| ms as u32)
+    }
+
+    /// V, None when not set.
+    pub fn dropout_threshold_v(&self) -> Option<f32> {
+        self.dropout_threshold.parse().ok()
+    }
+
+    /// mA, 0 when not set.
+    pub fn overcurrent_threshold_ma(&self) -> f32 {
+        self.overcurrent_threshold.parse().unwrap_or(0.0)
+    }
+
+    /// Host name of the time server, pool.ntp.org when not set.
+    pub fn sntp_server(&self) -> &str {
+        if self.sntp_server.is_empty() { DEFAULT_SNTP_SERVER } else { &self.sntp_server }
+    }
+
+    /// Value by name for display, the wifi password is masked.
+    pub fn get(&self, name: &str) -> Result<&str, SettingError> {
+        match name {
+            "wifi_ssid" => Ok(&self.wifi_ssid),
+            "wifi_password" if self.wifi_password.is_empty() => Ok(""),
+            "wifi_password" => Ok(MASKED_PASSWORD),
+            "energy_price" => Ok(&self.energy_price),
+            "battery_capacity" => Ok(&self.battery_capacity),
+            "battery_usable" => Ok(&self.battery_usable),
+            "battery_self_discharge" => Ok(&self.battery_self_discharge),
+            "quality_window" => Ok(&self.quality_window),
+            "dropout_threshold" => Ok(&self.dropout_threshold),
+            "overcurrent_threshold" => Ok(&self.overcurrent_threshold),
+            "sntp_server" => Ok(self.sntp_server()),
+            _ => {
+                let (address, input) = channel_setting(name)?;
+                Ok(self.channel(address, input).map(|channel| channel.name.as_str()).unwrap_or(""))
+            }
+        }
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), SettingError> {
+        match name {
+            "wifi_ssid" => self.wifi_ssid = read_string(value.as_bytes()).ok_or(SettingError::InvalidValue)?,
+            "wifi_password" => self.wifi_password = read_string(value.as_bytes()).ok_or(SettingError::InvalidValue)?,
+            "energy_price" => self.energy_price = read_number(value)?,
+            "battery_capacity" => self.battery_capacity = read_number(value)?,
+            "battery_usable" => self.battery_usable = read_number(value)?,
+            "battery_self_discharge" => self.battery_self_discharge = read_number(value)?,
+            "quality_window" => self.quality_window = read_number(value)?,
+            "dropout_threshold" => self.dropout_threshold = read_number(value)?,
+            "overcurrent_threshold" => self.overcurrent_threshold = read_number(value)?,
+            "sntp_server" => self.sntp_server = read_string(value.as_bytes()).ok_or(SettingError::InvalidValue)?,
+            _ => {
+                let (address, input) = channel_setting(name)?;
+                let name = read_string(value.as_bytes()).ok_or(SettingError::InvalidValue)?;
+                self.channel_mut(address, input).ok_or(SettingError::InvalidValue)?.name = name;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn channel(&self, address: u8, input: u8) -> Option<&ChannelSettings> {
+        self.channels.iter().find(|channel| (channel.address, channel.input) == (address, input))
+    }
+
+    /// Settings of the channel, added with defaults when there are none yet.
+    pub fn channel_mut(&mut self, address: u8, input: u8) -> Option<&mut ChannelSettings> {
+        let index = match self.channels.iter().position(|channel| (channel.address, channel.input) == (address, input)) {
+            Some(index) => index,
+            None => {
+                self.channels.push(ChannelSettings { address, input, ..ChannelSettings::default() }).ok()?;
+                self.channels.len() - 1
+            }
+        };
+        self.channels.get_mut(index)
+    }
+
+    /// Replace the correction of the range of a channel.
+    pub fn set_correction(&mut self, address: u8, input: u8, correction: CorrectionSettings) -> Result<(), SettingError> {
+        let channel = self.channel_mut(address, input).ok_or(SettingError::InvalidValue)?;
+        channel.corrections.retain(|c| c.range != correction.range);
+        channel.corrections.push(correction).map_err(|_| SettingError::InvalidValue)
+    }
+
+    fn encode(&self, out: &mut [u8]) -> Option<usize> {
+        let mut writer = EntryWriter { out, len: 0 };
+        writer.put(KEY_WIFI_SSID, self.wifi_ssid.as_bytes())?;
+        writer.put(KEY_WIFI_PASSWORD, self.wifi_password.as_bytes())?;
+        writer.put(KEY_ENERGY_PRICE, self.energy_price.as_bytes())?;
+        writer.put(KEY_BATTERY_CAPACITY, self.battery_capacity.as_bytes())?;
+        writer.put(KEY_BATTERY_USABLE, self.battery_usable.as_bytes())?;
+        writer.put(KEY_BATTERY_SELF_DISCHARGE, self.battery_self_discharge.as_bytes())?;
+        writer.put(KEY_QUALITY_WINDOW, self.quality_window.as_bytes())?;
+        writer.put(KEY_DROPOUT_THRESHOLD, self.dropout_threshold.as_bytes())?;
+        writer.put(KEY_OVERCURRENT_THRESHOLD, self.overcurrent_threshold.as_bytes())?;
+        writer.put(KEY_SNTP_SERVER, self.sntp_server.as_bytes())?;
+        for channel in &self.channels {
+            let mut value = [0u8; 19];
+            let len = 3 + channel.name.len();
+            value[0] = channel.address;
+            value[1] = channel.input;
+            value[2] = channel.calibration;
+            value[3..len].copy_from_slice(channel.name.as_bytes());
+            writer.put(KEY_CHANNEL, &value[..len])?;
+        }
+        for channel in &self.channels {
+            for correction in &channel.corrections {
+                let mut value = [0u8; CORRECTION_LEN];
+                value[0] = channel.address;
+                value[1] = channel.input;
+                value[2] = correction.range;
+                value[3..7].copy_from_slice(&correction.gain.to_le_bytes());
+                value[7..11].copy_from_slice(&correction.offset.to_le_bytes());
+                value[11..15].copy_from_slice(&correction.calibrated_at.to_le_bytes());
+                writer.put(KEY_CORRECTION, &value)?;
+            }
+        }
+        Some(writer.len)
+    }
+
+    fn decode(mut payload: &[u8]) -> Self {
+        let mut settings = Settings::default();
+        while payload.len() >= 2 {
+            let key = payload[0];
+            let len = payload[1] as usize;
+            if payload.len() < 2 + len {
+                break;
+            }
+            let value = &payload[2..2 + len];
+            match key {
+                KEY_WIFI_SSID => settings.wifi_ssid = read_string(value).unwrap_or_default(),
+                KEY_WIFI_PASSWORD => settings.wifi_password = read_string(value).unwrap_or_default(),
+                KEY_ENERGY_PRICE => settings.energy_price = read_string(value).unwrap_or_default(),
+                KEY_BATTERY_CAPACITY => settings.battery_capacity = read_string(value).unwrap_or_default(),
+                KEY_BATTERY_USABLE => settings.battery_usable = read_string(value).unwrap_or_default(),
+                KEY_BATTERY_SELF_DISCHARGE => settings.battery_self_discharge = read_string(value).unwrap_or_default(),
+                KEY_QUALITY_WINDOW => settings.quality_window = read_string(value).unwrap_or_default(),
+                KEY_DROPOUT_THRESHOLD => settings.dropout_threshold = read_string(value).unwrap_or_default(),
+                KEY_OVERCURRENT_THRESHOLD => settings.overcurrent_threshold = read_string(value).unwrap_or_default(),
+                KEY_SNTP_SERVER => settings.sntp_server = read_string(value).unwrap_or_default(),
+                KEY_CHANNEL if value.len() >= 3 => {
+                    let _ = settings.channels.push(ChannelSettings {
+                        address: value[0],
+                        input: value[1],
+                        name: read_string(&value[3..]).unwrap_or_default(),
+                        calibration: value[2],
+                        corrections: Vec::new(),
+                    });
+                }
+                // the channel entries come first
+                KEY_CORRECTION if value.len() >= CORRECTION_LEN => {
+                    let f32_at = |i: usize| f32::from_le_bytes([value[i], value[i + 1], value[i + 2], value[i + 3]]);
+                    let correction = CorrectionSettings {
+                        range: value[2],
+                        gain: f32_at(3),
+                        offset: f32_at(7),
+                        calibrated_at: u32::from_le_bytes([value[11], value[12], value[13], value[14]]),
+                    };
+                    if let Some(channel) = settings.channels.iter_mut().find(|c| (c.address, c.input) == (value[0], value[1])) {
+                        let _ = channel.corrections.push(correction);
+                    }
+                }
+                _ => {}
+            }
+            payload = &payload[2 + len..];
+        }
+        settings
+    }
+}
+
+/// Address and input of a "chXX_name" or "chXX_N_name" setting.
+fn channel_setting(name: &str) -> Result<(u8, u8), SettingError> {
+    let channel = name.strip_prefix("ch")
+        .and_then(|name| name.strip_suffix("_name"))
+        .ok_or(SettingError::UnknownName)?;
+    let (address, input) = match channel.split_once('_') {
+        Some((address, input)) => (address, input.parse().map_err(|_| SettingError::UnknownName)?),
+        None => (channel, 0),
+    };
+    if address.len() != 2 {
+        return Err(SettingError::UnknownName);
+    }
+    let address = u8::from_str_radix(address, 16).map_err(|_| SettingError::UnknownName)?;
+    Ok((address, input))
+}
+
+/// A number that is not negative, empty clears the setting.
+fn read_number(value: &str) -> Result<String<16>, SettingError> {
+    if !value.is_empty() && !value.parse::<f32>().is_ok_and(|number| number.is_finite() && number >= 0.0) {
+        return Err(SettingError::InvalidValue);
+    }
+    read_string(value.as_bytes()).ok_or(SettingError::InvalidValue)
+}
+
+fn read_string<const N: usize>(value: &[u8]) -> Option<String<N>> {
+    let mut s = String::new();
+    s.push_str(core::str::from_utf8(value).ok()?).ok()?;
+    Some(s)
+}
+
+struct EntryWriter<'a> {
+    out: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> EntryWriter<'a> {
+    fn put(&mut self, key: u8, value: &[u8]) -> Option<()> {
+        if value.len() > u8::MAX as usize || self.len + 2 + value.len() > self.out.len() {
+            return None;
+        }
+        self.out[self.len] = key;
+        self.out[self.len + 1] = value.len() as u8;
+        self.out[self.len + 2..self.len + 2 + value.len()].copy_from_slice(value);
+        self.len += 2 + value.len();
+        Some(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigError<E> {
+    Flash(E),
+    TooLarge,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    sector: u32,
+    sequence: u32,
+}
+
+pub struct ConfigStore<F> {
+    flash: F,
+    offset: u32,
+    latest: Option<Option<Slot>>,
+}
+
+impl<F: NorFlash> ConfigStore<F> {
+    /// offset is the start of SECTOR_COUNT erase sectors reserved for settings
+    pub fn new(flash: F, offset: u32) -> Self {
+        ConfigStore {
+            flash,
+            offset,
+            latest: None,
+        }
+    }
+
+    pub fn release(self) -> F {
+        self.flash
+    }
+
+    pub fn load(&mut self) -> Result<Option<Settings>, ConfigError<F::Error>> {
+        let mut buf = [0u8; RECORD_LEN];
+        let mut latest: Option<(Slot, Settings)> = None;
+        for sector in 0..SECTOR_COUNT {
+            self.flash.read(self.sector_offset(sector), &mut buf).map_err(ConfigError::Flash)?;
+            if let Some((sequence, len)) = check_record(&buf) {
+                let newer = match &latest {
+                    Some((slot, _)) => is_newer(sequence, slot.sequence),
+                    None => true,
+                };
+                if newer {
+                    latest = Some((Slot { sector, sequence }, Settings::decode(&buf[HEADER_LEN..HEADER_LEN + len])));
+                }
+            }
+        }
+        self.latest = Some(latest.as_ref().map(|(slot, _)| *slot));
+        Ok(latest.map(|(_, settings)| settings))
+    }
+
+    pub fn save(&mut self, settings: &Settings) -> Result<(), ConfigError<F::Error>> {
+        if self.latest.is_none() {
+            self.load()?;
+        }
+        let latest = self.latest.flatten();
+
+        let mut buf = [0xFFu8; RECORD_LEN];
+        let len = settings.encode(&mut buf[HEADER_LEN..HEADER_LEN + MAX_PAYLOAD]).ok_or(ConfigError::TooLarge)?;
+        let slot = match latest {
+            Some(slot) => Slot {
+                sector: (slot.sector + 1) % SECTOR_COUNT,
+                sequence: slot.sequence.wrapping_add(1),
+            },
+            None => Slot { sector: 0, sequence: 0 },
+        };
+        buf[0..4].copy_from_slice(&MAGIC);
+        buf[4..8].copy_from_slice(&slot.sequence.to_le_bytes());
+        buf[8..10].copy_from_slice(&(len as u16).to_le_bytes());
+        buf[10..12].copy_from_slice(&0u16.to_le_bytes());
+        let crc = record_crc(&buf, len);
+        buf[12..16].copy_from_slice(&crc.to_le_bytes());
+
+        let start = self.sector_offset(slot.sector);
+        self.flash.erase(start, start + F::ERASE_SIZE as u32).map_err(ConfigError::Flash)?;
+        let write_len = (HEADER_LEN + len).div_ceil(F::WRITE_SIZE) * F::WRITE_SIZE;
+        self.flash.write(start, &buf[..write_len]).map_err(ConfigError::Flash)?;
+        self.latest = Some(Some(slot));
+        Ok(())
+    }
+
+    fn sector_offset(&self, sector: u32) -> u32 {
+        self.offset + sector * F::ERASE_SIZE as u32
+    }
+}
+
+fn is_newer(sequence: u32, than: u32) -> bool {
+    sequence != than && sequence.wrapping_sub(than) < u32::MAX / 2
+}
+
+fn check_record(buf: &[u8]) -> Option<(u32, usize)> {
+    if buf[0..4] != MAGIC {
+        return None;
+    }
+    let sequence = u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]);
+    let len = u16::from_le_bytes([buf[8], buf[9]]) as usize;
+    if len > MAX_PAYLOAD {
+        return None;
+    }
+    let crc = u32::from_le_bytes([buf[12], buf[13], buf[14], buf[15]]);
+    if record_crc(buf, len) != crc {
+        return None;
+    }
+    Some((sequence, len))
+}
+
+fn record_crc(buf: &[u8], len: usize) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(&buf[4..12]);
+    crc.update(&buf[HEADER_LEN..HEADER_LEN + len]);
+    crc.finish()
+}
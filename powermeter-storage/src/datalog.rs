@@ -0,0 +1,595 @@
+// Append-only data log on a flash partition
+//
+// The partition is a ring of erase sectors, written strictly in order, so
+// the sector after the newest one is always free or the oldest. When the
+// log is full that oldest sector is erased and reused.
+//
+// sector header (32 bytes)
+//   0  magic        "PMLG"
+//   4  sequence     u32, incremented for every sector opened
+//   8  session      u32
+//  12  part         u16, index of the sector within its session
+//  14  mode         u8
+//  15  calibration  u8
+//  16  start_unix   i64 us, i64::MIN if the clock was not synced
+//  24  interval_us  u32
+//  28  crc          u32 over bytes 4..28
+//
+// Every sector repeats the session description so a session stays readable
+// after its first sectors have been rotated out.
+//
+// record (4 byte aligned)
+//   0  kind         u8, 0xFF marks the end of the written area
+//   1  length       u8, payload length
+//   2  reserved     u16
+//   4  payload
+//   .  crc          u32 over kind, length and payload
+//
+// A record is programmed with a single write. If power fails during the
+// write its crc does not match and it ends the sector, appending then
+// continues in a fresh sector.
+
+use embedded_storage::nor_flash::NorFlash;
+use heapless::Vec;
+
+use crate::crc::Crc32;
+
+pub const MAX_SESSIONS: usize = 32;
+/// largest block of compact encoded samples per record
+pub const MAX_COMPACT_LEN: usize = 240;
+
+const MAGIC: [u8; 4] = *b"PMLG";
+const SECTOR_HEADER_LEN: usize = 32;
+const RECORD_HEADER_LEN: usize = 4;
+const CRC_LEN: usize = 4;
+pub const MAX_PAYLOAD: usize = 8 + MAX_COMPACT_LEN;
+const UNKNOWN_TIME: i64 = i64::MIN;
+
+const KIND_SAMPLE: u8 = 1;
+const KIND_AGGREGATE: u8 = 2;
+const KIND_COMPACT: u8 = 3;
+const KIND_ERASED: u8 = 0xFF;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogMode {
+    /// every sample is stored
+    Samples,
+    /// one Aggregate per interval
+    Aggregates,
+    /// raw sensor registers in Compact records, the session starts with a
+    /// record holding the format header
+    Compact,
+}
+
+impl LogMode {
+    pub fn code(self) -> u8 {
+        match self {
+            LogMode::Samples => 0,
+            LogMode::Aggregates => 1,
+            LogMode::Compact => 2,
+        }
+    }
+
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(LogMode::Samples),
+            1 => Some(LogMode::Aggregates),
+            2 => Some(LogMode::Compact),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionConfig {
+    pub mode: LogMode,
+    /// index of the sensor calibration in use
+    pub calibration: u8,
+    pub interval_us: u32,
+    pub start_unix_us: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionInfo {
+    pub id: u32,
+    pub config: SessionConfig,
+    /// offset of the last record
+    pub duration_us: u64,
+    pub records: u32,
+    /// the beginning was overwritten by newer sessions
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    /// time since the session start
+    pub offset_us: u64,
+    pub voltage: f32,
+    pub current: f32,
+    pub power: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aggregate {
+    /// time since the session start at the end of the interval
+    pub offset_us: u64,
+    pub count: u32,
+    pub voltage: f32,
+    pub current: f32,
+    pub power: f32,
+    pub current_min: f32,
+    pub current_max: f32,
+}
+
+/// Opaque bytes of the compact sample format.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Compact {
+    /// time since the session start of the last sample in data
+    pub offset_us: u64,
+    len: u8,
+    data: [u8; MAX_COMPACT_LEN],
+}
+
+impl Compact {
+    /// None if data is longer than MAX_COMPACT_LEN.
+    pub fn new(offset_us: u64, data: &[u8]) -> Option<Self> {
+        if data.len() > MAX_COMPACT_LEN {
+            return None;
+        }
+        let mut compact = Compact {
+            offset_us,
+            len: data.len() as u8,
+            data: [0u8; MAX_COMPACT_LEN],
+        };
+        compact.data[..data.len()].copy_from_slice(data);
+        Some(compact)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data[..self.len as usize]
+    }
+}
+
+// no allocator to box Compact, records only live on the stack briefly
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Record {
+    Sample(Sample),
+    Aggregate(Aggregate),
+    Compact(Compact),
+}
+
+impl Record {
+    pub fn offset_us(&self) -> u64 {
+        match self {
+            Record::Sample(sample) => sample.offset_us,
+            Record::Aggregate(aggregate) => aggregate.offset_us,
+            Record::Compact(compact) => compact.offset_us,
+        }
+    }
+
+    /// Write the payload into out, which must hold MAX_PAYLOAD bytes, and
+    /// return the record kind and payload length.
+    pub fn encode(&self, out: &mut [u8]) -> (u8, usize) {
+        let mut w = Writer { out, len: 0 };
+        match self {
+            Record::Sample(s) => {
+                w.put(&s.offset_us.to_le_bytes());
+                w.put(&s.voltage.to_le_bytes());
+                w.put(&s.current.to_le_bytes());
+                w.put(&s.power.to_le_bytes());
+                (KIND_SAMPLE, w.len)
+            }
+            Record::Aggregate(a) => {
+                w.put(&a.offset_us.to_le_bytes());
+                w.put(&a.count.to_le_bytes());
+                w.put(&a.voltage.to_le_bytes());
+                w.put(&a.current.to_le_bytes());
+                w.put(&a.power.to_le_bytes());
+                w.put(&a.current_min.to_le_bytes());
+                w.put(&a.current_max.to_le_bytes());
+                (KIND_AGGREGATE, w.len)
+            }
+            Record::Compact(c) => {
+                w.put(&c.offset_us.to_le_bytes());
+                w.put(c.as_bytes());
+                (KIND_COMPACT, w.len)
+            }
+        }
+    }
+
+    pub fn decode(kind: u8, payload: &[u8]) -> Option<Self> {
+        let mut r = Reader { buf: payload };
+        let record = match kind {
+            KIND_SAMPLE => Record::Sample(Sample {
+                offset_us: r.u64()?,
+                voltage: r.f32()?,
+                current: r.f32()?,
+                power: r.f32()?,
+            }),
+            KIND_AGGREGATE => Record::Aggregate(Aggregate {
+                offset_us: r.u64()?,
+                count: r.u32()?,
+                voltage: r.f32()?,
+                current: r.f32()?,
+                power: r.f32()?,
+                current_min: r.f32()?,
+                current_max: r.f32()?,
+            }),
+            KIND_COMPACT => Record::Compact(Compact::new(r.u64()?, r.buf)?),
+            _ => return None,
+        };
+        Some(record)
+    }
+}
+
+struct Writer<'a> {
+    out: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> Writer<'a> {
+    fn put(&mut self, bytes: &[u8]) {
+        self.out[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let bytes = self.buf.get(..N)?.try_into().ok()?;
+        self.buf = &self.buf[N..];
+        Some(bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.take().map(u64::from_le_bytes)
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        self.take().map(f32::from_le_bytes)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogError<E> {
+    Flash(E),
+    /// append without a started session
+    NoSession,
+    /// the partition needs at least two sectors
+    BadGeometry,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SectorHeader {
+    sequence: u32,
+    session: u32,
+    part: u16,
+    config: SessionConfig,
+}
+
+impl SectorHeader {
+    fn encode(&self) -> [u8; SECTOR_HEADER_LEN] {
+        let mut buf = [0u8; SECTOR_HEADER_LEN];
+        buf[0..4].copy_from_slice(&MAGIC);
+        buf[4..8].copy_from_slice(&self.sequence.to_le_bytes());
+        buf[8..12].copy_from_slice(&self.session.to_le_bytes());
+        buf[12..14].copy_from_slice(&self.part.to_le_bytes());
+        buf[14] = self.config.mode.code();
+        buf[15] = self.config.calibration;
+        buf[16..24].copy_from_slice(&self.config.start_unix_us.unwrap_or(UNKNOWN_TIME).to_le_bytes());
+        buf[24..28].copy_from_slice(&self.config.interval_us.to_le_bytes());
+        let mut crc = Crc32::new();
+        crc.update(&buf[4..28]);
+        buf[28..32].copy_from_slice(&crc.finish().to_le_bytes());
+        buf
+    }
+
+    fn decode(buf: &[u8; SECTOR_HEADER_LEN]) -> Option<Self> {
+        if buf[0..4] != MAGIC {
+            return None;
+        }
+        let mut crc = Crc32::new();
+        crc.update(&buf[4..28]);
+        if crc.finish().to_le_bytes() != buf[28..32] {
+            return None;
+        }
+        let mut r = Reader { buf: &buf[4..] };
+        let sequence = r.u32()?;
+        let session = r.u32()?;
+        let part = u16::from_le_bytes(r.take()?);
+        let [mode, calibration] = r.take()?;
+        let mode = LogMode::from_code(mode)?;
+        let start_unix_us = i64::from_le_bytes(r.take()?);
+        let interval_us = r.u32()?;
+        Some(SectorHeader {
+            sequence,
+            session,
+            part,
+            config: SessionConfig {
+                mode,
+                calibration,
+                interval_us,
+                start_unix_us: if start_unix_us == UNKNOWN_TIME { None } else { Some(start_unix_us) },
+            },
+        })
+    }
+}
+
+/// Position of a reader in a session, see DataLog::next_record.
+#[derive(Debug, Clone, Copy)]
+pub struct Cursor {
+    session: u32,
+    step: u32,
+    sector: u32,
+    sequence: Option<u32>,
+    pos: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Active {
+    sector: u32,
+    pos: usize,
+    header: SectorHeader,
+}
+
+pub struct DataLog<F> {
+    flash: F,
+    offset: u32,
+    sectors: u32,
+    next_sector: u32,
+    next_sequence: u32,
+    next_session: u32,
+    active: Option<Active>,
+}
+
+impl<F: NorFlash> DataLog<F> {
+    /// Scan the partition at offset with the given size in bytes.
+    pub fn mount(mut flash: F, offset: u32, size: u32) -> Result<Self, LogError<F::Error>> {
+        let sectors = size / F::ERASE_SIZE as u32;
+        if sectors < 2 {
+            return Err(LogError::BadGeometry);
+        }
+        let mut newest: Option<(u32, SectorHeader)> = None;
+        let mut next_session = 0;
+        for sector in 0..sectors {
+            if let Some(header) = read_header(&mut flash, offset + sector * F::ERASE_SIZE as u32)? {
+                // sequence numbers may wrap, newer means less than half the range ahead
+                let is_newer = match newest {
+                    Some((_, newest)) => header.sequence.wrapping_sub(newest.sequence) < u32::MAX / 2,
+                    None => true,
+                };
+                if is_newer {
+                    newest = Some((sector, header));
+                }
+            }
+        }
+        if let Some((_, header)) = newest {
+            next_session = header.session.wrapping_add(1);
+        }
+        Ok(DataLog {
+            flash,
+            offset,
+            sectors,
+            next_sector: newest.map_or(0, |(sector, _)| (sector + 1) % sectors),
+            next_sequence: newest.map_or(0, |(_, header)| header.sequence.wrapping_add(1)),
+            next_session,
+            active: None,
+        })
+    }
+
+    pub fn release(self) -> F {
+        self.flash
+    }
+
+    pub fn sector_count(&self) -> u32 {
+        self.sectors
+    }
+
+    pub fn active_session(&self) -> Option<u32> {
+        self.active.map(|active| active.header.session)
+    }
+
+    /// Start a new session in a fresh sector and return its id.
+    pub fn start_session(&mut self, config: SessionConfig) -> Result<u32, LogError<F::Error>> {
+        let session = self.next_session;
+        self.next_session = self.next_session.wrapping_add(1);
+        self.open_sector(session, 0, config)?;
+        Ok(session)
+    }
+
+    pub fn end_session(&mut self) {
+        self.active = None;
+    }
+
+    pub fn append(&mut self, record: &Record) -> Result<(), LogError<F::Error>> {
+        let mut active = self.active.ok_or(LogError::NoSession)?;
+
+        let mut buf = [0xFFu8; RECORD_HEADER_LEN + MAX_PAYLOAD + CRC_LEN];
+        let (kind, len) = record.encode(&mut buf[RECORD_HEADER_LEN..]);
+        buf[0] = kind;
+        buf[1] = len as u8;
+        let mut crc = Crc32::new();
+        crc.update(&buf[0..2]);
+        crc.update(&buf[RECORD_HEADER_LEN..RECORD_HEADER_LEN + len]);
+        buf[RECORD_HEADER_LEN + len..RECORD_HEADER_LEN + len + CRC_LEN].copy_from_slice(&crc.finish().to_le_bytes());
+        let total = record_len(len);
+
+        if active.pos + total > F::ERASE_SIZE {
+            let header = active.header;
+            active = self.open_sector(header.session, header.part.wrapping_add(1), header.config)?;
+        }
+        let address = self.sector_offset(active.sector) + active.pos as u32;
+        if let Err(e) = self.flash.write(address, &buf[..total]) {
+            // the record may be half written, continue in the next sector
+            active.pos = F::ERASE_SIZE;
+            self.active = Some(active);
+            return Err(LogError::Flash(e));
+        }
+        active.pos += total;
+        self.active = Some(active);
+        Ok(())
+    }
+
+    /// All sessions still on flash, oldest first. Only the newest
+    /// MAX_SESSIONS are returned.
+    pub fn sessions(&mut self) -> Result<Vec<SessionInfo, MAX_SESSIONS>, LogError<F::Error>> {
+        let mut sessions: Vec<SessionInfo, MAX_SESSIONS> = Vec::new();
+        for i in 0..self.sectors {
+            let sector = (self.next_sector + i) % self.sectors;
+            let Some(header) = self.read_header(sector)? else { continue };
+            if sessions.last().map(|s| s.id) != Some(header.session) {
+                if sessions.is_full() {
+                    sessions.remove(0);
+                }
+                let _ = sessions.push(SessionInfo {
+                    id: header.session,
+                    config: header.config,
+                    duration_us: 0,
+                    records: 0,
+                    truncated: header.part != 0,
+                });
+            }
+            let info = sessions.last_mut().unwrap();
+            self.scan_sector(sector, |record| {
+                info.records += 1;
+                info.duration_us = info.duration_us.max(record.offset_us());
+            })?;
+        }
+        Ok(sessions)
+    }
+
+    /// Call f for every record of the session in the order written.
+    pub fn read_session(&mut self, session: u32, mut f: impl FnMut(&Record)) -> Result<(), LogError<F::Error>> {
+        let mut cursor = self.cursor(session);
+        while let Some(record) = self.next_record(&mut cursor)? {
+            f(&record);
+        }
+        Ok(())
+    }
+
+    /// Start reading a session with next_record.
+    pub fn cursor(&self, session: u32) -> Cursor {
+        Cursor {
+            session,
+            step: 0,
+            sector: self.next_sector,
+            sequence: None,
+            pos: SECTOR_HEADER_LEN,
+        }
+    }
+
+    /// The next record of the cursor's session, None after the last one.
+    /// Appending in between is fine, sectors rotated out while reading are
+    /// skipped.
+    pub fn next_record(&mut self, cursor: &mut Cursor) -> Result<Option<Record>, LogError<F::Error>> {
+        while cursor.step < self.sectors {
+            let header = self.read_header(cursor.sector)?;
+            let current = header.is_some_and(|header| {
+                header.session == cursor.session && cursor.sequence.unwrap_or(header.sequence) == header.sequence
+            });
+            if current {
+                cursor.sequence = header.map(|header| header.sequence);
+                if let Some((record, len)) = self.read_record(cursor.sector, cursor.pos)? {
+                    cursor.pos += len;
+                    return Ok(Some(record));
+                }
+            }
+            cursor.step += 1;
+            cursor.sector = (cursor.sector + 1) % self.sectors;
+            cursor.sequence = None;
+            cursor.pos = SECTOR_HEADER_LEN;
+        }
+        Ok(None)
+    }
+
+    fn open_sector(&mut self, session: u32, part: u16, config: SessionConfig) -> Result<Active, LogError<F::Error>> {
+        self.active = None;
+        let sector = self.next_sector;
+        let header = SectorHeader {
+            sequence: self.next_sequence,
+            session,
+            part,
+            config,
+        };
+        // the sequence and position advance even if this fails, a half
+        // erased or written sector has no valid header and is skipped
+        self.next_sector = (sector + 1) % self.sectors;
+        self.next_sequence = self.next_sequence.wrapping_add(1);
+
+        let start = self.sector_offset(sector);
+        self.flash.erase(start, start + F::ERASE_SIZE as u32).map_err(LogError::Flash)?;
+        self.flash.write(start, &header.encode()).map_err(LogError::Flash)?;
+        let active = Active {
+            sector,
+            pos: SECTOR_HEADER_LEN,
+            header,
+        };
+        self.active = Some(active);
+        Ok(active)
+    }
+
+    fn scan_sector(&mut self, sector: u32, mut f: impl FnMut(Record)) -> Result<(), LogError<F::Error>> {
+        let mut pos = SECTOR_HEADER_LEN;
+        while let Some((record, len)) = self.read_record(sector, pos)? {
+            f(record);
+            pos += len;
+        }
+        Ok(())
+    }
+
+    /// The record at pos and its length on flash, None at the end of the
+    /// written area or at a torn write, nothing valid follows either.
+    fn read_record(&mut self, sector: u32, pos: usize) -> Result<Option<(Record, usize)>, LogError<F::Error>> {
+        if pos + RECORD_HEADER_LEN > F::ERASE_SIZE {
+            return Ok(None);
+        }
+        let start = self.sector_offset(sector) + pos as u32;
+        let mut buf = [0u8; RECORD_HEADER_LEN + MAX_PAYLOAD + CRC_LEN];
+        self.flash.read(start, &mut buf[..RECORD_HEADER_LEN]).map_err(LogError::Flash)?;
+        let kind = buf[0];
+        let len = buf[1] as usize;
+        if kind == KIND_ERASED || len > MAX_PAYLOAD {
+            return Ok(None);
+        }
+        let total = record_len(len);
+        if pos + total > F::ERASE_SIZE {
+            return Ok(None);
+        }
+        self.flash.read(start + RECORD_HEADER_LEN as u32, &mut buf[RECORD_HEADER_LEN..total]).map_err(LogError::Flash)?;
+        let mut crc = Crc32::new();
+        crc.update(&buf[0..2]);
+        crc.update(&buf[RECORD_HEADER_LEN..RECORD_HEADER_LEN + len]);
+        if crc.finish().to_le_bytes() != buf[RECORD_HEADER_LEN + len..RECORD_HEADER_LEN + len + CRC_LEN] {
+            return Ok(None);
+        }
+        Ok(Record::decode(kind, &buf[RECORD_HEADER_LEN..RECORD_HEADER_LEN + len]).map(|record| (record, total)))
+    }
+
+    fn read_header(&mut self, sector: u32) -> Result<Option<SectorHeader>, LogError<F::Error>> {
+        let address = self.sector_offset(sector);
+        read_header(&mut self.flash, address)
+    }
+
+    fn sector_offset(&self, sector: u32) -> u32 {
+        self.offset + sector * F::ERASE_SIZE as u32
+    }
+}
+
+fn record_len(payload_len: usize) -> usize {
+    (RECORD_HEADER_LEN + payload_len + CRC_LEN).div_ceil(4) * 4
+}
+
+fn read_header<F: NorFlash>(flash: &mut F, address: u32) -> Result<Option<SectorHeader>, LogError<F::Error>> {
+    let mut buf = [0u8; SECTOR_HEADER_LEN];
+    flash.read(address, &mut buf).map_err(LogError::Flash)?;
+    Ok(SectorHeader::decode(&buf))
+}
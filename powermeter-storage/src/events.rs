@@ -0,0 +1,155 @@
+// Event ring on a flash partition
+//
+// The partition holds fixed size slots, written strictly in order, so the
+// slot after the newest one is free or belongs to the oldest sector. A slot
+// starting a sector is only written after its sector was erased, a full
+// ring so drops the oldest sector at once.
+//
+// slot (SLOT_LEN bytes)
+//   0  sequence  u32, a free slot is all 0xFF
+//   4  payload   PAYLOAD_LEN bytes, see powermeter_protocol::event
+//  36  crc       u32 over sequence and payload
+//
+// A slot is programmed with a single write. If power fails during the
+// write its crc does not match, it is skipped and appending continues in
+// the next sector.
+
+use embedded_storage::nor_flash::NorFlash;
+use heapless::Deque;
+
+use crate::crc::Crc32;
+
+pub const PAYLOAD_LEN: usize = 32;
+const SLOT_LEN: usize = 4 + PAYLOAD_LEN + 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventError<E> {
+    Flash(E),
+    /// the partition needs at least two sectors
+    BadGeometry,
+}
+
+/// What is in a slot.
+enum Slot {
+    Free,
+    Event(u32, [u8; PAYLOAD_LEN]),
+    Damaged,
+}
+
+pub struct EventStore<F> {
+    flash: F,
+    offset: u32,
+    sectors: u32,
+    /// slot the next event goes to
+    next: u32,
+    sequence: u32,
+}
+
+impl<F: NorFlash> EventStore<F> {
+    /// Find the newest event in the size bytes from offset.
+    pub fn mount(flash: F, offset: u32, size: u32) -> Result<Self, EventError<F::Error>> {
+        let sectors = size / F::ERASE_SIZE as u32;
+        if sectors < 2 {
+            return Err(EventError::BadGeometry);
+        }
+        let mut store = EventStore { flash, offset, sectors, next: 0, sequence: 0 };
+        let mut newest: Option<(u32, u32)> = None;
+        for slot in 0..store.slots() {
+            if let Slot::Event(sequence, _) = store.read_slot(slot)? {
+                if newest.is_none_or(|(_, newest)| sequence > newest) {
+                    newest = Some((slot, sequence));
+                }
+            }
+        }
+        if let Some((slot, sequence)) = newest {
+            store.sequence = sequence.wrapping_add(1);
+            store.next = (slot + 1) % store.slots();
+            if store.sector_start(store.next) != store.next && !matches!(store.read_slot(store.next)?, Slot::Free) {
+                // damaged by a power loss, the rest of the sector is skipped
+                store.next = store.sector_start(store.next + store.slots_per_sector());
+            }
+        }
+        Ok(store)
+    }
+
+    pub fn release(self) -> F {
+        self.flash
+    }
+
+    /// Sequence the next event gets.
+    pub fn sequence(&self) -> u32 {
+        self.sequence
+    }
+
+    pub fn append(&mut self, payload: &[u8; PAYLOAD_LEN]) -> Result<(), EventError<F::Error>> {
+        let slot = self.next;
+        if self.sector_start(slot) == slot {
+            let start = self.offset + slot / self.slots_per_sector() * F::ERASE_SIZE as u32;
+            self.flash.erase(start, start + F::ERASE_SIZE as u32).map_err(EventError::Flash)?;
+        }
+        let mut buf = [0u8; SLOT_LEN];
+        buf[0..4].copy_from_slice(&self.sequence.to_le_bytes());
+        buf[4..4 + PAYLOAD_LEN].copy_from_slice(payload);
+        let crc = slot_crc(&buf);
+        buf[4 + PAYLOAD_LEN..].copy_from_slice(&crc.to_le_bytes());
+        let written = self.flash.write(self.slot_offset(slot), &buf);
+        // a failed write leaves a damaged slot, reading skips it
+        self.next = (slot + 1) % self.slots();
+        self.sequence = self.sequence.wrapping_add(1);
+        written.map_err(EventError::Flash)
+    }
+
+    /// The newest N payloads, the oldest first.
+    pub fn newest<const N: usize>(&mut self) -> Result<Deque<[u8; PAYLOAD_LEN], N>, EventError<F::Error>> {
+        let mut payloads = Deque::new();
+        // from the free slot on the ring goes from old to new
+        for i in 0..self.slots() {
+            if let Slot::Event(_, payload) = self.read_slot((self.next + i) % self.slots())? {
+                if payloads.is_full() {
+                    payloads.pop_front();
+                }
+                let _ = payloads.push_back(payload);
+            }
+        }
+        Ok(payloads)
+    }
+
+    fn read_slot(&mut self, slot: u32) -> Result<Slot, EventError<F::Error>> {
+        let mut buf = [0u8; SLOT_LEN];
+        self.flash.read(self.slot_offset(slot), &mut buf).map_err(EventError::Flash)?;
+        if buf.iter().all(|b| *b == 0xFF) {
+            return Ok(Slot::Free);
+        }
+        let sequence = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+        let crc = u32::from_le_bytes(buf[4 + PAYLOAD_LEN..].try_into().unwrap());
+        if slot_crc(&buf) != crc {
+            return Ok(Slot::Damaged);
+        }
+        Ok(Slot::Event(sequence, buf[4..4 + PAYLOAD_LEN].try_into().unwrap()))
+    }
+
+    fn slots_per_sector(&self) -> u32 {
+        (F::ERASE_SIZE / SLOT_LEN) as u32
+    }
+
+    fn slots(&self) -> u32 {
+        self.sectors * self.slots_per_sector()
+    }
+
+    /// First slot of the sector slot is in, wrapping around the ring.
+    fn sector_start(&self, slot: u32) -> u32 {
+        slot % self.slots() / self.slots_per_sector() * self.slots_per_sector()
+    }
+
+    fn slot_offset(&self, slot: u32) -> u32 {
+        let sector = slot / self.slots_per_sector();
+        let index = slot % self.slots_per_sector();
+        self.offset + sector * F::ERASE_SIZE as u32 + index * SLOT_LEN as u32
+    }
+}
+
+fn slot_crc(buf: &[u8; SLOT_LEN]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(&buf[..4 + PAYLOAD_LEN]);
+    crc.finish()
+}
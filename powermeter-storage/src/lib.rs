@@ -0,0 +1,6 @@
+#![no_std]
+
+pub mod config;
+pub mod datalog;
+pub mod events;
+mod crc;
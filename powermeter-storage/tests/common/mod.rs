@@ -0,0 +1,115 @@
+use embedded_storage::nor_flash::{ErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash};
+
+pub const SECTOR_SIZE: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RamFlashError {
+    OutOfBounds,
+    NotAligned,
+    PowerLoss,
+}
+
+impl NorFlashError for RamFlashError {
+    fn kind(&self) -> NorFlashErrorKind {
+        match self {
+            RamFlashError::OutOfBounds => NorFlashErrorKind::OutOfBounds,
+            RamFlashError::NotAligned => NorFlashErrorKind::NotAligned,
+            RamFlashError::PowerLoss => NorFlashErrorKind::Other,
+        }
+    }
+}
+
+/// NOR flash in RAM. Writes can only clear bits, like the real thing.
+/// `power_budget` simulates a power loss: once that many bytes have been
+/// programmed or erased every further operation fails and the interrupted
+/// one is left half done.
+pub struct RamFlash {
+    pub data: Vec<u8>,
+    pub power_budget: Option<usize>,
+}
+
+impl RamFlash {
+    pub fn new(sectors: usize) -> Self {
+        RamFlash {
+            data: vec![0xFF; sectors * SECTOR_SIZE],
+            power_budget: None,
+        }
+    }
+
+    /// Restore power, the flash content stays as it is.
+    pub fn power_on(&mut self) {
+        self.power_budget = None;
+    }
+
+    fn spend(&mut self, bytes: usize) -> usize {
+        match self.power_budget {
+            None => bytes,
+            Some(budget) => {
+                let done = budget.min(bytes);
+                self.power_budget = Some(budget - done);
+                done
+            }
+        }
+    }
+}
+
+impl ErrorType for RamFlash {
+    type Error = RamFlashError;
+}
+
+impl ReadNorFlash for RamFlash {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        let start = offset as usize;
+        let end = start + bytes.len();
+        if end > self.data.len() {
+            return Err(RamFlashError::OutOfBounds);
+        }
+        bytes.copy_from_slice(&self.data[start..end]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.data.len()
+    }
+}
+
+impl NorFlash for RamFlash {
+    const WRITE_SIZE: usize = 4;
+    const ERASE_SIZE: usize = SECTOR_SIZE;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        let (from, to) = (from as usize, to as usize);
+        if from % SECTOR_SIZE != 0 || to % SECTOR_SIZE != 0 {
+            return Err(RamFlashError::NotAligned);
+        }
+        if to > self.data.len() {
+            return Err(RamFlashError::OutOfBounds);
+        }
+        let done = self.spend(to - from);
+        self.data[from..from + done].fill(0xFF);
+        if done < to - from {
+            return Err(RamFlashError::PowerLoss);
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        let start = offset as usize;
+        if !start.is_multiple_of(Self::WRITE_SIZE) || !bytes.len().is_multiple_of(Self::WRITE_SIZE) {
+            return Err(RamFlashError::NotAligned);
+        }
+        if start + bytes.len() > self.data.len() {
+            return Err(RamFlashError::OutOfBounds);
+        }
+        let done = self.spend(bytes.len());
+        for (cell, byte) in self.data[start..start + done].iter_mut().zip(bytes) {
+            *cell &= *byte;
+        }
+        if done < bytes.len() {
+            return Err(RamFlashError::PowerLoss);
+        }
+        Ok(())
+    }
+}
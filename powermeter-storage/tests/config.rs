@@ -0,0 +1,210 @@
+mod common;
+
+use common::{RamFlash, SECTOR_SIZE};
+use powermeter_storage::config::{ConfigStore, CorrectionSettings, SettingError, Settings, DEFAULT_SNTP_SERVER};
+
+fn settings(ssid: &str, password: &str) -> Settings {
+    let mut settings = Settings::default();
+    settings.wifi_ssid.push_str(ssid).unwrap();
+    settings.wifi_password.push_str(password).unwrap();
+    settings
+}
+
+#[test]
+fn empty_flash_has_no_settings() {
+    let mut store = ConfigStore::new(RamFlash::new(2), 0);
+    assert_eq!(store.load().unwrap(), None);
+}
+
+#[test]
+fn saved_settings_are_loaded_after_reboot() {
+    let mut store = ConfigStore::new(RamFlash::new(2), 0);
+    store.save(&settings("lab", "secret")).unwrap();
+    store.save(&settings("bench", "hunter22")).unwrap();
+    store.save(&settings("office", "pa ss")).unwrap();
+
+    let mut store = ConfigStore::new(store.release(), 0);
+    let loaded = store.load().unwrap().unwrap();
+    assert_eq!(loaded, settings("office", "pa ss"));
+    assert!(loaded.has_wifi_credentials());
+}
+
+#[test]
+fn store_respects_offset() {
+    let mut flash = RamFlash::new(4);
+    flash.data[..SECTOR_SIZE].fill(0x00);
+    let mut store = ConfigStore::new(flash, 2 * SECTOR_SIZE as u32);
+    store.save(&settings("lab", "secret")).unwrap();
+    let flash = store.release();
+    assert!(flash.data[..SECTOR_SIZE].iter().all(|b| *b == 0));
+    assert_eq!(ConfigStore::new(flash, 2 * SECTOR_SIZE as u32).load().unwrap(), Some(settings("lab", "secret")));
+}
+
+#[test]
+fn interrupted_save_keeps_previous_settings() {
+    for budget in [0, 10, SECTOR_SIZE, SECTOR_SIZE + 8, SECTOR_SIZE + 20] {
+        let mut store = ConfigStore::new(RamFlash::new(2), 0);
+        store.save(&settings("old", "old-password")).unwrap();
+
+        let mut flash = store.release();
+        flash.power_budget = Some(budget);
+        let mut store = ConfigStore::new(flash, 0);
+        assert!(store.save(&settings("new", "new-password")).is_err());
+
+        let mut flash = store.release();
+        flash.power_on();
+        let mut store = ConfigStore::new(flash, 0);
+        assert_eq!(store.load().unwrap(), Some(settings("old", "old-password")), "budget {}", budget);
+    }
+}
+
+#[test]
+fn corrupted_record_falls_back_to_other_sector() {
+    let mut store = ConfigStore::new(RamFlash::new(2), 0);
+    store.save(&settings("first", "1")).unwrap();
+    store.save(&settings("second", "2")).unwrap();
+    let mut flash = store.release();
+    // second record is in sector 1, flip a payload bit
+    flash.data[SECTOR_SIZE + 20] ^= 0x01;
+    assert_eq!(ConfigStore::new(flash, 0).load().unwrap(), Some(settings("first", "1")));
+}
+
+#[test]
+fn channel_settings_by_address() {
+    let mut settings = settings("lab", "secret");
+    assert_eq!(settings.get("ch41_name"), Ok(""));
+    settings.set("ch41_name", "3V3").unwrap();
+    settings.set("ch44_name", "5V").unwrap();
+    settings.set("ch40_3_name", "USB").unwrap();
+    settings.channel_mut(0x41, 0).unwrap().calibration = 2;
+    assert_eq!(settings.get("ch41_name"), Ok("3V3"));
+    assert_eq!(settings.get("ch40_3_name"), Ok("USB"));
+    assert_eq!(settings.get("ch40_name"), Ok(""));
+    assert_eq!(settings.get("ch4_name"), Err(SettingError::UnknownName));
+    assert_eq!(settings.set("ch41_name", "a very long channel name"), Err(SettingError::InvalidValue));
+
+    let mut store = ConfigStore::new(RamFlash::new(2), 0);
+    store.save(&settings).unwrap();
+    let loaded = ConfigStore::new(store.release(), 0).load().unwrap().unwrap();
+    assert_eq!(loaded, settings);
+    assert_eq!(loaded.channel(0x41, 0).map(|channel| channel.calibration), Some(2));
+    assert_eq!(loaded.channel(0x44, 0).map(|channel| channel.name.as_str()), Some("5V"));
+    assert_eq!(loaded.channel(0x40, 3).map(|channel| channel.name.as_str()), Some("USB"));
+}
+
+#[test]
+fn corrections_per_range() {
+    let mut settings = settings("lab", "secret");
+    settings.set("ch41_name", "3V3").unwrap();
+    let tare = CorrectionSettings { range: 0, gain: 1.0, offset: -2.5, calibrated_at: 1_714_564_800 };
+    let two_point = CorrectionSettings { range: 2, gain: 1.02, offset: -0.75, calibrated_at: 0 };
+    settings.set_correction(0x41, 0, tare).unwrap();
+    settings.set_correction(0x41, 0, two_point).unwrap();
+    // calibrating a range again replaces it
+    settings.set_correction(0x41, 0, CorrectionSettings { offset: -3.0, ..tare }).unwrap();
+    settings.set_correction(0x40, 2, two_point).unwrap();
+
+    let mut store = ConfigStore::new(RamFlash::new(2), 0);
+    store.save(&settings).unwrap();
+    let loaded = ConfigStore::new(store.release(), 0).load().unwrap().unwrap();
+    assert_eq!(loaded, settings);
+    let channel = loaded.channel(0x41, 0).unwrap();
+    assert_eq!(channel.name.as_str(), "3V3");
+    assert_eq!(channel.corrections.as_slice(), &[two_point, CorrectionSettings { offset: -3.0, ..tare }]);
+    assert_eq!(loaded.channel(0x40, 2).unwrap().corrections.as_slice(), &[two_point]);
+}
+
+#[test]
+fn energy_price_is_checked() {
+    let mut settings = settings("lab", "secret");
+    assert_eq!(settings.energy_price_per_kwh(), 0.0);
+    settings.set("energy_price", "0.32").unwrap();
+    assert_eq!(settings.get("energy_price"), Ok("0.32"));
+    assert_eq!(settings.set("energy_price", "cheap"), Err(SettingError::InvalidValue));
+    assert_eq!(settings.set("energy_price", "-1"), Err(SettingError::InvalidValue));
+    assert_eq!(settings.energy_price_per_kwh(), 0.32);
+
+    let mut store = ConfigStore::new(RamFlash::new(2), 0);
+    store.save(&settings).unwrap();
+    let loaded = ConfigStore::new(store.release(), 0).load().unwrap().unwrap();
+    assert_eq!(loaded.energy_price_per_kwh(), 0.32);
+
+    settings.set("energy_price", "").unwrap();
+    assert_eq!(settings.energy_price_per_kwh(), 0.0);
+}
+
+#[test]
+fn battery_capacity_is_a_number() {
+    let mut settings = settings("lab", "secret");
+    assert_eq!(settings.battery_capacity_mah(), 0.0);
+    settings.set("battery_capacity", "2000").unwrap();
+    assert_eq!(settings.set("battery_capacity", "2 Ah"), Err(SettingError::InvalidValue));
+
+    let mut store = ConfigStore::new(RamFlash::new(2), 0);
+    store.save(&settings).unwrap();
+    let loaded = ConfigStore::new(store.release(), 0).load().unwrap().unwrap();
+    assert_eq!(loaded.get("battery_capacity"), Ok("2000"));
+    assert_eq!(loaded.battery_capacity_mah(), 2000.0);
+}
+
+#[test]
+fn battery_fractions_are_percent() {
+    let mut settings = settings("lab", "secret");
+    assert_eq!(settings.battery_usable(), None);
+    assert_eq!(settings.battery_self_discharge(), None);
+    settings.set("battery_usable", "85").unwrap();
+    settings.set("battery_self_discharge", "2.5").unwrap();
+    assert_eq!(settings.set("battery_usable", "most"), Err(SettingError::InvalidValue));
+
+    let mut store = ConfigStore::new(RamFlash::new(2), 0);
+    store.save(&settings).unwrap();
+    let loaded = ConfigStore::new(store.release(), 0).load().unwrap().unwrap();
+    assert_eq!(loaded.get("battery_self_discharge"), Ok("2.5"));
+    assert_eq!(loaded.battery_usable(), Some(0.85));
+    assert_eq!(loaded.battery_self_discharge(), Some(0.025));
+}
+
+#[test]
+fn quality_settings_are_numbers() {
+    let mut settings = settings("lab", "secret");
+    assert_eq!(settings.quality_window_ms(), None);
+    assert_eq!(settings.dropout_threshold_v(), None);
+    settings.set("quality_window", "200").unwrap();
+    settings.set("dropout_threshold", "3.1").unwrap();
+    assert_eq!(settings.set("dropout_threshold", "-1"), Err(SettingError::InvalidValue));
+
+    let mut store = ConfigStore::new(RamFlash::new(2), 0);
+    store.save(&settings).unwrap();
+    let loaded = ConfigStore::new(store.release(), 0).load().unwrap().unwrap();
+    assert_eq!(loaded.get("quality_window"), Ok("200"));
+    assert_eq!(loaded.quality_window_ms(), Some(200));
+    assert_eq!(loaded.dropout_threshold_v(), Some(3.1));
+}
+
+#[test]
+fn sntp_server_defaults_to_the_pool() {
+    let mut settings = settings("lab", "secret");
+    assert_eq!(settings.get("sntp_server"), Ok(DEFAULT_SNTP_SERVER));
+    settings.set("sntp_server", "time.lab.example").unwrap();
+    assert_eq!(settings.set("sntp_server", "a.host.name.much.too.long.for.it.example"), Err(SettingError::InvalidValue));
+
+    let mut store = ConfigStore::new(RamFlash::new(2), 0);
+    store.save(&settings).unwrap();
+    let loaded = ConfigStore::new(store.release(), 0).load().unwrap().unwrap();
+    assert_eq!(loaded.sntp_server(), "time.lab.example");
+    settings.set("sntp_server", "").unwrap();
+    assert_eq!(settings.sntp_server(), DEFAULT_SNTP_SERVER);
+}
+
+#[test]
+fn overcurrent_threshold_is_off_until_set() {
+    let mut settings = settings("lab", "secret");
+    assert_eq!(settings.overcurrent_threshold_ma(), 0.0);
+    settings.set("overcurrent_threshold", "750").unwrap();
+
+    let mut store = ConfigStore::new(RamFlash::new(2), 0);
+    store.save(&settings).unwrap();
+    let loaded = ConfigStore::new(store.release(), 0).load().unwrap().unwrap();
+    assert_eq!(loaded.get("overcurrent_threshold"), Ok("750"));
+    assert_eq!(loaded.overcurrent_threshold_ma(), 750.0);
+}
@@ -0,0 +1,196 @@
+mod common;
+
+use common::{RamFlash, SECTOR_SIZE};
+use powermeter_storage::datalog::{Aggregate, Compact, DataLog, LogError, LogMode, Record, Sample, SessionConfig, MAX_COMPACT_LEN};
+
+const SECTORS: usize = 4;
+
+fn config(calibration: u8) -> SessionConfig {
+    SessionConfig {
+        mode: LogMode::Samples,
+        calibration,
+        interval_us: 10_000,
+        start_unix_us: Some(1_700_000_000_000_000),
+    }
+}
+
+fn sample(i: u64) -> Record {
+    Record::Sample(Sample {
+        offset_us: i * 10_000,
+        voltage: 5.0,
+        current: i as f32,
+        power: 5.0 * i as f32,
+    })
+}
+
+fn mount(flash: RamFlash) -> DataLog<RamFlash> {
+    DataLog::mount(flash, 0, (SECTORS * SECTOR_SIZE) as u32).unwrap()
+}
+
+fn records(log: &mut DataLog<RamFlash>, session: u32) -> Vec<Record> {
+    let mut records = Vec::new();
+    log.read_session(session, |record| records.push(*record)).unwrap();
+    records
+}
+
+#[test]
+fn empty_log_has_no_sessions() {
+    let mut log = mount(RamFlash::new(SECTORS));
+    assert!(log.sessions().unwrap().is_empty());
+    assert_eq!(log.append(&sample(0)), Err(LogError::NoSession));
+}
+
+#[test]
+fn partition_needs_two_sectors() {
+    assert!(matches!(DataLog::mount(RamFlash::new(1), 0, SECTOR_SIZE as u32), Err(LogError::BadGeometry)));
+}
+
+#[test]
+fn sessions_survive_remount() {
+    let mut log = mount(RamFlash::new(SECTORS));
+    let first = log.start_session(config(0)).unwrap();
+    for i in 0..200 {
+        log.append(&sample(i)).unwrap();
+    }
+    log.end_session();
+    let aggregates = SessionConfig {
+        mode: LogMode::Aggregates,
+        calibration: 2,
+        interval_us: 1_000_000,
+        start_unix_us: None,
+    };
+    let second = log.start_session(aggregates).unwrap();
+    let aggregate = Record::Aggregate(Aggregate {
+        offset_us: 1_000_000,
+        count: 100,
+        voltage: 3.25,
+        current: 12.5,
+        power: 40.625,
+        current_min: 0.5,
+        current_max: 80.0,
+    });
+    log.append(&aggregate).unwrap();
+
+    let mut log = mount(log.release());
+    let sessions = log.sessions().unwrap();
+    assert_eq!(sessions.len(), 2);
+    assert_eq!(sessions[0].id, first);
+    assert_eq!(sessions[0].config, config(0));
+    assert_eq!(sessions[0].records, 200);
+    assert_eq!(sessions[0].duration_us, 199 * 10_000);
+    assert!(!sessions[0].truncated);
+    assert_eq!(sessions[1].id, second);
+    assert_eq!(sessions[1].config, aggregates);
+    assert_eq!(sessions[1].duration_us, 1_000_000);
+
+    assert_eq!(records(&mut log, first), (0..200).map(sample).collect::<Vec<_>>());
+    assert_eq!(records(&mut log, second), vec![aggregate]);
+
+    // ids keep counting after a remount
+    assert_eq!(log.start_session(config(0)).unwrap(), second + 1);
+}
+
+#[test]
+fn compact_records_keep_their_bytes() {
+    let mut log = mount(RamFlash::new(SECTORS));
+    let session = log.start_session(SessionConfig { mode: LogMode::Compact, ..config(1) }).unwrap();
+    let header = Record::Compact(Compact::new(0, b"PMRZ header").unwrap());
+    let block: Vec<u8> = (0..MAX_COMPACT_LEN as u32).map(|i| (i * 7) as u8).collect();
+    let blocks: Vec<Record> = (1..40).map(|i| Record::Compact(Compact::new(i * 320_000, &block[..i as usize * 6]).unwrap())).collect();
+    log.append(&header).unwrap();
+    for record in &blocks {
+        log.append(record).unwrap();
+    }
+    assert!(Compact::new(0, &[0; MAX_COMPACT_LEN + 1]).is_none());
+
+    let mut log = mount(log.release());
+    let mut expected = vec![header];
+    expected.extend_from_slice(&blocks);
+    assert_eq!(records(&mut log, session), expected);
+    let sessions = log.sessions().unwrap();
+    assert_eq!(sessions[0].config.mode, LogMode::Compact);
+    assert_eq!(sessions[0].duration_us, 39 * 320_000);
+}
+
+#[test]
+fn full_log_overwrites_oldest_sectors() {
+    let mut log = mount(RamFlash::new(SECTORS));
+    let old = log.start_session(config(0)).unwrap();
+    log.append(&sample(0)).unwrap();
+    log.end_session();
+
+    // 28 bytes per sample record, enough for more than the whole partition
+    let session = log.start_session(config(1)).unwrap();
+    let count = 4 * SECTOR_SIZE as u64 / 28;
+    for i in 0..count {
+        log.append(&sample(i)).unwrap();
+    }
+
+    let mut log = mount(log.release());
+    let sessions = log.sessions().unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].id, session);
+    assert!(sessions[0].truncated);
+    assert_eq!(sessions[0].duration_us, (count - 1) * 10_000);
+    assert!(records(&mut log, old).is_empty());
+
+    // what is left is the newest, contiguous and in order
+    let kept = records(&mut log, session);
+    let first = kept[0].offset_us() / 10_000;
+    assert_eq!(kept, (first..count).map(sample).collect::<Vec<_>>());
+}
+
+#[test]
+fn power_loss_keeps_completed_records() {
+    // cut power at every point of an append that opens a new sector
+    let per_sector = (SECTOR_SIZE as u64 - 32) / 28;
+    for budget in (0..SECTOR_SIZE + 64).step_by(4) {
+        let mut log = mount(RamFlash::new(SECTORS));
+        let session = log.start_session(config(0)).unwrap();
+        for i in 0..per_sector {
+            log.append(&sample(i)).unwrap();
+        }
+
+        let mut flash = log.release();
+        flash.power_budget = Some(budget);
+        let mut log = mount(flash);
+        log.start_session(config(0)).ok();
+        let _ = log.append(&sample(1000));
+
+        let mut flash = log.release();
+        flash.power_on();
+        let mut log = mount(flash);
+        assert_eq!(records(&mut log, session), (0..per_sector).map(sample).collect::<Vec<_>>(), "budget {}", budget);
+        let sessions = log.sessions().unwrap();
+        assert!(sessions.len() <= 2, "budget {}", budget);
+        if let Some(interrupted) = sessions.get(1) {
+            assert!(interrupted.records <= 1, "budget {}", budget);
+        }
+    }
+}
+
+#[test]
+fn torn_record_is_dropped_and_logging_continues() {
+    // budget runs out while writing the first record of a new session
+    for torn_bytes in 0..28 {
+        let mut log = mount(RamFlash::new(SECTORS));
+        let session = log.start_session(config(0)).unwrap();
+        log.append(&sample(0)).unwrap();
+
+        let mut flash = log.release();
+        flash.power_budget = Some(SECTOR_SIZE + 32 + torn_bytes);
+        let mut log = mount(flash);
+        let torn = log.start_session(config(1)).unwrap();
+        assert!(log.append(&sample(1)).is_err());
+
+        let mut flash = log.release();
+        flash.power_on();
+        let mut log = mount(flash);
+        assert_eq!(records(&mut log, session), vec![sample(0)], "torn {}", torn_bytes);
+        assert!(records(&mut log, torn).is_empty(), "torn {}", torn_bytes);
+
+        let next = log.start_session(config(0)).unwrap();
+        log.append(&sample(2)).unwrap();
+        assert_eq!(records(&mut log, next), vec![sample(2)], "torn {}", torn_bytes);
+    }
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use common::{RamFlash, SECTOR_SIZE};
+use powermeter_storage::events::{EventError, EventStore, PAYLOAD_LEN};
+
+const SECTORS: usize = 2;
+/// 40 byte slots
+const PER_SECTOR: u32 = (SECTOR_SIZE / 40) as u32;
+
+fn payload(i: u32) -> [u8; PAYLOAD_LEN] {
+    let mut payload = [0u8; PAYLOAD_LEN];
+    payload[..4].copy_from_slice(&i.to_le_bytes());
+    payload[PAYLOAD_LEN - 1] = 0xA5;
+    payload
+}
+
+fn mount(flash: RamFlash) -> EventStore<RamFlash> {
+    EventStore::mount(flash, 0, (SECTORS * SECTOR_SIZE) as u32).unwrap()
+}
+
+fn newest(store: &mut EventStore<RamFlash>) -> Vec<[u8; PAYLOAD_LEN]> {
+    store.newest::<64>().unwrap().into_iter().collect()
+}
+
+#[test]
+fn empty_store_has_no_events() {
+    let mut store = mount(RamFlash::new(SECTORS));
+    assert_eq!(store.sequence(), 0);
+    assert!(newest(&mut store).is_empty());
+}
+
+#[test]
+fn partition_needs_two_sectors() {
+    assert!(matches!(EventStore::mount(RamFlash::new(1), 0, SECTOR_SIZE as u32), Err(EventError::BadGeometry)));
+}
+
+#[test]
+fn events_survive_remount() {
+    let mut store = mount(RamFlash::new(SECTORS));
+    for i in 0..10 {
+        store.append(&payload(i)).unwrap();
+    }
+    let mut store = mount(store.release());
+    assert_eq!(store.sequence(), 10);
+    assert_eq!(newest(&mut store), (0..10).map(payload).collect::<Vec<_>>());
+    store.append(&payload(10)).unwrap();
+    assert_eq!(newest(&mut store).last(), Some(&payload(10)));
+}
+
+#[test]
+fn full_ring_drops_the_oldest_sector() {
+    let mut store = mount(RamFlash::new(SECTORS));
+    // two and three quarter times around
+    let count = 5 * PER_SECTOR + PER_SECTOR / 2;
+    for i in 0..count {
+        store.append(&payload(i)).unwrap();
+    }
+    let mut store = mount(store.release());
+    assert_eq!(store.sequence(), count);
+    assert_eq!(newest(&mut store), (count - 64..count).map(payload).collect::<Vec<_>>());
+    // the sector written last is half full, the one before it is complete
+    let kept: Vec<_> = store.newest::<1024>().unwrap().into_iter().collect();
+    assert_eq!(kept.len() as u32, PER_SECTOR + PER_SECTOR / 2);
+    assert_eq!(kept.first(), Some(&payload(count - kept.len() as u32)));
+}
+
+#[test]
+fn torn_slot_is_skipped_and_appending_continues() {
+    for torn_bytes in 0..40 {
+        let mut store = mount(RamFlash::new(SECTORS));
+        for i in 0..3 {
+            store.append(&payload(i)).unwrap();
+        }
+
+        let mut flash = store.release();
+        flash.power_budget = Some(torn_bytes);
+        let mut store = mount(flash);
+        assert!(store.append(&payload(3)).is_err());
+
+        let mut flash = store.release();
+        flash.power_on();
+        let mut store = mount(flash);
+        assert_eq!(newest(&mut store), (0..3).map(payload).collect::<Vec<_>>(), "torn {}", torn_bytes);
+
+        store.append(&payload(4)).unwrap();
+        let mut store = mount(store.release());
+        let events = newest(&mut store);
+        assert_eq!(events.len(), 4, "torn {}", torn_bytes);
+        assert_eq!(events.last(), Some(&payload(4)), "torn {}", torn_bytes);
+    }
+}
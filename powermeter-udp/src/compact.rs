@@ -0,0 +1,55 @@
+// Decoder for compact raw register logs, see powermeter_protocol::compact
+
+use std::io::{self, Write};
+
+use powermeter_protocol::compact::{decode_header, Blocks, Calibration, CompactError, RawSample, HEADER_LEN};
+
+pub const CSV_HEADER: &str = "timestamp_us,voltage_v,shunt_mv,current_ma,power_mw,overflow";
+
+#[derive(Debug)]
+pub struct CompactLog {
+    pub calibration: Calibration,
+    pub samples: Vec<RawSample>,
+    /// bytes outside of any block with a valid crc
+    pub skipped_bytes: usize,
+    /// blocks with a valid crc that could not be decoded
+    pub bad_blocks: usize,
+}
+
+/// Decode a header followed by blocks. Damaged blocks are skipped, only a
+/// damaged header is an error.
+pub fn decode(data: &[u8]) -> Result<CompactLog, CompactError> {
+    let calibration = decode_header(data)?;
+    let mut blocks = Blocks::new(&data[HEADER_LEN..]);
+    let mut samples = Vec::new();
+    let mut bad_blocks = 0;
+    for block in &mut blocks {
+        let mut decoded = Vec::with_capacity(block.len());
+        match block.samples(|sample| decoded.push(sample)) {
+            Ok(()) => samples.extend(decoded),
+            Err(_) => bad_blocks += 1,
+        }
+    }
+    Ok(CompactLog {
+        calibration,
+        samples,
+        skipped_bytes: blocks.skipped_bytes,
+        bad_blocks,
+    })
+}
+
+impl CompactLog {
+    pub fn write_csv<W: Write>(&self, mut out: W) -> io::Result<()> {
+        writeln!(out, "{}", CSV_HEADER)?;
+        for sample in &self.samples {
+            writeln!(out, "{},{:.3},{:.2},{:.3},{:.3},{}",
+                     sample.timestamp_us,
+                     sample.voltage(),
+                     sample.shunt_mv(),
+                     sample.current(&self.calibration),
+                     sample.power(&self.calibration),
+                     sample.overflow() as u8)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,78 @@
+pub mod compact;
+
+use std::io::{self, Write};
+
+use powermeter_protocol::stream::Packet;
+use powermeter_protocol::time::UtcDateTime;
+
+pub const CSV_HEADER: &str = "sequence,timestamp_us,utc,voltage_v,current_ma,power_mw,range";
+
+/// Counts packets that never arrived based on the sequence number.
+/// Late or duplicated packets are counted separately and do not reduce
+/// the loss count.
+#[derive(Debug, Default)]
+pub struct LossTracker {
+    next: Option<u32>,
+    pub received: u64,
+    pub lost: u64,
+    pub late: u64,
+}
+
+impl LossTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of packets missing right before this one.
+    pub fn observe(&mut self, sequence: u32) -> u32 {
+        self.received += 1;
+        let missing = match self.next {
+            None => 0,
+            Some(next) => {
+                let gap = sequence.wrapping_sub(next);
+                if gap > u32::MAX / 2 {
+                    self.late += 1;
+                    return 0;
+                }
+                gap
+            }
+        };
+        self.lost += missing as u64;
+        self.next = Some(sequence.wrapping_add(1));
+        missing
+    }
+}
+
+pub struct CsvWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> CsvWriter<W> {
+    pub fn new(mut out: W) -> io::Result<Self> {
+        writeln!(out, "{}", CSV_HEADER)?;
+        Ok(CsvWriter { out })
+    }
+
+    pub fn write_packet(&mut self, packet: &Packet) -> io::Result<()> {
+        for sample in packet.samples() {
+            write!(self.out, "{},{},", packet.sequence, sample.timestamp_us)?;
+            if let Some(utc_us) = packet.utc_us(&sample) {
+                write!(self.out, "{}", UtcDateTime::from_unix_us(utc_us))?;
+            }
+            writeln!(self.out, ",{},{},{},{}",
+                     sample.voltage,
+                     sample.current,
+                     sample.power,
+                     sample.range)?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
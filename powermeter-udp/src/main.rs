@@ -0,0 +1,69 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::net::{SocketAddr, UdpSocket};
+use std::process::exit;
+use std::time::{Duration, Instant};
+
+use powermeter_protocol::stream::{decode, DEFAULT_PORT, MAX_PACKET_LEN, SUBSCRIBE_REQUEST, SUBSCRIBE_TIMEOUT_SECS};
+use powermeter_udp::{CsvWriter, LossTracker};
+
+fn usage() -> ! {
+    eprintln!("usage: powermeter-udp <device-ip>[:port] [-o output.csv]");
+    exit(2);
+}
+
+fn main() -> io::Result<()> {
+    let mut device: Option<SocketAddr> = None;
+    let mut output: Option<String> = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => output = Some(args.next().unwrap_or_else(|| usage())),
+            "-h" | "--help" => usage(),
+            _ => {
+                let addr = if arg.contains(':') { arg } else { format!("{}:{}", arg, DEFAULT_PORT) };
+                device = Some(addr.parse().unwrap_or_else(|_| usage()));
+            }
+        }
+    }
+    let device = device.unwrap_or_else(|| usage());
+
+    let out: Box<dyn Write> = match output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+    let mut csv = CsvWriter::new(out)?;
+    let mut loss = LossTracker::new();
+
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    let keepalive = Duration::from_secs(SUBSCRIBE_TIMEOUT_SECS / 2);
+    socket.set_read_timeout(Some(keepalive))?;
+    socket.send_to(&SUBSCRIBE_REQUEST, device)?;
+    let mut last_subscribe = Instant::now();
+
+    let mut buf = [0u8; MAX_PACKET_LEN];
+    loop {
+        if last_subscribe.elapsed() >= keepalive {
+            socket.send_to(&SUBSCRIBE_REQUEST, device)?;
+            last_subscribe = Instant::now();
+        }
+        let len = match socket.recv_from(&mut buf) {
+            Ok((len, from)) if from.ip() == device.ip() => len,
+            Ok(_) => continue,
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => continue,
+            Err(e) => return Err(e),
+        };
+        match decode(&buf[..len]) {
+            Ok(packet) => {
+                let missing = loss.observe(packet.sequence);
+                if missing != 0 {
+                    eprintln!("lost {} packet(s) before #{} ({} total)", missing, packet.sequence, loss.lost);
+                }
+                csv.write_packet(&packet)?;
+                csv.flush()?;
+            }
+            Err(e) => eprintln!("dropping packet: {:?}", e),
+        }
+    }
+}
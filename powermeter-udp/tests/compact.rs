@@ -0,0 +1,147 @@
+use powermeter_protocol::compact::{encode_header, BlockEncoder, Calibration, CompactError, RawSample, HEADER_LEN, KEYFRAME_INTERVAL, MAX_BLOCK_LEN};
+use powermeter_udp::compact::{decode, CSV_HEADER};
+
+// Calibration_32V_2A with the 0.1 ohm shunt of the breakout
+const CALIBRATION: Calibration = Calibration {
+    config: 0x399F,
+    calibration: 4096,
+    shunt_uohm: 100_000,
+};
+
+fn raw(i: u64) -> RawSample {
+    // slow ramp with some noise and a load step in the middle
+    let noise = [0i16, 3, -2, 1, -4, 2][(i % 6) as usize];
+    let step = if i >= 50 { 900 } else { 0 };
+    RawSample {
+        timestamp_us: 5_000_000 + i * 10_000 + i % 3,
+        shunt: 120 + step + noise,
+        bus: (((1250 + i / 10) as u16) << 3) | 0x02,
+        power: 31 + step as u16 / 4,
+        current: 1200 + step * 10 + noise * 10,
+    }
+}
+
+fn encode(samples: &[RawSample]) -> Vec<u8> {
+    let mut data = encode_header(&CALIBRATION).to_vec();
+    let mut encoder = BlockEncoder::new();
+    for sample in samples {
+        if !encoder.push(sample) {
+            data.extend_from_slice(encoder.finish());
+            assert!(encoder.push(sample));
+        }
+    }
+    data.extend_from_slice(encoder.finish());
+    data
+}
+
+#[test]
+fn samples_survive_encode_decode() {
+    let input: Vec<RawSample> = (0..100).map(raw).collect();
+    let data = encode(&input);
+    // keyframes included, still far below 16 bytes per sample
+    assert!(data.len() < input.len() * 8, "{} bytes", data.len());
+
+    let log = decode(&data).unwrap();
+    assert_eq!(log.calibration, CALIBRATION);
+    assert_eq!(log.samples, input);
+    assert_eq!(log.skipped_bytes, 0);
+    assert_eq!(log.bad_blocks, 0);
+}
+
+#[test]
+fn extreme_values_roundtrip() {
+    let input = vec![
+        RawSample { timestamp_us: 0, shunt: i16::MIN, bus: 0, power: 0, current: i16::MIN },
+        RawSample { timestamp_us: u64::MAX / 2, shunt: i16::MAX, bus: u16::MAX, power: u16::MAX, current: i16::MAX },
+        RawSample { timestamp_us: u64::MAX / 2, shunt: i16::MIN, bus: 0, power: 0, current: i16::MIN },
+    ];
+    assert_eq!(decode(&encode(&input)).unwrap().samples, input);
+}
+
+#[test]
+fn blocks_start_with_keyframes() {
+    let mut encoder = BlockEncoder::new();
+    let mut pushed = 0;
+    while encoder.push(&raw(pushed)) {
+        pushed += 1;
+    }
+    assert_eq!(pushed as usize, KEYFRAME_INTERVAL);
+    assert!(encoder.finish().len() <= MAX_BLOCK_LEN);
+    assert!(encoder.finish().is_empty());
+}
+
+#[test]
+fn time_going_backwards_starts_new_block() {
+    let mut encoder = BlockEncoder::new();
+    assert!(encoder.push(&raw(5)));
+    assert!(!encoder.push(&raw(4)));
+}
+
+#[test]
+fn engineering_units_from_header() {
+    let sample = RawSample { timestamp_us: 0, shunt: 1000, bus: (3000 << 3) | 0x01, power: 50, current: 2000 };
+    assert!((CALIBRATION.current_lsb_ma() - 0.1).abs() < 1e-6);
+    assert!((CALIBRATION.power_lsb_mw() - 2.0).abs() < 1e-5);
+    assert!((sample.voltage() - 12.0).abs() < 1e-4);
+    assert!((sample.shunt_mv() - 10.0).abs() < 1e-4);
+    assert!((sample.current(&CALIBRATION) - 200.0).abs() < 1e-3);
+    assert!((sample.power(&CALIBRATION) - 100.0).abs() < 1e-3);
+    assert!(sample.overflow());
+}
+
+#[test]
+fn corrupted_block_is_skipped() {
+    let input: Vec<RawSample> = (0..3 * KEYFRAME_INTERVAL as u64).map(raw).collect();
+    let clean = encode(&input);
+
+    // flip every single byte of the second block in turn
+    let first_block = encode(&input[..KEYFRAME_INTERVAL]).len() - HEADER_LEN;
+    let second_block = encode(&input[..2 * KEYFRAME_INTERVAL]).len() - HEADER_LEN - first_block;
+    for pos in HEADER_LEN + first_block..HEADER_LEN + first_block + second_block {
+        let mut data = clean.clone();
+        data[pos] ^= 0x10;
+        let log = decode(&data).unwrap();
+        let mut expected = input[..KEYFRAME_INTERVAL].to_vec();
+        expected.extend_from_slice(&input[2 * KEYFRAME_INTERVAL..]);
+        assert_eq!(log.samples, expected, "byte {}", pos);
+        assert!(log.skipped_bytes > 0);
+    }
+}
+
+#[test]
+fn garbage_and_truncation_are_tolerated() {
+    let input: Vec<RawSample> = (0..2 * KEYFRAME_INTERVAL as u64).map(raw).collect();
+    let clean = encode(&input);
+
+    // garbage with sync markers between the blocks
+    let first_block = encode(&input[..KEYFRAME_INTERVAL]).len();
+    let mut data = clean[..first_block].to_vec();
+    data.extend_from_slice(&[0xA5, 0x5A, 0xFF, 0x00, 0xA5, 0x5A, 0x10, 0x00, 0x42]);
+    data.extend_from_slice(&clean[first_block..]);
+    assert_eq!(decode(&data).unwrap().samples, input);
+
+    // cut in the middle of the last block, as after a power loss
+    let log = decode(&clean[..clean.len() - 5]).unwrap();
+    assert_eq!(log.samples, input[..KEYFRAME_INTERVAL]);
+}
+
+#[test]
+fn damaged_header_is_an_error() {
+    let mut data = encode(&[raw(0)]);
+    assert_eq!(decode(&data[..HEADER_LEN - 1]).unwrap_err(), CompactError::TooShort);
+    data[8] ^= 1;
+    assert_eq!(decode(&data).unwrap_err(), CompactError::BadCrc);
+    data[0] = b'X';
+    assert_eq!(decode(&data).unwrap_err(), CompactError::BadMagic);
+}
+
+#[test]
+fn csv_has_engineering_units() {
+    let log = decode(&encode(&[raw(0)])).unwrap();
+    let mut out = Vec::new();
+    log.write_csv(&mut out).unwrap();
+    let text = String::from_utf8(out).unwrap();
+    let mut lines = text.lines();
+    assert_eq!(lines.next(), Some(CSV_HEADER));
+    assert_eq!(lines.next(), Some("5000000,5.000,1.20,120.000,62.000,0"));
+}
@@ -0,0 +1,138 @@
+use powermeter_protocol::stream::{decode, DecodeError, PacketBuilder, Sample, HEADER_LEN, MAX_SAMPLES, SAMPLE_LEN};
+use powermeter_udp::{CsvWriter, LossTracker, CSV_HEADER};
+
+fn sample(i: u64) -> Sample {
+    Sample {
+        timestamp_us: 1_000_000 + i * 1000,
+        voltage: 3.25 + i as f32 * 0.5,
+        current: 12.5 - i as f32 * 0.25,
+        power: 41.25,
+        range: (i % 3) as u8,
+    }
+}
+
+#[test]
+fn samples_survive_encode_decode() {
+    let mut builder = PacketBuilder::new();
+    let input: Vec<Sample> = (0..10).map(sample).collect();
+    for s in &input {
+        assert!(builder.push(s));
+    }
+    let bytes = builder.finish().to_vec();
+    assert_eq!(bytes.len(), HEADER_LEN + 10 * SAMPLE_LEN);
+
+    let packet = decode(&bytes).unwrap();
+    assert_eq!(packet.sequence, 0);
+    assert_eq!(packet.base_us, 1_000_000);
+    assert_eq!(packet.samples().collect::<Vec<_>>(), input);
+}
+
+#[test]
+fn sequence_increments_per_packet() {
+    let mut builder = PacketBuilder::new();
+    for expected in 0..3u32 {
+        builder.push(&sample(expected as u64));
+        let packet = decode(builder.finish()).unwrap();
+        assert_eq!(packet.sequence, expected);
+        assert_eq!(packet.len(), 1);
+    }
+}
+
+#[test]
+fn full_packet_rejects_more_samples() {
+    let mut builder = PacketBuilder::new();
+    for i in 0..MAX_SAMPLES as u64 {
+        assert!(builder.push(&sample(i)));
+    }
+    assert!(builder.is_full());
+    assert!(!builder.push(&sample(MAX_SAMPLES as u64)));
+    assert_eq!(decode(builder.finish()).unwrap().len(), MAX_SAMPLES);
+}
+
+#[test]
+fn offset_overflow_starts_new_packet() {
+    let mut builder = PacketBuilder::new();
+    assert!(builder.push(&sample(0)));
+    let mut far = sample(0);
+    far.timestamp_us += u32::MAX as u64 + 1;
+    assert!(!builder.push(&far));
+    builder.finish();
+    assert!(builder.push(&far));
+    assert_eq!(decode(builder.finish()).unwrap().base_us, far.timestamp_us);
+}
+
+#[test]
+fn malformed_packets_are_rejected() {
+    let mut builder = PacketBuilder::new();
+    builder.push(&sample(0));
+    builder.push(&sample(1));
+    let bytes = builder.finish().to_vec();
+
+    assert_eq!(decode(&bytes[..HEADER_LEN - 1]).unwrap_err(), DecodeError::TooShort);
+    assert_eq!(decode(&bytes[..bytes.len() - 1]).unwrap_err(), DecodeError::Truncated);
+
+    let mut bad = bytes.clone();
+    bad[0] = b'X';
+    assert_eq!(decode(&bad).unwrap_err(), DecodeError::BadMagic);
+
+    let mut bad = bytes.clone();
+    bad[2] = 99;
+    assert_eq!(decode(&bad).unwrap_err(), DecodeError::UnsupportedVersion(99));
+}
+
+#[test]
+fn csv_contains_one_row_per_sample() {
+    let mut builder = PacketBuilder::new();
+    builder.push(&sample(0));
+    builder.push(&sample(1));
+    let bytes = builder.finish().to_vec();
+
+    let mut csv = CsvWriter::new(Vec::new()).unwrap();
+    csv.write_packet(&decode(&bytes).unwrap()).unwrap();
+    let text = String::from_utf8(csv.into_inner()).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines[0], CSV_HEADER);
+    assert_eq!(lines[1], "0,1000000,,3.25,12.5,41.25,0");
+    assert_eq!(lines[2], "0,1001000,,3.75,12.25,41.25,1");
+    assert_eq!(lines.len(), 3);
+}
+
+#[test]
+fn lost_and_late_packets_are_counted() {
+    let mut loss = LossTracker::new();
+    assert_eq!(loss.observe(7), 0);
+    assert_eq!(loss.observe(8), 0);
+    assert_eq!(loss.observe(11), 2);
+    assert_eq!(loss.observe(9), 0);
+    assert_eq!(loss.lost, 2);
+    assert_eq!(loss.late, 1);
+    assert_eq!(loss.received, 4);
+}
+
+#[test]
+fn sequence_wraparound_is_not_loss() {
+    let mut loss = LossTracker::new();
+    loss.observe(u32::MAX);
+    assert_eq!(loss.observe(0), 0);
+    assert_eq!(loss.lost, 0);
+}
+
+#[test]
+fn csv_has_utc_time_when_synced() {
+    let mut builder = PacketBuilder::new();
+    // 2024-05-01T12:00:00Z is 1714564800 s after the epoch, boot was 1 s earlier
+    builder.set_utc_offset(Some(1_714_564_799_000_000));
+    builder.push(&sample(0));
+    builder.push(&sample(1));
+    let bytes = builder.finish().to_vec();
+
+    let packet = decode(&bytes).unwrap();
+    assert_eq!(packet.utc_offset_us, Some(1_714_564_799_000_000));
+
+    let mut csv = CsvWriter::new(Vec::new()).unwrap();
+    csv.write_packet(&packet).unwrap();
+    let text = String::from_utf8(csv.into_inner()).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines[1], "0,1000000,2024-05-01T12:00:00.000Z,3.25,12.5,41.25,0");
+    assert_eq!(lines[2], "0,1001000,2024-05-01T12:00:00.001Z,3.75,12.25,41.25,1");
+}
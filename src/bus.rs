@@ -0,0 +1,40 @@
+// Sensor bus recovery
+//
+// A sensor reset in the middle of a read can hold SDA low until it sees
+// enough clock pulses. The I2C controller of the ESP32-S2 sends them on its
+// own, so the pins stay with the driver. All sensors are powered from the
+// i2c_power rail on gpio7, switching it off resets every one of them.
+
+use embassy_time::{Duration, Timer};
+use esp_hal::gpio::{GpioPin, Output, PushPull};
+use esp_hal::peripherals::I2C0;
+use esp_hal::prelude::*;
+
+// nine pulses release any byte in flight
+const RECOVERY_PULSES: u8 = 9;
+const RAIL_OFF: Duration = Duration::from_millis(100);
+// the sensors take well below this to start up
+const RAIL_SETTLE: Duration = Duration::from_millis(10);
+
+pub type RailPin = GpioPin<Output<PushPull>, 7>;
+
+/// Clock out a transfer a device is stuck in.
+pub fn clock_out() {
+    // the driver never touches this register
+    let i2c = unsafe { &*I2C0::PTR };
+    i2c.scl_sp_conf().modify(|_, w| unsafe { w.scl_rst_slv_num().bits(RECOVERY_PULSES) }.scl_rst_slv_en().set_bit());
+    // the hardware clears the bit once the pulses are out
+    for _ in 0..10_000 {
+        if i2c.scl_sp_conf().read().scl_rst_slv_en().bit_is_clear() {
+            break;
+        }
+    }
+}
+
+/// Switch the sensors off and on, they have to be initialised again.
+pub async fn power_cycle(rail: &mut RailPin) {
+    let _ = rail.set_low();
+    Timer::after(RAIL_OFF).await;
+    let _ = rail.set_high();
+    Timer::after(RAIL_SETTLE).await;
+}
@@ -0,0 +1,191 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use embassy_futures::select::{select3, Either3};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::mutex::Mutex;
+use embassy_time::{Duration, Instant};
+use esp_storage::FlashStorage;
+use log::{info, warn};
+use powermeter_core::pipeline::LOG_INTERVAL_US;
+use powermeter_protocol::compact::{self, BlockEncoder, RawSample};
+use powermeter_protocol::stream::Sample;
+use powermeter_storage::datalog::{Aggregate, Compact, DataLog, LogMode, Record, SessionConfig};
+
+use crate::sntp;
+
+// "datalog" in partitions.csv
+const DATALOG_OFFSET: u32 = 0x220000;
+const DATALOG_SIZE: u32 = 0x1E0000;
+
+// one aggregate per second fits about 16 hours into the partition
+const AGGREGATE_INTERVAL: Duration = Duration::from_secs(1);
+
+pub static LOGGING: AtomicBool = AtomicBool::new(false);
+// handle_power sends raw registers instead of samples
+pub static RAW_LOGGING: AtomicBool = AtomicBool::new(false);
+
+pub static LOG_CHANNEL: embassy_sync::channel::Channel<CriticalSectionRawMutex, Sample, 64> = embassy_sync::channel::Channel::new();
+
+pub static RAW_CHANNEL: embassy_sync::channel::Channel<CriticalSectionRawMutex, RawFrame, 64> = embassy_sync::channel::Channel::new();
+
+// shared with the serial task that lists and reads sessions, None until
+// handle_datalog mounted the partition
+pub static DATA_LOG: Mutex<CriticalSectionRawMutex, Option<DataLog<FlashStorage>>> = Mutex::new(None);
+
+pub static LOG_COMMAND: embassy_sync::signal::Signal<CriticalSectionRawMutex, LogCommand> = embassy_sync::signal::Signal::new();
+
+#[derive(Debug, Clone, Copy)]
+pub enum LogCommand {
+    /// calibration is the index of the sensor calibration in use
+    Start { calibration: u8, mode: LogMode },
+    Stop,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum RawFrame {
+    /// sent once before the first sample of a session
+    Header(compact::Calibration),
+    Sample(RawSample),
+}
+
+#[derive(Default)]
+struct Accumulator {
+    count: u32,
+    voltage: f32,
+    current: f32,
+    power: f32,
+    current_min: f32,
+    current_max: f32,
+}
+
+impl Accumulator {
+    fn add(&mut self, sample: &Sample) {
+        if self.count == 0 {
+            self.current_min = sample.current;
+            self.current_max = sample.current;
+        }
+        self.count += 1;
+        self.voltage += sample.voltage;
+        self.current += sample.current;
+        self.power += sample.power;
+        self.current_min = self.current_min.min(sample.current);
+        self.current_max = self.current_max.max(sample.current);
+    }
+
+    fn take(&mut self, offset_us: u64) -> Option<Aggregate> {
+        if self.count == 0 {
+            return None;
+        }
+        let n = self.count as f32;
+        let aggregate = Aggregate {
+            offset_us,
+            count: self.count,
+            voltage: self.voltage / n,
+            current: self.current / n,
+            power: self.power / n,
+            current_min: self.current_min,
+            current_max: self.current_max,
+        };
+        *self = Accumulator::default();
+        Some(aggregate)
+    }
+}
+
+#[embassy_executor::task]
+pub async fn handle_datalog() {
+    let mut log = match DataLog::mount(FlashStorage::new(), DATALOG_OFFSET, DATALOG_SIZE) {
+        Ok(log) => log,
+        Err(e) => {
+            warn!("mounting data log failed {:?}", e);
+            return;
+        }
+    };
+    if let Ok(sessions) = log.sessions() {
+        info!("data log has {} sessions", sessions.len());
+    }
+    DATA_LOG.lock().await.replace(log);
+
+    let mut start = Instant::now();
+    let mut interval_end = start + AGGREGATE_INTERVAL;
+    let mut accumulator = Accumulator::default();
+    let mut encoder = BlockEncoder::new();
+    let mut last_raw_us = 0;
+    loop {
+        let event = select3(LOG_COMMAND.wait(), LOG_CHANNEL.receive(), RAW_CHANNEL.receive()).await;
+        let mut guard = DATA_LOG.lock().await;
+        let log = guard.as_mut().unwrap();
+        let record = match event {
+            Either3::First(LogCommand::Start { calibration, mode }) => {
+                start = Instant::now();
+                interval_end = start + AGGREGATE_INTERVAL;
+                accumulator = Accumulator::default();
+                encoder.finish();
+                let config = SessionConfig {
+                    mode,
+                    calibration,
+                    interval_us: match mode {
+                        LogMode::Aggregates => AGGREGATE_INTERVAL.as_micros() as u32,
+                        _ => LOG_INTERVAL_US as u32,
+                    },
+                    start_unix_us: sntp::utc_offset_us().map(|offset| start.as_micros() as i64 + offset),
+                };
+                match log.start_session(config) {
+                    Ok(session) => {
+                        info!("data log session {} started", session);
+                        RAW_LOGGING.store(mode == LogMode::Compact, Ordering::Relaxed);
+                        LOGGING.store(true, Ordering::Relaxed);
+                    }
+                    Err(e) => warn!("starting data log session failed {:?}", e),
+                }
+                None
+            }
+            Either3::First(LogCommand::Stop) => {
+                LOGGING.store(false, Ordering::Relaxed);
+                RAW_LOGGING.store(false, Ordering::Relaxed);
+                while LOG_CHANNEL.try_receive().is_ok() {}
+                while RAW_CHANNEL.try_receive().is_ok() {}
+                if let Some(aggregate) = accumulator.take(start.elapsed().as_micros()) {
+                    let _ = log.append(&Record::Aggregate(aggregate));
+                }
+                if !encoder.is_empty() {
+                    let offset_us = last_raw_us.saturating_sub(start.as_micros());
+                    let _ = log.append(&Record::Compact(Compact::new(offset_us, encoder.finish()).unwrap()));
+                }
+                log.end_session();
+                info!("data log session stopped");
+                None
+            }
+            Either3::Second(sample) => {
+                let timestamp = Instant::from_micros(sample.timestamp_us);
+                let mut record = None;
+                if timestamp >= interval_end {
+                    let offset_us = (interval_end - start).as_micros();
+                    while timestamp >= interval_end {
+                        interval_end += AGGREGATE_INTERVAL;
+                    }
+                    record = accumulator.take(offset_us).map(Record::Aggregate);
+                }
+                accumulator.add(&sample);
+                record
+            }
+            Either3::Third(RawFrame::Header(calibration)) => {
+                Compact::new(0, &compact::encode_header(&calibration)).map(Record::Compact)
+            }
+            Either3::Third(RawFrame::Sample(sample)) => {
+                let mut record = None;
+                if !encoder.push(&sample) {
+                    let offset_us = last_raw_us.saturating_sub(start.as_micros());
+                    record = Compact::new(offset_us, encoder.finish()).map(Record::Compact);
+                    encoder.push(&sample);
+                }
+                last_raw_us = sample.timestamp_us;
+                record
+            }
+        };
+        if let (Some(record), Some(_)) = (record, log.active_session()) {
+            if let Err(e) = log.append(&record) {
+                warn!("writing data log failed {:?}", e);
+            }
+        }
+    }
+}
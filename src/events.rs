@@ -0,0 +1,137 @@
+use embassy_net::tcp::TcpSocket;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::mutex::Mutex;
+use embassy_time::{Duration, Timer};
+use esp_storage::FlashStorage;
+use heapless::Deque;
+use log::{info, warn};
+use powermeter_core::events::{event_line, EventLog, MAX_EVENTS};
+use powermeter_protocol::event::{self, Event, EventKind, EVENTS_PATH};
+use powermeter_protocol::http::{parse_request, ParseError, HTTP_PORT, MAX_REQUEST_LEN};
+use powermeter_storage::events::EventStore;
+
+use crate::sntp;
+use crate::wifi::NetStack;
+
+// "events" in partitions.csv
+const EVENTS_OFFSET: u32 = 0x212000;
+const EVENTS_SIZE: u32 = 0x4000;
+
+// the csv of a full log with the headers
+const RESPONSE_LEN: usize = 6 * 1024;
+
+#[derive(Debug, Clone, Copy)]
+struct Occurred {
+    kind: EventKind,
+    channel: u8,
+    value: f32,
+    duration_us: u32,
+    /// embassy_time microseconds
+    at_us: u64,
+}
+
+static EVENT_CHANNEL: embassy_sync::channel::Channel<CriticalSectionRawMutex, Occurred, 16> = embassy_sync::channel::Channel::new();
+
+// shared with the ui and the serial task, None until handle_events restored
+// it from the flash
+pub static EVENT_LOG: Mutex<CriticalSectionRawMutex, Option<EventLog>> = Mutex::new(None);
+
+/// Log an event that happened at at_us, see EventKind for the value. Does
+/// not wait, an event is dropped when too many are pending.
+pub fn record(kind: EventKind, channel: u8, value: f32, duration_us: u64, at_us: u64) {
+    let occurred = Occurred { kind, channel, value, duration_us: duration_us.min(u32::MAX as u64) as u32, at_us };
+    if EVENT_CHANNEL.try_send(occurred).is_err() {
+        warn!("event {:?} dropped", kind);
+    }
+}
+
+#[embassy_executor::task]
+pub async fn handle_events() {
+    // the log in RAM works without the flash as well
+    let mut store = match EventStore::mount(FlashStorage::new(), EVENTS_OFFSET, EVENTS_SIZE) {
+        Ok(store) => Some(store),
+        Err(e) => {
+            warn!("mounting event store failed {:?}", e);
+            None
+        }
+    };
+    let log = match store.as_mut() {
+        Some(store) => {
+            let restored = store.newest::<MAX_EVENTS>().unwrap_or_else(|e| {
+                warn!("reading event store failed {:?}", e);
+                Deque::new()
+            });
+            EventLog::restore(restored.iter().filter_map(Event::decode), store.sequence())
+        }
+        None => EventLog::default(),
+    };
+    info!("event log has {} events, boot #{}", log.events().len(), log.boot());
+    EVENT_LOG.lock().await.replace(log);
+
+    loop {
+        let occurred = EVENT_CHANNEL.receive().await;
+        let unix_us = sntp::utc_offset_us().map(|offset| occurred.at_us as i64 + offset);
+        let event = match EVENT_LOG.lock().await.as_mut() {
+            Some(log) => log.record(occurred.kind, occurred.channel, occurred.value, occurred.duration_us,
+                                    occurred.at_us, unix_us),
+            None => continue,
+        };
+        info!("event {}", event_line(&event));
+        if let Some(store) = store.as_mut() {
+            if let Err(e) = store.append(&event.encode()) {
+                warn!("storing event failed {:?}", e);
+            }
+        }
+    }
+}
+
+#[embassy_executor::task]
+pub async fn handle_http(stack: &'static NetStack) {
+    let mut rx_buffer = [0u8; 1024];
+    let mut tx_buffer = [0u8; 2048];
+    let mut request = [0u8; MAX_REQUEST_LEN];
+    let mut response = [0u8; RESPONSE_LEN];
+
+    stack.wait_config_up().await;
+    if let Some(config) = stack.config_v4() {
+        info!("event log on http://{}{}", config.address.address(), EVENTS_PATH);
+    }
+    loop {
+        let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
+        socket.set_timeout(Some(Duration::from_secs(10)));
+        if socket.accept(HTTP_PORT).await.is_err() {
+            continue;
+        }
+
+        let mut len = 0;
+        let reply = loop {
+            match socket.read(&mut request[len..]).await {
+                Ok(0) | Err(_) => break None,
+                Ok(n) => len += n,
+            }
+            match parse_request(&request[..len]) {
+                Ok(parsed) => {
+                    let guard = EVENT_LOG.lock().await;
+                    let events = guard.as_ref().map(|log| log.events().iter()).into_iter().flatten();
+                    break event::handle_http(&parsed, events, &mut response);
+                }
+                Err(ParseError::Incomplete) if len < request.len() => {}
+                Err(_) => break None,
+            }
+        };
+
+        if let Some(reply_len) = reply {
+            let mut sent = 0;
+            while sent < reply_len {
+                match socket.write(&response[sent..reply_len]).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => sent += n,
+                }
+            }
+            let _ = socket.flush().await;
+        }
+        socket.close();
+        Timer::after(Duration::from_millis(100)).await;
+        socket.abort();
+    }
+}
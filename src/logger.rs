@@ -0,0 +1,40 @@
+use core::str::FromStr;
+
+use embassy_time::Instant;
+use esp_println::println;
+use log::{LevelFilter, Log, Metadata, Record};
+
+use crate::sntp;
+
+// like esp_println::logger but prefixed with UTC once the clock is synced
+struct UtcLogger;
+
+static LOGGER: UtcLogger = UtcLogger;
+
+pub fn init_logger_from_env() {
+    let level = option_env!("ESP_LOGLEVEL")
+        .and_then(|level| LevelFilter::from_str(level).ok())
+        .unwrap_or(LevelFilter::Info);
+    unsafe {
+        log::set_logger_racy(&LOGGER).unwrap();
+        log::set_max_level_racy(level);
+    }
+}
+
+impl Log for UtcLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        match sntp::now_utc() {
+            Some(utc) => println!("{} {:<5} - {}", utc, record.level(), record.args()),
+            None => println!("+{}ms {:<5} - {}", Instant::now().as_millis(), record.level(), record.args()),
+        }
+    }
+
+    fn flush(&self) {}
+}
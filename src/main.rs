@@ -4,31 +4,24 @@
 
 extern crate alloc;
 
-use alloc::format;
 use core::cell::RefCell;
 use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, Ordering};
 
 use display_interface_spi::SPIInterfaceNoCS;
 use eg_seven_segment::SevenSegmentStyleBuilder;
 use embassy_embedded_hal::shared_bus::blocking;
 use embassy_executor::Spawner;
+use embassy_net::{Config, Ipv4Cidr, Stack, StackResources, StaticConfigV4};
 use embassy_sync::blocking_mutex;
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
-use embassy_time::{Delay, Duration, Ticker, Timer};
+use embassy_time::{Delay, Duration, Instant, Ticker, Timer, with_timeout};
 use embedded_graphics::draw_target::DrawTarget;
-use embedded_graphics::Drawable;
-use embedded_graphics::geometry::{Point, Size};
+use embedded_graphics::geometry::Size;
 use embedded_graphics::mono_font::MonoTextStyle;
 use embedded_graphics::pixelcolor::{Rgb565, RgbColor};
-use embedded_graphics::prelude::Primitive;
-use embedded_graphics::primitives::{PrimitiveStyleBuilder, Rectangle};
-use embedded_graphics::text::{Alignment, Baseline, Text, TextStyle, TextStyleBuilder};
-use embedded_graphics::text::renderer::TextRenderer;
 use embedded_hal_async::digital::Wait;
-use enum_iterator::cardinality;
-use enum_iterator::Sequence;
 use esp32_utils_crate::dummy_pin::DummyPin;
-use esp32_utils_crate::graphics::GraphicUtils;
 use esp_backtrace;
 use esp_hal::{clock::ClockControl, embassy, IO, peripherals::Peripherals, prelude::*, psram};
 use esp_hal::clock::Clocks;
@@ -36,59 +29,119 @@ use esp_hal::gpio::{GpioPin, Unknown};
 use esp_hal::i2c::I2C;
 use esp_hal::ledc::{channel, LEDC, LowSpeed, LSGlobalClkSource, timer};
 use esp_hal::peripherals::I2C0;
+use esp_hal::rng::Rng;
 use esp_hal::spi::master::Spi;
 use esp_hal::spi::SpiMode;
 use esp_hal::timer::TimerGroup;
-use esp_println::println;
-use heapless::String;
-use ina219_rs::ina219::{Calibration, INA219, INA219_ADDR, PowerMonitor};
-use profont::{PROFONT_12_POINT, PROFONT_18_POINT, PROFONT_24_POINT};
+use esp_hal::uart::{config::Config as UartConfig, TxRxPins, Uart};
+use esp_wifi::{EspWifiInitFor, initialize};
+use esp_wifi::wifi::{WifiApDevice, WifiStaDevice};
+use heapless::{String, Vec};
+use log::{error, info, warn};
+use powermeter_core::autorange::AutoRange;
+use powermeter_core::battery::Battery;
+use powermeter_core::channel::{Channel, MAX_CHANNELS};
+use powermeter_core::correction::Correction;
+use powermeter_core::drivers::bus::{self as i2c_bus, BusCounters, Counted};
+use powermeter_core::drivers::ina219::{scan, Calibration};
+use powermeter_core::drivers::ina3221::Ina3221Chip;
+use powermeter_core::drivers::max17048::{Max17048, MAX17048_ADDR};
+use powermeter_core::drivers::sensor::{detect, PowerMonitor, PowerSensor, Sampling, Sensor};
+use powermeter_core::events::{EventLog, LipoWarning};
+use powermeter_core::filter::{FilterKind, FilterTarget};
+use powermeter_core::menu::SettingsItem;
+use powermeter_core::pipeline::{Consumers, Pipeline, Recover};
+use powermeter_core::quality::QualitySettings;
+use powermeter_core::scope::ScopeSettings;
+use powermeter_core::spectrum::{Spectrum, FFT_SIZE};
+use powermeter_core::ui::{self, Action, Button, Input, Theme, Ui};
+use powermeter_protocol::event::EventKind;
+use powermeter_protocol::portal;
+use powermeter_protocol::serial::BAUD_RATE;
+use powermeter_storage::config::{CorrectionSettings, Settings};
+use powermeter_storage::datalog::LogMode;
+use profont::{PROFONT_18_POINT, PROFONT_24_POINT};
 use st7789::{Orientation, ST7789};
 use static_cell::{make_static, StaticCell};
 
-use crate::max1704x::Max17048;
+use crate::datalog::RawFrame;
+use crate::provisioning::ApStack;
+use crate::wifi::NetStack;
+
+mod bus;
+mod datalog;
+mod events;
+mod logger;
+mod provisioning;
+mod serial;
+mod settings;
+mod sntp;
+mod stream;
+mod wifi;
+
+const LONG_PRESS: Duration = Duration::from_millis(1000);
+
+// how often the LiPo of the meter is checked for the low battery event,
+// a monitor plugged in after boot is found then as well
+const LIPO_CHECK: Duration = Duration::from_secs(60);
+
+// how often handle_power looks for sensors that started answering
+const SENSOR_RESCAN: Duration = Duration::from_secs(5);
+
+static INPUT_CHANNEL: embassy_sync::channel::Channel<CriticalSectionRawMutex, Input, 1> = embassy_sync::channel::Channel::new();
+
+// range changes from the ui
+enum RangeCommand {
+    Fixed(Calibration),
+    Auto(bool),
+    Correct(Calibration, Correction),
+}
 
-mod max1704x;
+// channel index and how its range changes
+static RANGE_CHANNEL: embassy_sync::channel::Channel<CriticalSectionRawMutex, (u8, RangeCommand), 4> = embassy_sync::channel::Channel::new();
 
-const ROWSTART: i32 = 40;
-const COLSTART: i32 = 54;
+// longest wait for the first conversion in a new range, 128 samples
+// averaged take 68 ms
+const RANGE_SETTLE: Duration = Duration::from_millis(100);
 
-#[derive(Debug, Clone)]
-struct InputData {
-    button: i8,
-    power: PowerMonitor,
-    msg: Option<heapless::String<128>>,
-}
+// how conversions are started, from the ui
+static SAMPLING_COMMAND: embassy_sync::signal::Signal<CriticalSectionRawMutex, Sampling> = embassy_sync::signal::Signal::new();
 
-impl InputData {
-    fn new() -> Self {
-        InputData {
-            button: -1,
-            power: PowerMonitor {
-                Shunt: 0.0,
-                Voltage: 0.0,
-                Current: 0.0,
-                Power: 0.0,
-            },
-            msg: None,
-        }
-    }
-}
+// the sleep current profile page is up
+static PROFILING: AtomicBool = AtomicBool::new(false);
+
+// the spectrum page is up
+static SPECTRUM: AtomicBool = AtomicBool::new(false);
+
+// the quality page is up
+static QUALITY: AtomicBool = AtomicBool::new(false);
+
+// ripple window and dropout threshold, at boot and from the quality page
+static QUALITY_COMMAND: embassy_sync::signal::Signal<CriticalSectionRawMutex, QualitySettings> = embassy_sync::signal::Signal::new();
+
+// the histogram page starts the current distribution over
+static HISTOGRAM_RESET: AtomicBool = AtomicBool::new(false);
 
-static INPUT_CHANNEL: embassy_sync::channel::Channel<CriticalSectionRawMutex, InputData, 1> = embassy_sync::channel::Channel::new();
+// the scope page arms the scope, None when it closes
+static SCOPE_COMMAND: embassy_sync::signal::Signal<CriticalSectionRawMutex, Option<ScopeSettings>> = embassy_sync::signal::Signal::new();
 
-static CALIBRATION_SIGNAL: embassy_sync::signal::Signal<CriticalSectionRawMutex, Calibration> = embassy_sync::signal::Signal::new();
+// filter changes from the ui
+static FILTER_CHANNEL: embassy_sync::channel::Channel<CriticalSectionRawMutex, (FilterTarget, FilterKind), 2> = embassy_sync::channel::Channel::new();
+
+type PowerBus = blocking_mutex::Mutex<CriticalSectionRawMutex, RefCell<I2C<'static, I2C0>>>;
+type PowerI2c = Counted<'static, blocking::i2c::I2cDevice<'static, CriticalSectionRawMutex, I2C<'static, I2C0>>>;
+
+const I2C_CLOCK_KHZ: u32 = 100;
+
+// retries and errors of the sensors, shown on the diagnostics page
+static BUS_COUNTERS: BusCounters = BusCounters::new();
+
+// what the inputs of an INA3221 share, by the low nibble of its address
+static INA3221_CHIPS: [Ina3221Chip; 16] = [const { Ina3221Chip::new() }; 16];
 
 #[global_allocator]
 static ALLOCATOR: esp_alloc::EspHeap = esp_alloc::EspHeap::empty();
 
-#[derive(Debug, PartialEq, Sequence)]
-enum PowerDisplay {
-    Voltage,
-    Current,
-    Power,
-}
-
 fn init_psram_heap() {
     unsafe {
         ALLOCATOR.init(psram::psram_vaddr_start() as *mut u8, psram::PSRAM_BYTES);
@@ -102,33 +155,14 @@ macro_rules! singleton {
     }};
 }
 
-fn display_text<D, S>(display: &mut D, pos: Point, character_style: S,
-                      text_style: TextStyle, text: &str) where D: DrawTarget<Color=Rgb565>, S: TextRenderer<Color=Rgb565> {
-    let _ = Text::with_text_style(
-        text,
-        pos,
-        character_style,
-        text_style,
-    )
-        .draw(display);
-}
-
-fn create_point_from(point: Point) -> Point {
-    create_point(point.x, point.y)
-}
-
-fn create_point(x: i32, y: i32) -> Point {
-    Point::new(x + ROWSTART, y + COLSTART)
-}
-
 #[embassy_executor::task]
 pub async fn handle_button_d0(pin: GpioPin<Unknown, 0>) {
     let mut button = pin.into_pull_up_input();
     loop {
         button.wait_for_low().await.unwrap();
-        let mut input_data = InputData::new();
-        input_data.button = 0;
-        INPUT_CHANNEL.send(input_data).await;
+        let long_press = with_timeout(LONG_PRESS, button.wait_for_high()).await.is_err();
+        INPUT_CHANNEL.send(Input::Button { button: Button::Select, long_press }).await;
+        button.wait_for_high().await.unwrap();
         Timer::after(Duration::from_millis(500)).await
     }
 }
@@ -138,9 +172,9 @@ pub async fn handle_button_d1(pin: GpioPin<Unknown, 1>) {
     let mut button = pin.into_pull_down_input();
     loop {
         button.wait_for_high().await.unwrap();
-        let mut input_data = InputData::new();
-        input_data.button = 1;
-        INPUT_CHANNEL.send(input_data).await;
+        let long_press = with_timeout(LONG_PRESS, button.wait_for_low()).await.is_err();
+        INPUT_CHANNEL.send(Input::Button { button: Button::Previous, long_press }).await;
+        button.wait_for_low().await.unwrap();
         Timer::after(Duration::from_millis(500)).await
     }
 }
@@ -150,73 +184,415 @@ pub async fn handle_button_d2(pin: GpioPin<Unknown, 2>) {
     let mut button = pin.into_pull_down_input();
     loop {
         button.wait_for_high().await.unwrap();
-        let mut input_data = InputData::new();
-        input_data.button = 2;
-        INPUT_CHANNEL.send(input_data).await;
+        let long_press = with_timeout(LONG_PRESS, button.wait_for_low()).await.is_err();
+        INPUT_CHANNEL.send(Input::Button { button: Button::Next, long_press }).await;
+        button.wait_for_low().await.unwrap();
         Timer::after(Duration::from_millis(500)).await
     }
 }
 
-#[embassy_executor::task]
-pub async fn handle_power(i2c: blocking::i2c::I2cDevice<'static, CriticalSectionRawMutex, I2C<'static, I2C0>>) {
-    let mut ina219 = INA219::new(i2c);
-    match ina219.init(Calibration::Calibration_32V_2A) {
-        Err(e) => {
-            println!("{:?}", e);
-            return;
+/// Program a new range and wait until the sensor converted with it, the
+/// conversion running during the switch is not valid.
+async fn switch_range(sensor: &mut Sensor<'static, PowerI2c>, calibration: Calibration) {
+    if let Err(e) = sensor.init(calibration) {
+        error!("{} 0x{:02x} init failed {:?}", sensor.kind().text(), sensor.address(), e);
+        return;
+    }
+    let settled = with_timeout(RANGE_SETTLE, async {
+        while !matches!(sensor.conversion_ready(), Ok(true)) {
+            Timer::after(Duration::from_micros(200)).await;
         }
-        _ => {}
+    }).await;
+    if settled.is_err() {
+        warn!("{} 0x{:02x} no conversion after range switch", sensor.kind().text(), sensor.address());
     }
+}
 
-    let mut ticker = Ticker::every(Duration::from_millis(1000));
+/// The sample of the conversion started by trigger, None when it did not
+/// finish in time.
+async fn sense_triggered(sensor: &mut Sensor<'static, PowerI2c>) -> Result<Option<PowerMonitor>, <Sensor<'static, PowerI2c> as PowerSensor>::Error> {
+    let deadline = Instant::now() + RANGE_SETTLE;
     loop {
-        if CALIBRATION_SIGNAL.signaled() {
-            let cal = CALIBRATION_SIGNAL.wait().await;
-            ina219.init(cal.clone()).unwrap();
-            Timer::after(Duration::from_secs(2)).await
+        if let Some(measured) = sensor.sense_ready()? {
+            return Ok(Some(measured));
+        }
+        if Instant::now() >= deadline {
+            return Ok(None);
+        }
+        Timer::after(Duration::from_micros(200)).await;
+    }
+}
+
+/// FFT_SIZE currents of the primary channel as fast as the sensor converts
+/// in a buffer on the PSRAM heap, and the sample rate in Hz. The spikes and
+/// dropouts during the burst go to the event log.
+async fn capture_burst(sensor: &mut Sensor<'static, PowerI2c>, channel: &Channel, sampling: Sampling,
+                       pipeline: &mut Pipeline) -> Option<(alloc::vec::Vec<f32>, f32)> {
+    let mut burst = alloc::vec::Vec::with_capacity(FFT_SIZE);
+    let mut first = None;
+    while burst.len() < FFT_SIZE {
+        let fresh = match sampling {
+            Sampling::Continuous => sensor.sense_ready(),
+            Sampling::Triggered => match sensor.trigger() {
+                Ok(()) => sense_triggered(sensor).await,
+                Err(e) => Err(e),
+            },
+        };
+        match fresh {
+            Ok(Some(measured)) => {
+                let now = Instant::now();
+                first.get_or_insert(now);
+                let (current, occurred) = pipeline.process_burst(channel, &measured, now.as_micros());
+                for event in &occurred {
+                    events::record(event.kind, event.channel, event.value, event.duration_us, event.at_us);
+                }
+                burst.push(current);
+            }
+            // polling without a pause would starve the other tasks
+            Ok(None) => Timer::after(Duration::from_micros(100)).await,
+            Err(e) => {
+                warn!("{} burst failed {:?}", channel.label(), e);
+                return None;
+            }
+        }
+    }
+    let elapsed_us = first?.elapsed().as_micros().max(1);
+    Some((burst, (FFT_SIZE - 1) as f32 * 1e6 / elapsed_us as f32))
+}
+
+// Samples every channel each tick and sends what the pipeline makes of
+// them on, see pipeline.rs. Only finished conversions are read, a tick
+// without one has no sample. In triggered sampling every tick starts one
+// conversion per sensor and the sensors power down until the next. While
+// the spectrum page is up a burst of the primary channel is captured
+// after the display readings, the next tick waits meanwhile. Sensors that
+// start answering later get the next channels, the ui hears of them first.
+#[embassy_executor::task]
+pub async fn handle_power(mut sensors: Vec<(Sensor<'static, PowerI2c>, Channel), MAX_CHANNELS>, power_bus: &'static PowerBus, mut rail: bus::RailPin, overcurrent_ma: f32) {
+    // initialise before the next sample, at start and after a recovery
+    let mut reinit = [true; MAX_CHANNELS];
+    let mut retry_at: [Option<Instant>; MAX_CHANNELS] = [None; MAX_CHANNELS];
+
+    let mut sampling = Sampling::Continuous;
+    let mut pipeline = Pipeline::new(sensors.len(), overcurrent_ma);
+    let mut sample_interval_us = 0;
+    let mut ticker = Ticker::every(Duration::from_secs(1));
+    let mut rescanned = Instant::now();
+    loop {
+        if rescanned.elapsed() >= SENSOR_RESCAN && !sensors.is_full() {
+            rescanned = Instant::now();
+            // the sensors known already may be recovering, they are not probed
+            let addresses: Vec<u8, 16> = scan(&mut blocking::i2c::I2cDevice::new(power_bus)).into_iter()
+                .filter(|address| !sensors.iter().any(|(_, channel)| channel.address == *address))
+                .collect();
+            if !addresses.is_empty() {
+                let known = sensors.len();
+                add_sensors(power_bus, &addresses, &settings::load(), &mut sensors);
+                for (_, channel) in &sensors[known..] {
+                    pipeline.add_channel();
+                    INPUT_CHANNEL.send(Input::Channel(channel.clone())).await;
+                }
+            }
+        }
+        while let Ok((index, command)) = RANGE_CHANNEL.try_receive() {
+            let Some((sensor, channel)) = sensors.get_mut(index as usize) else {
+                continue;
+            };
+            match command {
+                RangeCommand::Fixed(cal) => {
+                    channel.set_calibration(cal);
+                    channel.auto_range = None;
+                    switch_range(sensor, cal).await;
+                    events::record(EventKind::RangeChange, index, cal.index() as f32, 0, Instant::now().as_micros());
+                }
+                RangeCommand::Auto(enabled) => {
+                    channel.auto_range = if enabled { Some(AutoRange::new()) } else { None };
+                }
+                RangeCommand::Correct(cal, correction) => channel.set_correction(cal, correction),
+            }
+        }
+        if HISTOGRAM_RESET.swap(false, Ordering::Relaxed) {
+            pipeline.reset_histogram();
+        }
+        if let Some(settings) = SCOPE_COMMAND.try_take() {
+            pipeline.set_scope(settings);
+        }
+        if let Some(settings) = QUALITY_COMMAND.try_take() {
+            pipeline.set_quality(settings);
+        }
+        if let Some(next) = SAMPLING_COMMAND.try_take() {
+            sampling = next;
+            for (sensor, channel) in sensors.iter_mut() {
+                if let Err(e) = sensor.set_sampling(sampling) {
+                    error!("{} sampling not changed {:?}", channel.label(), e);
+                }
+            }
+        }
+        while let Ok((target, kind)) = FILTER_CHANNEL.try_receive() {
+            pipeline.set_filter(target, kind);
+        }
+        let consumers = Consumers {
+            streaming: stream::STREAMING.load(Ordering::Relaxed),
+            logging: datalog::LOGGING.load(Ordering::Relaxed),
+            raw_logging: datalog::RAW_LOGGING.load(Ordering::Relaxed),
+            live: serial::LIVE.load(Ordering::Relaxed),
+            profiling: PROFILING.load(Ordering::Relaxed),
+            scoping: pipeline.is_scoping(),
+            quality: QUALITY.load(Ordering::Relaxed),
+            spectrum: SPECTRUM.load(Ordering::Relaxed),
+        };
+        if consumers.sample_interval_us() != sample_interval_us {
+            sample_interval_us = consumers.sample_interval_us();
+            ticker = Ticker::every(Duration::from_micros(sample_interval_us));
+        }
+        let timestamp_us = Instant::now().as_micros();
+        let route = pipeline.route(&consumers, timestamp_us);
+        let mut pending = None;
+        let mut burst = false;
+        if sampling == Sampling::Triggered {
+            // all sensors convert at the same time, errors show up when reading
+            for (index, (sensor, _)) in sensors.iter_mut().enumerate() {
+                if !reinit[index] {
+                    let _ = sensor.trigger();
+                }
+            }
+        }
+        for (index, (sensor, channel)) in sensors.iter_mut().enumerate() {
+            if retry_at[index].is_some_and(|at| Instant::now() < at) {
+                continue;
+            }
+            if reinit[index] {
+                // probes the sensor as well, it may just have come back,
+                // in triggered sampling it also starts a conversion
+                if let Err(e) = sensor.set_sampling(sampling).and_then(|_| sensor.init(channel.calibration)) {
+                    error!("{} 0x{:02x} init failed {:?}", sensor.kind().text(), channel.address, e);
+                    if let Some(recover) = pipeline.failed(index) {
+                        pending = Some((index, recover));
+                        break;
+                    }
+                    continue;
+                }
+                reinit[index] = false;
+                retry_at[index] = None;
+            }
+            let primary = index == 0;
+            let fresh = match sampling {
+                Sampling::Continuous => sensor.sense_ready(),
+                Sampling::Triggered => sense_triggered(sensor).await,
+            };
+            let measured = match fresh {
+                Ok(fresh) => {
+                    if pipeline.answered(index) {
+                        info!("{} is back", channel.label());
+                    }
+                    // no conversion finished since the last sample
+                    let Some(measured) = fresh else {
+                        continue;
+                    };
+                    measured
+                }
+                Err(_) => {
+                    if let Some(recover) = pipeline.failed(index) {
+                        pending = Some((index, recover));
+                        break;
+                    }
+                    continue;
+                }
+            };
+            // the compact log format is made for the INA219 registers, they
+            // hold the conversion just read until the next one
+            if let Some(ina219) = sensor.as_ina219().filter(|_| primary) {
+                if route.raw_header {
+                    match ina219.registers() {
+                        Ok(calibration) => datalog::RAW_CHANNEL.send(RawFrame::Header(calibration)).await,
+                        Err(e) => error!("reading ina219 calibration failed {:?}", e),
+                    }
+                }
+                if route.raw {
+                    if let Ok(sample) = ina219.raw_sample(timestamp_us) {
+                        let _ = datalog::RAW_CHANNEL.try_send(RawFrame::Sample(sample));
+                    }
+                }
+            }
+            // the compact log has one range in its header, it stays fixed
+            let hold = primary && consumers.raw_logging;
+            if let Some(auto_range) = channel.auto_range.as_mut().filter(|_| !hold) {
+                if let Some(cal) = auto_range.update(channel.calibration, &measured, sensor.overflow()) {
+                    info!("{} auto range {}", channel.label(), cal.text());
+                    events::record(EventKind::RangeChange, index as u8, cal.index() as f32, 0, timestamp_us);
+                    // keeps the statistics, they are in mA whatever the range
+                    channel.calibration = cal;
+                    switch_range(sensor, cal).await;
+                    // the sample is clipped or coarse, drop it
+                    continue;
+                }
+            }
+            let outputs = pipeline.process(index, channel, &measured, timestamp_us);
+            for event in &outputs.events {
+                events::record(event.kind, event.channel, event.value, event.duration_us, event.at_us);
+            }
+            if let Some(sample) = outputs.sample {
+                if route.stream {
+                    let _ = stream::STREAM_CHANNEL.try_send(sample);
+                }
+                if route.log {
+                    let _ = datalog::LOG_CHANNEL.try_send(sample);
+                }
+                if route.live {
+                    let _ = serial::LIVE_CHANNEL.try_send(sample);
+                }
+            }
+            burst |= outputs.burst;
+            for input in outputs.inputs() {
+                INPUT_CHANNEL.send(input).await;
+            }
         }
-        if let Ok(power_monitor) = ina219.sense() {
-            let mut input_data = InputData::new();
-            input_data.power = power_monitor;
-            INPUT_CHANNEL.send(input_data).await;
+        if let Some((sensor, channel)) = sensors.first_mut().filter(|_| burst && pending.is_none()) {
+            if let Some((burst, sample_rate_hz)) = capture_burst(sensor, channel, sampling, &mut pipeline).await {
+                if let Some(spectrum) = Spectrum::analyze(&burst, sample_rate_hz) {
+                    INPUT_CHANNEL.send(Input::Spectrum(spectrum)).await;
+                }
+            }
+        }
+        if let Some((index, recover)) = pending {
+            let status = pipeline.status(index);
+            warn!("{} {:?}, {}", sensors[index].1.label(), recover, status.text());
+            events::record(EventKind::SensorError, index as u8, status.code() as f32, 0, Instant::now().as_micros());
+            match recover {
+                Recover::ClockOut => bus::clock_out(),
+                Recover::Retry { wait_ms } => {
+                    retry_at[index] = Some(Instant::now() + Duration::from_millis(wait_ms as u64));
+                }
+                Recover::PowerCycle { wait_ms } => {
+                    Timer::after(Duration::from_millis(wait_ms as u64)).await;
+                    bus::power_cycle(&mut rail).await;
+                    reinit.fill(true);
+                }
+            }
+            reinit[index] = true;
+            INPUT_CHANNEL.send(Input::Status { channel: index as u8, status }).await;
         }
         ticker.next().await;
     }
 }
 
-fn get_calibration(index: usize) -> Calibration {
-    match index {
-        0 => Calibration::Calibration_32V_2A,
-        1 => Calibration::Calibration_32V_1A,
-        2 => Calibration::Calibration_16V_400mA,
-        _ => Calibration::Calibration_32V_2A
+/// Add a sensor for every input of the devices at addresses, the channels
+/// as saved in the settings.
+fn add_sensors(power_bus: &'static PowerBus, addresses: &[u8], settings: &Settings, sensors: &mut Vec<(Sensor<'static, PowerI2c>, Channel), MAX_CHANNELS>) {
+    let mut probe = blocking::i2c::I2cDevice::new(power_bus);
+    for &address in addresses {
+        let Ok(kind) = detect(&mut probe, address) else {
+            continue;
+        };
+        info!("{} found at 0x{:02x}", kind.text(), address);
+        // the INA3221 inputs are numbered 1 to 3, single sensors have input 0
+        let inputs = if kind.inputs() > 1 { 1..=kind.inputs() } else { 0..=0 };
+        for input in inputs {
+            let mut channel = Channel::with_input(address, input);
+            if let Some(saved) = settings.channel(address, input) {
+                channel.name = saved.name.clone();
+                channel.calibration = Calibration::from_index(saved.calibration).unwrap_or(Calibration::Range32V2A);
+                for correction in &saved.corrections {
+                    if let Some(cal) = Calibration::from_index(correction.range) {
+                        channel.set_correction(cal, Correction {
+                            gain: correction.gain,
+                            offset: correction.offset,
+                            calibrated_at: Some(correction.calibrated_at).filter(|at| *at != 0),
+                        });
+                    }
+                }
+            }
+            let i2c = Counted::new(blocking::i2c::I2cDevice::new(power_bus), &BUS_COUNTERS);
+            let sensor = Sensor::new(kind, i2c, &INA3221_CHIPS[(address & 0x0F) as usize], address, input);
+            if sensors.push((sensor, channel)).is_err() {
+                return;
+            }
+        }
     }
 }
 
-fn get_calibration_text(cal: Calibration) -> heapless::String<128> {
-    match cal {
-        Calibration::Calibration_32V_2A => "32V - 2A".parse().unwrap(),
-        Calibration::Calibration_32V_1A => "32V - 1A".parse().unwrap(),
-        Calibration::Calibration_16V_400mA => "16V - 400mA".parse().unwrap(),
+/// The LiPo monitor of the meter if it answers.
+fn find_lipo(power_bus: &'static PowerBus) -> Option<Max17048<PowerI2c>> {
+    // https://github.com/adafruit/Adafruit_CircuitPython_MAX1704x/blob/main/adafruit_max1704x.py
+    blocking::i2c::I2cDevice::new(power_bus).read(MAX17048_ADDR, &mut [0]).ok()?;
+    // counted like the sensors for the diagnostics page, the probe above
+    // is not, a board without the monitor has no errors
+    match Max17048::new(Counted::new(blocking::i2c::I2cDevice::new(power_bus), &BUS_COUNTERS)) {
+        Ok(monitor) => Some(monitor),
+        Err(e) => {
+            warn!("lipo compensation failed {:?}", e);
+            None
+        }
     }
 }
 
-// fn get_calibration_index(cal: Calibration) -> i8 {
-//     match cal {
-//         Calibration::Calibration_32V_2A => 0,
-//         Calibration::Calibration_32V_1A => 1,
-//         Calibration::Calibration_16V_400mA => 2,
-//     }
-// }
-
-// fn get_calibration_indicator_pos(index: usize, display_size: Size, indicator_size: Size) -> Point {
-//     match index {
-//         0 => create_point(0, 0),
-//         1 => create_point(0, (display_size.height / 2 - indicator_size.height / 2 - 10) as i32),
-//         2 => create_point(0, display_size.height as i32 - 10 - indicator_size.height as i32),
-//         _ => create_point(0, 0)
-//     }
-// }
+/// Remember the correction of a range of the channel across reboots.
+fn save_correction(address: u8, input: u8, calibration: Calibration, correction: &Correction) {
+    let mut settings = settings::load();
+    let correction = CorrectionSettings {
+        range: calibration.index(),
+        gain: correction.gain,
+        offset: correction.offset,
+        calibrated_at: correction.calibrated_at.unwrap_or(0),
+    };
+    if settings.set_correction(address, input, correction).is_err() {
+        return;
+    }
+    if let Err(e) = settings::save(&settings) {
+        warn!("saving correction failed {:?}", e);
+    }
+}
+
+/// Remember a new range of the channel across reboots.
+fn save_calibration(address: u8, input: u8, calibration: Calibration) {
+    let mut settings = settings::load();
+    let Some(channel) = settings.channel_mut(address, input) else {
+        return;
+    };
+    channel.calibration = calibration.index();
+    if let Err(e) = settings::save(&settings) {
+        warn!("saving calibration failed {:?}", e);
+    }
+}
+
+/// Remember the ripple window and the dropout threshold across reboots.
+fn save_quality(quality: &QualitySettings) {
+    let mut settings = settings::load();
+    let values = [
+        ("quality_window", quality.window_ms as f32),
+        ("dropout_threshold", quality.dropout_v),
+    ];
+    for (name, value) in values {
+        let mut text: String<16> = String::new();
+        let _ = write!(text, "{}", value);
+        if settings.set(name, &text).is_err() {
+            return;
+        }
+    }
+    if let Err(e) = settings::save(&settings) {
+        warn!("saving quality failed {:?}", e);
+    }
+}
+
+/// Remember the battery of the device under test across reboots.
+fn save_battery(battery: &Battery) {
+    let mut settings = settings::load();
+    let values = [
+        ("battery_capacity", battery.capacity_mah),
+        ("battery_usable", battery.usable * 100.0),
+        ("battery_self_discharge", battery.self_discharge * 100.0),
+    ];
+    for (name, value) in values {
+        let mut text: String<16> = String::new();
+        let _ = write!(text, "{}", value);
+        if settings.set(name, &text).is_err() {
+            return;
+        }
+    }
+    if let Err(e) = settings::save(&settings) {
+        warn!("saving battery failed {:?}", e);
+    }
+}
 
 #[main]
 async fn main(spawner: Spawner) -> ! {
@@ -234,19 +610,34 @@ async fn main(spawner: Spawner) -> ! {
     let timer_group0 = TimerGroup::new(peripherals.TIMG0, &clocks);
     embassy::init(&clocks, timer_group0);
 
-    esp_println::logger::init_logger_from_env();
+    let timer_group1 = TimerGroup::new(peripherals.TIMG1, &clocks);
+    let wifi_init = initialize(
+        EspWifiInitFor::Wifi,
+        timer_group1.timer0,
+        Rng::new(peripherals.RNG),
+        system.radio_clock_control,
+        &clocks,
+    ).unwrap();
+
+    logger::init_logger_from_env();
+
+    // the reset reason tells a brown-out from a normal boot
+    let reset_reason = esp_hal::reset::get_reset_reason().map(|reason| reason as u8).unwrap_or(0);
+    info!("reset reason 0x{:02x}", reset_reason);
+    spawner.must_spawn(events::handle_events());
+    events::record(EventKind::Boot, 0, reset_reason as f32, 0, Instant::now().as_micros());
 
     let io = IO::new(peripherals.GPIO, peripherals.IO_MUX);
 
-    // enable i2c_power
-    let i2c_power = io.pins.gpio7;
-    i2c_power.into_push_pull_output().set_high().unwrap();
+    // enable i2c_power, handle_power switches it off and on to recover the sensors
+    let mut i2c_power = io.pins.gpio7.into_push_pull_output();
+    i2c_power.set_high().unwrap();
 
     let i2c0 = I2C::new(
         peripherals.I2C0,
         io.pins.gpio3,
         io.pins.gpio4,
-        100u32.kHz(),
+        I2C_CLOCK_KHZ.kHz(),
         clocks,
     );
 
@@ -254,17 +645,14 @@ async fn main(spawner: Spawner) -> ! {
     let i2c0_bus_static = make_static!(i2c0_bus);
 
     let mut i2c0_dev0 = blocking::i2c::I2cDevice::new(i2c0_bus_static);
-    let mut i2c0_dev1 = blocking::i2c::I2cDevice::new(i2c0_bus_static);
 
-    let has_ina219 = i2c0_dev0.read(INA219_ADDR, &mut [0]).is_ok();
-    println!("has_ina219 = {}", has_ina219);
+    let settings = settings::load();
+    let mut sensors: Vec<(Sensor<'static, PowerI2c>, Channel), MAX_CHANNELS> = Vec::new();
+    add_sensors(i2c0_bus_static, &scan(&mut i2c0_dev0), &settings, &mut sensors);
+    let channels: Vec<Channel, MAX_CHANNELS> = sensors.iter().map(|(_, channel)| channel.clone()).collect();
 
-    // TODO lipo battery monitor at 0x36
-    // https://github.com/adafruit/Adafruit_CircuitPython_MAX1704x/blob/main/adafruit_max1704x.py
-    let has_lipo_monitor = i2c0_dev1.read(0x36, &mut [0]).is_ok();
-    println!("has_lipo_monitor = {}", has_lipo_monitor);
-
-    let mut lipo = Max17048::new(i2c0_dev1);
+    let mut lipo = find_lipo(i2c0_bus_static);
+    info!("has_lipo_monitor = {}", lipo.is_some());
 
     let sclk = io.pins.gpio36;
     let mosi = io.pins.gpio35;
@@ -302,8 +690,6 @@ async fn main(spawner: Spawner) -> ! {
 
     let spi_iface = SPIInterfaceNoCS::new(spi2, dc);
 
-    let display_size = Size::new(240, 135);
-
     let mut display = ST7789::new(
         spi_iface,
         Some(rst),
@@ -312,11 +698,6 @@ async fn main(spawner: Spawner) -> ! {
         135,
     );
 
-
-    let background_color_default = Rgb565::BLACK;
-    let display_width = display_size.width;
-    let display_height = display_size.height;
-
     // initialize`
     display.init(&mut Delay).unwrap();
 
@@ -325,27 +706,7 @@ async fn main(spawner: Spawner) -> ! {
 
     display.clear(Rgb565::BLACK).unwrap();
 
-    let background_style = PrimitiveStyleBuilder::new()
-        .fill_color(Rgb565::BLACK)
-        .build();
-
-    // let red_style = PrimitiveStyleBuilder::new()
-    //     .fill_color(Rgb565::RED)
-    //     .build();
-    //
-    // let green_style = PrimitiveStyleBuilder::new()
-    //     .fill_color(Rgb565::GREEN)
-    //     .build();
-
-    let center_text_style = TextStyleBuilder::new()
-        .alignment(Alignment::Left)
-        .baseline(Baseline::Middle)
-        .build();
-
-    let left_text_style = TextStyleBuilder::new()
-        .alignment(Alignment::Left)
-        .baseline(Baseline::Top)
-        .build();
+    let background_color_default = Rgb565::BLACK;
 
     let mut voltage_segment_style = SevenSegmentStyleBuilder::new()
         .digit_size(Size::new(30, 80)) // digits are 10x20 pixels
@@ -365,129 +726,189 @@ async fn main(spawner: Spawner) -> ! {
         Rgb565::WHITE);
     medium_character_style.background_color = Some(background_color_default);
 
-    let mut small_character_style = MonoTextStyle::new(
-        &PROFONT_12_POINT,
-        Rgb565::WHITE);
-    small_character_style.background_color = Some(background_color_default);
-
-    let mut power_display_buf: String<64> = String::new();
-    let mut unit_display_buf: String<2> = String::new();
-    let unit_display_width = (large_character_style.font.character_size.width * 2) as i32;
+    let theme = Theme {
+        digits: voltage_segment_style,
+        large: large_character_style,
+        medium: medium_character_style,
+        background: background_color_default,
+    };
+
+    // companion cli, see powermeter-cli
+    let uart1 = Uart::new_with_config(
+        peripherals.UART1,
+        UartConfig {
+            baudrate: BAUD_RATE,
+            ..UartConfig::default()
+        },
+        Some(TxRxPins::new_tx_rx(
+            io.pins.gpio5.into_push_pull_output(),
+            io.pins.gpio6.into_floating_input(),
+        )),
+        &clocks,
+    );
+    spawner.must_spawn(serial::handle_serial(uart1));
 
     spawner.must_spawn(handle_button_d0(io.pins.gpio0));
     spawner.must_spawn(handle_button_d1(io.pins.gpio1));
     spawner.must_spawn(handle_button_d2(io.pins.gpio2));
-    if has_ina219 {
-        spawner.must_spawn(handle_power(i2c0_dev0));
+    spawner.must_spawn(handle_power(sensors, i2c0_bus_static, i2c_power, settings.overcurrent_threshold_ma()));
+    spawner.must_spawn(datalog::handle_datalog());
+
+    // the settings go to the wifi task
+    let energy_price = settings.energy_price_per_kwh();
+    let defaults = Battery::default();
+    let battery = Battery {
+        capacity_mah: settings.battery_capacity_mah(),
+        usable: settings.battery_usable().unwrap_or(defaults.usable),
+        self_discharge: settings.battery_self_discharge().unwrap_or(defaults.self_discharge),
+    };
+    let defaults = QualitySettings::default();
+    let quality = QualitySettings {
+        window_ms: settings.quality_window_ms().unwrap_or(defaults.window_ms),
+        dropout_v: settings.dropout_threshold_v().unwrap_or(defaults.dropout_v),
+    };
+    // the dropout detector runs whatever page is up
+    QUALITY_COMMAND.signal(quality);
+    if provisioning::take_boot_request() || !settings.has_wifi_credentials() {
+        let (wifi_interface, controller) = esp_wifi::wifi::new_with_mode(&wifi_init, peripherals.WIFI, WifiApDevice).unwrap();
+        let stack: &'static ApStack = make_static!(Stack::new(
+            wifi_interface,
+            Config::ipv4_static(StaticConfigV4 {
+                address: Ipv4Cidr::new(provisioning::ap_address(), 24),
+                gateway: Some(provisioning::ap_address()),
+                dns_servers: Default::default(),
+            }),
+            make_static!(StackResources::<5>::new()),
+            1234,
+        ));
+        spawner.must_spawn(provisioning::handle_access_point(controller));
+        spawner.must_spawn(provisioning::handle_ap_net(stack));
+        spawner.must_spawn(provisioning::handle_dhcp(stack));
+        spawner.must_spawn(provisioning::handle_dns(stack));
+        spawner.must_spawn(provisioning::handle_portal(stack));
+
+        ui::draw_lines(&mut display, &theme, &["Wi-Fi setup", provisioning::AP_SSID, portal::PORTAL_URL]);
+        Timer::after(Duration::from_secs(5)).await;
+        display.clear(Rgb565::BLACK).unwrap();
     } else {
-        let _ = GraphicUtils::display_text_with_background(&mut display,
-                                                           create_point(0, (display_height / 2) as i32),
-                                                           large_character_style,
-                                                           center_text_style,
-                                                           "No ina219 found",
-                                                           background_style,
-                                                           display_width).unwrap();
+        let (wifi_interface, controller) = esp_wifi::wifi::new_with_mode(&wifi_init, peripherals.WIFI, WifiStaDevice).unwrap();
+        let stack: &'static NetStack = make_static!(Stack::new(
+            wifi_interface,
+            Config::dhcpv4(Default::default()),
+            make_static!(StackResources::<4>::new()),
+            1234,
+        ));
+        spawner.must_spawn(wifi::handle_connection(controller, settings));
+        spawner.must_spawn(wifi::handle_net(stack));
+        spawner.must_spawn(stream::handle_stream(stack));
+        spawner.must_spawn(sntp::handle_sntp(stack));
+        spawner.must_spawn(events::handle_http(stack));
     }
 
-    if has_lipo_monitor {
-        let mut battery_voltage: String<64> = String::new();
-        let lipo_voltage = lipo.vcell().unwrap();
-        write!(battery_voltage, "Battery {:1.1} V", lipo_voltage).unwrap();
-        let _ = GraphicUtils::display_text_with_background(&mut display,
-                                                           create_point(0, (display_height / 2) as i32),
-                                                           large_character_style,
-                                                           center_text_style,
-                                                           battery_voltage.as_str(),
-                                                           background_style,
-                                                           display_width).unwrap();
-        Timer::after(Duration::from_secs(5)).await
+    if let Some(lipo) = lipo.as_mut() {
+        match lipo.vcell() {
+            Ok(vcell) => {
+                ui::draw_battery(&mut display, &theme, vcell);
+                Timer::after(Duration::from_secs(5)).await
+            }
+            Err(e) => warn!("reading lipo voltage failed {:?}", e),
+        }
     }
 
-    let mut cal_index = 0;
-
-    // let rect_size = Size::new(20, 20);
-
-    // let rect_top_red = Rectangle::new(create_point(0, 10), rect_size).into_styled(red_style);
-    // let rect_top_green = Rectangle::new(create_point(0, 10), rect_size).into_styled(green_style);
-    //
-    // let rect_middle_red = Rectangle::new(create_point(0, (display_height / 2 - rect_size.height / 2) as i32), rect_size).into_styled(red_style);
-    // let rect_middle_green = Rectangle::new(create_point(0, (display_height / 2 - rect_size.height / 2) as i32), rect_size).into_styled(green_style);
-    //
-    // let rect_bottom_red = Rectangle::new(create_point(0, display_height as i32 - 10 - rect_size.height as i32), rect_size).into_styled(red_style);
-    // let rect_bottom_green = Rectangle::new(create_point(0, display_height as i32 - 10 - rect_size.height as i32), rect_size).into_styled(green_style);
-
-    let mut power_display: PowerDisplay = PowerDisplay::Voltage;
-
-    let mut last_power_display_buf: String<64> = String::new();
-
+    let mut ui = Ui::with_channels(channels);
+    ui.set_energy_price(energy_price);
+    ui.set_battery(battery);
+    ui.set_quality_settings(quality);
+    if ui.channels().is_empty() {
+        // until handle_power finds one
+        ui.show_message("No sensor found");
+        ui.draw(&mut display, &theme);
+    }
+    let mut lipo_warning = LipoWarning::default();
+    let mut lipo_checked = Instant::now();
     loop {
-        let mut input_data = INPUT_CHANNEL.receive().await;
-        if input_data.button != -1 {
-            match input_data.button {
-                0 => {
-                    // rect_top_red.draw(&mut display);
-                    cal_index = (cal_index + 1) % cardinality::<Calibration>();
-                    CALIBRATION_SIGNAL.signal(get_calibration(cal_index));
-                    input_data.msg = Some(get_calibration_text(get_calibration(cal_index)).clone());
-                    last_power_display_buf.clear();
-                }
-                1 => {
-                    // rect_middle_green.draw(&mut display);
-                    power_display = power_display.previous().unwrap_or(PowerDisplay::Voltage);
-                }
-                2 => {
-                    power_display = power_display.next().unwrap_or(PowerDisplay::Power);
+        let input = INPUT_CHANNEL.receive().await;
+        if lipo_checked.elapsed() >= LIPO_CHECK {
+            lipo_checked = Instant::now();
+            if lipo.is_none() {
+                lipo = find_lipo(i2c0_bus_static);
+            }
+            if let Some(lipo) = lipo.as_mut() {
+                match lipo.vcell() {
+                    Ok(vcell) if lipo_warning.update(vcell) => {
+                        warn!("lipo low {:.2} V", vcell);
+                        events::record(EventKind::BatteryLow, 0, vcell, 0, lipo_checked.as_micros());
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn!("reading lipo voltage failed {:?}", e),
                 }
-                _ => {}
             }
         }
-        power_display_buf.clear();
-        unit_display_buf.clear();
-
-        match power_display {
-            // PowerDisplay::Shunt => {
-            //     if input_data.power.Current != 0.0 {
-            //         write!(power_display_buf, "{:.3}", input_data.power.Shunt).unwrap();
-            //     } else {
-            //         write!(power_display_buf, "{:.3}", 0.0).unwrap();
-            //     }
-            //     write!(unit_display_buf, "mV").unwrap();
-            // }
-            PowerDisplay::Voltage => {
-                if input_data.power.Current != 0.0 {
-                    write!(power_display_buf, "{:>2.3}", input_data.power.Voltage).unwrap();
-                } else {
-                    write!(power_display_buf, "{:>2.3}", 0.0).unwrap();
+        match ui.handle(&input) {
+            Action::None => {}
+            Action::Calibrate { channel, calibration } => {
+                RANGE_CHANNEL.send((channel, RangeCommand::Fixed(calibration))).await;
+                if let Some(channel) = ui.channels().get(channel as usize) {
+                    save_calibration(channel.address, channel.input, calibration);
                 }
-                write!(unit_display_buf, "V ").unwrap();
             }
-            PowerDisplay::Current => {
-                if input_data.power.Current != 0.0 {
-                    write!(power_display_buf, "{:>5}", input_data.power.Current).unwrap();
-                } else {
-                    write!(power_display_buf, "{:>5}", 0.0).unwrap();
+            Action::AutoRange { channel, enabled } => {
+                RANGE_CHANNEL.send((channel, RangeCommand::Auto(enabled))).await;
+            }
+            Action::Sampling(sampling) => SAMPLING_COMMAND.signal(sampling),
+            Action::Filter { target, kind } => FILTER_CHANNEL.send((target, kind)).await,
+            Action::Profile(enabled) => PROFILING.store(enabled, Ordering::Relaxed),
+            Action::Battery(battery) => save_battery(&battery),
+            Action::Scope(settings) => SCOPE_COMMAND.signal(settings),
+            Action::ResetHistogram => HISTOGRAM_RESET.store(true, Ordering::Relaxed),
+            Action::Spectrum(enabled) => SPECTRUM.store(enabled, Ordering::Relaxed),
+            Action::Quality(Some(settings)) => {
+                QUALITY_COMMAND.signal(settings);
+                QUALITY.store(true, Ordering::Relaxed);
+            }
+            Action::Quality(None) => {
+                QUALITY.store(false, Ordering::Relaxed);
+                save_quality(ui.quality_settings());
+            }
+            Action::Correct { channel, calibration, correction } => {
+                let correction = Correction {
+                    // the date stays unknown without a synced clock
+                    calibrated_at: sntp::utc_offset_us()
+                        .map(|offset| ((Instant::now().as_micros() as i64 + offset) / 1_000_000) as u32),
+                    ..correction
+                };
+                RANGE_CHANNEL.send((channel, RangeCommand::Correct(calibration, correction))).await;
+                ui.set_correction(channel as usize, calibration, correction);
+                if let Some(channel) = ui.channels().get(channel as usize) {
+                    save_correction(channel.address, channel.input, calibration, &correction);
                 }
-                write!(unit_display_buf, "mA").unwrap();
             }
-            PowerDisplay::Power => {
-                if input_data.power.Current != 0.0 {
-                    write!(power_display_buf, "{:>5}", input_data.power.Power).unwrap();
+            Action::Activate(SettingsItem::WifiSetup) => provisioning::restart_into_provisioning(),
+            Action::Activate(item @ (SettingsItem::DataLog | SettingsItem::RawLog)) => {
+                let msg = if datalog::LOGGING.load(Ordering::Relaxed) {
+                    datalog::LOG_COMMAND.signal(datalog::LogCommand::Stop);
+                    "Logging off"
                 } else {
-                    write!(power_display_buf, "{:>5}", 0.0).unwrap();
-                }
-                write!(unit_display_buf, "mW").unwrap();
+                    let mode = if item == SettingsItem::RawLog { LogMode::Compact } else { LogMode::Aggregates };
+                    datalog::LOG_COMMAND.signal(datalog::LogCommand::Start { calibration: ui.channels().first().map(|channel| channel.calibration.index()).unwrap_or(0), mode });
+                    "Logging on"
+                };
+                ui.show_message(msg);
+                ui.draw(&mut display, &theme);
+                Timer::after(Duration::from_secs(2)).await;
             }
-        }
-        // Rectangle::new(get_calibration_indicator_pos(cal_index, display_size, rect_size), rect_size).into_styled(green_style).draw(&mut display);
-        if input_data.msg.is_some() {
-            let _ = Rectangle::new(create_point(0, 0), display_size).into_styled(background_style).draw(&mut display);
-            let _ = GraphicUtils::display_text(&mut display, create_point(10, (display_height / 2) as i32), large_character_style, center_text_style, input_data.msg.unwrap().as_str());
-        } else {
-            if power_display_buf != last_power_display_buf {
-                let _ = GraphicUtils::display_text_with_background(&mut display, create_point(0, (display_height / 2) as i32), voltage_segment_style, center_text_style, power_display_buf.as_str(), background_style, display_width);
-                let _ = GraphicUtils::display_text_with_background(&mut display, create_point(display_width as i32 - unit_display_width, (display_height / 2) as i32), large_character_style, center_text_style, unit_display_buf.as_str(), background_style, display_width);
+            Action::Activate(SettingsItem::Diagnostics) => {
+                let devices = i2c_bus::scan(&mut i2c0_dev0, &BUS_COUNTERS);
+                info!("{} devices on the bus", devices.len());
+                ui.show_diagnostics(&devices, I2C_CLOCK_KHZ);
+            }
+            Action::Activate(SettingsItem::Events) => {
+                let log = events::EVENT_LOG.lock().await;
+                ui.show_events(log.as_ref().unwrap_or(&EventLog::default()));
             }
-            last_power_display_buf = String::from(power_display_buf.clone());
+            // the others are handled by the ui
+            Action::Activate(_) => {}
         }
+        ui.draw(&mut display, &theme);
     }
 }
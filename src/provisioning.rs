@@ -0,0 +1,168 @@
+use embassy_net::tcp::TcpSocket;
+use embassy_net::udp::{PacketMetadata, UdpSocket};
+use embassy_net::{Ipv4Address, Stack};
+use embassy_time::{Duration, Timer};
+use esp_hal::macros::ram;
+use esp_hal::reset::software_reset;
+use esp_wifi::wifi::{AccessPointConfiguration, Configuration, WifiApDevice, WifiController, WifiDevice, WifiEvent};
+use log::{info, warn};
+use powermeter_core::ui::Input;
+use powermeter_protocol::http::{parse_request, ParseError, HTTP_PORT, MAX_REQUEST_LEN};
+use powermeter_protocol::portal::{self, Credentials, AP_ADDRESS};
+use powermeter_protocol::{dhcp, dns};
+
+use crate::{settings, INPUT_CHANNEL};
+
+pub const AP_SSID: &str = "powermeter-setup";
+
+const PROVISIONING_REQUEST: u32 = 0x5052_4F56;
+
+// survives the software reset used to switch the wifi mode
+#[ram(rtc_fast, uninitialized)]
+static mut BOOT_REQUEST: u32 = 0;
+
+pub type ApStack = Stack<WifiDevice<'static, WifiApDevice>>;
+
+/// Restart into provisioning mode even if credentials are stored.
+pub fn restart_into_provisioning() -> ! {
+    unsafe { BOOT_REQUEST = PROVISIONING_REQUEST };
+    software_reset();
+    loop {}
+}
+
+/// True once after restart_into_provisioning().
+pub fn take_boot_request() -> bool {
+    unsafe {
+        let requested = BOOT_REQUEST == PROVISIONING_REQUEST;
+        BOOT_REQUEST = 0;
+        requested
+    }
+}
+
+pub fn ap_address() -> Ipv4Address {
+    Ipv4Address::from_bytes(&AP_ADDRESS)
+}
+
+#[embassy_executor::task]
+pub async fn handle_access_point(mut controller: WifiController<'static>) {
+    let config = Configuration::AccessPoint(AccessPointConfiguration {
+        ssid: AP_SSID.try_into().unwrap(),
+        ..Default::default()
+    });
+    controller.set_configuration(&config).unwrap();
+    controller.start().await.unwrap();
+    info!("provisioning access point {} started", AP_SSID);
+    loop {
+        controller.wait_for_event(WifiEvent::ApStaconnected).await;
+        info!("client connected to access point");
+    }
+}
+
+#[embassy_executor::task]
+pub async fn handle_ap_net(stack: &'static ApStack) {
+    stack.run().await
+}
+
+#[embassy_executor::task]
+pub async fn handle_dhcp(stack: &'static ApStack) {
+    let mut rx_meta = [PacketMetadata::EMPTY; 4];
+    let mut rx_buffer = [0u8; 2 * dhcp::MAX_MESSAGE_LEN];
+    let mut tx_meta = [PacketMetadata::EMPTY; 4];
+    let mut tx_buffer = [0u8; 2 * dhcp::MAX_MESSAGE_LEN];
+    let mut socket = UdpSocket::new(stack, &mut rx_meta, &mut rx_buffer, &mut tx_meta, &mut tx_buffer);
+    socket.bind(dhcp::SERVER_PORT).unwrap();
+
+    let mut server = dhcp::DhcpServer::new(AP_ADDRESS);
+    let mut request = [0u8; dhcp::MAX_MESSAGE_LEN];
+    let mut reply = [0u8; dhcp::MAX_MESSAGE_LEN];
+    loop {
+        let Ok((len, _)) = socket.recv_from(&mut request).await else { continue };
+        if let Some(reply_len) = server.handle(&request[..len], &mut reply) {
+            let _ = socket.send_to(&reply[..reply_len], (Ipv4Address::BROADCAST, dhcp::CLIENT_PORT)).await;
+        }
+    }
+}
+
+#[embassy_executor::task]
+pub async fn handle_dns(stack: &'static ApStack) {
+    let mut rx_meta = [PacketMetadata::EMPTY; 4];
+    let mut rx_buffer = [0u8; 1024];
+    let mut tx_meta = [PacketMetadata::EMPTY; 4];
+    let mut tx_buffer = [0u8; 1024];
+    let mut socket = UdpSocket::new(stack, &mut rx_meta, &mut rx_buffer, &mut tx_meta, &mut tx_buffer);
+    socket.bind(dns::DNS_PORT).unwrap();
+
+    let mut query = [0u8; dns::MAX_MESSAGE_LEN];
+    let mut reply = [0u8; dns::MAX_MESSAGE_LEN];
+    loop {
+        let Ok((len, endpoint)) = socket.recv_from(&mut query).await else { continue };
+        if let Some(reply_len) = dns::answer(&query[..len], AP_ADDRESS, &mut reply) {
+            let _ = socket.send_to(&reply[..reply_len], endpoint).await;
+        }
+    }
+}
+
+#[embassy_executor::task]
+pub async fn handle_portal(stack: &'static ApStack) {
+    let mut rx_buffer = [0u8; 1024];
+    let mut tx_buffer = [0u8; 2048];
+    let mut request = [0u8; MAX_REQUEST_LEN];
+    let mut response = [0u8; 2048];
+
+    loop {
+        let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
+        socket.set_timeout(Some(Duration::from_secs(10)));
+        if socket.accept(HTTP_PORT).await.is_err() {
+            continue;
+        }
+
+        let mut len = 0;
+        let reply = loop {
+            match socket.read(&mut request[len..]).await {
+                Ok(0) | Err(_) => break None,
+                Ok(n) => len += n,
+            }
+            match parse_request(&request[..len]) {
+                Ok(parsed) => break Some(portal::handle(&parsed, &mut response)),
+                Err(ParseError::Incomplete) if len < request.len() => {}
+                Err(_) => break None,
+            }
+        };
+
+        let mut credentials = None;
+        if let Some(reply) = reply {
+            let mut sent = 0;
+            while sent < reply.len {
+                match socket.write(&response[sent..reply.len]).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => sent += n,
+                }
+            }
+            let _ = socket.flush().await;
+            credentials = reply.credentials;
+        }
+        socket.close();
+        Timer::after(Duration::from_millis(100)).await;
+        socket.abort();
+        drop(socket);
+
+        if let Some(credentials) = credentials {
+            store_credentials(credentials).await;
+        }
+    }
+}
+
+async fn store_credentials(credentials: Credentials) {
+    let mut settings = settings::load();
+    settings.wifi_ssid = credentials.ssid;
+    settings.wifi_password = credentials.password;
+    match settings::save(&settings) {
+        Ok(_) => {
+            info!("wifi credentials for {} saved", settings.wifi_ssid);
+            INPUT_CHANNEL.send(Input::Message("Wi-Fi saved".parse().unwrap())).await;
+            Timer::after(Duration::from_secs(2)).await;
+            software_reset();
+        }
+        Err(e) => warn!("saving wifi credentials failed {:?}", e),
+    }
+}
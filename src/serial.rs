@@ -0,0 +1,176 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embedded_io_async::{Read, Write};
+use heapless::Deque;
+use esp_hal::peripherals::UART1;
+use esp_hal::uart::{Uart, UartTx};
+use log::{info, warn};
+use powermeter_protocol::serial::{ErrorCode, FrameReader, Request, Response, SessionEntry, MAX_FRAME_LEN, VERSION};
+use powermeter_protocol::stream::Sample;
+use powermeter_storage::config::SettingError;
+use powermeter_storage::datalog::{SessionInfo, MAX_PAYLOAD};
+
+use crate::datalog::DATA_LOG;
+use crate::events::EVENT_LOG;
+use crate::settings;
+
+pub static LIVE: AtomicBool = AtomicBool::new(false);
+
+pub static LIVE_CHANNEL: embassy_sync::channel::Channel<CriticalSectionRawMutex, Sample, 16> = embassy_sync::channel::Channel::new();
+
+type SerialTx = UartTx<'static, UART1>;
+
+fn session_entry(info: &SessionInfo) -> SessionEntry {
+    SessionEntry {
+        id: info.id,
+        mode: info.config.mode.code(),
+        calibration: info.config.calibration,
+        interval_us: info.config.interval_us,
+        start_unix_us: info.config.start_unix_us,
+        duration_us: info.duration_us,
+        records: info.records,
+        truncated: info.truncated,
+    }
+}
+
+fn setting_error(e: SettingError) -> ErrorCode {
+    match e {
+        SettingError::UnknownName => ErrorCode::UnknownName,
+        SettingError::InvalidValue => ErrorCode::InvalidValue,
+    }
+}
+
+async fn send(tx: &mut SerialTx, response: &Response<'_>) {
+    let mut frame = [0u8; MAX_FRAME_LEN];
+    if let Some(len) = response.encode(&mut frame) {
+        if let Err(e) = tx.write_all(&frame[..len]).await {
+            warn!("serial write failed {:?}", e);
+        }
+    }
+}
+
+#[embassy_executor::task]
+pub async fn handle_serial(uart: Uart<'static, UART1>) {
+    let (mut tx, mut rx) = uart.split();
+    let mut reader = FrameReader::<MAX_FRAME_LEN>::new();
+    let mut buf = [0u8; 64];
+    info!("serial protocol v{} ready", VERSION);
+
+    loop {
+        let len = match select(rx.read(&mut buf), LIVE_CHANNEL.receive()).await {
+            Either::First(Ok(len)) => len,
+            Either::First(Err(e)) => {
+                warn!("serial read failed {:?}", e);
+                continue;
+            }
+            Either::Second(sample) => {
+                if LIVE.load(Ordering::Relaxed) {
+                    send(&mut tx, &Response::Sample(sample)).await;
+                }
+                continue;
+            }
+        };
+        for &byte in &buf[..len] {
+            let request = match reader.push(byte) {
+                Some(Ok(message)) => Request::decode(message),
+                Some(Err(e)) => {
+                    warn!("dropping serial frame {:?}", e);
+                    continue;
+                }
+                None => continue,
+            };
+            match request {
+                Ok(request) => handle_request(&mut tx, &request).await,
+                Err(_) => send(&mut tx, &Response::Error(ErrorCode::UnknownRequest)).await,
+            }
+        }
+    }
+}
+
+async fn handle_request(tx: &mut SerialTx, request: &Request<'_>) {
+    match request {
+        Request::Ping => send(tx, &Response::Pong { version: VERSION }).await,
+        Request::LiveStart => LIVE.store(true, Ordering::Relaxed),
+        Request::LiveStop => {
+            LIVE.store(false, Ordering::Relaxed);
+            while LIVE_CHANNEL.try_receive().is_ok() {}
+            send(tx, &Response::End).await;
+        }
+        Request::ConfigGet { name } => {
+            let settings = settings::load();
+            match settings.get(name) {
+                Ok(value) => send(tx, &Response::ConfigValue { value }).await,
+                Err(e) => send(tx, &Response::Error(setting_error(e))).await,
+            }
+        }
+        Request::ConfigSet { name, value } => {
+            let mut settings = settings::load();
+            let response = match settings.set(name, value) {
+                // takes effect on the next boot like the captive portal
+                Ok(()) => match settings::save(&settings) {
+                    Ok(()) => Response::Ok,
+                    Err(e) => {
+                        warn!("saving settings failed {:?}", e);
+                        Response::Error(ErrorCode::Storage)
+                    }
+                },
+                Err(e) => Response::Error(setting_error(e)),
+            };
+            send(tx, &response).await;
+        }
+        Request::SessionList => {
+            let sessions = match DATA_LOG.lock().await.as_mut().map(|log| log.sessions()) {
+                Some(Ok(sessions)) => sessions,
+                _ => return send(tx, &Response::Error(ErrorCode::Storage)).await,
+            };
+            for info in &sessions {
+                send(tx, &Response::Session(session_entry(info))).await;
+            }
+            send(tx, &Response::End).await;
+        }
+        Request::SessionRead { id } => read_session(tx, *id).await,
+        Request::EventList => {
+            // a copy, recording goes on while sending
+            let events = EVENT_LOG.lock().await.as_ref().map(|log| log.events().clone()).unwrap_or_else(Deque::new);
+            for event in &events {
+                send(tx, &Response::Event(*event)).await;
+            }
+            send(tx, &Response::End).await;
+        }
+    }
+}
+
+// the log is locked per record so logging carries on during a download
+async fn read_session(tx: &mut SerialTx, id: u32) {
+    let (info, mut cursor) = {
+        let mut guard = DATA_LOG.lock().await;
+        let Some(log) = guard.as_mut() else {
+            return send(tx, &Response::Error(ErrorCode::Storage)).await;
+        };
+        let info = match log.sessions() {
+            Ok(sessions) => sessions.iter().find(|info| info.id == id).copied(),
+            Err(_) => return send(tx, &Response::Error(ErrorCode::Storage)).await,
+        };
+        match info {
+            Some(info) => (info, log.cursor(id)),
+            None => return send(tx, &Response::Error(ErrorCode::NotFound)).await,
+        }
+    };
+    send(tx, &Response::Session(session_entry(&info))).await;
+    loop {
+        let record = match DATA_LOG.lock().await.as_mut().map(|log| log.next_record(&mut cursor)) {
+            Some(Ok(Some(record))) => record,
+            Some(Ok(None)) => break,
+            _ => {
+                warn!("reading data log session {} failed", id);
+                break;
+            }
+        };
+        let mut payload = [0u8; MAX_PAYLOAD];
+        let (kind, len) = record.encode(&mut payload);
+        send(tx, &Response::Record { kind, payload: &payload[..len] }).await;
+    }
+    send(tx, &Response::End).await;
+}
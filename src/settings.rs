@@ -0,0 +1,21 @@
+use esp_storage::FlashStorage;
+use log::warn;
+use powermeter_storage::config::{ConfigError, ConfigStore, Settings};
+
+// "config" in partitions.csv
+const CONFIG_OFFSET: u32 = 0x210000;
+
+pub fn load() -> Settings {
+    let mut store = ConfigStore::new(FlashStorage::new(), CONFIG_OFFSET);
+    match store.load() {
+        Ok(settings) => settings.unwrap_or_default(),
+        Err(e) => {
+            warn!("loading settings failed {:?}", e);
+            Settings::default()
+        }
+    }
+}
+
+pub fn save(settings: &Settings) -> Result<(), ConfigError<esp_storage::FlashStorageError>> {
+    ConfigStore::new(FlashStorage::new(), CONFIG_OFFSET).save(settings)
+}
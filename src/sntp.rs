@@ -0,0 +1,109 @@
+use core::cell::RefCell;
+
+use embassy_net::dns::DnsQueryType;
+use embassy_net::udp::{PacketMetadata, UdpSocket};
+use embassy_sync::blocking_mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_time::{with_timeout, Duration, Instant, Timer};
+use log::{info, warn};
+use powermeter_protocol::sntp::{measure, parse_response, request, Measurement, SntpError, NTP_PORT, PACKET_LEN};
+use powermeter_protocol::time::{UtcDateTime, WallClock};
+
+use crate::settings;
+use crate::wifi::NetStack;
+
+const SYNC_INTERVAL: Duration = Duration::from_secs(15 * 60);
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+static WALL_CLOCK: blocking_mutex::Mutex<CriticalSectionRawMutex, RefCell<WallClock>> = blocking_mutex::Mutex::new(RefCell::new(WallClock::new()));
+
+#[derive(Debug)]
+enum SyncError {
+    Dns,
+    Send,
+    Timeout,
+    Response(SntpError),
+}
+
+fn local_us() -> i64 {
+    Instant::now().as_micros() as i64
+}
+
+/// Offset from embassy_time microseconds to unix time, None until the
+/// first successful sync.
+pub fn utc_offset_us() -> Option<i64> {
+    WALL_CLOCK.lock(|clock| clock.borrow().offset_us(local_us()))
+}
+
+pub fn to_utc(instant: Instant) -> Option<UtcDateTime> {
+    WALL_CLOCK.lock(|clock| clock.borrow().unix_us(instant.as_micros() as i64))
+        .map(UtcDateTime::from_unix_us)
+}
+
+pub fn now_utc() -> Option<UtcDateTime> {
+    to_utc(Instant::now())
+}
+
+#[embassy_executor::task]
+pub async fn handle_sntp(stack: &'static NetStack) {
+    stack.wait_config_up().await;
+
+    let mut rx_meta = [PacketMetadata::EMPTY; 2];
+    let mut rx_buffer = [0u8; 2 * PACKET_LEN];
+    let mut tx_meta = [PacketMetadata::EMPTY; 2];
+    let mut tx_buffer = [0u8; 2 * PACKET_LEN];
+    let mut socket = UdpSocket::new(stack, &mut rx_meta, &mut rx_buffer, &mut tx_meta, &mut tx_buffer);
+    socket.bind(0).unwrap();
+
+    // a changed server is used after the next boot
+    let settings = settings::load();
+    let server = settings.sntp_server();
+    loop {
+        let next = match sync(stack, &socket, server).await {
+            Ok(measurement) => {
+                let (drift_ppb, syncs) = WALL_CLOCK.lock(|clock| {
+                    let clock = clock.borrow();
+                    (clock.drift_ppb(), clock.syncs())
+                });
+                info!("sntp sync #{} offset {} us delay {} us drift {} ppb",
+                      syncs, measurement.offset_us, measurement.delay_us, drift_ppb);
+                SYNC_INTERVAL
+            }
+            Err(e) => {
+                warn!("sntp sync with {} failed {:?}", server, e);
+                RETRY_INTERVAL
+            }
+        };
+        Timer::after(next).await
+    }
+}
+
+async fn sync(stack: &'static NetStack, socket: &UdpSocket<'_>, host: &str) -> Result<Measurement, SyncError> {
+    let addrs = stack.dns_query(host, DnsQueryType::A).await.map_err(|_| SyncError::Dns)?;
+    let server = *addrs.first().ok_or(SyncError::Dns)?;
+
+    let t1 = local_us();
+    let nonce = t1 as u64;
+    socket.send_to(&request(nonce), (server, NTP_PORT)).await.map_err(|_| SyncError::Send)?;
+
+    let mut buf = [0u8; PACKET_LEN];
+    loop {
+        let (len, _) = match with_timeout(RESPONSE_TIMEOUT, socket.recv_from(&mut buf)).await {
+            Ok(Ok(received)) => received,
+            Ok(Err(_)) => continue,
+            Err(_) => return Err(SyncError::Timeout),
+        };
+        let t4 = local_us();
+        match parse_response(&buf[..len], nonce) {
+            // late answer to an earlier request
+            Err(SntpError::OriginMismatch) => continue,
+            Err(e) => return Err(SyncError::Response(e)),
+            Ok(response) => {
+                let measurement = measure(t1, &response, t4);
+                WALL_CLOCK.lock(|clock| clock.borrow_mut().update((t1 + t4) / 2, &measurement));
+                return Ok(measurement);
+            }
+        }
+    }
+}
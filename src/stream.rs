@@ -0,0 +1,87 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use embassy_futures::select::{select, Either};
+use embassy_net::udp::{PacketMetadata, UdpSocket};
+use embassy_net::IpEndpoint;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_time::{with_timeout, Duration, Instant};
+use log::{info, warn};
+use powermeter_protocol::stream::{is_subscribe_request, PacketBuilder, Sample, DEFAULT_PORT, MAX_PACKET_LEN, SUBSCRIBE_TIMEOUT_SECS};
+
+use crate::sntp;
+use crate::wifi::NetStack;
+
+// max time a sample waits in a partially filled packet
+const STREAM_FLUSH: Duration = Duration::from_millis(50);
+
+pub static STREAMING: AtomicBool = AtomicBool::new(false);
+
+pub static STREAM_CHANNEL: embassy_sync::channel::Channel<CriticalSectionRawMutex, Sample, 256> = embassy_sync::channel::Channel::new();
+
+#[embassy_executor::task]
+pub async fn handle_stream(stack: &'static NetStack) {
+    stack.wait_config_up().await;
+
+    let mut rx_meta = [PacketMetadata::EMPTY; 4];
+    let mut rx_buffer = [0u8; 64];
+    let mut tx_meta = [PacketMetadata::EMPTY; 4];
+    let mut tx_buffer = [0u8; 4 * MAX_PACKET_LEN];
+    let mut socket = UdpSocket::new(stack, &mut rx_meta, &mut rx_buffer, &mut tx_meta, &mut tx_buffer);
+    socket.bind(DEFAULT_PORT).unwrap();
+    info!("stream listening on port {}", DEFAULT_PORT);
+
+    let mut builder = PacketBuilder::new();
+    let mut receiver: Option<IpEndpoint> = None;
+    let mut last_subscribe = Instant::now();
+    let mut request = [0u8; 8];
+
+    loop {
+        let timeout = if builder.is_empty() { Duration::from_secs(1) } else { STREAM_FLUSH };
+        let event = with_timeout(timeout, select(socket.recv_from(&mut request), STREAM_CHANNEL.receive())).await;
+        match event {
+            Ok(Either::First(Ok((len, endpoint)))) => {
+                if is_subscribe_request(&request[..len]) {
+                    if receiver != Some(endpoint) {
+                        info!("stream receiver {}", endpoint);
+                    }
+                    receiver = Some(endpoint);
+                    last_subscribe = Instant::now();
+                    STREAMING.store(true, Ordering::Relaxed);
+                }
+            }
+            Ok(Either::First(Err(_))) => {}
+            Ok(Either::Second(sample)) => {
+                if !builder.push(&sample) {
+                    send_packet(&socket, &mut builder, receiver).await;
+                    builder.push(&sample);
+                }
+                if builder.is_full() {
+                    send_packet(&socket, &mut builder, receiver).await;
+                }
+            }
+            Err(_) => {
+                if !builder.is_empty() {
+                    send_packet(&socket, &mut builder, receiver).await;
+                }
+            }
+        }
+
+        if receiver.is_some() && last_subscribe.elapsed() > Duration::from_secs(SUBSCRIBE_TIMEOUT_SECS) {
+            info!("stream receiver timed out");
+            receiver = None;
+            STREAMING.store(false, Ordering::Relaxed);
+            while STREAM_CHANNEL.try_receive().is_ok() {}
+            builder.finish();
+        }
+    }
+}
+
+async fn send_packet(socket: &UdpSocket<'_>, builder: &mut PacketBuilder, receiver: Option<IpEndpoint>) {
+    builder.set_utc_offset(sntp::utc_offset_us());
+    let packet = builder.finish();
+    if let Some(endpoint) = receiver {
+        if let Err(e) = socket.send_to(packet, endpoint).await {
+            warn!("stream send failed {:?}", e);
+        }
+    }
+}
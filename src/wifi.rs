@@ -0,0 +1,38 @@
+use embassy_net::Stack;
+use embassy_time::{Duration, Timer};
+use esp_wifi::wifi::{ClientConfiguration, Configuration, WifiController, WifiDevice, WifiEvent, WifiStaDevice, WifiState};
+use log::{info, warn};
+use powermeter_storage::config::Settings;
+
+pub type NetStack = Stack<WifiDevice<'static, WifiStaDevice>>;
+
+#[embassy_executor::task]
+pub async fn handle_connection(mut controller: WifiController<'static>, settings: Settings) {
+    loop {
+        if esp_wifi::wifi::get_wifi_state() == WifiState::StaConnected {
+            controller.wait_for_event(WifiEvent::StaDisconnected).await;
+            Timer::after(Duration::from_secs(5)).await
+        }
+        if !matches!(controller.is_started(), Ok(true)) {
+            let client_config = Configuration::Client(ClientConfiguration {
+                ssid: settings.wifi_ssid.as_str().try_into().unwrap(),
+                password: settings.wifi_password.as_str().try_into().unwrap(),
+                ..Default::default()
+            });
+            controller.set_configuration(&client_config).unwrap();
+            controller.start().await.unwrap();
+        }
+        match controller.connect().await {
+            Ok(_) => info!("wifi connected"),
+            Err(e) => {
+                warn!("wifi connect failed {:?}", e);
+                Timer::after(Duration::from_secs(5)).await
+            }
+        }
+    }
+}
+
+#[embassy_executor::task]
+pub async fn handle_net(stack: &'static NetStack) {
+    stack.run().await
+}